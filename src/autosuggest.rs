@@ -14,45 +14,439 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use fst::{automaton::Str, Automaton, IntoStreamer};
+use fst::{
+    automaton::{Levenshtein, Str},
+    Automaton, IntoStreamer, Map, MapBuilder, Streamer,
+};
 
+use crate::entity_index::EntityIndex;
+use crate::index::Index;
 use crate::Result;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::io;
 use std::path::Path;
 
+const NUM_SUGGESTIONS: usize = 10;
+
+/// Prefixes matching more suggestions than this get their top suggestions computed
+/// when the suggestions are loaded, so short prefixes don't scan a large part of
+/// the map on every keystroke. All other prefixes are cheap to scan in full.
+const MIN_PRECOMPUTED_MATCHES: usize = 1_000;
+
+/// Stop looking at matches of a misspelled prefix after this many. Unlike exact
+/// prefixes, these can't be precomputed, so their suggestions are the most popular
+/// among the first matches rather than among all of them.
+const MAX_SCANNED_FUZZY_CANDIDATES: usize = 10_000;
+
+/// Only the most popular suggestions are kept when building.
+const MAX_STORED_SUGGESTIONS: usize = 1_000_000;
+
+/// Titles longer than this are unlikely to be typed as a query.
+const MAX_SUGGESTION_CHARS: usize = 64;
+
+const PAGE_TITLE_WEIGHT: u64 = 1;
+const ENTITY_TITLE_WEIGHT: u64 = 5;
+const QUERY_LOG_WEIGHT: u64 = 10;
+
+fn normalize(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn max_edit_distance(prefix: &str) -> u32 {
+    match prefix.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
 pub struct Autosuggest {
-    queries: fst::Set<Vec<u8>>,
+    queries: Map<Vec<u8>>,
+    top_by_prefix: HashMap<String, Vec<String>>,
+}
+
+/// A prefix of the query currently visited by [`precompute_top_suggestions`].
+#[derive(Default)]
+struct PrefixTop {
+    num_matches: usize,
+    top: BinaryHeap<(Reverse<u64>, String)>,
+}
+
+/// The top suggestions for every prefix that matches more than
+/// [`MIN_PRECOMPUTED_MATCHES`] suggestions, found in a single pass over the map.
+/// The map is sorted, so the queries starting with a prefix are consecutive and a
+/// prefix is done as soon as a query no longer starts with it.
+fn precompute_top_suggestions(queries: &Map<Vec<u8>>) -> HashMap<String, Vec<String>> {
+    let mut top_by_prefix = HashMap::new();
+
+    // path[i] is the prefix of the previous query of length i bytes
+    let mut path: Vec<PrefixTop> = Vec::new();
+    let mut prev: Vec<u8> = Vec::new();
+
+    let mut finish = |prefix: &[u8], prefix_top: PrefixTop| {
+        if prefix_top.num_matches <= MIN_PRECOMPUTED_MATCHES {
+            return;
+        }
+
+        // byte prefixes that split a character can never be looked up
+        if let Ok(prefix) = std::str::from_utf8(prefix) {
+            let top = prefix_top
+                .top
+                .into_sorted_vec()
+                .into_iter()
+                .map(|(_, query)| query)
+                .collect();
+
+            top_by_prefix.insert(prefix.to_string(), top);
+        }
+    };
+
+    let mut stream = queries.stream();
+    while let Some((query, weight)) = stream.next() {
+        let common = prev
+            .iter()
+            .zip(query)
+            .take_while(|(prev, next)| prev == next)
+            .count();
+
+        while path.len() > common + 1 {
+            let prefix_top = path.pop().unwrap();
+            finish(&prev[..path.len()], prefix_top);
+        }
+
+        while path.len() < query.len() + 1 {
+            path.push(PrefixTop::default());
+        }
+
+        for prefix_top in &mut path {
+            prefix_top.num_matches += 1;
+
+            // queries are visited in order, so a query with the same weight as the
+            // least popular of the top suggestions sorts after it and is skipped.
+            let is_top = prefix_top.top.len() < NUM_SUGGESTIONS
+                || matches!(prefix_top.top.peek(), Some((Reverse(least), _)) if weight > *least);
+
+            if is_top {
+                prefix_top
+                    .top
+                    .push((Reverse(weight), String::from_utf8_lossy(query).to_string()));
+
+                if prefix_top.top.len() > NUM_SUGGESTIONS {
+                    prefix_top.top.pop();
+                }
+            }
+        }
+
+        prev = query.to_vec();
+    }
+
+    while let Some(prefix_top) = path.pop() {
+        finish(&prev[..path.len()], prefix_top);
+    }
+
+    top_by_prefix
 }
 
 impl Autosuggest {
-    pub fn load_csv<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut queries: Vec<String> = Vec::new();
+    /// Loads suggestions from a csv file if the path has a `csv` extension, and
+    /// otherwise from a file written by [`AutosuggestBuilder::build`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        if path.as_ref().extension() == Some("csv".as_ref()) {
+            Self::load_csv(path)
+        } else {
+            Self::open(path)
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::from_map(Map::new(fs::read(path)?)?))
+    }
 
+    fn from_map(queries: Map<Vec<u8>>) -> Self {
+        Self {
+            top_by_prefix: precompute_top_suggestions(&queries),
+            queries,
+        }
+    }
+
+    /// The first column of the csv is the query and the optional second column its
+    /// weight. Queries without a weight are assumed to be ordered by popularity.
+    pub fn load_csv<P: AsRef<Path>>(path: P) -> Result<Self> {
         let mut rdr = csv::Reader::from_path(path)?;
-        for result in rdr.records() {
-            let record = result?;
+        let records = rdr
+            .records()
+            .collect::<std::result::Result<Vec<_>, csv::Error>>()?;
+
+        let num_records = records.len() as u64;
+        let mut builder = AutosuggestBuilder::default();
+
+        for (rank, record) in records.iter().enumerate() {
             if let Some(query) = record.get(0) {
-                queries.push(query.to_string());
+                let weight = record
+                    .get(1)
+                    .and_then(|weight| weight.trim().parse().ok())
+                    .unwrap_or(num_records - rank as u64);
+
+                builder.insert(query, weight);
             }
         }
 
-        queries.sort();
+        builder.build_in_memory()
+    }
 
-        let queries = fst::Set::from_iter(queries)?;
+    fn top_matches<A: Automaton>(&self, automaton: A, max_scanned: usize) -> Vec<String> {
+        let mut stream = self.queries.search(automaton).into_stream();
+        let mut heap = BinaryHeap::with_capacity(NUM_SUGGESTIONS + 1);
+        let mut num_scanned = 0;
 
-        Ok(Self { queries })
-    }
+        while let Some((query, weight)) = stream.next() {
+            heap.push((Reverse(weight), String::from_utf8_lossy(query).to_string()));
 
-    pub fn suggestions(&self, query: &str) -> Result<Vec<String>> {
-        let query = query.to_ascii_lowercase();
-        let q = Str::new(query.as_str()).starts_with();
+            if heap.len() > NUM_SUGGESTIONS {
+                heap.pop();
+            }
 
-        Ok(self
-            .queries
-            .search(q)
-            .into_stream()
-            .into_strs()?
+            num_scanned += 1;
+            if num_scanned >= max_scanned {
+                break;
+            }
+        }
+
+        heap.into_sorted_vec()
             .into_iter()
-            .take(10)
-            .collect())
+            .map(|(_, query)| query)
+            .collect()
+    }
+
+    /// The most popular suggestions starting with `query`. If there are too few of
+    /// them, suggestions starting with a prefix a few edits away from `query` are added.
+    pub fn suggestions(&self, query: &str) -> Vec<String> {
+        let mut prefix = normalize(query);
+        if query.ends_with(char::is_whitespace) && !prefix.is_empty() {
+            prefix.push(' ');
+        }
+
+        let mut suggestions = match self.top_by_prefix.get(&prefix) {
+            Some(top) => top.clone(),
+            None => self.top_matches(Str::new(prefix.as_str()).starts_with(), usize::MAX),
+        };
+
+        let dist = max_edit_distance(&prefix);
+        if suggestions.len() < NUM_SUGGESTIONS && dist > 0 {
+            if let Ok(automaton) = Levenshtein::new(&prefix, dist) {
+                for suggestion in
+                    self.top_matches(automaton.starts_with(), MAX_SCANNED_FUZZY_CANDIDATES)
+                {
+                    if suggestions.len() >= NUM_SUGGESTIONS {
+                        break;
+                    }
+
+                    if !suggestions.contains(&suggestion) {
+                        suggestions.push(suggestion);
+                    }
+                }
+            }
+        }
+
+        suggestions
+    }
+}
+
+/// Collects weighted suggestions from page titles, entity titles and query logs.
+#[derive(Default)]
+pub struct AutosuggestBuilder {
+    weights: HashMap<String, u64>,
+}
+
+impl AutosuggestBuilder {
+    pub fn insert(&mut self, query: &str, weight: u64) {
+        let query = normalize(query);
+
+        if query.is_empty() || query.chars().count() > MAX_SUGGESTION_CHARS {
+            return;
+        }
+
+        *self.weights.entry(query).or_default() += weight;
+    }
+
+    /// Titles count once per site, so a site that repeats its title on every page
+    /// doesn't outweigh titles that are shared by many sites.
+    pub fn insert_page_titles(&mut self, index: &Index) -> Result<()> {
+        index
+            .inverted_index
+            .for_each_site_title(|title| self.insert(title, PAGE_TITLE_WEIGHT))
+    }
+
+    pub fn insert_entity_titles(&mut self, index: &EntityIndex) -> Result<()> {
+        index.for_each_title(|title| self.insert(title, ENTITY_TITLE_WEIGHT))
+    }
+
+    /// Inserts queries from a csv file with the query in the first column and the
+    /// number of times it was searched in the second.
+    pub fn insert_query_log<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut rdr = csv::Reader::from_path(path)?;
+
+        for record in rdr.records() {
+            let record = record?;
+
+            if let (Some(query), Some(count)) = (record.get(0), record.get(1)) {
+                self.insert(query, count.trim().parse::<u64>()? * QUERY_LOG_WEIGHT);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn top_suggestions(self) -> Vec<(String, u64)> {
+        let mut suggestions: Vec<_> = self.weights.into_iter().collect();
+
+        if suggestions.len() > MAX_STORED_SUGGESTIONS {
+            suggestions
+                .select_nth_unstable_by_key(MAX_STORED_SUGGESTIONS, |(_, weight)| Reverse(*weight));
+            suggestions.truncate(MAX_STORED_SUGGESTIONS);
+        }
+
+        suggestions.sort();
+        suggestions
+    }
+
+    pub fn build<P: AsRef<Path>>(self, path: P) -> Result<()> {
+        let wrt = io::BufWriter::new(File::create(path)?);
+        let mut builder = MapBuilder::new(wrt)?;
+
+        for (query, weight) in self.top_suggestions() {
+            builder.insert(query, weight)?;
+        }
+
+        builder.finish()?;
+
+        Ok(())
+    }
+
+    pub fn build_in_memory(self) -> Result<Autosuggest> {
+        Ok(Autosuggest::from_map(Map::from_iter(
+            self.top_suggestions(),
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn autosuggest(queries: &[(&str, u64)]) -> Autosuggest {
+        let mut builder = AutosuggestBuilder::default();
+
+        for (query, weight) in queries {
+            builder.insert(query, *weight);
+        }
+
+        builder.build_in_memory().unwrap()
+    }
+
+    #[test]
+    fn ranked_by_weight() {
+        let autosuggest = autosuggest(&[
+            ("new york", 10),
+            ("new york times", 50),
+            ("newcastle", 5),
+            ("New  York", 10),
+            ("old york", 100),
+        ]);
+
+        assert_eq!(
+            autosuggest.suggestions("new"),
+            vec![
+                "new york times".to_string(),
+                "new york".to_string(),
+                "newcastle".to_string()
+            ]
+        );
+        assert_eq!(
+            autosuggest.suggestions("New  Y"),
+            vec!["new york times".to_string(), "new york".to_string()]
+        );
+        assert!(autosuggest.suggestions("xyz").is_empty());
+    }
+
+    #[test]
+    fn only_top_suggestions() {
+        let queries: Vec<_> = (0..20).map(|i| (format!("test {i}"), i)).collect();
+        let queries: Vec<_> = queries
+            .iter()
+            .map(|(query, weight)| (query.as_str(), *weight))
+            .collect();
+        let autosuggest = autosuggest(&queries);
+
+        let suggestions = autosuggest.suggestions("test");
+        assert_eq!(suggestions.len(), NUM_SUGGESTIONS);
+        assert_eq!(suggestions[0], "test 19".to_string());
+        assert_eq!(suggestions[9], "test 10".to_string());
+    }
+
+    #[test]
+    fn precomputed_prefixes() {
+        let queries: Vec<_> = (0..2 * MIN_PRECOMPUTED_MATCHES as u64)
+            .map(|i| (format!("query {i:05}"), i))
+            .chain([("quiz".to_string(), 1), ("quick".to_string(), 10_000)])
+            .collect();
+        let queries: Vec<_> = queries
+            .iter()
+            .map(|(query, weight)| (query.as_str(), *weight))
+            .collect();
+        let autosuggest = autosuggest(&queries);
+
+        assert!(autosuggest.top_by_prefix.contains_key("query 0"));
+        assert!(autosuggest.top_by_prefix.contains_key("qu"));
+        assert!(!autosuggest.top_by_prefix.contains_key("qui"));
+
+        let suggestions = autosuggest.suggestions("qu");
+        assert_eq!(suggestions.len(), NUM_SUGGESTIONS);
+        assert_eq!(suggestions[0], "quick".to_string());
+        assert_eq!(suggestions[1], "query 01999".to_string());
+
+        assert_eq!(
+            autosuggest.suggestions("qui"),
+            vec!["quick".to_string(), "quiz".to_string()]
+        );
+        assert_eq!(
+            autosuggest.suggestions("query 0"),
+            autosuggest.top_matches(Str::new("query 0").starts_with(), usize::MAX)
+        );
+    }
+
+    #[test]
+    fn misspelled_prefix() {
+        let autosuggest = autosuggest(&[("wikipedia", 10), ("wiki how", 1), ("weather", 5)]);
+
+        assert_eq!(
+            autosuggest.suggestions("wikpe"),
+            vec!["wikipedia".to_string()]
+        );
+        assert_eq!(
+            autosuggest.suggestions("wiki"),
+            vec!["wikipedia".to_string(), "wiki how".to_string()]
+        );
+        assert_eq!(autosuggest.suggestions("wik").len(), 2);
+    }
+
+    #[test]
+    fn non_ascii() {
+        let autosuggest = autosuggest(&[("københavn", 10), ("köln", 5)]);
+
+        assert_eq!(
+            autosuggest.suggestions("Køb"),
+            vec!["københavn".to_string()]
+        );
+        assert_eq!(
+            autosuggest.suggestions("kobenh"),
+            vec!["københavn".to_string()]
+        );
     }
 }
@@ -18,14 +18,19 @@ use std::{collections::HashMap, fs, path::Path};
 
 use itertools::intersperse;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 use crate::{
-    query::{parser::Term, Query},
+    query::parser::{self, Term},
     webpage::Url,
+    Result,
 };
 
 pub const BANG_PREFIX: char = '!';
 
+/// Maximum number of bangs returned by [`Bangs::search`].
+const MAX_SEARCH_RESULTS: usize = 50;
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Bang {
     #[serde(rename = "c")]
@@ -56,37 +61,130 @@ pub struct BangHit {
     pub redirect_to: Url,
 }
 
+impl Bang {
+    /// Bangs are followed as redirects, so they may only lead to http(s) urls.
+    fn redirects_to_http(&self) -> bool {
+        let url = self.url.trim_start().to_lowercase();
+        url.starts_with("http://") || url.starts_with("https://")
+    }
+}
+
+/// Maps each tag to its bang. When several bangs in the same source share a tag,
+/// the one with the highest ranking is used. Bangs that don't redirect to an
+/// http(s) url are skipped.
+fn by_tag(bangs: impl IntoIterator<Item = Bang>) -> HashMap<String, Bang> {
+    let mut res: HashMap<String, Bang> = HashMap::new();
+
+    for mut bang in bangs {
+        if !bang.redirects_to_http() {
+            continue;
+        }
+
+        bang.tag = bang.tag.to_lowercase();
+
+        match res.get(&bang.tag) {
+            Some(existing) if existing.ranking >= bang.ranking => {}
+            _ => {
+                res.insert(bang.tag.clone(), bang);
+            }
+        }
+    }
+
+    res
+}
+
+/// A term in the query that might be a bang.
+struct PossibleBang<'a> {
+    tag: String,
+    term: &'a Term,
+}
+
+/// Bangs can be written as `!tag` anywhere in the query, or as `tag!` as the first
+/// or last term of the query. A query consisting of only `tag!` is a search for
+/// something like "Yahoo!" rather than a bang.
+fn possible_bangs(terms: &[Box<Term>]) -> Vec<PossibleBang<'_>> {
+    terms
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, term)| {
+            let tag = match term.as_ref() {
+                Term::PossibleBang(tag) => tag.as_str(),
+                Term::Simple(text) if terms.len() > 1 && (idx == 0 || idx == terms.len() - 1) => {
+                    text.strip_suffix(BANG_PREFIX)?
+                }
+                _ => return None,
+            };
+
+            if tag.is_empty() {
+                return None;
+            }
+
+            Some(PossibleBang {
+                tag: tag.to_lowercase(),
+                term: term.as_ref(),
+            })
+        })
+        .collect()
+}
+
+/// Bangs from a number of sources. When the same tag is defined by several sources,
+/// the bang from the source with the highest precedence is used.
+#[derive(Default)]
 pub struct Bangs {
     bangs: HashMap<String, Bang>,
 }
 
 impl Bangs {
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
-        let json = fs::read_to_string(path).unwrap();
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let json = fs::read_to_string(path)?;
 
         Self::from_json(json.as_str())
     }
 
-    pub fn from_json(json: &str) -> Self {
-        let all_bangs: Vec<Bang> = serde_json::from_str(json).unwrap();
+    pub fn from_json(json: &str) -> Result<Self> {
+        let all_bangs: Vec<Bang> = serde_json::from_str(json)?;
 
-        Self {
-            bangs: all_bangs
-                .into_iter()
-                .map(|bang| (bang.tag.clone(), bang))
-                .collect(),
-        }
+        Ok(Self {
+            bangs: by_tag(all_bangs),
+        })
     }
 
-    pub fn get(&self, query: &Query) -> Option<BangHit> {
-        for possible_bang in query.terms().iter().filter_map(|term| {
-            if let Term::PossibleBang(possible_bang) = term.as_ref() {
-                Some(possible_bang)
-            } else {
-                None
+    /// Loads the bangs from each path, in order of decreasing precedence. Files that
+    /// cannot be loaded are reported and skipped.
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Self {
+        let mut bangs = Self::default();
+
+        for path in paths {
+            match Self::from_path(path) {
+                Ok(source) => bangs.add_lower_precedence(source),
+                Err(err) => warn!(
+                    "failed to load bangs from {}: {:?}",
+                    path.as_ref().display(),
+                    err
+                ),
             }
-        }) {
-            if let Some(bang) = self.bangs.get(possible_bang) {
+        }
+
+        bangs
+    }
+
+    fn add_lower_precedence(&mut self, other: Bangs) {
+        for (tag, bang) in other.bangs {
+            self.bangs.entry(tag).or_insert(bang);
+        }
+    }
+
+    /// Finds the first bang in the query. The bangs of the user are checked before
+    /// the bangs of `self`.
+    pub fn get(&self, query: &str, user_bangs: &[Bang]) -> Option<BangHit> {
+        let terms = parser::parse(query);
+        let user_bangs = by_tag(user_bangs.iter().cloned());
+
+        for possible_bang in possible_bangs(&terms) {
+            if let Some(bang) = user_bangs
+                .get(&possible_bang.tag)
+                .or_else(|| self.bangs.get(&possible_bang.tag))
+            {
                 return Some(BangHit {
                     bang: bang.clone(),
                     redirect_to: bang
@@ -94,16 +192,9 @@ impl Bangs {
                         .replace(
                             "{{{s}}}",
                             intersperse(
-                                query
-                                    .terms()
+                                terms
                                     .iter()
-                                    .filter(|term| {
-                                        if let Term::PossibleBang(bang) = term.as_ref() {
-                                            bang != possible_bang
-                                        } else {
-                                            true
-                                        }
-                                    })
+                                    .filter(|term| term.as_ref() != possible_bang.term)
                                     .map(|term| term.as_ref().to_string()),
                                 " ".to_string(),
                             )
@@ -117,25 +208,38 @@ impl Bangs {
 
         None
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::sync::Arc;
+    /// Bangs where the tag starts with `query`, or where the site or domain contains
+    /// it. The most popular bangs are returned first.
+    pub fn search(&self, query: &str) -> Vec<Bang> {
+        let query = query.trim().trim_matches(BANG_PREFIX).to_lowercase();
 
-    use tantivy::tokenizer::TokenizerManager;
+        let mut res: Vec<_> = self
+            .bangs
+            .values()
+            .filter(|bang| {
+                bang.tag.starts_with(&query)
+                    || [&bang.site, &bang.domain].into_iter().any(|name| {
+                        name.as_ref()
+                            .map(|name| name.to_lowercase().contains(&query))
+                            .unwrap_or(false)
+                    })
+            })
+            .cloned()
+            .collect();
 
-    use crate::{ranking::SignalAggregator, schema::create_schema};
+        res.sort_by(|a, b| b.ranking.cmp(&a.ranking).then_with(|| a.tag.cmp(&b.tag)));
+        res.truncate(MAX_SEARCH_RESULTS);
 
-    use super::*;
+        res
+    }
+}
 
-    #[test]
-    fn simple_bang() {
-        let schema = Arc::new(create_schema());
-        let tokenizer_manager = TokenizerManager::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let bangs = Bangs::from_json(
-            r#"[{
+    const YOUTUBE: &str = r#"[{
             "c": "Multimedia",
             "d": "www.youtube.com",
             "r": 1646,
@@ -143,36 +247,32 @@ mod tests {
             "sc": "Video",
             "t": "ty",
             "u": "https://www.youtube.com/results?search_query={{{s}}}"
-        }]"#,
-        );
+        }]"#;
 
-        let query = Query::parse(
-            "no bangs",
-            schema.clone(),
-            &tokenizer_manager,
-            &SignalAggregator::default(),
-        )
-        .unwrap();
-        assert_eq!(bangs.get(&query), None);
+    fn bang(tag: &str, url: &str, ranking: Option<usize>) -> Bang {
+        Bang {
+            category: None,
+            sub_category: None,
+            domain: None,
+            ranking,
+            site: None,
+            tag: tag.to_string(),
+            url: url.to_string(),
+        }
+    }
 
-        let query = Query::parse(
-            "!no bangs",
-            schema.clone(),
-            &tokenizer_manager,
-            &SignalAggregator::default(),
-        )
-        .unwrap();
-        assert_eq!(bangs.get(&query), None);
+    fn redirect(bangs: &Bangs, q: &str, user_bangs: &[Bang]) -> Option<String> {
+        bangs.get(q, user_bangs).map(|hit| hit.redirect_to.full())
+    }
 
-        let query = Query::parse(
-            "!ty bangs",
-            schema,
-            &tokenizer_manager,
-            &SignalAggregator::default(),
-        )
-        .unwrap();
+    #[test]
+    fn simple_bang() {
+        let bangs = Bangs::from_json(YOUTUBE).unwrap();
+
+        assert_eq!(bangs.get("no bangs", &[]), None);
+        assert_eq!(bangs.get("!no bangs", &[]), None);
         assert_eq!(
-            bangs.get(&query),
+            bangs.get("!ty bangs", &[]),
             Some(BangHit {
                 bang: Bang {
                     category: Some("Multimedia".to_string()),
@@ -189,4 +289,126 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn suffix_bang() {
+        let bangs = Bangs::from_json(YOUTUBE).unwrap();
+        let expected = Some("https://www.youtube.com/results?search_query=cute cats".to_string());
+
+        assert_eq!(redirect(&bangs, "ty! cute cats", &[]), expected);
+        assert_eq!(redirect(&bangs, "cute cats ty!", &[]), expected);
+        assert_eq!(redirect(&bangs, "cute TY! cats", &[]), None);
+        assert_eq!(redirect(&bangs, "cute !TY cats", &[]), expected);
+        assert_eq!(redirect(&bangs, "cute cats !", &[]), None);
+        assert_eq!(redirect(&bangs, "ty!", &[]), None);
+        assert_eq!(
+            redirect(&bangs, "!ty", &[]),
+            Some("https://www.youtube.com/results?search_query=".to_string())
+        );
+    }
+
+    #[test]
+    fn only_http_redirects() {
+        let bangs = Bangs::from_json(
+            r#"[
+            {"t": "js", "u": "javascript:alert({{{s}}})"},
+            {"t": "rel", "u": "/search?q={{{s}}}"},
+            {"t": "ok", "u": "HTTPS://example.com/?q={{{s}}}"}
+        ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(redirect(&bangs, "!js test", &[]), None);
+        assert_eq!(redirect(&bangs, "!rel test", &[]), None);
+        assert!(redirect(&bangs, "!ok test", &[]).is_some());
+        assert_eq!(
+            redirect(
+                &bangs,
+                "!ok test",
+                &[bang("ok", "data:text/html,{{{s}}}", None)]
+            ),
+            redirect(&bangs, "!ok test", &[])
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        let dir = crate::gen_temp_path();
+        fs::create_dir_all(&dir).unwrap();
+
+        let builtin = dir.join("builtin.json");
+        let custom = dir.join("custom.json");
+        let broken = dir.join("broken.json");
+
+        fs::write(&builtin, YOUTUBE).unwrap();
+        fs::write(
+            &custom,
+            r#"[{"t": "ty", "u": "https://example.com/?q={{{s}}}"}]"#,
+        )
+        .unwrap();
+        fs::write(&broken, "not json").unwrap();
+
+        let bangs = Bangs::from_paths(&[&broken, &custom, &builtin, &dir.join("missing.json")]);
+
+        assert_eq!(
+            redirect(&bangs, "!ty test", &[]),
+            Some("https://example.com/?q=test".to_string())
+        );
+        assert_eq!(
+            redirect(
+                &bangs,
+                "!ty test",
+                &[bang("ty", "https://user.com/?q={{{s}}}", None)]
+            ),
+            Some("https://user.com/?q=test".to_string())
+        );
+
+        let bangs = Bangs::from_paths(&[&builtin, &custom]);
+        assert_eq!(
+            redirect(&bangs, "!ty test", &[]),
+            Some("https://www.youtube.com/results?search_query=test".to_string())
+        );
+    }
+
+    #[test]
+    fn highest_ranking_on_collision() {
+        let bangs = Bangs {
+            bangs: by_tag(vec![
+                bang("w", "https://a.com/?q={{{s}}}", Some(10)),
+                bang("w", "https://b.com/?q={{{s}}}", Some(100)),
+                bang("w", "https://c.com/?q={{{s}}}", None),
+            ]),
+        };
+
+        assert_eq!(
+            redirect(&bangs, "!w test", &[]),
+            Some("https://b.com/?q=test".to_string())
+        );
+    }
+
+    #[test]
+    fn search() {
+        let bangs = Bangs::from_json(
+            r#"[
+            {"t": "w", "s": "Wikipedia", "r": 100, "u": "https://en.wikipedia.org/?q={{{s}}}"},
+            {"t": "wde", "s": "Wikipedia DE", "r": 10, "u": "https://de.wikipedia.org/?q={{{s}}}"},
+            {"t": "yt", "s": "Youtube", "r": 1000, "u": "https://youtube.com/?q={{{s}}}"}
+        ]"#,
+        )
+        .unwrap();
+
+        let tags = |query: &str| -> Vec<String> {
+            bangs
+                .search(query)
+                .into_iter()
+                .map(|bang| bang.tag)
+                .collect()
+        };
+
+        assert_eq!(tags("w"), vec!["w".to_string(), "wde".to_string()]);
+        assert_eq!(tags("!wd"), vec!["wde".to_string()]);
+        assert_eq!(tags("wiki"), vec!["w".to_string(), "wde".to_string()]);
+        assert_eq!(tags("tube"), vec!["yt".to_string()]);
+        assert_eq!(tags("").len(), 3);
+    }
 }
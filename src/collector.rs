@@ -28,12 +28,14 @@ use crate::{
     inverted_index::{DocAddress, WebsitePointer},
     prehashed::{combine_u64s, Prehashed},
     schema::FastField,
+    simhash::NearDuplicateIndex,
 };
 
 // lower scale -> higher penalty
 const SITE_SCALE: f64 = 14.0;
 const TITLE_SCALE: f64 = 6.0;
 const URL_SCALE: f64 = 0.1;
+const NEAR_DUPLICATE_SCALE: f64 = 0.1;
 
 #[derive(Clone)]
 pub struct MaxDocsConsidered {
@@ -46,6 +48,7 @@ pub struct Hashes {
     site: Prehashed,
     title: Prehashed,
     url: Prehashed,
+    simhash: u64,
 }
 
 pub trait Doc: Clone {
@@ -198,6 +201,11 @@ impl SegmentCollector for TopSegmentCollector {
                 site: self.get_hash(&doc, &FastField::SiteHash),
                 title: self.get_hash(&doc, &FastField::TitleHash),
                 url: self.get_hash(&doc, &FastField::UrlHash),
+                simhash: self
+                    .fastfield_segment_cache
+                    .get_doc_cache(&FastField::SimHash)
+                    .get_u64(&doc)
+                    .unwrap_or(0),
             },
             id: doc,
             segment: self.segment_ord,
@@ -247,6 +255,7 @@ impl<T: Doc> From<T> for ScoredDoc<T> {
 #[derive(Default)]
 struct BucketCount {
     buckets: HashMap<Prehashed, usize>,
+    near_duplicates: NearDuplicateIndex<()>,
 }
 
 impl BucketCount {
@@ -265,6 +274,9 @@ impl BucketCount {
         let taken_titles = self.buckets.get(&hashes.title).unwrap_or(&0);
         adjuster *= TITLE_SCALE / (TITLE_SCALE + (*taken_titles as f64));
 
+        let taken_near_duplicates = self.near_duplicates.near_duplicates(hashes.simhash).len();
+        adjuster *= NEAR_DUPLICATE_SCALE / (NEAR_DUPLICATE_SCALE + (taken_near_duplicates as f64));
+
         doc.adjusted_score = *doc.doc.score() * adjuster;
     }
 
@@ -274,6 +286,8 @@ impl BucketCount {
         *self.buckets.entry(hashes.site).or_default() += 1;
         *self.buckets.entry(hashes.url).or_default() += 1;
         *self.buckets.entry(hashes.title).or_default() += 1;
+
+        self.near_duplicates.insert(hashes.simhash, ());
     }
 }
 
@@ -476,6 +490,7 @@ mod tests {
                         site: 1.into(),
                         title: 1.into(),
                         url: 1.into(),
+                        simhash: 0,
                     },
                     123,
                     1.0,
@@ -485,6 +500,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     124,
                     2.0,
@@ -494,6 +510,7 @@ mod tests {
                         site: 3.into(),
                         title: 3.into(),
                         url: 3.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -503,6 +520,7 @@ mod tests {
                         site: 4.into(),
                         title: 4.into(),
                         url: 4.into(),
+                        simhash: 0,
                     },
                     126,
                     4.0,
@@ -512,6 +530,7 @@ mod tests {
                         site: 5.into(),
                         title: 5.into(),
                         url: 5.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -531,6 +550,7 @@ mod tests {
                         site: 3.into(),
                         title: 3.into(),
                         url: 3.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -540,6 +560,7 @@ mod tests {
                         site: 4.into(),
                         title: 4.into(),
                         url: 4.into(),
+                        simhash: 0,
                     },
                     126,
                     4.0,
@@ -549,6 +570,7 @@ mod tests {
                         site: 5.into(),
                         title: 5.into(),
                         url: 5.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -568,6 +590,7 @@ mod tests {
                         site: 1.into(),
                         title: 1.into(),
                         url: 1.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -577,6 +600,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     126,
                     3.1,
@@ -586,6 +610,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -602,6 +627,7 @@ mod tests {
                         site: 1.into(),
                         title: 1.into(),
                         url: 1.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -611,6 +637,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     126,
                     3.1,
@@ -620,6 +647,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -628,4 +656,44 @@ mod tests {
             &[(5.0, 127), (3.0, 125)],
         );
     }
+
+    #[test]
+    fn near_duplicates_de_prioritised() {
+        test(
+            3,
+            &[
+                (
+                    Hashes {
+                        site: 1.into(),
+                        title: 1.into(),
+                        url: 1.into(),
+                        simhash: 0b1111,
+                    },
+                    125,
+                    5.0,
+                ),
+                (
+                    Hashes {
+                        site: 2.into(),
+                        title: 2.into(),
+                        url: 2.into(),
+                        simhash: 0b0111,
+                    },
+                    126,
+                    4.0,
+                ),
+                (
+                    Hashes {
+                        site: 3.into(),
+                        title: 3.into(),
+                        url: 3.into(),
+                        simhash: u64::MAX,
+                    },
+                    127,
+                    3.0,
+                ),
+            ],
+            &[(5.0, 125), (3.0, 127), (4.0, 126)],
+        );
+    }
 }
@@ -0,0 +1,468 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A polite crawler that fetches pages from a set of seed hosts and writes them
+//! as warc files that the indexer can consume. Each host is crawled sequentially,
+//! respecting its robots.txt and crawl-delay, while several hosts are crawled concurrently.
+//! Only links pointing to the host of the page are followed, where `www.` and the bare
+//! host are considered the same so sites redirecting between them can still be crawled.
+
+mod robots;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use futures::StreamExt;
+use tokio::time::sleep;
+use tracing::{debug, info};
+
+use crate::{
+    prehashed::{hash, Prehashed},
+    sources,
+    warc::{WarcRecord, WarcWriter},
+    webpage::{Html, Url},
+    Result,
+};
+
+pub use self::robots::Robots;
+
+const ROBOTS_USER_AGENT: &str = "CuelyBot";
+const TIMEOUT: Duration = Duration::from_secs(30);
+// RFC 9309 section 2.3.1.2 requires following at least five redirects for robots.txt
+const MAX_ROBOTS_REDIRECTS: usize = 5;
+
+pub struct CrawlerSettings {
+    pub seeds: Vec<String>,
+    pub output_path: PathBuf,
+    pub max_pages: usize,
+    pub max_pages_per_host: usize,
+    pub min_crawl_delay: Duration,
+    pub max_crawl_delay: Duration,
+    pub pages_per_warc: usize,
+    pub max_concurrent_hosts: usize,
+}
+
+struct WarcOutput {
+    folder: PathBuf,
+    pages_per_warc: usize,
+    writer: Option<WarcWriter<File>>,
+    pages_in_current: usize,
+    names: Vec<String>,
+}
+
+impl WarcOutput {
+    fn new(folder: PathBuf, pages_per_warc: usize) -> Self {
+        Self {
+            folder,
+            pages_per_warc: pages_per_warc.max(1),
+            writer: None,
+            pages_in_current: 0,
+            names: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, record: &WarcRecord) -> Result<()> {
+        if self.writer.is_none() {
+            let name = format!("crawl-{:05}.warc.gz", self.names.len());
+            let file = File::create(self.folder.join(&name))?;
+
+            self.writer = Some(WarcWriter::new(file)?);
+            self.names.push(name);
+        }
+
+        self.writer.as_mut().unwrap().write(record)?;
+        self.pages_in_current += 1;
+
+        if self.pages_in_current >= self.pages_per_warc {
+            self.finish_current()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish_current(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+
+        self.pages_in_current = 0;
+
+        Ok(())
+    }
+}
+
+struct HostCrawl {
+    frontier: VecDeque<Url>,
+}
+
+pub struct Crawler {
+    settings: CrawlerSettings,
+    client: reqwest::Client,
+    robots_client: reqwest::Client,
+    seen: Mutex<HashSet<Prehashed>>,
+    output: Mutex<WarcOutput>,
+    num_pages: AtomicUsize,
+}
+
+fn origin(url: &Url) -> String {
+    format!("{}://{}", url.protocol(), url.site())
+}
+
+/// The site without a `www.` prefix, so `www.example.com` and `example.com` are
+/// considered the same site regardless of the protocol.
+fn site_key(url: &Url) -> &str {
+    let site = url.site();
+    site.strip_prefix("www.").unwrap_or(site)
+}
+
+fn same_site(a: &Url, b: &Url) -> bool {
+    site_key(a) == site_key(b)
+}
+
+fn path_and_query(url: &Url) -> &str {
+    let url = url.strip_protocol();
+
+    match url.find('/') {
+        Some(start) => &url[start..],
+        None => "/",
+    }
+}
+
+impl Crawler {
+    pub fn new(settings: CrawlerSettings) -> Result<Self> {
+        std::fs::create_dir_all(&settings.output_path)?;
+
+        let output = WarcOutput::new(settings.output_path.clone(), settings.pages_per_warc);
+
+        Ok(Self {
+            settings,
+            client: sources::http_client(TIMEOUT)?,
+            robots_client: reqwest::Client::builder()
+                .user_agent(sources::USER_AGENT)
+                .redirect(reqwest::redirect::Policy::limited(MAX_ROBOTS_REDIRECTS))
+                .timeout(TIMEOUT)
+                .build()?,
+            seen: Mutex::new(HashSet::new()),
+            output: Mutex::new(output),
+            num_pages: AtomicUsize::new(0),
+        })
+    }
+
+    /// Groups the seeds by site. All pages of a site are fetched one at a time, so
+    /// seeds for both http and https or both `www.` and the bare host don't double
+    /// the load on the site.
+    fn host_crawls(&self) -> HashMap<String, HostCrawl> {
+        let mut hosts: HashMap<String, HostCrawl> = HashMap::new();
+
+        for seed in &self.settings.seeds {
+            let url = Url::from(seed.clone()).normalized();
+
+            if !self.mark_seen(&url) {
+                continue;
+            }
+
+            hosts
+                .entry(site_key(&url).to_string())
+                .or_insert_with(|| HostCrawl {
+                    frontier: VecDeque::new(),
+                })
+                .frontier
+                .push_back(url);
+        }
+
+        hosts
+    }
+
+    /// Crawl all seeds and return the names of the written warc files.
+    pub async fn run(self) -> Result<Vec<String>> {
+        let hosts = self.host_crawls();
+
+        info!("crawling {} hosts", hosts.len());
+
+        let results: Vec<_> = futures::stream::iter(hosts.into_values())
+            .map(|host| self.crawl_host(host))
+            .buffer_unordered(self.settings.max_concurrent_hosts.max(1))
+            .collect()
+            .await;
+
+        for res in results {
+            if let Err(err) = res {
+                debug!("host crawl failed: {:?}", err);
+            }
+        }
+
+        let mut output = self.output.lock().unwrap();
+        output.finish_current()?;
+
+        info!(
+            "crawled {} pages into {} warc files",
+            self.num_pages.load(Ordering::SeqCst),
+            output.names.len()
+        );
+
+        Ok(output.names.clone())
+    }
+
+    /// Returns true if the url has not been seen before.
+    /// The hash is the same as the one stored in the `UrlHash` field of the index.
+    fn mark_seen(&self, url: &Url) -> bool {
+        self.seen.lock().unwrap().insert(hash(url.full()))
+    }
+
+    fn is_done(&self) -> bool {
+        self.num_pages.load(Ordering::SeqCst) >= self.settings.max_pages
+    }
+
+    async fn robots(&self, origin: &str) -> Robots {
+        let url = format!("{origin}/robots.txt");
+
+        match self.robots_client.get(&url).send().await {
+            Ok(res) if res.status().is_success() => match res.text().await {
+                Ok(robots_txt) => Robots::parse(&robots_txt, ROBOTS_USER_AGENT),
+                Err(_) => Robots::disallow_all(),
+            },
+            // a missing robots.txt means that everything is allowed
+            Ok(res) if res.status().is_client_error() => Robots::allow_all(),
+            // too many redirects are treated as an unavailable robots.txt
+            Err(err) if err.is_redirect() => Robots::allow_all(),
+            // the host is unavailable, so we assume that nothing is allowed
+            _ => Robots::disallow_all(),
+        }
+    }
+
+    fn delay(&self, robots: &Robots) -> Duration {
+        robots
+            .crawl_delay()
+            .unwrap_or_default()
+            .max(self.settings.min_crawl_delay)
+            .min(self.settings.max_crawl_delay)
+    }
+
+    /// Links to unseen pages on the same site as the page.
+    fn new_links(&self, record: &WarcRecord, page: &Url) -> Vec<Url> {
+        let mut links: Vec<Url> = Vec::new();
+
+        if let Some(location) = record.response.redirect_location() {
+            links.extend(page.resolve(location));
+        } else if record.response.is_success()
+            && matches!(
+                record.response.http.content_type.as_deref(),
+                None | Some("text/html") | Some("application/xhtml+xml")
+            )
+        {
+            let html = Html::parse_without_text(&record.response.body(), &record.request.url);
+            links.extend(html.links().into_iter().map(|link| link.destination));
+        }
+
+        links
+            .into_iter()
+            .filter(|link| same_site(link, page))
+            .filter(|link| self.mark_seen(link))
+            .collect()
+    }
+
+    async fn crawl_host(&self, mut host: HostCrawl) -> Result<()> {
+        // the frontier can span both the `www.` and bare origin (and http and https),
+        // which each have their own robots.txt
+        let mut robots: HashMap<String, Robots> = HashMap::new();
+
+        let mut num_fetched = 0;
+
+        while let Some(url) = host.frontier.pop_front() {
+            if self.is_done() || num_fetched >= self.settings.max_pages_per_host {
+                break;
+            }
+
+            let origin = origin(&url);
+            if !robots.contains_key(&origin) {
+                let origin_robots = self.robots(&origin).await;
+                robots.insert(origin.clone(), origin_robots);
+            }
+            let robots = &robots[&origin];
+
+            if !robots.is_allowed(path_and_query(&url)) {
+                debug!("{} disallowed by robots.txt", url);
+                continue;
+            }
+
+            if num_fetched > 0 {
+                sleep(self.delay(robots)).await;
+            }
+
+            num_fetched += 1;
+
+            let record = match sources::fetch(&self.client, url.full()).await {
+                Ok(record) => record,
+                Err(err) => {
+                    debug!("failed to fetch {}: {:?}", url, err);
+                    continue;
+                }
+            };
+
+            host.frontier.extend(self.new_links(&record, &url));
+
+            self.output.lock().unwrap().write(&record)?;
+            self.num_pages.fetch_add(1, Ordering::SeqCst);
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the names of the warc files, so they can be used as a `Local` warc source.
+pub fn write_warc_paths<P: AsRef<Path>>(folder: P, names: &[String]) -> Result<()> {
+    std::fs::write(folder.as_ref().join("warc.paths"), names.join("\n"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        response::{Html, Redirect},
+        routing::get,
+        Router,
+    };
+
+    use crate::{gen_temp_path, sources::serve_locally, warc::WarcFile};
+
+    use super::*;
+
+    #[test]
+    fn path_and_query_of_url() {
+        let url: Url = "https://example.com/a/b?c=d".to_string().into();
+        assert_eq!(path_and_query(&url), "/a/b?c=d");
+
+        let url: Url = "https://example.com".to_string().into();
+        assert_eq!(path_and_query(&url), "/");
+    }
+
+    #[test]
+    fn www_is_same_site() {
+        let apex: Url = "http://example.com/".to_string().into();
+        let www: Url = "https://www.example.com/a".to_string().into();
+        let other: Url = "https://blog.example.com/".to_string().into();
+
+        assert!(same_site(&apex, &www));
+        assert!(same_site(&www, &apex));
+        assert!(!same_site(&apex, &other));
+    }
+
+    #[test]
+    fn seeds_grouped_by_site() {
+        let crawler = Crawler::new(CrawlerSettings {
+            seeds: vec![
+                "http://example.com".to_string(),
+                "https://www.example.com/a".to_string(),
+                "https://example.com/b".to_string(),
+                "https://blog.example.com".to_string(),
+            ],
+            output_path: gen_temp_path(),
+            max_pages: 100,
+            max_pages_per_host: 100,
+            min_crawl_delay: Duration::from_millis(1),
+            max_crawl_delay: Duration::from_millis(10),
+            pages_per_warc: 2,
+            max_concurrent_hosts: 2,
+        })
+        .unwrap();
+
+        let hosts = crawler.host_crawls();
+
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts["example.com"].frontier.len(), 3);
+        assert_eq!(hosts["blog.example.com"].frontier.len(), 1);
+    }
+
+    #[test]
+    fn crawl_local_site() {
+        let addr = serve_locally(|_| {
+            Router::new()
+                .route(
+                    "/robots.txt",
+                    get(|| async { Redirect::permanent("/robots/moved.txt") }),
+                )
+                .route(
+                    "/robots/moved.txt",
+                    get(|| async { "User-agent: *\nDisallow: /private\nCrawl-delay: 0" }),
+                )
+                .route(
+                    "/",
+                    get(|| async {
+                        Html(
+                            r#"
+                            <a href="/a">a</a>
+                            <a href="a#fragment">a again</a>
+                            <a href="/private/secret">secret</a>
+                            <a href="/moved">moved</a>
+                            <a href="https://other.example.com/">other</a>
+                            "#,
+                        )
+                    }),
+                )
+                .route("/a", get(|| async { Html(r#"<a href="/">home</a>"#) }))
+                .route("/moved", get(|| async { Redirect::permanent("/b") }))
+                .route("/b", get(|| async { Html("b") }))
+                .route("/private/secret", get(|| async { Html("secret") }))
+        });
+
+        let output_path = gen_temp_path();
+
+        let crawler = Crawler::new(CrawlerSettings {
+            seeds: vec![format!("http://{addr}/")],
+            output_path: output_path.clone(),
+            max_pages: 100,
+            max_pages_per_host: 100,
+            min_crawl_delay: Duration::from_millis(1),
+            max_crawl_delay: Duration::from_millis(10),
+            pages_per_warc: 2,
+            max_concurrent_hosts: 1,
+        })
+        .unwrap();
+
+        let names = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(crawler.run())
+            .unwrap();
+
+        assert_eq!(names.len(), 2);
+
+        let mut urls = Vec::new();
+        for name in names {
+            let file = WarcFile::open(output_path.join(name)).unwrap();
+            urls.extend(file.records().map(|record| record.unwrap().request.url));
+        }
+
+        assert_eq!(
+            urls,
+            vec![
+                format!("http://{addr}"),
+                format!("http://{addr}/a"),
+                format!("http://{addr}/moved"),
+                format!("http://{addr}/b"),
+            ]
+        );
+
+        std::fs::remove_dir_all(output_path).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A small robots.txt parser following RFC 9309. Rules from the groups matching our
+//! user agent are used if any exist, otherwise the rules from the `*` groups.
+//! The longest matching pattern decides, and `allow` wins ties.
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".to_string(),
+            }],
+            crawl_delay: None,
+        }
+    }
+
+    /// `user_agent` is the product token of the crawler, e.g. `CuelyBot`.
+    pub fn parse(robots_txt: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_ascii_lowercase();
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut current = Group::default();
+        let mut last_was_user_agent = false;
+
+        for line in robots_txt.lines() {
+            let line = match line.split_once('#') {
+                Some((line, _comment)) => line,
+                None => line,
+            };
+
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            match key.as_str() {
+                "user-agent" => {
+                    if !last_was_user_agent && !current.user_agents.is_empty() {
+                        groups.push(std::mem::take(&mut current));
+                    }
+
+                    current.user_agents.push(value.to_ascii_lowercase());
+                    last_was_user_agent = true;
+                }
+                "allow" | "disallow" => {
+                    last_was_user_agent = false;
+
+                    // an empty disallow means everything is allowed
+                    if !value.is_empty() {
+                        current.rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    last_was_user_agent = false;
+
+                    current.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|delay| delay.is_finite() && *delay >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+
+        if !current.user_agents.is_empty() {
+            groups.push(current);
+        }
+
+        // the product token is matched case-insensitively and must be equal,
+        // so e.g. a group for `bot` does not apply to `CuelyBot`.
+        let specific: Vec<_> = groups
+            .iter()
+            .filter(|group| {
+                group
+                    .user_agents
+                    .iter()
+                    .any(|agent| !agent.is_empty() && *agent == user_agent)
+            })
+            .collect();
+
+        let matching = if specific.is_empty() {
+            groups
+                .iter()
+                .filter(|group| group.user_agents.iter().any(|agent| agent == "*"))
+                .collect()
+        } else {
+            specific
+        };
+
+        Self {
+            rules: matching
+                .iter()
+                .flat_map(|group| group.rules.iter().cloned())
+                .collect(),
+            crawl_delay: matching.iter().find_map(|group| group.crawl_delay),
+        }
+    }
+
+    /// `path` is the path and query of the url, e.g. `/search?q=test`.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .map_or(true, |rule| rule.allow)
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+}
+
+/// `*` matches any sequence of characters and a trailing `$` anchors the pattern at the end.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<_> = parts.collect();
+
+    if parts.is_empty() {
+        return !anchored || rest.is_empty();
+    }
+
+    for (idx, part) in parts.iter().enumerate() {
+        if anchored && idx == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+
+        match rest.find(part) {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(pattern_matches("/", "/anything"));
+        assert!(pattern_matches("/fish", "/fish.html"));
+        assert!(!pattern_matches("/fish", "/Fish.asp"));
+        assert!(pattern_matches("/fish*.php", "/fish/salmon.php"));
+        assert!(!pattern_matches("/fish*.php", "/fish/salmon.html"));
+        assert!(pattern_matches("/*.php$", "/filename.php"));
+        assert!(!pattern_matches("/*.php$", "/filename.php?parameters"));
+        assert!(pattern_matches("/fish$", "/fish"));
+        assert!(!pattern_matches("/fish$", "/fishheads"));
+    }
+
+    #[test]
+    fn groups() {
+        let robots_txt = r#"
+            # comment
+            User-agent: *
+            Disallow: /private
+            Crawl-delay: 5
+
+            User-agent: googlebot
+            User-agent: CuelyBot
+            Disallow: /
+            Allow: /public
+            Crawl-delay: 0.5
+        "#;
+
+        let cuely = Robots::parse(robots_txt, "CuelyBot");
+        assert!(!cuely.is_allowed("/"));
+        assert!(cuely.is_allowed("/public/page"));
+        assert!(cuely.is_allowed("/robots.txt"));
+        assert_eq!(cuely.crawl_delay(), Some(Duration::from_millis(500)));
+
+        let other = Robots::parse(robots_txt, "OtherBot");
+        assert!(other.is_allowed("/"));
+        assert!(!other.is_allowed("/private/page"));
+        assert_eq!(other.crawl_delay(), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn empty_user_agent_ignored() {
+        let robots_txt = "User-agent:\nDisallow: /\n\nUser-agent: *\nDisallow: /private";
+
+        let robots = Robots::parse(robots_txt, "CuelyBot");
+        assert!(robots.is_allowed("/"));
+        assert!(!robots.is_allowed("/private"));
+    }
+
+    #[test]
+    fn user_agent_must_equal_product_token() {
+        let robots_txt = "User-agent: bot\nDisallow: /\n\nUser-agent: *\nDisallow: /private";
+
+        let robots = Robots::parse(robots_txt, "CuelyBot");
+        assert!(robots.is_allowed("/"));
+        assert!(!robots.is_allowed("/private"));
+
+        let robots = Robots::parse("User-agent: cuelybot\nDisallow: /", "CuelyBot");
+        assert!(!robots.is_allowed("/"));
+    }
+
+    #[test]
+    fn longest_match_wins() {
+        let robots = Robots::parse(
+            "User-agent: *\nAllow: /page\nDisallow: /*.htm\nDisallow: /\nAllow: /$",
+            "CuelyBot",
+        );
+
+        assert!(robots.is_allowed("/"));
+        assert!(!robots.is_allowed("/other"));
+        assert!(robots.is_allowed("/page"));
+        assert!(!robots.is_allowed("/page.htm"));
+    }
+
+    #[test]
+    fn empty_disallow() {
+        let robots = Robots::parse("User-agent: *\nDisallow:", "CuelyBot");
+        assert!(robots.is_allowed("/anything"));
+
+        assert!(Robots::allow_all().is_allowed("/anything"));
+        assert!(!Robots::disallow_all().is_allowed("/anything"));
+    }
+}
@@ -15,7 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
     path::Path,
     sync::Arc,
@@ -25,8 +25,12 @@ use std::{
 use serde::{Deserialize, Serialize};
 use tantivy::{
     collector::TopDocs,
+    fastfield::{BytesFastFieldReader, Column, DynamicFastFieldReader},
     query::{BooleanQuery, MoreLikeThisQuery, Occur, QueryClone, TermQuery},
-    schema::{BytesOptions, IndexRecordOption, Schema, TextFieldIndexing, TextOptions},
+    schema::{
+        BytesOptions, Cardinality, IndexRecordOption, NumericOptions, Schema, TextFieldIndexing,
+        TextOptions,
+    },
     tokenizer::Tokenizer,
     DocAddress, IndexReader, IndexWriter, Searcher, Term,
 };
@@ -38,12 +42,33 @@ use crate::{
     kv::{rocksdb_store::RocksDbStore, Kv},
     tokenizer::Normal,
     webpage::Url,
-    Result,
+    Error, Result,
 };
 
 use self::entity::{Entity, Link, Span};
 pub(crate) mod entity;
 
+/// Number of entities matching the title that are scored against each other. Entities
+/// whose title without qualifier is exactly the query are fetched separately, so they
+/// are always scored no matter how many other titles share some of its words.
+const NUM_CANDIDATES: usize = 50;
+
+/// Maximum number of candidates returned by [`EntityIndex::search_candidates`].
+const MAX_RETURNED_CANDIDATES: usize = 5;
+
+/// The best candidate is only shown when its confidence is above this. Two equally
+/// popular entities with the same name are both below it.
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// Weight of the possibility that the query refers to none of the candidates, as a
+/// quarter of an exact match without inbound links. Without it a lone candidate would
+/// have the confidence of its title match, however obscure the entity is.
+const NO_ENTITY_WEIGHT: f64 = 0.25;
+
+/// Number of distinct link targets counted in memory before the counts are added to
+/// the inbound link store.
+const MAX_PENDING_INBOUND_LINKS: usize = 100_000;
+
 pub struct EntityIndex {
     image_store: EntityImageStore,
     image_downloader: ImageDownloader<String>,
@@ -52,6 +77,34 @@ pub struct EntityIndex {
     schema: Arc<Schema>,
     stopwords: HashSet<String>,
     attribute_occurrences: Box<dyn Kv<String, u32>>,
+    inbound_links: Box<dyn Kv<String, u32>>,
+    pending_inbound_links: HashMap<String, u32>,
+}
+
+/// An entity that might be what the query refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityCandidate {
+    pub title: String,
+    /// How sure we are that the query refers to this entity, between 0 and 1.
+    pub confidence: f64,
+    doc: DocAddress,
+}
+
+/// Links use the title of the page they point to, but with any capitalisation of the
+/// first letter, underscores for spaces and possibly a section.
+fn normalize_title(title: &str) -> String {
+    let title = title.split('#').next().unwrap_or_default();
+
+    title.replace('_', " ").trim().to_lowercase()
+}
+
+/// The title without the parenthesised qualifier Wikipedia uses to tell entities with
+/// the same name apart, like "Mercury (planet)".
+fn base_title(title: &str) -> &str {
+    match title.find(" (") {
+        Some(idx) if title.ends_with(')') => &title[..idx],
+        _ => title,
+    }
 }
 
 fn schema() -> Schema {
@@ -79,6 +132,19 @@ fn schema() -> Schema {
     );
     builder.add_bytes_field("info", BytesOptions::default().set_stored());
     builder.add_bytes_field("links", BytesOptions::default().set_stored());
+    builder.add_text_field(
+        "base_title",
+        TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("raw")
+                .set_index_option(IndexRecordOption::Basic),
+        ),
+    );
+    builder.add_bytes_field("candidate_title", BytesOptions::default().set_fast());
+    builder.add_u64_field(
+        "inbound_links",
+        NumericOptions::default().set_fast(Cardinality::SingleValue),
+    );
     builder.add_text_field(
         "has_image",
         TextOptions::default()
@@ -95,6 +161,10 @@ fn schema() -> Schema {
 fn entity_to_tantivy(entity: Entity, schema: &tantivy::schema::Schema) -> tantivy::Document {
     let mut doc = tantivy::Document::new();
 
+    doc.add_bytes(
+        schema.get_field("candidate_title").unwrap(),
+        entity.title.as_bytes().to_vec(),
+    );
     doc.add_text(schema.get_field("title").unwrap(), entity.title);
     doc.add_text(
         schema.get_field("abstract").unwrap(),
@@ -164,13 +234,26 @@ impl EntityIndex {
         let schema = schema();
         let tv_path = path.as_ref().join("inverted_index");
         let tantivy_index = if tv_path.exists() {
-            tantivy::Index::open_in_dir(&tv_path)?
+            let index = tantivy::Index::open_in_dir(&tv_path)?;
+
+            // Indexes built before titles were keyed and inbound links were counted
+            // can't rank candidates, so they have to be rebuilt.
+            if index.schema().get_field("base_title").is_none()
+                || index.schema().get_field("inbound_links").is_none()
+            {
+                return Err(Error::OutdatedEntityIndex(
+                    path.as_ref().display().to_string(),
+                ));
+            }
+
+            index
         } else {
             fs::create_dir_all(&tv_path)?;
             tantivy::Index::create_in_dir(&tv_path, schema.clone())?
         };
 
         let attribute_occurrences = RocksDbStore::open(path.as_ref().join("attribute_occurrences"));
+        let inbound_links = RocksDbStore::open(path.as_ref().join("inbound_links"));
 
         let stopwords: HashSet<String> = include_str!("../../stopwords/English.txt")
             .lines()
@@ -196,10 +279,51 @@ impl EntityIndex {
             schema: Arc::new(schema),
             stopwords,
             attribute_occurrences,
+            inbound_links,
+            pending_inbound_links: HashMap::new(),
         })
     }
 
+    /// Count the links of the entity. Candidates are ranked by their number of inbound
+    /// links, which is stored with each entity, so all entities must have their links
+    /// counted before the first one is inserted.
+    pub fn count_inbound_links(&mut self, entity: &Entity) {
+        let targets: HashSet<_> = entity
+            .page_abstract
+            .links
+            .iter()
+            .chain(
+                entity
+                    .paragraphs
+                    .iter()
+                    .flat_map(|paragraph| paragraph.content.links.iter()),
+            )
+            .map(|link| normalize_title(&link.target))
+            .filter(|target| !target.is_empty())
+            .collect();
+
+        for target in targets {
+            *self.pending_inbound_links.entry(target).or_default() += 1;
+        }
+
+        if self.pending_inbound_links.len() >= MAX_PENDING_INBOUND_LINKS {
+            self.flush_inbound_links();
+        }
+    }
+
+    fn flush_inbound_links(&mut self) {
+        for (title, count) in self.pending_inbound_links.drain() {
+            let current = self.inbound_links.get(&title).unwrap_or(0);
+            self.inbound_links.insert(title, current + count);
+        }
+        self.inbound_links.flush();
+    }
+
     pub fn insert(&mut self, entity: Entity) {
+        if !self.pending_inbound_links.is_empty() {
+            self.flush_inbound_links();
+        }
+
         for attribute in entity.info.keys() {
             let current = self.attribute_occurrences.get(attribute).unwrap_or(0);
             self.attribute_occurrences
@@ -218,7 +342,17 @@ impl EntityIndex {
                 timeout: Some(Duration::from_secs(10)),
             });
         }
-        let doc = entity_to_tantivy(entity, &self.schema);
+        let key = self.title_key(base_title(&entity.title));
+        let inbound_links = self
+            .inbound_links
+            .get(&normalize_title(&entity.title))
+            .unwrap_or(0);
+        let mut doc = entity_to_tantivy(entity, &self.schema);
+        doc.add_text(self.schema.get_field("base_title").unwrap(), key);
+        doc.add_u64(
+            self.schema.get_field("inbound_links").unwrap(),
+            inbound_links as u64,
+        );
         self.writer.add_document(doc).unwrap();
     }
 
@@ -226,6 +360,8 @@ impl EntityIndex {
         self.writer.commit().unwrap();
         self.reader.reload().unwrap();
         self.attribute_occurrences.flush();
+        self.flush_inbound_links();
+
         info!("downloading images");
         self.image_downloader.download(&mut self.image_store);
     }
@@ -263,47 +399,197 @@ impl EntityIndex {
         }
     }
 
-    pub fn search(&self, query: &str) -> Option<StoredEntity> {
-        let searcher = self.reader.searcher();
+    fn tokens(&self, text: &str) -> HashSet<String> {
+        let mut tokens = HashSet::new();
+        let mut stream = Normal::default().token_stream(text);
 
-        let title = self.schema.get_field("title").unwrap();
-        let entity_abstract = self.schema.get_field("abstract").unwrap();
+        while let Some(token) = stream.next() {
+            if !self.stopwords.contains(&token.text) {
+                tokens.insert(token.text.clone());
+            }
+        }
+
+        tokens
+    }
+
+    /// The tokens of a title in order, so titles can be matched exactly regardless of
+    /// casing, punctuation and stopwords.
+    fn title_key(&self, text: &str) -> String {
+        let mut tokens = Vec::new();
+        let mut stream = Normal::default().token_stream(text);
 
-        let mut term_queries = Vec::new();
-        let mut stream = Normal::default().token_stream(query);
         while let Some(token) = stream.next() {
-            if self.stopwords.contains(&token.text) {
-                continue;
+            if token.text.chars().any(char::is_alphanumeric)
+                && !self.stopwords.contains(&token.text)
+            {
+                tokens.push(token.text.clone());
             }
+        }
 
-            term_queries.push((
-                Occur::Must,
-                TermQuery::new(
-                    Term::from_field_text(title, &token.text),
-                    IndexRecordOption::WithFreqsAndPositions,
-                )
-                .box_clone(),
-            ));
-
-            term_queries.push((
-                Occur::Should,
-                TermQuery::new(
-                    Term::from_field_text(entity_abstract, &token.text),
-                    IndexRecordOption::WithFreqsAndPositions,
-                )
-                .box_clone(),
-            ));
+        tokens.join(" ")
+    }
+
+    /// Entities whose title matches the query, most likely first.
+    ///
+    /// How well a title matches is the fraction of the query found in the title,
+    /// scaled by the square root of the fraction of the title found in the query.
+    /// Among the candidates, more popular entities are more likely, so the confidence
+    /// of a candidate is its match times its share of the match-weighted inbound
+    /// links of all candidates and the possibility of no entity at all.
+    ///
+    /// Candidates are scored from their fast fields only, so the stored entities are
+    /// not read until one of them is shown.
+    pub fn search_candidates(&self, query: &str) -> Vec<EntityCandidate> {
+        let searcher = self.reader.searcher();
+        let title_field = self.schema.get_field("title").unwrap();
+        let base_title_field = self.schema.get_field("base_title").unwrap();
+
+        let query_tokens = self.tokens(query);
+        if query_tokens.is_empty() {
+            return Vec::new();
         }
 
-        let query = BooleanQuery::from(term_queries);
+        let term_queries: Vec<_> = query_tokens
+            .iter()
+            .map(|token| {
+                (
+                    Occur::Should,
+                    TermQuery::new(
+                        Term::from_field_text(title_field, token),
+                        IndexRecordOption::WithFreqsAndPositions,
+                    )
+                    .box_clone(),
+                )
+            })
+            .collect();
 
-        searcher
-            .search(&query, &TopDocs::with_limit(1))
-            .unwrap()
-            .first()
-            .map(|(_score, doc_address)| {
-                self.retrieve_stored_entity(&searcher, *doc_address, true, true, true)
+        let exact_query = TermQuery::new(
+            Term::from_field_text(base_title_field, &self.title_key(query)),
+            IndexRecordOption::Basic,
+        );
+
+        let exact_docs = searcher
+            .search(&exact_query, &TopDocs::with_limit(NUM_CANDIDATES))
+            .unwrap_or_default();
+        let docs = match searcher.search(
+            &BooleanQuery::from(term_queries),
+            &TopDocs::with_limit(NUM_CANDIDATES),
+        ) {
+            Ok(docs) => docs,
+            Err(_) => return Vec::new(),
+        };
+
+        let candidate_title_field = self.schema.get_field("candidate_title").unwrap();
+        let inbound_links_field = self.schema.get_field("inbound_links").unwrap();
+        let mut fast_fields: HashMap<u32, (BytesFastFieldReader, DynamicFastFieldReader<u64>)> =
+            HashMap::new();
+
+        let mut seen = HashSet::new();
+        let mut candidates = Vec::new();
+        let mut total_weight = NO_ENTITY_WEIGHT;
+
+        for (_, doc) in exact_docs.into_iter().chain(docs) {
+            if !seen.insert(doc) {
+                continue;
+            }
+
+            if !fast_fields.contains_key(&doc.segment_ord) {
+                let segment_fast_fields = searcher.segment_reader(doc.segment_ord).fast_fields();
+
+                match (
+                    segment_fast_fields.bytes(candidate_title_field),
+                    segment_fast_fields.u64(inbound_links_field),
+                ) {
+                    (Ok(titles), Ok(inbound_links)) => {
+                        fast_fields.insert(doc.segment_ord, (titles, inbound_links));
+                    }
+                    _ => continue,
+                }
+            }
+            let (titles, inbound_links) = &fast_fields[&doc.segment_ord];
+
+            let title = match std::str::from_utf8(titles.get_bytes(doc.doc_id)) {
+                Ok(title) => title.to_string(),
+                Err(_) => continue,
+            };
+
+            let title_tokens = self.tokens(&title);
+            let base_tokens = self.tokens(base_title(&title));
+
+            let query_coverage =
+                query_tokens.intersection(&title_tokens).count() as f64 / query_tokens.len() as f64;
+            let title_coverage = if base_tokens.is_empty() {
+                0.0
+            } else {
+                base_tokens.intersection(&query_tokens).count() as f64 / base_tokens.len() as f64
+            };
+
+            let title_match = query_coverage * title_coverage.sqrt();
+            if title_match == 0.0 {
+                continue;
+            }
+
+            let weight = title_match * (1 + inbound_links.get_val(doc.doc_id as u64)) as f64;
+            total_weight += weight;
+
+            candidates.push((title, doc, title_match, weight));
+        }
+
+        let mut candidates: Vec<_> = candidates
+            .into_iter()
+            .map(|(title, doc, title_match, weight)| EntityCandidate {
+                title,
+                confidence: title_match * weight / total_weight,
+                doc,
             })
+            .collect();
+
+        candidates.sort_by(|a, b| {
+            b.confidence
+                .partial_cmp(&a.confidence)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        candidates.truncate(MAX_RETURNED_CANDIDATES);
+
+        candidates
+    }
+
+    /// The entity the query most likely refers to, if we are confident enough that the
+    /// query refers to an entity at all.
+    pub fn search(&self, query: &str) -> Option<StoredEntity> {
+        let best = self.search_candidates(query).into_iter().next()?;
+
+        if best.confidence <= MIN_CONFIDENCE {
+            return None;
+        }
+
+        let searcher = self.reader.searcher();
+        Some(self.retrieve_stored_entity(&searcher, best.doc, true, true, true))
+    }
+
+    /// Calls `f` with the title of every entity in the index.
+    pub fn for_each_title<F: FnMut(&str)>(&self, mut f: F) -> Result<()> {
+        let searcher = self.reader.searcher();
+        let title = self.schema.get_field("title").unwrap();
+
+        for (segment_ord, reader) in searcher.segment_readers().iter().enumerate() {
+            for doc_id in 0..reader.max_doc() {
+                if reader.is_deleted(doc_id) {
+                    continue;
+                }
+
+                let doc = searcher.doc(DocAddress {
+                    segment_ord: segment_ord as u32,
+                    doc_id,
+                })?;
+
+                if let Some(tantivy::schema::Value::Str(title)) = doc.get_first(title) {
+                    f(title);
+                }
+            }
+        }
+
+        Ok(())
     }
 
     fn retrieve_stored_entity(
@@ -446,4 +732,160 @@ mod tests {
             "the ashes"
         );
     }
+
+    fn entity(title: &str, links: &[&str]) -> Entity {
+        Entity {
+            title: title.to_string(),
+            page_abstract: Span {
+                text: String::new(),
+                links: links
+                    .iter()
+                    .map(|target| Link {
+                        start: 0,
+                        end: 0,
+                        target: target.to_string(),
+                    })
+                    .collect(),
+            },
+            info: BTreeMap::new(),
+            image: None,
+            paragraphs: Vec::new(),
+            categories: HashSet::new(),
+        }
+    }
+
+    fn build_index(entities: Vec<Entity>) -> EntityIndex {
+        let mut index = EntityIndex::open(crate::gen_temp_path()).unwrap();
+
+        for entity in &entities {
+            index.count_inbound_links(entity);
+        }
+
+        for entity in entities {
+            index.insert(entity);
+        }
+
+        index.commit();
+
+        index
+    }
+
+    #[test]
+    fn popular_entity_preferred() {
+        let index = build_index(vec![
+            entity("Mercury (planet)", &[]),
+            entity("Mercury (element)", &[]),
+            entity("Solar System", &["Mercury_(planet)"]),
+            entity("Venus", &["mercury (planet)#Orbit", "Mercury (planet)"]),
+            entity("Thermometer", &["Mercury (element)"]),
+        ]);
+
+        let candidates = index.search_candidates("mercury");
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].title.as_str(), "Mercury (planet)");
+        assert!((candidates[0].confidence - 3.0 / 5.25).abs() < 1e-9);
+        assert_eq!(candidates[1].title.as_str(), "Mercury (element)");
+        assert!((candidates[1].confidence - 2.0 / 5.25).abs() < 1e-9);
+
+        assert_eq!(
+            index.search("mercury").unwrap().title.as_str(),
+            "Mercury (planet)"
+        );
+        assert_eq!(
+            index.search("mercury element").unwrap().title.as_str(),
+            "Mercury (element)"
+        );
+    }
+
+    #[test]
+    fn ambiguous_names_not_shown() {
+        let mut index = EntityIndex::open(crate::gen_temp_path()).unwrap();
+
+        index.insert(entity("Mercury (planet)", &[]));
+        index.insert(entity("Mercury (element)", &[]));
+        index.commit();
+
+        assert_eq!(index.search_candidates("mercury").len(), 2);
+        assert_eq!(index.search("mercury"), None);
+    }
+
+    #[test]
+    fn partial_title_matches_not_shown() {
+        let mut index = EntityIndex::open(crate::gen_temp_path()).unwrap();
+
+        index.insert(entity("New York", &[]));
+        index.commit();
+
+        assert_eq!(index.search("new york").unwrap().title.as_str(), "New York");
+        assert_eq!(index.search("best pizza new york"), None);
+    }
+
+    #[test]
+    fn popularity_matters_for_single_candidate() {
+        let index = build_index(vec![
+            entity("New York", &[]),
+            entity("Pizza", &[]),
+            entity("Bagel", &["Pizza"]),
+        ]);
+
+        let obscure = index.search_candidates("new york");
+        let popular = index.search_candidates("pizza");
+        assert_eq!(obscure.len(), 1);
+        assert_eq!(popular.len(), 1);
+        assert!(popular[0].confidence > obscure[0].confidence);
+        assert!(obscure[0].confidence < 1.0);
+    }
+
+    #[test]
+    fn exact_titles_are_candidates() {
+        let mut index = EntityIndex::open(crate::gen_temp_path()).unwrap();
+
+        for i in 0..NUM_CANDIDATES * 2 {
+            index.insert(entity(&format!("Paris Paris Paris {i}"), &[]));
+        }
+        index.insert(entity("Paris", &[]));
+        index.commit();
+
+        assert!(index
+            .search_candidates("paris")
+            .iter()
+            .any(|candidate| candidate.title == "Paris"));
+    }
+
+    #[test]
+    fn inbound_links_counted_in_batches() {
+        let mut index = EntityIndex::open(crate::gen_temp_path()).unwrap();
+
+        let targets: Vec<String> = (0..MAX_PENDING_INBOUND_LINKS)
+            .map(|i| format!("Target {i}"))
+            .collect();
+        let targets: Vec<&str> = targets.iter().map(String::as_str).collect();
+
+        index.count_inbound_links(&entity("Index", &targets));
+        assert!(index.pending_inbound_links.is_empty());
+
+        index.count_inbound_links(&entity("Other", &["Target 0"]));
+        assert_eq!(index.pending_inbound_links.len(), 1);
+
+        index.insert(entity("Target 0", &[]));
+        index.commit();
+
+        let candidates = index.search_candidates("target 0");
+        assert_eq!(candidates.len(), 1);
+        assert!((candidates[0].confidence - 3.0 / 3.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn outdated_index_must_be_rebuilt() {
+        let path = crate::gen_temp_path();
+        let mut schema = Schema::builder();
+        schema.add_text_field("title", TextOptions::default().set_stored());
+        fs::create_dir_all(path.join("inverted_index")).unwrap();
+        tantivy::Index::create_in_dir(path.join("inverted_index"), schema.build()).unwrap();
+
+        assert!(matches!(
+            EntityIndex::open(&path),
+            Err(Error::OutdatedEntityIndex(_))
+        ));
+    }
 }
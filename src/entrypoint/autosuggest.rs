@@ -0,0 +1,53 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::path::Path;
+
+use tracing::info;
+
+use crate::{autosuggest::AutosuggestBuilder, entity_index::EntityIndex, index::Index, Result};
+
+pub struct Autosuggest {}
+
+impl Autosuggest {
+    pub fn build<P: AsRef<Path>>(
+        index_paths: &[String],
+        entity_index_path: Option<P>,
+        query_log_path: Option<P>,
+        output_path: P,
+    ) -> Result<()> {
+        let mut builder = AutosuggestBuilder::default();
+
+        for path in index_paths {
+            info!("adding page titles from {}", path);
+            let index = Index::open(path)?;
+            builder.insert_page_titles(&index)?;
+        }
+
+        if let Some(path) = entity_index_path {
+            info!("adding entity titles");
+            let index = EntityIndex::open(path)?;
+            builder.insert_entity_titles(&index)?;
+        }
+
+        if let Some(path) = query_log_path {
+            info!("adding queries from query log");
+            builder.insert_query_log(path)?;
+        }
+
+        builder.build(output_path)
+    }
+}
@@ -0,0 +1,75 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{path::Path, time::Duration};
+
+use tracing::info;
+
+use crate::{
+    crawler::{self, CrawlerSettings},
+    ranking::centrality_store::CentralityStore,
+    CrawlerConfig, Result,
+};
+
+pub struct Crawler {}
+
+impl Crawler {
+    fn seeds(config: &CrawlerConfig) -> Vec<String> {
+        let mut seeds = config.seeds.clone();
+
+        if let Some(path) = &config.centrality_store_path {
+            let store = CentralityStore::new(path);
+
+            seeds.extend(
+                store
+                    .top(config.num_seed_hosts.unwrap_or(1_000))
+                    .into_iter()
+                    .map(|(host, _)| format!("https://{host}/")),
+            );
+        }
+
+        seeds
+    }
+
+    pub fn run(config: &CrawlerConfig) -> Result<()> {
+        let output_path = config
+            .output_path
+            .clone()
+            .unwrap_or_else(|| "data/crawl".to_string());
+
+        let settings = CrawlerSettings {
+            seeds: Self::seeds(config),
+            output_path: Path::new(&output_path).to_path_buf(),
+            max_pages: config.max_pages.unwrap_or(usize::MAX),
+            max_pages_per_host: config.max_pages_per_host.unwrap_or(1_000),
+            min_crawl_delay: Duration::from_millis(config.min_crawl_delay_ms.unwrap_or(1_000)),
+            max_crawl_delay: Duration::from_millis(config.max_crawl_delay_ms.unwrap_or(30_000)),
+            pages_per_warc: config.pages_per_warc.unwrap_or(1_000),
+            max_concurrent_hosts: config.max_concurrent_hosts.unwrap_or(32),
+        };
+
+        info!("crawling from {} seeds", settings.seeds.len());
+
+        let names = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(crawler::Crawler::new(settings)?.run())?;
+
+        crawler::write_warc_paths(&output_path, &names)?;
+
+        Ok(())
+    }
+}
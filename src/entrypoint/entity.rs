@@ -32,6 +32,18 @@ use bzip2::bufread::MultiBzDecoder;
 use parse_wiki_text::Node;
 use quick_xml::{events::Event, Reader};
 
+/// Names of the templates marking a page as a disambiguation page.
+const DISAMBIGUATION_TEMPLATES: [&str; 8] = [
+    "disambiguation",
+    "disambig",
+    "disamb",
+    "dab",
+    "hndis",
+    "geodis",
+    "human name disambiguation",
+    "place name disambiguation",
+];
+
 struct EntityIterator<R: BufRead> {
     inside_title: bool,
     inside_text: bool,
@@ -105,20 +117,30 @@ impl<R: BufRead> Iterator for EntityIterator<R> {
     }
 }
 
+fn entities(wikipedia_dump_path: &str) -> Result<impl Iterator<Item = Entity>> {
+    let reader = BufReader::new(File::open(wikipedia_dump_path)?);
+    let reader = BufReader::new(MultiBzDecoder::new(reader));
+
+    Ok(EntityIterator::from(reader)
+        .filter(|entity| !entity.categories.is_empty())
+        .take(200_000))
+}
+
 pub struct EntityIndexer;
 
 impl EntityIndexer {
+    /// The dump is read twice: first to count the inbound links of every entity and
+    /// then to index the entities together with their counts.
     pub fn run(wikipedia_dump_path: String, output_path: String) -> Result<()> {
-        let reader = BufReader::new(File::open(wikipedia_dump_path)?);
-        let reader = BufReader::new(MultiBzDecoder::new(reader));
         let mut index = EntityIndex::open(output_path)?;
-        EntityIterator::from(reader)
-            .into_iter()
-            .filter(|entity| !entity.categories.is_empty())
-            .take(200_000)
-            .for_each(|entity| {
-                index.insert(entity);
-            });
+
+        for entity in entities(&wikipedia_dump_path)? {
+            index.count_inbound_links(&entity);
+        }
+
+        for entity in entities(&wikipedia_dump_path)? {
+            index.insert(entity);
+        }
 
         index.commit();
 
@@ -295,11 +317,10 @@ impl EntityBuilder {
         (page_abstract, paragraphs)
     }
 
-    fn extract_categories(&self) -> HashSet<String> {
+    fn extract_categories(nodes: &[Node]) -> HashSet<String> {
         let mut result = HashSet::new();
-        let parsed_wiki = parse_wiki_text::Configuration::default().parse(&self.text);
 
-        for node in parsed_wiki.nodes {
+        for node in nodes {
             if let Node::Category {
                 end: _,
                 ordinal: _,
@@ -316,10 +337,33 @@ impl EntityBuilder {
         result
     }
 
+    /// Disambiguation pages only list other entities. They are usually marked with a
+    /// template like `{{disambiguation}}`, which is what adds their category.
+    fn is_disambiguation(&self, nodes: &[Node], categories: &HashSet<String>) -> bool {
+        if self.title.ends_with("(disambiguation)")
+            || categories.iter().any(|category| {
+                category == "Disambiguation" || category.ends_with("disambiguation pages")
+            })
+        {
+            return true;
+        }
+
+        nodes.iter().any(|node| match node {
+            Node::Template { name, .. } => name.iter().any(|node| match node {
+                Node::Text { value, .. } => {
+                    DISAMBIGUATION_TEMPLATES.contains(&value.trim().to_lowercase().as_str())
+                }
+                _ => false,
+            }),
+            _ => false,
+        })
+    }
+
     pub fn build(self) -> Option<Entity> {
-        let categories = self.extract_categories();
+        let parsed_wiki = parse_wiki_text::Configuration::default().parse(&self.text);
+        let categories = Self::extract_categories(&parsed_wiki.nodes);
 
-        if categories.contains("Disambiguation") {
+        if self.is_disambiguation(&parsed_wiki.nodes, &categories) {
             return None;
         }
 
@@ -501,4 +545,37 @@ mod tests {
         .build()
         .is_none());
     }
+
+    #[test]
+    fn skip_disambiguation_templates_and_titles() {
+        let text = "'''Mercury''' may refer to:\n\n* [[Mercury (planet)]], the closest planet to the Sun\n* [[Mercury (element)]], a chemical element\n\n== See also ==\n* [[Mercury (mythology)]]\n";
+
+        assert!(EntityBuilder {
+            title: "Mercury".to_string(),
+            text: text.to_string() + "\n{{Disambiguation}}",
+        }
+        .build()
+        .is_none());
+
+        assert!(EntityBuilder {
+            title: "Mercury".to_string(),
+            text: text.to_string() + "\n{{hndis|Mercury}}",
+        }
+        .build()
+        .is_none());
+
+        assert!(EntityBuilder {
+            title: "Mercury (disambiguation)".to_string(),
+            text: text.to_string(),
+        }
+        .build()
+        .is_none());
+
+        assert!(EntityBuilder {
+            title: "Mercury".to_string(),
+            text: text.to_string(),
+        }
+        .build()
+        .is_some());
+    }
 }
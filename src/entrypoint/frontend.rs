@@ -14,17 +14,91 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::{net::SocketAddr, sync::Arc};
+
 use anyhow::Result;
 
-use crate::frontend::router;
+use crate::{frontend::router, query_log::QueryLog, GoggleRegistryConfig, QueryLogConfig};
+
+/// Writes the counts of the current day every flush interval.
+fn spawn_periodic_flush(query_log: Arc<QueryLog>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(query_log.flush_interval());
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let query_log = Arc::clone(&query_log);
+            let res = tokio::task::spawn_blocking(move || query_log.flush()).await;
+
+            if let Ok(Err(err)) = res {
+                tracing::warn!("failed to flush query log: {:?}", err);
+            }
+        }
+    });
+}
+
+/// Resolves on ctrl-c or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
 
-pub async fn run(queries_csv_path: &str, host: &str, shards: Vec<Vec<String>>) -> Result<()> {
-    let app = router(queries_csv_path, shards)?;
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("shutting down");
+}
+
+pub async fn run(
+    queries_csv_path: &str,
+    host: &str,
+    shards: Vec<Vec<String>>,
+    goggles: GoggleRegistryConfig,
+    query_log: Option<QueryLogConfig>,
+    bangs_paths: &[String],
+) -> Result<()> {
+    let query_log = query_log.map(QueryLog::open).transpose()?.map(Arc::new);
+
+    if let Some(query_log) = &query_log {
+        spawn_periodic_flush(Arc::clone(query_log));
+    }
+
+    let app = router(
+        queries_csv_path,
+        shards,
+        goggles,
+        query_log.clone(),
+        bangs_paths,
+    )?;
     let addr = host.parse()?;
     tracing::info!("listening on {}", addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
         .await?;
 
+    if let Some(query_log) = query_log {
+        tokio::task::spawn_blocking(move || query_log.flush()).await??;
+    }
+
     Ok(())
 }
@@ -14,33 +14,48 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use futures::StreamExt;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 
 use itertools::Itertools;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use tokio::pin;
-use tracing::{debug, info, trace};
+use tracing::{debug, error, info, trace};
 
 use crate::entrypoint::async_download_all_warc_files;
 use crate::index::{FrozenIndex, Index};
 use crate::mapreduce::{Manager, Map, Reduce, Worker};
+use crate::prehashed::{hash, Prehashed};
 use crate::ranking::centrality_store::CentralityStore;
 use crate::ranking::SignalAggregator;
+use crate::simhash::NearDuplicateIndex;
+use crate::trackers;
 use crate::warc::WarcFile;
 use crate::webgraph::{Node, Webgraph, WebgraphBuilder};
-use crate::webpage::{Html, Link, Webpage};
+use crate::webpage::{
+    self, Backlink, ContentType, Document, Html, Link, LinkRel, Truncation, Url, Webpage,
+};
 use crate::{
-    HttpConfig, IndexingLocalConfig, IndexingMasterConfig, LocalConfig, Result, WarcSource,
+    DirectoryConfig, ExtractionConfig, HtmlLimits, HttpConfig, IndexingLocalConfig,
+    IndexingMasterConfig, LocalConfig, Result, SitemapConfig, WarcSource,
 };
 
 pub struct Indexer {}
 
+/// Number of fingerprints a job compares new pages against. Older pages are
+/// forgotten first, which bounds the memory used per job.
+const MAX_NEAR_DUPLICATE_FINGERPRINTS: usize = 500_000;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 enum JobConfig {
     Http(HttpConfig),
     Local(LocalConfig),
+    Directory(DirectoryConfig),
+    Sitemap(SitemapConfig),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,6 +65,35 @@ struct Job {
     warc_paths: Vec<String>,
     base_path: String,
     host_centrality_threshold: Option<f64>,
+    /// Only index the page with the highest host centrality among pages of the job
+    /// that are near-duplicates of each other.
+    drop_near_duplicates: bool,
+    /// Tracker list used instead of the bundled one.
+    tracker_list_path: Option<String>,
+    extraction: ExtractionConfig,
+    html_limits: HtmlLimits,
+}
+
+/// Counters reported when a job finishes.
+#[derive(Debug, Default)]
+struct IndexingStats {
+    records: usize,
+    indexed: usize,
+    failed: usize,
+    panicked: usize,
+    truncated_body: usize,
+    truncated_depth: usize,
+    truncated_text: usize,
+    truncated_links: usize,
+}
+
+impl IndexingStats {
+    fn add_truncation(&mut self, truncation: Truncation) {
+        self.truncated_body += truncation.body as usize;
+        self.truncated_depth += truncation.depth as usize;
+        self.truncated_text += truncation.text as usize;
+        self.truncated_links += truncation.links as usize;
+    }
 }
 
 struct IndexingWorker {
@@ -77,110 +121,276 @@ impl IndexingWorker {
     }
 }
 
+/// Inserts the parsed pages of a job into its index.
+struct JobIndexer<'a> {
+    job: &'a Job,
+    worker: &'a IndexingWorker,
+    index: Index,
+    signal_aggregator: SignalAggregator,
+    near_duplicates: NearDuplicateIndex<f64>,
+    indexed_urls: HashSet<Prehashed>,
+    stats: IndexingStats,
+}
+
+impl<'a> JobIndexer<'a> {
+    /// Indexes the page. A pathological page must not take down the whole job, so
+    /// a panic anywhere while processing it only skips the page.
+    fn insert(&mut self, html: Html, fetch_time_ms: u64) {
+        let url = html.url().clone();
+
+        if panic::catch_unwind(AssertUnwindSafe(|| {
+            self.insert_unguarded(html, fetch_time_ms)
+        }))
+        .is_err()
+        {
+            debug!("indexing panicked: {}", url);
+            self.stats.panicked += 1;
+        }
+    }
+
+    fn insert_unguarded(&mut self, mut html: Html, fetch_time_ms: u64) {
+        if !self.indexed_urls.insert(hash(html.url().full())) {
+            trace!("skipping already indexed url: {:?}", html.url());
+            return;
+        }
+
+        let host_centrality = self
+            .worker
+            .host_centrality_store
+            .get(html.url().host_without_specific_subdomains())
+            .unwrap_or_default();
+
+        if let Some(host_centrality_threshold) = self.job.host_centrality_threshold {
+            if host_centrality < host_centrality_threshold {
+                return;
+            }
+        }
+
+        html.parse_text();
+        self.stats.add_truncation(html.truncation());
+
+        if self.job.drop_near_duplicates {
+            let simhash = html.simhash();
+
+            if self
+                .near_duplicates
+                .near_duplicates(simhash)
+                .into_iter()
+                .any(|other_centrality| *other_centrality > host_centrality)
+            {
+                trace!("dropping near-duplicate: {:?}", html.url());
+                return;
+            }
+
+            // copies with lower centrality that were indexed before this page are replaced
+            // by it. They are deleted before the page is inserted, so the page itself is
+            // kept even if its simhash is the same.
+            for (fingerprint, _) in self
+                .near_duplicates
+                .remove_near_duplicates(simhash, |other_centrality| {
+                    *other_centrality < host_centrality
+                })
+            {
+                trace!("replacing near-duplicate with: {:?}", html.url());
+                self.index.inverted_index.delete_simhash(fingerprint);
+            }
+
+            self.near_duplicates.insert(simhash, host_centrality);
+        }
+
+        let backlinks: Vec<Backlink> = self
+            .worker
+            .webgraph
+            .as_ref()
+            .map(|webgraph| {
+                webgraph
+                    .ingoing_edges(Node::from(html.url()))
+                    .into_iter()
+                    .map(|edge| {
+                        let source = Url::from(edge.from.name);
+                        let source_centrality = self
+                            .worker
+                            .host_centrality_store
+                            .get(source.host_without_specific_subdomains())
+                            .unwrap_or_default();
+
+                        Backlink {
+                            link: Link {
+                                source,
+                                destination: edge.to.name.into(),
+                                text: edge.label.text,
+                                rel: LinkRel::default(),
+                                position: edge.label.position,
+                                context: edge.label.context,
+                            },
+                            source_centrality,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new);
+
+        let page_centrality = self
+            .worker
+            .page_centrality_store
+            .get(html.url().raw())
+            .unwrap_or_default();
+
+        trace!("inserting webpage: {:?}", html.url());
+
+        trace!("title = {:?}", html.title());
+        trace!("text = {:?}", html.clean_text());
+
+        let mut webpage = Webpage {
+            html,
+            backlinks,
+            page_centrality,
+            host_centrality,
+            fetch_time_ms,
+            primary_image: None,
+            pre_computed_score: 0.0,
+        };
+
+        webpage.pre_computed_score = self
+            .signal_aggregator
+            .precompute_score(&webpage, &self.index.region_count);
+
+        match self.index.insert(webpage) {
+            Ok(_) => self.stats.indexed += 1,
+            Err(err) => {
+                debug!("{:?}", err);
+                self.stats.failed += 1;
+            }
+        }
+    }
+
+    /// Download the images of the inserted pages and commit them to the index.
+    fn finish_batch(&mut self) {
+        if self.job.download_images {
+            info!("downloading images");
+            self.index.download_pending_images();
+        }
+
+        self.index.commit().unwrap();
+    }
+}
+
+/// Applies the settings of the job that are global to the worker process.
+fn configure(job: &Job) -> Result<()> {
+    if let Some(path) = &job.tracker_list_path {
+        trackers::load_database(path)?;
+    }
+
+    webpage::configure_extraction(&job.extraction)?;
+    webpage::configure_limits(&job.html_limits)?;
+
+    Ok(())
+}
+
 async fn async_process_job(job: &Job, worker: &IndexingWorker) -> Index {
     let name = job.warc_paths.first().unwrap().split('/').last().unwrap();
 
     info!("processing {}", name);
 
-    let mut index = Index::open(Path::new(&job.base_path).join(name)).unwrap();
+    let index = Index::open(Path::new(&job.base_path).join(name)).unwrap();
+
+    // the worker keeps serving other jobs, and the job comes back without any pages
+    if let Err(err) = configure(job) {
+        error!("skipping {}: {}", name, err);
+        return index;
+    }
 
     let source = match job.source_config.clone() {
         JobConfig::Http(config) => WarcSource::HTTP(config),
         JobConfig::Local(config) => WarcSource::Local(config),
+        JobConfig::Directory(config) => WarcSource::Directory(config),
+        JobConfig::Sitemap(config) => WarcSource::Sitemap(config),
     };
 
     let warc_files = async_download_all_warc_files(&job.warc_paths, &source, &job.base_path).await;
     pin!(warc_files);
 
-    let signal_aggregator = SignalAggregator::default();
+    let mut pages = JobIndexer {
+        job,
+        worker,
+        index,
+        signal_aggregator: SignalAggregator::default(),
+        near_duplicates: NearDuplicateIndex::with_max_entries(MAX_NEAR_DUPLICATE_FINGERPRINTS),
+        indexed_urls: HashSet::new(),
+        stats: IndexingStats::default(),
+    };
 
     while let Some(file) = warc_files.next().await {
         let name = file.split('/').last().unwrap();
         let path = Path::new(&job.base_path).join("warc_files").join(name);
 
         if let Ok(file) = WarcFile::open(path) {
-            for record in
-                file.records()
-                    .flatten()
-                    .filter(|record| match &record.response.payload_type {
-                        Some(payload_type) => !matches!(payload_type.as_str(), "application/pdf"),
-                        None => true,
-                    })
+            // pages whose canonical url points elsewhere are indexed after the other pages
+            // of the file, so the record fetched from the canonical url itself wins the
+            // deduplication. They are only kept until the end of the file, as their
+            // parsed documents are large.
+            let mut non_canonical: Vec<(Html, u64)> = Vec::new();
+
+            for record in file
+                .records()
+                .flatten()
+                .filter(|record| record.response.is_success())
             {
-                let mut html = Html::parse_without_text(&record.response.body, &record.request.url);
-
-                let host_centrality = worker
-                    .host_centrality_store
-                    .get(html.url().host_without_specific_subdomains())
-                    .unwrap_or_default();
-
-                if let Some(host_centrality_threshold) = job.host_centrality_threshold {
-                    if host_centrality < host_centrality_threshold {
+                pages.stats.records += 1;
+
+                let content_type =
+                    match ContentType::guess(record.response.mime(), &record.request.url) {
+                        Some(content_type) => content_type,
+                        None => continue,
+                    };
+
+                // binary payloads are read as-is, so only textual content is decoded
+                let body = match content_type {
+                    ContentType::Pdf => Cow::Borrowed(""),
+                    _ => record.response.body(),
+                };
+                let document = Document::new(content_type, &body, &record.response.payload);
+
+                // a single malformed record must not take down the whole job
+                let html = match panic::catch_unwind(AssertUnwindSafe(|| {
+                    Html::parse_document_without_text(document, &record.request.url)
+                })) {
+                    Ok(Ok(html)) => html,
+                    Ok(Err(err)) => {
+                        debug!("{:?}", err);
+                        pages.stats.failed += 1;
+                        continue;
+                    }
+                    Err(_) => {
+                        debug!("parsing panicked: {}", record.request.url);
+                        pages.stats.panicked += 1;
                         continue;
                     }
-                }
-
-                html.parse_text();
-
-                let backlinks: Vec<Link> = worker
-                    .webgraph
-                    .as_ref()
-                    .map(|webgraph| {
-                        webgraph
-                            .ingoing_edges(Node::from(html.url()))
-                            .into_iter()
-                            .map(|edge| Link {
-                                source: edge.from.name.into(),
-                                destination: edge.to.name.into(),
-                                text: edge.label,
-                            })
-                            .collect()
-                    })
-                    .unwrap_or_else(Vec::new);
-
-                let page_centrality = worker
-                    .page_centrality_store
-                    .get(html.url().raw())
-                    .unwrap_or_default();
-
-                let fetch_time_ms = record.metadata.fetch_time_ms as u64;
-
-                trace!("inserting webpage: {:?}", html.url());
-
-                trace!("title = {:?}", html.title());
-                trace!("text = {:?}", html.clean_text());
-
-                let mut webpage = Webpage {
-                    html,
-                    backlinks,
-                    page_centrality,
-                    host_centrality,
-                    fetch_time_ms,
-                    primary_image: None,
-                    pre_computed_score: 0.0,
                 };
 
-                webpage.pre_computed_score =
-                    signal_aggregator.precompute_score(&webpage, &index.region_count);
+                let fetch_time_ms = record.metadata.fetch_time_ms as u64;
 
-                if let Err(err) = index.insert(webpage) {
-                    debug!("{:?}", err);
+                if html.canonical_url().is_some() {
+                    non_canonical.push((html, fetch_time_ms));
+                } else {
+                    pages.insert(html, fetch_time_ms);
                 }
             }
-            if job.download_images {
-                info!("downloading images");
-                index.download_pending_images();
+
+            for (mut html, fetch_time_ms) in non_canonical {
+                html.fold_into_canonical();
+                pages.insert(html, fetch_time_ms);
             }
-        }
 
-        index.commit().unwrap();
+            pages.finish_batch();
+        }
 
         std::fs::remove_file(file).ok();
     }
 
-    info!("{} done", name);
+    info!("{} done: {:?}", name, pages.stats);
 
-    index
+    pages.index
 }
 
 fn process_job(job: &Job, worker: &IndexingWorker) -> Index {
@@ -260,7 +470,8 @@ impl Indexer {
             .block_on(async {
                 info!("Running master for index construction");
 
-                let warc_paths = config.warc_source.paths().unwrap();
+                let mut warc_source = config.warc_source.clone();
+                let warc_paths = warc_source.paths().unwrap();
 
                 let workers: Vec<SocketAddr> = config
                     .workers
@@ -268,9 +479,11 @@ impl Indexer {
                     .map(|worker| worker.parse().unwrap())
                     .collect();
 
-                let job_config = match config.warc_source.clone() {
+                let job_config = |warc_paths: &[String]| match warc_source.for_warcs(warc_paths) {
                     WarcSource::HTTP(config) => JobConfig::Http(config),
                     WarcSource::Local(config) => JobConfig::Local(config),
+                    WarcSource::Directory(config) => JobConfig::Directory(config),
+                    WarcSource::Sitemap(config) => JobConfig::Sitemap(config),
                 };
 
                 let mut warc_paths: Box<dyn Iterator<Item = Job> + Send> = Box::new(
@@ -278,15 +491,23 @@ impl Indexer {
                         .into_iter()
                         .chunks(config.batch_size.unwrap_or(1))
                         .into_iter()
-                        .map(|warc_paths| Job {
-                            source_config: job_config.clone(),
-                            warc_paths: warc_paths.collect_vec(),
-                            download_images: config.download_images.unwrap_or(true),
-                            host_centrality_threshold: config.host_centrality_threshold,
-                            base_path: config
-                                .index_base_path
-                                .clone()
-                                .unwrap_or_else(|| "data/index".to_string()),
+                        .map(|warc_paths| {
+                            let warc_paths = warc_paths.collect_vec();
+
+                            Job {
+                                source_config: job_config(&warc_paths),
+                                warc_paths,
+                                download_images: config.download_images.unwrap_or(true),
+                                host_centrality_threshold: config.host_centrality_threshold,
+                                drop_near_duplicates: config.drop_near_duplicates.unwrap_or(false),
+                                tracker_list_path: config.tracker_list_path.clone(),
+                                extraction: config.extraction.clone().unwrap_or_default(),
+                                html_limits: config.html_limits.clone().unwrap_or_default(),
+                                base_path: config
+                                    .index_base_path
+                                    .clone()
+                                    .unwrap_or_else(|| "data/index".to_string()),
+                            }
                         })
                         .collect_vec()
                         .into_iter(),
@@ -334,11 +555,14 @@ impl Indexer {
     }
 
     pub fn run_locally(config: &IndexingLocalConfig) -> Result<()> {
-        let warc_paths = config.warc_source.paths()?;
+        let mut warc_source = config.warc_source.clone();
+        let warc_paths = warc_source.paths()?;
 
-        let job_config = match config.warc_source.clone() {
+        let job_config = |warc_paths: &[String]| match warc_source.for_warcs(warc_paths) {
             WarcSource::HTTP(config) => JobConfig::Http(config),
             WarcSource::Local(config) => JobConfig::Local(config),
+            WarcSource::Directory(config) => JobConfig::Directory(config),
+            WarcSource::Sitemap(config) => JobConfig::Sitemap(config),
         };
 
         let worker = IndexingWorker::new(
@@ -351,15 +575,23 @@ impl Indexer {
             .take(config.limit_warc_files.unwrap_or(usize::MAX))
             .chunks(config.batch_size.unwrap_or(1))
             .into_iter()
-            .map(|warc_paths| Job {
-                source_config: job_config.clone(),
-                warc_paths: warc_paths.collect_vec(),
-                download_images: config.download_images.unwrap_or(true),
-                host_centrality_threshold: config.host_centrality_threshold,
-                base_path: config
-                    .output_path
-                    .clone()
-                    .unwrap_or_else(|| "data/index".to_string()),
+            .map(|warc_paths| {
+                let warc_paths = warc_paths.collect_vec();
+
+                Job {
+                    source_config: job_config(&warc_paths),
+                    warc_paths,
+                    download_images: config.download_images.unwrap_or(true),
+                    host_centrality_threshold: config.host_centrality_threshold,
+                    drop_near_duplicates: config.drop_near_duplicates.unwrap_or(false),
+                    tracker_list_path: config.tracker_list_path.clone(),
+                    extraction: config.extraction.clone().unwrap_or_default(),
+                    html_limits: config.html_limits.clone().unwrap_or_default(),
+                    base_path: config
+                        .output_path
+                        .clone()
+                        .unwrap_or_else(|| "data/index".to_string()),
+                }
             })
             .collect_vec()
             // .into_iter()
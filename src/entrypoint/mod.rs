@@ -13,19 +13,25 @@
 //
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
+mod autosuggest;
 mod centrality;
+mod crawler;
 mod entity;
 pub mod frontend;
 mod indexer;
 pub mod search_server;
+mod spell;
 mod webgraph;
 
 use std::{fs::File, path::Path};
 
+pub use autosuggest::Autosuggest;
 pub use centrality::Centrality;
+pub use crawler::Crawler;
 pub use entity::EntityIndexer;
 use futures::{Stream, StreamExt};
 pub use indexer::Indexer;
+pub use spell::Spell;
 use tracing::debug;
 pub use webgraph::Webgraph;
 
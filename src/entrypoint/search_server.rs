@@ -17,7 +17,6 @@
 use std::net::SocketAddr;
 
 use crate::{
-    bangs::Bangs,
     entity_index::EntityIndex,
     index::Index,
     inverted_index,
@@ -27,17 +26,20 @@ use crate::{
 };
 
 pub async fn run(config: SearchServerConfig) -> Result<()> {
+    let entity_index = config
+        .entity_index_path
+        .map(EntityIndex::open)
+        .transpose()?;
+    let mut search_index = Index::open(config.index_path)?;
+    if let Some(spell_path) = config.spell_path {
+        search_index.use_spell_model(spell_path)?;
+    }
+
     let addr: SocketAddr = config.host.parse().unwrap();
     let server = sonic::Server::bind(addr).await.unwrap();
     tracing::info!("listening on {}", addr);
 
-    let entity_index = config
-        .entity_index_path
-        .map(|path| EntityIndex::open(path).unwrap());
-    let bangs = config.bangs_path.map(Bangs::from_path);
-    let search_index = Index::open(config.index_path)?;
-
-    let local_searcher = LocalSearcher::new(search_index, entity_index, bangs);
+    let local_searcher = LocalSearcher::new(search_index, entity_index);
 
     loop {
         if let Ok(req) = server.accept::<searcher::distributed::Request>().await {
@@ -48,7 +50,7 @@ pub async fn run(config: SearchServerConfig) -> Result<()> {
                             req.respond(sonic::Response::Content(response)).await.ok();
                         }
                         Err(_) => {
-                            req.respond::<searcher::InitialSearchResult>(sonic::Response::Empty)
+                            req.respond::<searcher::InitialWebsiteResult>(sonic::Response::Empty)
                                 .await
                                 .ok();
                         }
@@ -70,28 +72,20 @@ pub async fn run(config: SearchServerConfig) -> Result<()> {
                 }
                 searcher::Request::SearchPrettified(query) => {
                     match local_searcher.search_initial(query, false) {
-                        Ok(result) => match result {
-                            searcher::InitialSearchResult::Websites(result) => {
-                                let res = search_prettifier::initial(result, &local_searcher);
-
-                                req.respond(sonic::Response::Content(
-                                    searcher::InitialPrettifiedSearchResult::Websites(res),
-                                ))
-                                .await
-                                .ok();
-                            }
-                            searcher::InitialSearchResult::Bang(bang) => {
-                                req.respond(sonic::Response::Content(
-                                    searcher::InitialPrettifiedSearchResult::Bang(bang),
-                                ))
-                                .await
-                                .ok();
-                            }
-                        },
+                        Ok(result) => {
+                            req.respond(sonic::Response::Content(search_prettifier::initial(
+                                result,
+                                &local_searcher,
+                            )))
+                            .await
+                            .ok();
+                        }
                         Err(_) => {
-                            req.respond::<inverted_index::SearchResult>(sonic::Response::Empty)
-                                .await
-                                .ok();
+                            req.respond::<search_prettifier::InitialWebsiteResult>(
+                                sonic::Response::Empty,
+                            )
+                            .await
+                            .ok();
                         }
                     }
                 }
@@ -0,0 +1,175 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use tracing::info;
+
+use crate::{
+    index::{Index, SpellModel},
+    spell::{distance::LevenshteinDistance, LogarithmicEdit, QueryCorrector, SpellChecker},
+    Error, Result,
+};
+
+const COMMIT_EVERY_N_PAGES: usize = 10_000;
+const NUM_INSPECT_CANDIDATES: usize = 10;
+
+pub struct Spell {}
+
+/// Builds a model in a fresh folder next to `output_path` and only replaces
+/// `output_path` once it is complete. Opening the existing model would add the new
+/// counts to the old ones.
+fn build<P, F>(output_path: P, build: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut SpellModel) -> Result<()>,
+{
+    let output_path = output_path.as_ref();
+    let build_path = output_path.with_extension("building");
+
+    if build_path.exists() {
+        fs::remove_dir_all(&build_path)?;
+    }
+
+    let mut model = SpellModel::open(Some(&build_path))?;
+    build(&mut model)?;
+    model.commit()?;
+    drop(model);
+
+    if output_path.exists() {
+        fs::remove_dir_all(output_path)?;
+    }
+    fs::rename(build_path, output_path)?;
+
+    Ok(())
+}
+
+impl Spell {
+    /// Builds the spell model from the pages stored in the index at `index_path`.
+    /// A model already in `output_path` is replaced.
+    pub fn build_from_index<P: AsRef<Path>>(index_path: P, output_path: P) -> Result<()> {
+        let index = Index::open(index_path)?;
+
+        build(output_path, |model| {
+            let mut num_pages = 0;
+            let mut res = Ok(());
+
+            index.inverted_index.for_each_webpage(|webpage| {
+                if res.is_ok() {
+                    res = model.insert_text(&webpage.body);
+                }
+                num_pages += 1;
+
+                if res.is_ok() && num_pages % COMMIT_EVERY_N_PAGES == 0 {
+                    res = model.commit();
+                    info!("added {} pages to the spell model", num_pages);
+                }
+            })?;
+            res?;
+
+            info!("done building spell model from {} pages", num_pages);
+
+            Ok(())
+        })
+    }
+
+    /// Builds the spell model from a file with a tab separated term and frequency on
+    /// each line. Terms consisting of two words are added as bigrams. A model already
+    /// in `output_path` is replaced.
+    pub fn build_from_frequencies<P: AsRef<Path>>(
+        frequencies_path: P,
+        output_path: P,
+    ) -> Result<()> {
+        let reader = BufReader::new(File::open(frequencies_path)?);
+
+        build(output_path, |model| {
+            for (line_num, line) in reader.lines().enumerate() {
+                let line = line?;
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let (term, frequency) = line.rsplit_once('\t').ok_or_else(|| {
+                    Error::ParsingError(format!(
+                        "line {} is not a tab separated term and frequency",
+                        line_num + 1
+                    ))
+                })?;
+
+                model.insert_with_frequency(term, frequency.trim().parse()?);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Prints what the spell model in `model_path` knows about each term, and the
+    /// correction it would suggest for all the terms as one query.
+    pub fn inspect<P: AsRef<Path>>(model_path: P, terms: &[String]) -> Result<()> {
+        let model = SpellModel::open(Some(model_path))?;
+        let dict = model.unigrams();
+        let spell_checker = SpellChecker::new(dict, LogarithmicEdit::new(4));
+
+        println!("total frequency: {}", dict.total_frequency());
+
+        for term in terms {
+            let term = term.to_lowercase();
+            println!();
+            println!("{}: {}", term, dict.frequency(&term).unwrap_or_default());
+
+            for candidate in spell_checker.correct_top(&term, NUM_INSPECT_CANDIDATES) {
+                println!(
+                    "  {} (distance {}, frequency {})",
+                    candidate,
+                    LevenshteinDistance::compare(&term, &candidate),
+                    dict.frequency(&candidate).unwrap_or_default()
+                );
+            }
+        }
+
+        println!();
+        match QueryCorrector::new(&model, LogarithmicEdit::new(4)).correct(terms) {
+            Some(correction) => println!("correction: {}", correction.join(" ")),
+            None => println!("no correction"),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuild_replaces_model() {
+        let frequencies_path = crate::gen_temp_path();
+        fs::write(&frequencies_path, "hello\t3\nhello world\t2\n").unwrap();
+        let output_path = crate::gen_temp_path();
+
+        Spell::build_from_frequencies(&frequencies_path, &output_path).unwrap();
+        Spell::build_from_frequencies(&frequencies_path, &output_path).unwrap();
+
+        let model = SpellModel::open(Some(&output_path)).unwrap();
+        assert_eq!(model.unigrams().frequency("hello"), Some(3));
+        assert!(!output_path.with_extension("building").exists());
+    }
+}
@@ -18,9 +18,10 @@ use crate::{
     entrypoint::async_download_all_warc_files,
     mapreduce::{Manager, Map, Reduce, StatelessWorker, Worker},
     warc::WarcFile,
-    webgraph::{self, FrozenWebgraph, Node, WebgraphBuilder},
-    webpage::Html,
-    HttpConfig, LocalConfig, Result, WarcSource, WebgraphLocalConfig, WebgraphMasterConfig,
+    webgraph::{self, FrozenWebgraph, Label, Node, WebgraphBuilder},
+    webpage::{Html, Url},
+    DirectoryConfig, HttpConfig, LocalConfig, Result, SitemapConfig, WarcSource,
+    WebgraphLocalConfig, WebgraphMasterConfig,
 };
 use futures::StreamExt;
 use itertools::Itertools;
@@ -37,6 +38,8 @@ struct GraphPointer(String);
 enum JobConfig {
     Http(HttpConfig),
     Local(LocalConfig),
+    Directory(DirectoryConfig),
+    Sitemap(SitemapConfig),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,6 +66,8 @@ async fn async_process_job(job: &Job) -> webgraph::Webgraph {
     let source = match job.config.clone() {
         JobConfig::Http(config) => WarcSource::HTTP(config),
         JobConfig::Local(config) => WarcSource::Local(config),
+        JobConfig::Directory(config) => WarcSource::Directory(config),
+        JobConfig::Sitemap(config) => WarcSource::Sitemap(config),
     };
 
     let warc_files =
@@ -77,18 +82,45 @@ async fn async_process_job(job: &Job) -> webgraph::Webgraph {
 
         if let Ok(file) = WarcFile::open(path) {
             for record in file.records().flatten() {
-                let webpage = Html::parse_without_text(&record.response.body, &record.request.url);
+                if let Some(location) = record.response.redirect_location() {
+                    let source = Url::from(record.request.url.clone()).normalized();
+                    let destination = match source.resolve(location) {
+                        Some(destination) => destination,
+                        None => continue,
+                    };
+
+                    if source.domain() != destination.domain() {
+                        trace!("inserting redirect {:?} -> {:?}", source, destination);
+                        graph.insert(
+                            Node::from(source),
+                            Node::from(destination),
+                            Label::default(),
+                        );
+                    }
+
+                    continue;
+                }
+
+                if !record.response.is_success() {
+                    continue;
+                }
+
+                let mut webpage =
+                    Html::parse_without_text(&record.response.body(), &record.request.url);
+                webpage.fold_into_canonical();
+
                 for link in webpage
                     .links()
                     .into_iter()
+                    .filter(|link| link.rel.is_endorsement())
                     .filter(|link| matches!(link.destination.protocol(), "http" | "https"))
                     .filter(|link| link.source.domain() != link.destination.domain())
                 {
                     trace!("inserting link {:?}", link);
                     graph.insert(
-                        Node::from(link.source),
-                        Node::from(link.destination),
-                        link.text,
+                        Node::from(link.source.clone()),
+                        Node::from(link.destination.clone()),
+                        Label::from(link),
                     );
                 }
             }
@@ -184,7 +216,8 @@ impl Webgraph {
             .block_on(async {
                 info!("Running master for webgraph construction");
 
-                let warc_paths = config.warc_source.paths().unwrap();
+                let mut warc_source = config.warc_source.clone();
+                let warc_paths = warc_source.paths().unwrap();
 
                 let workers: Vec<SocketAddr> = config
                     .workers
@@ -192,9 +225,11 @@ impl Webgraph {
                     .map(|worker| worker.parse().unwrap())
                     .collect();
 
-                let job_config = match config.warc_source.clone() {
+                let job_config = |warc_paths: &[String]| match warc_source.for_warcs(warc_paths) {
                     WarcSource::HTTP(config) => JobConfig::Http(config),
                     WarcSource::Local(config) => JobConfig::Local(config),
+                    WarcSource::Directory(config) => JobConfig::Directory(config),
+                    WarcSource::Sitemap(config) => JobConfig::Sitemap(config),
                 };
 
                 let mut warc_paths: Box<dyn Iterator<Item = Job> + Send> = Box::new(
@@ -202,13 +237,17 @@ impl Webgraph {
                         .into_iter()
                         .chunks(config.batch_size.unwrap_or(1))
                         .into_iter()
-                        .map(|warc_paths| Job {
-                            config: job_config.clone(),
-                            warc_paths: warc_paths.into_iter().collect(),
-                            graph_base_path: config
-                                .graph_base_path
-                                .clone()
-                                .unwrap_or_else(|| "data/webgraph".to_string()),
+                        .map(|warc_paths| {
+                            let warc_paths: Vec<_> = warc_paths.into_iter().collect();
+
+                            Job {
+                                config: job_config(&warc_paths),
+                                warc_paths,
+                                graph_base_path: config
+                                    .graph_base_path
+                                    .clone()
+                                    .unwrap_or_else(|| "data/webgraph".to_string()),
+                            }
                         })
                         .collect::<Vec<_>>()
                         .into_iter(),
@@ -249,11 +288,14 @@ impl Webgraph {
     }
 
     pub fn run_locally(config: &WebgraphLocalConfig) -> Result<()> {
-        let warc_paths = config.warc_source.paths()?;
+        let mut warc_source = config.warc_source.clone();
+        let warc_paths = warc_source.paths()?;
 
-        let job_config = match config.warc_source.clone() {
+        let job_config = |warc_paths: &[String]| match warc_source.for_warcs(warc_paths) {
             WarcSource::HTTP(config) => JobConfig::Http(config),
             WarcSource::Local(config) => JobConfig::Local(config),
+            WarcSource::Directory(config) => JobConfig::Directory(config),
+            WarcSource::Sitemap(config) => JobConfig::Sitemap(config),
         };
         let worker = StatelessWorker::default();
 
@@ -262,13 +304,17 @@ impl Webgraph {
             .take(config.limit_warc_files.unwrap_or(usize::MAX))
             .chunks(config.batch_size.unwrap_or(1))
             .into_iter()
-            .map(|warc_paths| Job {
-                config: job_config.clone(),
-                warc_paths: warc_paths.collect_vec(),
-                graph_base_path: config
-                    .graph_base_path
-                    .clone()
-                    .unwrap_or_else(|| "data/webgraph".to_string()),
+            .map(|warc_paths| {
+                let warc_paths = warc_paths.collect_vec();
+
+                Job {
+                    config: job_config(&warc_paths),
+                    warc_paths,
+                    graph_base_path: config
+                        .graph_base_path
+                        .clone()
+                        .unwrap_or_else(|| "data/webgraph".to_string()),
+                }
             })
             .collect_vec()
             .into_par_iter()
@@ -43,8 +43,8 @@ pub async fn search(
         .search_api(&SearchQuery {
             original: query.to_string(),
             selected_region,
-            goggle_program: None,
-            site_rankings: None,
+            goggles: Vec::new(),
+            bangs: Vec::new(),
             skip_pages,
         })
         .await
@@ -21,6 +21,8 @@ use serde::Serialize;
 
 use super::State;
 
+/// Marks the part of the suggestion that was not typed. Suggestions come from page
+/// titles and query logs, so they are escaped before being used as html.
 fn highlight(query: &str, suggestion: &str) -> String {
     let idx = suggestion
         .chars()
@@ -28,9 +30,12 @@ fn highlight(query: &str, suggestion: &str) -> String {
         .position(|(suggestion_char, query_char)| suggestion_char != query_char)
         .unwrap_or(query.len());
 
-    let mut new_suggestion: String = suggestion.chars().take(idx).collect();
+    let typed: String = suggestion.chars().take(idx).collect();
+    let rest: String = suggestion.chars().skip(idx).collect();
+
+    let mut new_suggestion = html_escape::encode_text(&typed).to_string();
     new_suggestion += "<b>";
-    new_suggestion += suggestion.chars().skip(idx).collect::<String>().as_str();
+    new_suggestion += &html_escape::encode_text(&rest);
     new_suggestion += "</b>";
     new_suggestion
 }
@@ -49,7 +54,7 @@ pub async fn route(
     if let Some(query) = params.get("q") {
         let mut suggestions = Vec::new();
 
-        for suggestion in state.autosuggest.suggestions(query).unwrap() {
+        for suggestion in state.autosuggest.suggestions(query) {
             let highlighted = highlight(query, &suggestion);
             suggestions.push(Suggestion {
                 highlighted,
@@ -69,7 +74,7 @@ pub async fn browser(
     Extension(state): Extension<Arc<State>>,
 ) -> impl IntoResponse {
     if let Some(query) = params.get("q") {
-        Json((query.clone(), state.autosuggest.suggestions(query).unwrap()))
+        Json((query.clone(), state.autosuggest.suggestions(query)))
     } else {
         Json((String::new(), Vec::new()))
     }
@@ -87,4 +92,12 @@ mod tests {
         assert_eq!(&highlight("tes", "test"), "tes<b>t</b>");
         assert_eq!(&highlight("test", "test"), "test<b></b>");
     }
+
+    #[test]
+    fn suggestions_are_escaped() {
+        assert_eq!(
+            &highlight("<scr", "<script>alert(1)</script>"),
+            "&lt;scr<b>ipt&gt;alert(1)&lt;/script&gt;</b>"
+        );
+    }
 }
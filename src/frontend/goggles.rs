@@ -14,35 +14,59 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::HtmlTemplate;
+use std::sync::Arc;
+
+use super::{HtmlTemplate, State};
+use crate::goggle_registry::{RegisteredGoggle, LOCAL_PREFIX};
 use askama::Template;
-use axum::response::IntoResponse;
-
-pub const DEFAULT_GOGGLES: [GoggleLink; 2] = [
-    GoggleLink {
-        name: "Copycats removal",
-        url: "https://raw.githubusercontent.com/Cuely/sample-goggles/main/copycats_removal.goggle",
-    },
-    GoggleLink {
-        name: "Hacker News",
-        url: "https://raw.githubusercontent.com/Cuely/sample-goggles/main/hacker_news.goggle",
-    },
-];
+use axum::{
+    extract,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension,
+};
 
 #[derive(Debug, Clone)]
 pub struct GoggleLink {
-    pub name: &'static str,
-    pub url: &'static str,
+    pub name: String,
+    pub url: String,
+}
+
+impl From<&RegisteredGoggle> for GoggleLink {
+    fn from(goggle: &RegisteredGoggle) -> Self {
+        Self {
+            name: goggle.name.clone(),
+            url: goggle.url.clone(),
+        }
+    }
 }
 
 #[allow(clippy::unused_async)]
-pub async fn route() -> impl IntoResponse {
+pub async fn route(Extension(state): Extension<Arc<State>>) -> impl IntoResponse {
     let template = GogglesTemplate {
-        default_goggles: DEFAULT_GOGGLES.to_vec(),
+        default_goggles: state.goggles.local().map(GoggleLink::from).collect(),
     };
     HtmlTemplate(template)
 }
 
+/// Serves the source of a goggle from the local directory.
+#[allow(clippy::unused_async)]
+pub async fn source(
+    extract::Path(name): extract::Path<String>,
+    Extension(state): Extension<Arc<State>>,
+) -> impl IntoResponse {
+    let url = format!("{LOCAL_PREFIX}{name}");
+
+    match state.goggles.local().find(|goggle| goggle.url == url) {
+        Some(goggle) => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            goggle.parsed.source.clone(),
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
 #[derive(Template)]
 #[template(path = "settings/index.html")]
 struct GogglesTemplate {
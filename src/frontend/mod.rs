@@ -19,7 +19,11 @@ use tower_http::{compression::CompressionLayer, services::ServeDir};
 
 use crate::{
     autosuggest::Autosuggest,
+    bangs::Bangs,
+    goggle_registry::GoggleRegistry,
+    query_log::QueryLog,
     searcher::{DistributedSearcher, Shard},
+    GoggleRegistryConfig,
 };
 use anyhow::Result;
 use std::sync::Arc;
@@ -34,6 +38,7 @@ use axum::{
 mod about;
 mod api;
 mod autosuggest;
+mod bangs;
 mod goggles;
 mod index;
 mod opensearch;
@@ -46,6 +51,8 @@ pub struct HtmlTemplate<T>(T);
 pub struct State {
     pub searcher: DistributedSearcher,
     pub autosuggest: Autosuggest,
+    pub goggles: GoggleRegistry,
+    pub query_log: Option<Arc<QueryLog>>,
 }
 
 impl<T> IntoResponse for HtmlTemplate<T>
@@ -74,19 +81,28 @@ pub async fn favicon() -> impl IntoResponse {
         .unwrap()
 }
 
-pub fn router(queries_csv_path: &str, shards: Vec<Vec<String>>) -> Result<Router> {
+pub fn router(
+    queries_csv_path: &str,
+    shards: Vec<Vec<String>>,
+    goggles: GoggleRegistryConfig,
+    query_log: Option<Arc<QueryLog>>,
+    bangs_paths: &[String],
+) -> Result<Router> {
     let shards: Vec<_> = shards
         .into_iter()
         .enumerate()
         .map(|(id, replicas)| Shard::new(id as u32, replicas))
         .collect();
 
-    let autosuggest = Autosuggest::load_csv(queries_csv_path)?;
-    let searcher = DistributedSearcher::new(shards);
+    let autosuggest = Autosuggest::load(queries_csv_path)?;
+    let searcher = DistributedSearcher::new(shards, Bangs::from_paths(bangs_paths));
+    let goggles = GoggleRegistry::open(goggles)?;
 
     let state = Arc::new(State {
         searcher,
         autosuggest,
+        goggles,
+        query_log,
     });
 
     Ok(Router::new()
@@ -94,10 +110,12 @@ pub fn router(queries_csv_path: &str, shards: Vec<Vec<String>>) -> Result<Router
         .route("/search", get(search::route))
         .route("/autosuggest", get(autosuggest::route))
         .route("/autosuggest/browser", get(autosuggest::browser))
+        .route("/bangs", get(bangs::route))
         .route("/favicon.ico", get(favicon))
         .route("/about", get(about::route))
         .route("/settings", get(goggles::route))
         .route("/settings/goggles", get(goggles::route))
+        .route("/goggles/:name", get(goggles::source))
         .route("/settings/sites", get(sites::route))
         .route("/privacy-and-happy-lawyers", get(privacy::route))
         .route("/api/beta/search", get(api::search))
@@ -14,26 +14,30 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use axum::Extension;
 
 use crate::{
+    bangs::Bang,
     ranking::site_rankings::SiteRankings,
     search_prettifier::{thousand_sep_number, DisplayedEntity, DisplayedWebpage},
     searcher::{self, PrettifiedSearchResult, SearchQuery},
-    webpage::region::{Region, ALL_REGIONS},
+    webpage::region::Region,
 };
 
-use super::{
-    goggles::{GoggleLink, DEFAULT_GOGGLES},
-    HtmlTemplate, State,
-};
+use super::{goggles::GoggleLink, HtmlTemplate, State};
 use askama::Template;
 use axum::{
     extract,
+    http::{header, HeaderMap},
     response::{IntoResponse, Redirect},
 };
+use tracing::debug;
 
 #[derive(Template)]
 #[template(path = "search/index.html", escape = "none")]
@@ -52,6 +56,39 @@ struct SearchTemplate {
     current_goggle_url: Option<String>,
 }
 
+/// The address of the client. Behind a reverse proxy this is the address the proxy
+/// appended to `X-Forwarded-For`, as earlier entries can be set by the client.
+fn client_addr(peer: SocketAddr, headers: &HeaderMap, behind_proxy: bool) -> IpAddr {
+    if behind_proxy {
+        if let Some(addr) = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .last()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|addr| addr.trim().parse().ok())
+        {
+            return addr;
+        }
+    }
+
+    peer.ip()
+}
+
+const USER_BANGS_COOKIE: &str = "bangs";
+
+/// The value of the cookie called `name`, if the request has one.
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
 enum RegionSelection {
     Selected(Region),
     Unselected(Region),
@@ -63,21 +100,24 @@ pub async fn route(
     extract::Query(params): extract::Query<HashMap<String, String>>,
     Extension(state): Extension<Arc<State>>,
     extract::OriginalUri(uri): extract::OriginalUri,
+    extract::ConnectInfo(peer): extract::ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let query = params.get("q").cloned().unwrap_or_default();
 
     let skip_pages = params.get("p").and_then(|p| p.parse().ok());
 
-    let mut goggle = None;
+    let mut goggles = Vec::new();
     let mut current_goggle_url = None;
 
     if let Some(url) = params.get("goggle") {
         if !url.is_empty() {
-            if let Ok(res) = reqwest::get(url).await {
-                if let Ok(text) = res.text().await {
-                    goggle = Some(text);
+            match state.goggles.get(url).await {
+                Ok(composed) => {
+                    goggles.extend(composed);
                     current_goggle_url = Some(url.to_string());
                 }
+                Err(err) => debug!("failed to load goggle {}: {}", url, err),
             }
         }
     }
@@ -109,19 +149,40 @@ pub async fn route(
         None => None,
     };
 
+    // the user's own bangs, stored in a cookie as base64 encoded json. They are never
+    // read from the url, as a link could then redirect anyone who follows it anywhere.
+    let bangs: Vec<Bang> = cookie(&headers, USER_BANGS_COOKIE)
+        .and_then(|bangs| base64::decode(bangs).ok())
+        .and_then(|bangs| serde_json::from_slice(&bangs).ok())
+        .unwrap_or_default();
+
+    // the user's own site preferences are applied on top of the selected goggle
+    if let Some(site_rankings) = site_rankings {
+        goggles.push(site_rankings.into_goggle());
+    }
+
     match state
         .searcher
         .search_prettified(&SearchQuery {
             original: query.clone(),
             selected_region,
-            goggle_program: goggle,
+            goggles,
+            bangs,
             skip_pages,
-            site_rankings,
         })
         .await
     {
         Ok(result) => match result {
             PrettifiedSearchResult::Websites(result) => {
+                if let Some(query_log) = state.query_log.as_ref() {
+                    if skip_pages.is_none() {
+                        query_log.record(
+                            &query,
+                            client_addr(peer, &headers, query_log.behind_proxy()),
+                        );
+                    }
+                }
+
                 let entity = result.entity;
                 let spell_correction = result.spell_corrected_query;
 
@@ -130,8 +191,7 @@ pub async fn route(
                 let search_duration_sec =
                     format!("{:.2}", result.search_duration_ms as f64 / 1000.0);
 
-                let all_regions = ALL_REGIONS
-                    .into_iter()
+                let all_regions = Region::all()
                     .map(|region| {
                         if let Some(selected_region) = selected_region {
                             if region == selected_region {
@@ -181,7 +241,7 @@ pub async fn route(
                     current_page,
                     next_page_url,
                     prev_page_url,
-                    default_goggles: DEFAULT_GOGGLES.to_vec(),
+                    default_goggles: state.goggles.local().map(GoggleLink::from).collect(),
                     current_goggle_url,
                 };
 
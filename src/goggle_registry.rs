@@ -0,0 +1,931 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Goggles referenced by searches. Goggles in the local directory are loaded
+//! at startup, while remote goggles are fetched from an allow-list of hosts the
+//! first time they are used and cached afterwards. Cached goggles are
+//! revalidated with their ETag once they get old. Every goggle is parsed when
+//! it enters the registry, so invalid goggles are rejected before a search is
+//! sent to the shards. Urls serving the same source share the parsed goggle,
+//! which is keyed by the hash of its content.
+//!
+//! Goggles can build on other goggles with `! extends: <url>`, which inherits
+//! both instructions and alterations, and `! include: <url>`, which only adds
+//! the instructions. Relative urls are resolved against the url of the goggle
+//! that refers to them. A composed goggle is searched as the list of goggles it
+//! refers to, each at most once, so the instructions of one goggle never change
+//! the meaning of those of another.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use lru::LruCache;
+use reqwest::{header, redirect, StatusCode, Url};
+use thiserror::Error;
+
+use crate::{
+    ranking::goggles::{ast, CompiledGoggle, Goggle, GoggleHash},
+    GoggleRegistryConfig,
+};
+
+/// Url prefix of the goggles in the local directory.
+pub const LOCAL_PREFIX: &str = "/goggles/";
+
+const MAX_REDIRECTS: usize = 3;
+
+/// How deeply goggles can extend or include each other.
+const MAX_COMPOSITION_DEPTH: usize = 8;
+
+/// How many different goggles a goggle can refer to, directly or not.
+const MAX_COMPOSED_GOGGLES: usize = 32;
+
+/// How many instructions the goggles composed into one can have together.
+const MAX_COMPOSED_INSTRUCTIONS: usize = 2_000;
+
+/// Number of composed goggles kept, so searches using the same goggle don't
+/// compose it again.
+const COMPOSED_CACHE_SIZE: usize = 1_000;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Goggle url could not be parsed")]
+    InvalidUrl,
+
+    #[error("Goggles cannot be fetched from {0}")]
+    HostNotAllowed(String),
+
+    #[error("Goggle is larger than the allowed size")]
+    TooLarge,
+
+    #[error("Goggle host responded with status {0}")]
+    Status(StatusCode),
+
+    #[error("Failed to fetch goggle")]
+    Fetch(#[from] reqwest::Error),
+
+    #[error("Goggle is not valid UTF8")]
+    Utf8(#[from] std::string::FromUtf8Error),
+
+    #[error("Goggle could not be parsed")]
+    Invalid(#[from] crate::Error),
+
+    #[error("Goggle {0} extends or includes itself")]
+    Cycle(String),
+
+    #[error("Goggles extend or include each other too deeply")]
+    TooDeep,
+
+    #[error("Goggle refers to more than {MAX_COMPOSED_GOGGLES} goggles")]
+    TooManyGoggles,
+
+    #[error("Composed goggle has more than {MAX_COMPOSED_INSTRUCTIONS} instructions")]
+    TooManyInstructions,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub struct RegisteredGoggle {
+    pub name: String,
+    pub url: String,
+    pub parsed: Arc<ParsedGoggle>,
+}
+
+/// A goggle as parsed from its source, shared by all urls serving that source.
+#[derive(Debug)]
+pub struct ParsedGoggle {
+    name: Option<String>,
+    pub source: String,
+    /// The goggle itself, without the goggles it extends or includes.
+    pub compiled: CompiledGoggle,
+    pub extends: Option<String>,
+    pub includes: Vec<String>,
+}
+
+struct CachedGoggle {
+    goggle: Arc<RegisteredGoggle>,
+    etag: Option<String>,
+    validated_at: Instant,
+}
+
+#[derive(Default)]
+struct Cache {
+    by_url: HashMap<String, CachedGoggle>,
+    by_hash: HashMap<GoggleHash, Weak<ParsedGoggle>>,
+}
+
+impl Cache {
+    fn insert(&mut self, url: String, goggle: CachedGoggle, max_size: usize) {
+        self.by_url.insert(url, goggle);
+
+        while self.by_url.len() > max_size {
+            let oldest = self
+                .by_url
+                .iter()
+                .min_by_key(|(_, cached)| cached.validated_at)
+                .map(|(url, _)| url.clone())
+                .unwrap();
+
+            self.by_url.remove(&oldest);
+        }
+
+        // parsed goggles are kept for as long as some url refers to them
+        self.by_hash.retain(|_, goggle| goggle.strong_count() > 0);
+    }
+}
+
+/// The goggles a goggle refers to, in the order they are searched with.
+#[derive(Default)]
+struct Composition {
+    goggles: Vec<Arc<RegisteredGoggle>>,
+    urls: HashMap<String, usize>,
+    num_instructions: usize,
+}
+
+/// What the cache knows about a url.
+enum Lookup {
+    Fresh(Arc<RegisteredGoggle>),
+    Stale {
+        goggle: Option<Arc<RegisteredGoggle>>,
+        etag: Option<String>,
+    },
+}
+
+pub struct GoggleRegistry {
+    config: GoggleRegistryConfig,
+    client: reqwest::Client,
+    local: Vec<Arc<RegisteredGoggle>>,
+    cache: Mutex<Cache>,
+    composed: Mutex<LruCache<GoggleHash, Arc<Vec<CompiledGoggle>>>>,
+    /// Urls being fetched, so concurrent searches using a goggle that isn't
+    /// cached yet wait for a single fetch.
+    fetching: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl GoggleRegistry {
+    pub fn open(config: GoggleRegistryConfig) -> crate::Result<Self> {
+        let local = match &config.local_path {
+            Some(path) => load_local(Path::new(path))?,
+            None => Vec::new(),
+        };
+
+        let allowed_hosts = config.allowed_hosts.clone();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.fetch_timeout_ms))
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() > MAX_REDIRECTS
+                    || !is_allowed(attempt.url(), &allowed_hosts)
+                {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()?;
+
+        Ok(Self {
+            config,
+            client,
+            local,
+            cache: Mutex::new(Cache::default()),
+            composed: Mutex::new(LruCache::new(COMPOSED_CACHE_SIZE)),
+            fetching: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The goggles hosted by the frontend itself.
+    pub fn local(&self) -> impl Iterator<Item = &RegisteredGoggle> {
+        self.local.iter().map(|goggle| goggle.as_ref())
+    }
+
+    /// The goggle at `url` and all the goggles it extends or includes, in the
+    /// order they should be searched with. Only the goggles `url` extends,
+    /// directly or through its parents, bring their alterations along.
+    pub async fn get(&self, url: &str) -> Result<Vec<CompiledGoggle>> {
+        let mut composition = Composition::default();
+        self.compose(url.to_string(), &mut Vec::new(), &mut composition)
+            .await?;
+
+        // the goggles whose alterations are used are the chain of parents of `url`
+        let mut with_alterations = vec![false; composition.goggles.len()];
+        let mut current = Some(url.to_string());
+
+        while let Some(url) = current {
+            let idx = composition.urls[&url];
+            with_alterations[idx] = true;
+
+            current = match &composition.goggles[idx].parsed.extends {
+                Some(parent) => Some(resolve(&url, parent)?),
+                None => None,
+            };
+        }
+
+        // goggles whose alterations are dropped are hashed again, so they are
+        // cached apart from the same goggles with their alterations
+        let hash = GoggleHash::combine(composition.goggles.iter().zip(&with_alterations).map(
+            |(goggle, with_alterations)| {
+                let hash = goggle.parsed.compiled.hash;
+
+                if *with_alterations {
+                    hash
+                } else {
+                    GoggleHash::combine([hash].into_iter())
+                }
+            },
+        ));
+
+        if let Some(composed) = self.composed.lock().unwrap().get(&hash) {
+            return Ok(composed.as_ref().clone());
+        }
+
+        let composed: Vec<_> = composition
+            .goggles
+            .iter()
+            .zip(with_alterations)
+            .map(|(goggle, with_alterations)| {
+                let mut compiled = goggle.parsed.compiled.clone();
+
+                if !with_alterations {
+                    compiled.goggle.aggregator = Default::default();
+                }
+
+                compiled
+            })
+            .collect();
+
+        self.composed
+            .lock()
+            .unwrap()
+            .put(hash, Arc::new(composed.clone()));
+
+        Ok(composed)
+    }
+
+    /// Adds the goggle at `url` to the composition after the goggles it refers to.
+    /// Goggles that are already part of the composition are not visited again.
+    fn compose<'a>(
+        &'a self,
+        url: String,
+        parents: &'a mut Vec<String>,
+        composition: &'a mut Composition,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if parents.contains(&url) {
+                return Err(Error::Cycle(url));
+            }
+
+            if composition.urls.contains_key(&url) {
+                return Ok(());
+            }
+
+            if parents.len() >= MAX_COMPOSITION_DEPTH {
+                return Err(Error::TooDeep);
+            }
+
+            // every goggle visited so far is either composed or one of the parents
+            if composition.goggles.len() + parents.len() >= MAX_COMPOSED_GOGGLES {
+                return Err(Error::TooManyGoggles);
+            }
+
+            let registered = self.registered(&url).await?;
+            let parsed = Arc::clone(&registered.parsed);
+
+            parents.push(url.clone());
+
+            for include in &parsed.includes {
+                self.compose(resolve(&url, include)?, parents, composition)
+                    .await?;
+            }
+
+            if let Some(parent) = &parsed.extends {
+                self.compose(resolve(&url, parent)?, parents, composition)
+                    .await?;
+            }
+
+            parents.pop();
+
+            composition.num_instructions += parsed.compiled.goggle.instructions.len();
+            if composition.num_instructions > MAX_COMPOSED_INSTRUCTIONS {
+                return Err(Error::TooManyInstructions);
+            }
+
+            composition.urls.insert(url, composition.goggles.len());
+            composition.goggles.push(registered);
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    async fn registered(&self, url: &str) -> Result<Arc<RegisteredGoggle>> {
+        if let Some(goggle) = self.local.iter().find(|goggle| goggle.url == url) {
+            return Ok(Arc::clone(goggle));
+        }
+
+        if let Lookup::Fresh(goggle) = self.lookup(url) {
+            return Ok(goggle);
+        }
+
+        let lock = Arc::clone(
+            self.fetching
+                .lock()
+                .unwrap()
+                .entry(url.to_string())
+                .or_default(),
+        );
+
+        let res = {
+            let _guard = lock.lock().await;
+            self.fetch_and_cache(url).await
+        };
+
+        let mut fetching = self.fetching.lock().unwrap();
+        // the map and this request are the only ones left holding the lock
+        if Arc::strong_count(&lock) == 2 {
+            fetching.remove(url);
+        }
+
+        res
+    }
+
+    fn lookup(&self, url: &str) -> Lookup {
+        match self.cache.lock().unwrap().by_url.get(url) {
+            Some(cached)
+                if cached.validated_at.elapsed()
+                    < Duration::from_secs(self.config.revalidate_after_secs) =>
+            {
+                Lookup::Fresh(Arc::clone(&cached.goggle))
+            }
+            Some(cached) => Lookup::Stale {
+                goggle: Some(Arc::clone(&cached.goggle)),
+                etag: cached.etag.clone(),
+            },
+            None => Lookup::Stale {
+                goggle: None,
+                etag: None,
+            },
+        }
+    }
+
+    async fn fetch_and_cache(&self, url: &str) -> Result<Arc<RegisteredGoggle>> {
+        // another request might have fetched the goggle while this one waited
+        let (stale, etag) = match self.lookup(url) {
+            Lookup::Fresh(goggle) => return Ok(goggle),
+            Lookup::Stale { goggle, etag } => (goggle, etag),
+        };
+
+        let fetched = match self.fetch(url, etag.as_deref()).await {
+            Ok(fetched) => fetched,
+            Err(err) => {
+                // a goggle that has worked before keeps working while its host is down
+                return stale.ok_or(err);
+            }
+        };
+
+        let (goggle, etag) = match (fetched, stale) {
+            (Fetched::NotModified, Some(stale)) => (stale, etag),
+            (Fetched::NotModified, None) => return Err(Error::Status(StatusCode::NOT_MODIFIED)),
+            (Fetched::Source { source, etag }, _) => {
+                let parsed = self.parse(source)?;
+                (Arc::new(register(url.to_string(), url, parsed)), etag)
+            }
+        };
+
+        self.cache.lock().unwrap().insert(
+            url.to_string(),
+            CachedGoggle {
+                goggle: Arc::clone(&goggle),
+                etag,
+                validated_at: Instant::now(),
+            },
+            self.config.max_cached_goggles,
+        );
+
+        Ok(goggle)
+    }
+
+    /// Parses the source, unless the same source has been parsed before.
+    fn parse(&self, source: String) -> Result<Arc<ParsedGoggle>> {
+        let hash = GoggleHash::of(&source);
+
+        if let Some(parsed) = self
+            .cache
+            .lock()
+            .unwrap()
+            .by_hash
+            .get(&hash)
+            .and_then(Weak::upgrade)
+        {
+            return Ok(parsed);
+        }
+
+        let parsed = Arc::new(parse(source)?);
+
+        self.cache
+            .lock()
+            .unwrap()
+            .by_hash
+            .insert(hash, Arc::downgrade(&parsed));
+
+        Ok(parsed)
+    }
+
+    async fn fetch(&self, url: &str, etag: Option<&str>) -> Result<Fetched> {
+        let parsed = Url::parse(url).map_err(|_| Error::InvalidUrl)?;
+
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(Error::InvalidUrl);
+        }
+
+        if !is_allowed(&parsed, &self.config.allowed_hosts) {
+            return Err(Error::HostNotAllowed(
+                parsed.host_str().unwrap_or_default().to_string(),
+            ));
+        }
+
+        let mut request = self.client.get(parsed);
+
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+
+        let mut res = request.send().await?;
+
+        if res.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+
+        if !res.status().is_success() {
+            return Err(Error::Status(res.status()));
+        }
+
+        let max_bytes = self.config.max_goggle_bytes;
+
+        if res.content_length().unwrap_or(0) as usize > max_bytes {
+            return Err(Error::TooLarge);
+        }
+
+        let etag = res
+            .headers()
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(|etag| etag.to_string());
+
+        // the content length can't be trusted, so the size is also checked while reading
+        let mut bytes = Vec::new();
+        while let Some(chunk) = res.chunk().await? {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(Error::TooLarge);
+            }
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(Fetched::Source {
+            source: String::from_utf8(bytes)?,
+            etag,
+        })
+    }
+}
+
+enum Fetched {
+    NotModified,
+    Source {
+        source: String,
+        etag: Option<String>,
+    },
+}
+
+fn is_allowed(url: &Url, allowed_hosts: &[String]) -> bool {
+    url.host_str()
+        .map(|host| allowed_hosts.iter().any(|allowed| allowed == host))
+        .unwrap_or(false)
+}
+
+fn parse(source: String) -> Result<ParsedGoggle> {
+    let raw = ast::parse(&source)?;
+    let name = raw.header("name").map(|name| name.to_string());
+    let extends = raw.header("extends").map(|url| url.to_string());
+    let includes = raw.headers("include").map(|url| url.to_string()).collect();
+    let compiled = CompiledGoggle {
+        hash: GoggleHash::of(&source),
+        goggle: Goggle::try_from(raw)?,
+    };
+
+    Ok(ParsedGoggle {
+        name,
+        source,
+        compiled,
+        extends,
+        includes,
+    })
+}
+
+fn register(fallback_name: String, url: &str, parsed: Arc<ParsedGoggle>) -> RegisteredGoggle {
+    RegisteredGoggle {
+        name: parsed.name.clone().unwrap_or(fallback_name),
+        url: url.to_string(),
+        parsed,
+    }
+}
+
+/// The url of a goggle referred to from the goggle at `base`.
+fn resolve(base: &str, reference: &str) -> Result<String> {
+    if let Ok(url) = Url::parse(reference) {
+        return Ok(url.to_string());
+    }
+
+    if base.starts_with(LOCAL_PREFIX) {
+        if reference.starts_with(LOCAL_PREFIX) {
+            return Ok(reference.to_string());
+        }
+
+        // local goggles refer to each other by file name
+        return Ok(format!("{LOCAL_PREFIX}{reference}"));
+    }
+
+    Url::parse(base)
+        .and_then(|base| base.join(reference))
+        .map(|url| url.to_string())
+        .map_err(|_| Error::InvalidUrl)
+}
+
+fn load_local(path: &Path) -> crate::Result<Vec<Arc<RegisteredGoggle>>> {
+    let mut paths: Vec<_> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some("goggle".as_ref()))
+        .collect();
+    paths.sort();
+
+    let mut goggles = Vec::new();
+
+    for path in paths {
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+        let url = format!("{LOCAL_PREFIX}{file_name}");
+
+        let parsed = parse(fs::read_to_string(&path)?)
+            .map_err(|err| crate::Error::ParsingError(format!("{}: {}", path.display(), err)))?;
+
+        goggles.push(Arc::new(register(stem, &url, Arc::new(parsed))));
+    }
+
+    Ok(goggles)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{
+        http::{HeaderMap, StatusCode},
+        response::IntoResponse,
+        routing::get,
+        Router,
+    };
+
+    use crate::{
+        ranking::{
+            goggles::{Action, PatternOption},
+            Signal, SignalAggregator,
+        },
+        schema::TextField,
+        sources::serve_locally,
+    };
+
+    use super::*;
+
+    const GOGGLE: &str = "! name: Test\n$site=example.com,boost=2";
+
+    fn config(allowed_hosts: Vec<String>) -> GoggleRegistryConfig {
+        GoggleRegistryConfig {
+            local_path: None,
+            allowed_hosts,
+            revalidate_after_secs: 0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn local_goggles() {
+        let registry = GoggleRegistry::open(GoggleRegistryConfig {
+            local_path: Some("testcases/goggles".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let goggles: Vec<_> = registry
+            .local()
+            .map(|goggle| (goggle.name.as_str(), goggle.url.as_str()))
+            .collect();
+
+        assert_eq!(
+            goggles,
+            vec![
+                ("Copycat removal", "/goggles/copycats_removal.goggle"),
+                ("Hacker News / 1k short", "/goggles/hacker_news.goggle"),
+                ("Quickstart", "/goggles/quickstart.goggle"),
+            ]
+        );
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let goggles = runtime
+            .block_on(registry.get("/goggles/quickstart.goggle"))
+            .unwrap();
+        assert_eq!(goggles.len(), 1);
+        assert_eq!(goggles[0].hash, registry.local[2].parsed.compiled.hash);
+    }
+
+    #[test]
+    fn only_allowed_hosts() {
+        let registry = GoggleRegistry::open(config(vec!["example.com".to_string()])).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        assert!(matches!(
+            runtime.block_on(registry.get("http://127.0.0.1:1/a.goggle")),
+            Err(Error::HostNotAllowed(host)) if host == "127.0.0.1"
+        ));
+        assert!(matches!(
+            runtime.block_on(registry.get("file:///etc/passwd")),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(matches!(
+            runtime.block_on(registry.get("not a url")),
+            Err(Error::InvalidUrl)
+        ));
+    }
+
+    #[test]
+    fn cached_with_etag() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let addr = serve_locally(|_| {
+            let requests = Arc::clone(&requests);
+            let fetches = Arc::clone(&fetches);
+
+            Router::new()
+                .route(
+                    "/a.goggle",
+                    get(move |headers: HeaderMap| async move {
+                        requests.fetch_add(1, Ordering::SeqCst);
+
+                        if headers.get("if-none-match").is_some() {
+                            StatusCode::NOT_MODIFIED.into_response()
+                        } else {
+                            fetches.fetch_add(1, Ordering::SeqCst);
+                            ([("etag", "\"v1\"")], GOGGLE).into_response()
+                        }
+                    }),
+                )
+                .route("/b.goggle", get(|| async { GOGGLE }))
+                .route("/invalid.goggle", get(|| async { "$boost=a,b,c" }))
+                .route("/large.goggle", get(|| async { "! ".repeat(1024) }))
+        });
+
+        let registry = GoggleRegistry::open(GoggleRegistryConfig {
+            max_goggle_bytes: 1000,
+            ..config(vec!["127.0.0.1".to_string()])
+        })
+        .unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let a = runtime
+            .block_on(registry.registered(&format!("http://{addr}/a.goggle")))
+            .unwrap();
+        assert_eq!(a.name, "Test");
+
+        let again = runtime
+            .block_on(registry.registered(&format!("http://{addr}/a.goggle")))
+            .unwrap();
+        assert!(Arc::ptr_eq(&a, &again));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // same content from another url keeps its own url, but is only parsed once
+        let b = runtime
+            .block_on(registry.registered(&format!("http://{addr}/b.goggle")))
+            .unwrap();
+        assert_eq!(b.url, format!("http://{addr}/b.goggle"));
+        assert!(Arc::ptr_eq(&a.parsed, &b.parsed));
+
+        assert!(matches!(
+            runtime.block_on(registry.get(&format!("http://{addr}/invalid.goggle"))),
+            Err(Error::Invalid(_))
+        ));
+        assert!(matches!(
+            runtime.block_on(registry.get(&format!("http://{addr}/large.goggle"))),
+            Err(Error::TooLarge)
+        ));
+        assert!(matches!(
+            runtime.block_on(registry.get(&format!("http://{addr}/missing.goggle"))),
+            Err(Error::Status(StatusCode::NOT_FOUND))
+        ));
+    }
+
+    #[test]
+    fn concurrent_misses_fetch_once() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let addr = serve_locally(|_| {
+            let fetches = Arc::clone(&fetches);
+
+            Router::new().route(
+                "/slow.goggle",
+                get(move || async move {
+                    fetches.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    GOGGLE
+                }),
+            )
+        });
+
+        let registry = GoggleRegistry::open(GoggleRegistryConfig {
+            revalidate_after_secs: 60,
+            ..config(vec!["127.0.0.1".to_string()])
+        })
+        .unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let url = format!("http://{addr}/slow.goggle");
+
+        let goggles = runtime.block_on(futures::future::join_all(
+            (0..4).map(|_| registry.registered(&url)),
+        ));
+
+        assert!(goggles.iter().all(|goggle| goggle.is_ok()));
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(registry.fetching.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn composition() {
+        let addr = serve_locally(|_| {
+            Router::new()
+                .route(
+                    "/base.goggle",
+                    get(|| async {
+                        "@host_centrality = 3\n@field_title = 2\n$discard,site=base.com"
+                    }),
+                )
+                .route(
+                    "/child.goggle",
+                    get(|| async {
+                        "! extends: base.goggle\n@host_centrality = 5\n$boost=2,site=child.com"
+                    }),
+                )
+                .route(
+                    "/included.goggle",
+                    get(|| async { "! include: base.goggle\n$site=other.com" }),
+                )
+                .route(
+                    "/diamond.goggle",
+                    get(|| async {
+                        "! include: child.goggle\n! include: included.goggle\n$site=d.com"
+                    }),
+                )
+                .route(
+                    "/cycle_a.goggle",
+                    get(|| async { "! include: cycle_b.goggle\n$site=a.com" }),
+                )
+                .route(
+                    "/cycle_b.goggle",
+                    get(|| async { "! extends: cycle_a.goggle\n$site=b.com" }),
+                )
+        });
+
+        let registry = GoggleRegistry::open(config(vec!["127.0.0.1".to_string()])).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let child = runtime
+            .block_on(registry.get(&format!("http://{addr}/child.goggle")))
+            .unwrap();
+        assert_eq!(child.len(), 2);
+        assert_eq!(
+            child[0].goggle.instructions[0].options[0],
+            PatternOption::Action(Action::Discard)
+        );
+        let aggregator = aggregated(&child);
+        assert_eq!(aggregator.coefficients().get(&Signal::HostCentrality), 5.0);
+        assert_eq!(aggregator.field_boosts().get(&TextField::Title), 2.0);
+
+        // includes only bring the instructions along, and stay separate goggles
+        let included = runtime
+            .block_on(registry.get(&format!("http://{addr}/included.goggle")))
+            .unwrap();
+        assert_eq!(included.len(), 2);
+        assert_eq!(included[0].goggle.instructions.len(), 1);
+        assert_eq!(included[1].goggle.instructions.len(), 1);
+        assert_eq!(
+            aggregated(&included)
+                .coefficients()
+                .get(&Signal::HostCentrality),
+            SignalAggregator::default()
+                .coefficients()
+                .get(&Signal::HostCentrality)
+        );
+        assert_eq!(included[0].hash, child[0].hash);
+
+        // reaching the same goggle twice is fine as long as it doesn't refer to itself,
+        // but it is only searched with once
+        let diamond = runtime
+            .block_on(registry.get(&format!("http://{addr}/diamond.goggle")))
+            .unwrap();
+        assert_eq!(diamond.len(), 4);
+
+        assert!(matches!(
+            runtime.block_on(registry.get(&format!("http://{addr}/cycle_a.goggle"))),
+            Err(Error::Cycle(url)) if url.ends_with("/cycle_a.goggle")
+        ));
+    }
+
+    #[test]
+    fn composition_is_bounded() {
+        let addr = serve_locally(|_| {
+            Router::new()
+                .route(
+                    "/wide.goggle",
+                    get(|| async {
+                        (0..MAX_COMPOSED_GOGGLES)
+                            .map(|i| format!("! include: tiny/{i}\n"))
+                            .collect::<String>()
+                    }),
+                )
+                .route("/tiny/:i", get(|| async { "$site=tiny.com" }))
+                .route("/leaf/:i", get(|| async { "$site=leaf.com\n".repeat(200) }))
+                .route(
+                    "/long.goggle",
+                    get(|| async {
+                        (0..MAX_COMPOSED_GOGGLES / 2)
+                            .map(|i| format!("! include: leaf/{i}\n"))
+                            .collect::<String>()
+                    }),
+                )
+        });
+
+        let registry = GoggleRegistry::open(config(vec!["127.0.0.1".to_string()])).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        assert!(matches!(
+            runtime.block_on(registry.get(&format!("http://{addr}/wide.goggle"))),
+            Err(Error::TooManyGoggles)
+        ));
+        assert!(matches!(
+            runtime.block_on(registry.get(&format!("http://{addr}/long.goggle"))),
+            Err(Error::TooManyInstructions)
+        ));
+    }
+
+    fn aggregated(goggles: &[CompiledGoggle]) -> SignalAggregator {
+        let mut aggregator = SignalAggregator::default();
+
+        for compiled in goggles {
+            aggregator.merge(&compiled.goggle.aggregator);
+        }
+
+        aggregator
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(
+            resolve("https://example.com/goggles/a.goggle", "b.goggle").unwrap(),
+            "https://example.com/goggles/b.goggle"
+        );
+        assert_eq!(
+            resolve("https://example.com/a.goggle", "https://other.com/b.goggle").unwrap(),
+            "https://other.com/b.goggle"
+        );
+        assert_eq!(
+            resolve("/goggles/a.goggle", "b.goggle").unwrap(),
+            "/goggles/b.goggle"
+        );
+        assert_eq!(
+            resolve("/goggles/a.goggle", "/goggles/b.goggle").unwrap(),
+            "/goggles/b.goggle"
+        );
+        assert_eq!(
+            resolve("https://example.com/a.goggle", "/goggles/b.goggle").unwrap(),
+            "https://example.com/goggles/b.goggle"
+        );
+    }
+}
@@ -14,7 +14,11 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use futures::StreamExt;
-use std::{collections::HashSet, hash::Hash, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    time::Duration,
+};
 
 use serde::Serialize;
 
@@ -24,6 +28,19 @@ use crate::{
     webpage::Url,
 };
 
+/// Candidate urls kept per key. Pages rarely declare more than a handful of icons, so
+/// this leaves room for the candidates of the homepage and a few from other pages.
+const MAX_URLS_PER_KEY: usize = 10;
+
+/// The urls in order without duplicates, cut off at `MAX_URLS_PER_KEY`.
+fn dedup_urls(urls: impl Iterator<Item = Url>) -> Vec<Url> {
+    let mut seen = HashSet::new();
+
+    urls.filter(|url| seen.insert(url.clone()))
+        .take(MAX_URLS_PER_KEY)
+        .collect()
+}
+
 #[derive(Clone, Debug)]
 pub struct ImageDownloadJob<K>
 where
@@ -45,19 +62,24 @@ where
             }
 
             for duration in ExponentialBackoff::from_millis(10).take(5) {
-                if let Some(image) = url
+                match url
                     .download_bytes(self.timeout.unwrap_or_else(|| Duration::from_secs(20)))
                     .await
-                    .and_then(|bytes| Image::from_bytes(bytes).ok())
-                    .map(|image| DownloadedImage {
-                        image,
-                        key: self.key.clone(),
-                    })
                 {
-                    return Some(image);
-                }
+                    Some(bytes) => {
+                        // the server responded, so retrying won't make the image decodable.
+                        // move on to the next candidate instead.
+                        if let Ok(image) = Image::from_bytes(bytes) {
+                            return Some(DownloadedImage {
+                                image,
+                                key: self.key.clone(),
+                            });
+                        }
 
-                tokio::time::sleep(duration).await;
+                        break;
+                    }
+                    None => tokio::time::sleep(duration).await,
+                }
             }
         }
 
@@ -100,7 +122,7 @@ pub struct ImageDownloader<K>
 where
     K: std::fmt::Debug + Serialize + Hash + PartialEq + Eq + Clone,
 {
-    image_download_jobs: HashSet<ImageDownloadJob<K>>,
+    image_download_jobs: HashMap<K, ImageDownloadJob<K>>,
 }
 
 impl<K> ImageDownloader<K>
@@ -109,7 +131,7 @@ where
 {
     pub fn new() -> Self {
         Self {
-            image_download_jobs: HashSet::new(),
+            image_download_jobs: HashMap::new(),
         }
     }
     pub fn download(&mut self, store: &mut impl ImageStore<K>) {
@@ -126,7 +148,7 @@ where
         let results = futures::stream::iter(
             self.image_download_jobs
                 .drain()
-                .map(|job| async move { job.download().await }),
+                .map(|(_, job)| async move { job.download().await }),
         )
         .buffer_unordered(20)
         .collect::<Vec<Option<DownloadedImage<K>>>>()
@@ -139,7 +161,106 @@ where
         store.flush();
     }
 
+    /// Schedule a download. If a job for the same key is already scheduled, the urls
+    /// of the new job are tried after the ones already scheduled.
     pub fn schedule(&mut self, image_download_job: ImageDownloadJob<K>) {
-        self.image_download_jobs.insert(image_download_job);
+        self.merge(image_download_job, false);
+    }
+
+    /// Like [`ImageDownloader::schedule`], but the urls of the new job are tried
+    /// before the ones already scheduled for the same key.
+    pub fn schedule_preferred(&mut self, image_download_job: ImageDownloadJob<K>) {
+        self.merge(image_download_job, true);
+    }
+
+    fn merge(&mut self, mut image_download_job: ImageDownloadJob<K>, preferred: bool) {
+        match self.image_download_jobs.get_mut(&image_download_job.key) {
+            Some(existing) => {
+                let (first, rest) = if preferred {
+                    (image_download_job.urls, std::mem::take(&mut existing.urls))
+                } else {
+                    (std::mem::take(&mut existing.urls), image_download_job.urls)
+                };
+
+                existing.urls = dedup_urls(first.into_iter().chain(rest));
+            }
+            None => {
+                image_download_job.urls = dedup_urls(image_download_job.urls.into_iter());
+                self.image_download_jobs
+                    .insert(image_download_job.key.clone(), image_download_job);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(urls: &[&str]) -> ImageDownloadJob<String> {
+        ImageDownloadJob {
+            key: "example.com".to_string(),
+            urls: urls.iter().map(|url| Url::from(url.to_string())).collect(),
+            timeout: None,
+        }
+    }
+
+    fn scheduled_urls(downloader: &ImageDownloader<String>) -> Vec<String> {
+        downloader.image_download_jobs["example.com"]
+            .urls
+            .iter()
+            .map(|url| url.full())
+            .collect()
+    }
+
+    #[test]
+    fn jobs_for_same_key_are_merged() {
+        let mut downloader = ImageDownloader::new();
+
+        downloader.schedule(job(&["https://example.com/a.ico"]));
+        downloader.schedule(job(&[
+            "https://example.com/b.ico",
+            "https://example.com/a.ico",
+        ]));
+
+        assert_eq!(downloader.image_download_jobs.len(), 1);
+        assert_eq!(
+            scheduled_urls(&downloader),
+            vec![
+                "https://example.com/a.ico".to_string(),
+                "https://example.com/b.ico".to_string()
+            ]
+        );
+
+        downloader.schedule_preferred(job(&["https://example.com/c.ico"]));
+
+        assert_eq!(
+            scheduled_urls(&downloader),
+            vec![
+                "https://example.com/c.ico".to_string(),
+                "https://example.com/a.ico".to_string(),
+                "https://example.com/b.ico".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn urls_per_key_are_capped() {
+        let mut downloader = ImageDownloader::new();
+
+        for i in 0..MAX_URLS_PER_KEY * 2 {
+            downloader.schedule(job(&[&format!("https://example.com/{i}.ico")]));
+        }
+        assert_eq!(scheduled_urls(&downloader).len(), MAX_URLS_PER_KEY);
+
+        downloader.schedule_preferred(job(&[
+            "https://example.com/home.ico",
+            "https://example.com/home.ico",
+        ]));
+
+        let urls = scheduled_urls(&downloader);
+        assert_eq!(urls.len(), MAX_URLS_PER_KEY);
+        assert_eq!(urls[0], "https://example.com/home.ico");
+        assert_eq!(urls[1], "https://example.com/0.ico");
     }
 }
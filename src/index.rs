@@ -16,11 +16,13 @@
 
 use std::cmp::Ordering;
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
 use itertools::intersperse;
+use once_cell::unsync::OnceCell;
 use serde::{Deserialize, Serialize};
 use tantivy::collector::Collector;
 use tantivy::schema::Schema;
@@ -32,7 +34,8 @@ use crate::image_downloader::{ImageDownloadJob, ImageDownloader};
 use crate::image_store::{FaviconStore, Image, ImageStore, PrimaryImageStore};
 use crate::inverted_index::{self, InitialSearchResult, InvertedIndex, SearchResult};
 use crate::query::Query;
-use crate::spell::{Dictionary, LogarithmicEdit, SpellChecker, TermSplitter};
+use crate::ranking::SignalAggregator;
+use crate::spell::{LanguageModel, LogarithmicEdit, QueryCorrector, TermSplitter};
 use crate::subdomain_count::SubdomainCounter;
 use crate::webpage::region::{Region, RegionCount};
 use crate::webpage::{Url, Webpage};
@@ -41,18 +44,23 @@ use crate::Result;
 const INVERTED_INDEX_SUBFOLDER_NAME: &str = "inverted_index";
 const FAVICON_STORE_SUBFOLDER_NAME: &str = "favicon_store";
 const PRIMARY_IMAGE_STORE_SUBFOLDER_NAME: &str = "primary_image_store";
-const SPELL_SUBFOLDER_NAME: &str = "primary_image_store";
+const SPELL_SUBFOLDER_NAME: &str = "spell";
 const REGION_COUNT_FILE_NAME: &str = "region_count.json";
 const SUBDOMAIN_COUNT_SUBFOLDER_NAME: &str = "subdomain_count";
 const IMAGE_WEBPAGE_CENTRALITY_THRESHOLD: f64 = 0.0;
 
+pub type SpellModel = LanguageModel<100_000, 1_000_000>;
+
+/// A correction is only suggested if it has this many times the hits of the original query.
+const MIN_CORRECTION_HIT_RATIO: usize = 2;
+
 pub struct Index {
     pub inverted_index: InvertedIndex,
     favicon_store: FaviconStore,
     primary_image_store: PrimaryImageStore,
     favicon_downloader: ImageDownloader<String>,
     primary_image_downloader: ImageDownloader<Uuid>,
-    spell_dictionary: Dictionary<100_000>,
+    spell_model: SpellModel,
     pub region_count: RegionCount,
     pub subdomain_counter: SubdomainCounter,
     pub path: String,
@@ -64,6 +72,8 @@ impl Index {
             fs::create_dir_all(path.as_ref())?;
         }
 
+        Self::migrate_spell_model(path.as_ref())?;
+
         let favicon_store = FaviconStore::open(path.as_ref().join(FAVICON_STORE_SUBFOLDER_NAME));
         let primary_image_store =
             PrimaryImageStore::open(path.as_ref().join(PRIMARY_IMAGE_STORE_SUBFOLDER_NAME));
@@ -71,7 +81,7 @@ impl Index {
         let inverted_index =
             InvertedIndex::open(path.as_ref().join(INVERTED_INDEX_SUBFOLDER_NAME))?;
 
-        let region_count = RegionCount::open(path.as_ref().join(REGION_COUNT_FILE_NAME));
+        let region_count = RegionCount::open(path.as_ref().join(REGION_COUNT_FILE_NAME))?;
 
         Ok(Self {
             inverted_index,
@@ -80,7 +90,7 @@ impl Index {
             region_count,
             primary_image_downloader: ImageDownloader::new(),
             favicon_downloader: ImageDownloader::new(),
-            spell_dictionary: Dictionary::open(Some(path.as_ref().join(SPELL_SUBFOLDER_NAME)))?,
+            spell_model: SpellModel::open(Some(Self::spell_path(path.as_ref())))?,
             subdomain_counter: SubdomainCounter::open(
                 path.as_ref().join(SUBDOMAIN_COUNT_SUBFOLDER_NAME),
             ),
@@ -88,6 +98,37 @@ impl Index {
         })
     }
 
+    pub fn spell_path<P: AsRef<Path>>(path: P) -> PathBuf {
+        path.as_ref().join(SPELL_SUBFOLDER_NAME)
+    }
+
+    /// Use the spell model at `path`, e.g. one built with `cuely spell build`, instead of
+    /// the one built while indexing.
+    pub fn use_spell_model<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        if !path.as_ref().exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no spell model at {}", path.as_ref().display()),
+            )
+            .into());
+        }
+
+        self.spell_model = SpellModel::open(Some(path))?;
+
+        Ok(())
+    }
+
+    /// Older indexes kept the spell model in the primary image store folder.
+    fn migrate_spell_model(path: &Path) -> Result<()> {
+        let spell_path = Self::spell_path(path);
+
+        if !spell_path.exists() {
+            SpellModel::relocate(path.join(PRIMARY_IMAGE_STORE_SUBFOLDER_NAME), spell_path)?;
+        }
+
+        Ok(())
+    }
+
     pub fn tokenizers(&self) -> &TokenizerManager {
         self.inverted_index.tokenizers()
     }
@@ -101,7 +142,7 @@ impl Index {
     pub fn insert(&mut self, mut webpage: Webpage) -> Result<()> {
         self.maybe_insert_favicon(&webpage);
         self.maybe_insert_primary_image(&mut webpage);
-        self.spell_dictionary.insert_page(&webpage);
+        self.spell_model.insert_page(&webpage)?;
         self.subdomain_counter.increment(webpage.html.url().clone());
 
         if let Ok(region) = Region::guess_from(&webpage) {
@@ -112,7 +153,7 @@ impl Index {
     }
 
     pub fn commit(&mut self) -> Result<()> {
-        self.spell_dictionary.commit()?;
+        self.spell_model.commit()?;
         self.inverted_index.commit()?;
         self.region_count.commit();
         self.subdomain_counter.commit();
@@ -150,7 +191,7 @@ impl Index {
         self.primary_image_store.merge(other.primary_image_store);
         drop(self.primary_image_store);
 
-        self.spell_dictionary.merge(other.spell_dictionary);
+        self.spell_model.merge(other.spell_model);
 
         self.region_count.merge(other.region_count);
 
@@ -161,22 +202,36 @@ impl Index {
     }
 
     fn maybe_insert_favicon(&mut self, webpage: &Webpage) {
-        if !webpage.html.url().is_homepage()
-            || self
-                .favicon_store
-                .contains(&webpage.html.url().domain().to_string())
-        {
+        let key = webpage.html.url().domain().to_string();
+
+        if self.favicon_store.contains(&key) {
             return;
         }
 
-        if let Some(favicon) = webpage.html.favicon() {
-            if favicon.link.is_valid_uri() {
-                self.favicon_downloader.schedule(ImageDownloadJob {
-                    key: favicon.link.domain().to_string(),
-                    urls: vec![favicon.link],
-                    timeout: Some(Duration::from_secs(1)),
-                });
-            }
+        let urls: Vec<_> = webpage
+            .html
+            .favicons()
+            .into_iter()
+            .map(|favicon| favicon.link)
+            .filter(|link| link.is_valid_uri())
+            .collect();
+
+        if urls.is_empty() {
+            return;
+        }
+
+        let job = ImageDownloadJob {
+            key,
+            urls,
+            timeout: Some(Duration::from_secs(1)),
+        };
+
+        // the favicon declared on the homepage is the one that represents the site,
+        // so its candidates are tried before the ones found on other pages.
+        if webpage.html.url().is_homepage() {
+            self.favicon_downloader.schedule_preferred(job);
+        } else {
+            self.favicon_downloader.schedule(job);
         }
     }
 
@@ -223,34 +278,17 @@ impl Index {
     }
 
     fn spell_check(&self, terms: &[String]) -> Option<String> {
-        let spellchecker = SpellChecker::new(&self.spell_dictionary, LogarithmicEdit::new(4));
-        let mut corrections: Vec<String> = Vec::new();
-
-        for term in terms {
-            match spellchecker.correct(term.to_ascii_lowercase().as_str()) {
-                Some(correction) => corrections.push(correction.to_ascii_lowercase()),
-                None => corrections.push(term.to_ascii_lowercase()),
-            }
-        }
-
-        if corrections
-            .iter()
-            .cloned()
-            .zip(terms.iter().map(|term| term.to_ascii_lowercase()))
-            .all(|(correction, term)| correction == term)
-        {
-            None
-        } else {
-            Some(intersperse(corrections.into_iter(), " ".to_string()).collect())
-        }
+        QueryCorrector::new(&self.spell_model, LogarithmicEdit::new(4))
+            .correct(terms)
+            .map(|corrections| corrections.join(" "))
     }
 
     fn split_words(&self, terms: &[String]) -> Option<String> {
-        let splitter = TermSplitter::new(&self.spell_dictionary);
+        let splitter = TermSplitter::new(self.spell_model.unigrams());
         let mut corrections: Vec<String> = Vec::new();
 
         for term in terms {
-            let t = term.to_ascii_lowercase();
+            let t = term.to_lowercase();
             let split = splitter.split(t.as_str());
             if split.is_empty() {
                 corrections.push(t);
@@ -263,8 +301,8 @@ impl Index {
 
         if corrections
             .iter()
-            .map(|s| s.to_ascii_lowercase())
-            .zip(terms.iter().map(|term| term.to_ascii_lowercase()))
+            .map(|s| s.to_lowercase())
+            .zip(terms.iter().map(|term| term.to_lowercase()))
             .all(|(correction, term)| correction == term)
         {
             None
@@ -273,8 +311,36 @@ impl Index {
         }
     }
 
+    fn num_hits(&self, query: &str) -> Result<usize> {
+        let query = Query::parse(
+            query,
+            self.schema(),
+            self.tokenizers(),
+            &SignalAggregator::default(),
+        )?;
+
+        self.inverted_index.count(&query)
+    }
+
     pub fn spell_correction(&self, terms: &[String]) -> Option<String> {
-        self.spell_check(terms).or_else(|| self.split_words(terms))
+        // most queries have no correction, so the hits of the original query are
+        // only counted once there is a correction to compare with.
+        let original_hits = OnceCell::new();
+        let has_more_hits = |correction: &String| {
+            let original_hits =
+                match original_hits.get_or_try_init(|| self.num_hits(&terms.join(" "))) {
+                    Ok(hits) => *hits,
+                    Err(_) => return false,
+                };
+
+            self.num_hits(correction)
+                .map(|hits| hits > original_hits * MIN_CORRECTION_HIT_RATIO)
+                .unwrap_or(false)
+        };
+
+        self.spell_check(terms)
+            .filter(has_more_hits)
+            .or_else(|| self.split_words(terms).filter(has_more_hits))
     }
 
     pub fn num_segments(&self) -> usize {
@@ -418,4 +484,83 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn only_suggest_corrections_with_more_hits() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        // "yorkk" only appears in titles, so it is unknown to the language model
+        // but the query still has hits.
+        for (title, body, url) in [
+            ("News", "the new york times", "https://www.a.com"),
+            ("News", "the new york times", "https://www.b.com"),
+            ("News", "the new york times", "https://www.c.com"),
+            ("Yorkk", "welcome", "https://www.d.com"),
+            ("Yorkk", "welcome", "https://www.e.com"),
+        ] {
+            index
+                .insert(Webpage::new(
+                    &format!(
+                        r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    {body}
+                </body>
+            </html>
+            "#
+                    ),
+                    url,
+                ))
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        assert_eq!(
+            index.spell_correction(&["new".to_string(), "yrok".to_string()]),
+            Some("new york".to_string())
+        );
+        assert_eq!(index.spell_correction(&["yorkk".to_string()]), None);
+    }
+
+    #[test]
+    fn migrate_spell_model() {
+        let path = crate::gen_temp_path();
+
+        let mut legacy =
+            SpellModel::open(Some(path.join(PRIMARY_IMAGE_STORE_SUBFOLDER_NAME))).unwrap();
+        legacy.insert_text(CONTENT).unwrap();
+        legacy.commit().unwrap();
+        drop(legacy);
+
+        let index = Index::open(&path).expect("Unable to open index");
+
+        assert!(index.spell_model.unigrams().contains("this"));
+        assert!(Index::spell_path(&path).join("bigrams").exists());
+        assert!(!path
+            .join(PRIMARY_IMAGE_STORE_SUBFOLDER_NAME)
+            .join("bigrams")
+            .exists());
+    }
+
+    #[test]
+    fn use_spell_model() {
+        let spell_path = crate::gen_temp_path();
+
+        let mut model = SpellModel::open(Some(&spell_path)).unwrap();
+        model.insert_text("standalone").unwrap();
+        model.commit().unwrap();
+        drop(model);
+
+        let mut index = Index::temporary().expect("Unable to open index");
+        assert!(!index.spell_model.unigrams().contains("standalone"));
+
+        index.use_spell_model(&spell_path).unwrap();
+        assert!(index.spell_model.unigrams().contains("standalone"));
+
+        assert!(index.use_spell_model(crate::gen_temp_path()).is_err());
+    }
 }
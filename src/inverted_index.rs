@@ -22,18 +22,21 @@ use tantivy::merge_policy::NoMergePolicy;
 use tantivy::schema::Schema;
 use tantivy::tokenizer::TokenizerManager;
 use tantivy::{Document, IndexReader, IndexWriter, SegmentMeta};
+use whatlang::Lang;
 
 use crate::collector::Hashes;
 use crate::fastfield_cache::FastFieldCache;
 use crate::image_store::Image;
 use crate::query::Query;
 use crate::schema::{FastField, Field, TextField, ALL_FIELDS};
+use crate::schema_org::SchemaOrg;
 use crate::snippet;
 use crate::tokenizer::Identity;
-use crate::webpage::region::Region;
+use crate::webpage::region::{self, Region};
 use crate::webpage::{StoredPrimaryImage, Webpage};
 use crate::Result;
 use crate::{schema::create_schema, tokenizer::Tokenizer};
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use std::sync::{Arc, Weak};
@@ -169,6 +172,17 @@ impl InvertedIndex {
         Ok(())
     }
 
+    /// Deletes the pages with exactly this simhash that were inserted before.
+    pub fn delete_simhash(&mut self, simhash: u64) {
+        let field = self
+            .schema
+            .get_field(Field::Fast(FastField::SimHash).name())
+            .unwrap();
+
+        self.writer
+            .delete_term(tantivy::Term::from_field_u64(field, simhash));
+    }
+
     pub fn commit(&mut self) -> Result<()> {
         self.writer.commit()?;
         self.reader.reload()?;
@@ -189,6 +203,12 @@ impl InvertedIndex {
         })
     }
 
+    pub fn count(&self, query: &Query) -> Result<usize> {
+        let searcher = self.reader.searcher();
+
+        Ok(searcher.search(query, &Count)?)
+    }
+
     pub fn retrieve_websites(
         &self,
         websites: &[WebsitePointer],
@@ -223,7 +243,7 @@ impl InvertedIndex {
                 &page.body,
                 &page.dirty_body,
                 &page.description,
-                &page.region,
+                page.lang.as_ref(),
                 &searcher,
             )?;
         }
@@ -296,6 +316,82 @@ impl InvertedIndex {
         Ok(())
     }
 
+    /// Calls `f` with every webpage in the index.
+    pub fn for_each_webpage<F: FnMut(&RetrievedWebpage)>(&self, mut f: F) -> Result<()> {
+        let searcher = self.reader.searcher();
+
+        for (segment, reader) in searcher.segment_readers().iter().enumerate() {
+            for doc_id in 0..reader.max_doc() {
+                if reader.is_deleted(doc_id) {
+                    continue;
+                }
+
+                let address = DocAddress {
+                    segment: segment as u32,
+                    doc_id,
+                };
+                let webpage = self.retrieve_doc(address, &searcher)?;
+
+                f(&webpage);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Calls `f` with the title of every page, but only once for each distinct title
+    /// on a site. Repeated titles are recognised from the hash fast fields, so only
+    /// the stored documents of the first page with each title are loaded.
+    pub fn for_each_site_title<F: FnMut(&str)>(&self, mut f: F) -> Result<()> {
+        let searcher = self.reader.searcher();
+        let schema = searcher.schema();
+        let title_field = schema
+            .get_field(Field::Text(TextField::Title).name())
+            .unwrap();
+        let site_field = schema
+            .get_field(Field::Fast(FastField::SiteHash).name())
+            .unwrap();
+        let title_hash_field = schema
+            .get_field(Field::Fast(FastField::TitleHash).name())
+            .unwrap();
+
+        let mut seen = HashSet::new();
+
+        for (segment, reader) in searcher.segment_readers().iter().enumerate() {
+            let fast_fields = reader.fast_fields();
+            let site_hashes = fast_fields.u64s(site_field)?;
+            let title_hashes = fast_fields.u64s(title_hash_field)?;
+
+            for doc_id in 0..reader.max_doc() {
+                if reader.is_deleted(doc_id) {
+                    continue;
+                }
+
+                let mut hashes = Vec::with_capacity(4);
+                site_hashes.get_vals(doc_id, &mut hashes);
+                let mut title_hash = Vec::with_capacity(2);
+                title_hashes.get_vals(doc_id, &mut title_hash);
+                hashes.extend(title_hash);
+
+                if !seen.insert(hashes) {
+                    continue;
+                }
+
+                let address = DocAddress {
+                    segment: segment as u32,
+                    doc_id,
+                };
+                let doc = searcher.doc(address.into())?;
+
+                if let Some(title) = doc.get_first(title_field).and_then(|title| title.as_text()) {
+                    f(title);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn retrieve_doc(
         &self,
         doc_address: DocAddress,
@@ -385,6 +481,9 @@ pub struct RetrievedWebpage {
     pub primary_image: Option<StoredPrimaryImage>,
     pub updated_time: Option<NaiveDateTime>,
     pub region: Region,
+    #[serde(skip)]
+    pub lang: Option<Lang>,
+    pub schema_org: Vec<SchemaOrg>,
 }
 
 impl From<Document> for RetrievedWebpage {
@@ -433,6 +532,16 @@ impl From<Document> for RetrievedWebpage {
                         bincode::deserialize(bytes).unwrap()
                     }
                 }
+                Field::Text(TextField::SchemaOrg) => {
+                    webpage.schema_org = {
+                        let bytes = value
+                            .value
+                            .as_bytes()
+                            .expect("Schema.org field should be bytes");
+
+                        bincode::deserialize(bytes).unwrap_or_default()
+                    }
+                }
                 Field::Fast(FastField::LastUpdated) => {
                     webpage.updated_time = {
                         let timestamp = value.value.as_u64().unwrap() as i64;
@@ -456,6 +565,9 @@ impl From<Document> for RetrievedWebpage {
                         Region::from_id(id)
                     }
                 }
+                Field::Fast(FastField::Lang) => {
+                    webpage.lang = region::lang_from_id(value.value.as_u64().unwrap());
+                }
                 _ => {}
             }
         }
@@ -470,7 +582,7 @@ mod tests {
 
     use crate::{
         ranking::{Ranker, SignalAggregator},
-        webpage::{region::RegionCount, Html, Link},
+        webpage::{region::RegionCount, Backlink, Html, Link, LinkPosition, LinkRel},
     };
 
     use super::*;
@@ -701,10 +813,16 @@ mod tests {
                     ),
                     "https://www.b.com",
                 ),
-                backlinks: vec![Link {
-                    source: "https://www.a.com".to_string().into(),
-                    destination: "https://www.b.com".to_string().into(),
-                    text: "B site is great".to_string(),
+                backlinks: vec![Backlink {
+                    link: Link {
+                        source: "https://www.a.com".to_string().into(),
+                        destination: "https://www.b.com".to_string().into(),
+                        text: "B site is great".to_string(),
+                        rel: LinkRel::default(),
+                        position: LinkPosition::Content,
+                        context: String::new(),
+                    },
+                    source_centrality: 0.0,
                 }],
                 host_centrality: 1.0,
                 page_centrality: 0.0,
@@ -1053,4 +1171,55 @@ mod tests {
         assert_eq!(result.documents.len(), 1);
         assert_eq!(result.documents[0].url, "https://www.example.com");
     }
+
+    #[test]
+    fn delete_simhash() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+        let query = Query::parse(
+            "website",
+            index.schema(),
+            index.tokenizers(),
+            &SignalAggregator::default(),
+        )
+        .expect("Failed to parse query");
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        let page = |url: &str| {
+            Webpage::new(
+                &format!(
+                    r#"
+                        <html>
+                            <head>
+                                <title>Test website</title>
+                            </head>
+                            <body>
+                                {CONTENT}
+                            </body>
+                        </html>
+                    "#
+                ),
+                url,
+            )
+        };
+
+        let copy = page("https://copy.example.com");
+        let simhash = copy.html.simhash();
+        index.insert(copy).expect("failed to insert webpage");
+
+        index.delete_simhash(simhash);
+        index
+            .insert(page("https://www.example.com"))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let result = index
+            .search(&query, ranker.collector())
+            .expect("Search failed");
+        assert_eq!(result.num_docs, 1);
+        assert_eq!(result.documents[0].url, "https://www.example.com");
+    }
 }
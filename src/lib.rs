@@ -20,12 +20,14 @@
 #![allow(clippy::missing_errors_doc)]
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::num::ParseIntError;
 use std::path::PathBuf;
 use tantivy::TantivyError;
 use thiserror::Error;
+use webpage::{ExtractionStrategy, JustText};
 
 pub mod entrypoint;
 mod inverted_index;
@@ -35,27 +37,33 @@ pub mod mapreduce;
 mod autosuggest;
 mod bangs;
 mod collector;
+mod crawler;
 mod directory;
 mod entity_index;
 mod exponential_backoff;
 mod fastfield_cache;
 mod frontend;
+mod goggle_registry;
 mod image_downloader;
 mod image_store;
 pub mod index;
 mod kv;
 pub mod prehashed;
 mod query;
+mod query_log;
 mod ranking;
 mod schema;
 mod schema_org;
 mod search_prettifier;
 pub mod searcher;
+mod simhash;
 mod snippet;
 mod sonic;
+mod sources;
 mod spell;
 mod subdomain_count;
 mod tokenizer;
+mod trackers;
 #[allow(unused)]
 mod ttl_cache;
 mod warc;
@@ -72,6 +80,10 @@ pub struct IndexingMasterConfig {
     download_images: Option<bool>,
     host_centrality_threshold: Option<f64>,
     index_base_path: Option<String>,
+    drop_near_duplicates: Option<bool>,
+    tracker_list_path: Option<String>,
+    extraction: Option<ExtractionConfig>,
+    html_limits: Option<HtmlLimits>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -85,6 +97,46 @@ pub struct IndexingLocalConfig {
     download_images: Option<bool>,
     host_centrality_threshold: Option<f64>,
     centrality_store_path: String,
+    drop_near_duplicates: Option<bool>,
+    tracker_list_path: Option<String>,
+    extraction: Option<ExtractionConfig>,
+    html_limits: Option<HtmlLimits>,
+}
+
+/// How the main content of a page is found. A matching rule from `site_rules_path`
+/// takes precedence over `default_strategy`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ExtractionConfig {
+    #[serde(default)]
+    default_strategy: ExtractionStrategy,
+    site_rules_path: Option<String>,
+    #[serde(default)]
+    just_text: JustText,
+}
+
+/// Limits on how much of each page is parsed and indexed. Pages that exceed them are truncated.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct HtmlLimits {
+    /// Bytes of the response body that are parsed.
+    max_body_bytes: usize,
+    /// Elements nested deeper than this are dropped.
+    max_dom_depth: usize,
+    /// Bytes of text indexed per field.
+    max_field_bytes: usize,
+    /// Links extracted per page.
+    max_links: usize,
+}
+
+impl Default for HtmlLimits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: 5_000_000,
+            max_dom_depth: 256,
+            max_field_bytes: 500_000,
+            max_links: 5_000,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -109,12 +161,16 @@ pub struct WebgraphLocalConfig {
 pub enum WarcSource {
     HTTP(HttpConfig),
     Local(LocalConfig),
+    Directory(DirectoryConfig),
+    Sitemap(SitemapConfig),
 }
 
 impl WarcSource {
-    pub fn paths(&self) -> Result<Vec<String>> {
+    /// The names of the warc files. Sitemaps are fetched here, and the page urls of
+    /// each warc are kept in the source so they can be sent along with the jobs.
+    pub fn paths(&mut self) -> Result<Vec<String>> {
         let mut warc_paths = Vec::new();
-        match &self {
+        match self {
             WarcSource::HTTP(config) => {
                 let file = File::open(&config.warc_paths_file)?;
                 for line in io::BufReader::new(file).lines() {
@@ -124,10 +180,26 @@ impl WarcSource {
             WarcSource::Local(config) => {
                 warc_paths = config.names.clone();
             }
+            WarcSource::Directory(config) => {
+                warc_paths = sources::directory::warc_names(config)?;
+            }
+            WarcSource::Sitemap(config) => {
+                warc_paths = sources::sitemap::resolve(config)?;
+            }
         }
 
         Ok(warc_paths)
     }
+
+    /// The source a job needs to download `warc_paths`.
+    pub fn for_warcs(&self, warc_paths: &[String]) -> WarcSource {
+        match self {
+            WarcSource::Sitemap(config) => {
+                WarcSource::Sitemap(sources::sitemap::for_warcs(config, warc_paths))
+            }
+            source => source.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -142,18 +214,137 @@ pub struct HttpConfig {
     warc_paths_file: String,
 }
 
+/// Index a directory tree of documents as if they were served from `base_url`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DirectoryConfig {
+    folder: String,
+    base_url: String,
+    /// Regex replacements applied in order to the path of each file (relative to `folder`)
+    /// before it is joined with `base_url`.
+    #[serde(default)]
+    url_rules: Vec<UrlRule>,
+    files_per_warc: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UrlRule {
+    pattern: String,
+    replacement: String,
+}
+
+/// Fetch every page listed in the sitemaps (sitemap indexes are followed).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SitemapConfig {
+    sitemaps: Vec<String>,
+    urls_per_warc: Option<usize>,
+    max_urls: Option<usize>,
+    /// The page urls of each warc. Filled in when the warc names are listed, so the
+    /// sitemaps are only fetched once.
+    #[serde(default)]
+    pages: BTreeMap<String, Vec<String>>,
+}
+
+/// Seeds are the explicitly listed urls together with the homepages of the
+/// `num_seed_hosts` most central hosts in the centrality store (if any).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrawlerConfig {
+    #[serde(default)]
+    seeds: Vec<String>,
+    centrality_store_path: Option<String>,
+    num_seed_hosts: Option<usize>,
+    output_path: Option<String>,
+    max_pages: Option<usize>,
+    max_pages_per_host: Option<usize>,
+    min_crawl_delay_ms: Option<u64>,
+    max_crawl_delay_ms: Option<u64>,
+    pages_per_warc: Option<usize>,
+    max_concurrent_hosts: Option<usize>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FrontendConfig {
     pub queries_csv_path: String,
     pub host: String,
     pub search_servers: Vec<Vec<String>>,
+    pub goggles: Option<GoggleRegistryConfig>,
+    pub query_log: Option<QueryLogConfig>,
+    pub bangs_path: Option<String>,
+    /// Bangs that take precedence over the bangs in `bangs_path`. Earlier files
+    /// take precedence over later ones.
+    #[serde(default)]
+    pub custom_bangs_paths: Vec<String>,
+}
+
+/// Opt-in aggregation of what people search for. A query is only written to the daily
+/// csv if it was searched from enough different sessions that day, and neither
+/// addresses nor any other identifiers are stored.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct QueryLogConfig {
+    /// Directory the daily csv files are written to.
+    output_path: String,
+    /// Queries searched from fewer sessions than this on a day are dropped.
+    min_sessions_per_day: usize,
+    /// Queries not seen before are ignored once this many have been seen on a day.
+    max_queries_per_day: usize,
+    /// Use the address a reverse proxy appended to `X-Forwarded-For` instead of the
+    /// address of the connection.
+    behind_proxy: bool,
+    /// How often the counts of the current day are written, so they survive a crash.
+    flush_interval_secs: u64,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            output_path: "query_logs".to_string(),
+            min_sessions_per_day: 10,
+            max_queries_per_day: 1_000_000,
+            behind_proxy: false,
+            flush_interval_secs: 600,
+        }
+    }
+}
+
+/// Where goggles are loaded from, and how goggles hosted elsewhere are fetched.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GoggleRegistryConfig {
+    /// Directory of `.goggle` files served by the frontend itself. No goggles are
+    /// served locally unless it is set.
+    local_path: Option<String>,
+    /// Hosts that remote goggles may be fetched from.
+    allowed_hosts: Vec<String>,
+    fetch_timeout_ms: u64,
+    max_goggle_bytes: usize,
+    /// Cached goggles are revalidated with their host once they are this old.
+    revalidate_after_secs: u64,
+    max_cached_goggles: usize,
+}
+
+impl Default for GoggleRegistryConfig {
+    fn default() -> Self {
+        Self {
+            local_path: None,
+            allowed_hosts: vec![
+                "raw.githubusercontent.com".to_string(),
+                "gist.githubusercontent.com".to_string(),
+            ],
+            fetch_timeout_ms: 2_000,
+            max_goggle_bytes: 256 * 1024,
+            revalidate_after_secs: 10 * 60,
+            max_cached_goggles: 1_000,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SearchServerConfig {
     pub index_path: String,
     pub entity_index_path: Option<String>,
-    pub bangs_path: Option<String>,
+    /// Spell model built with `cuely spell build`. The model built while indexing is
+    /// used when this is not set.
+    pub spell_path: Option<String>,
     pub host: String,
 }
 
@@ -186,6 +377,9 @@ pub enum Error {
     #[error("Error while serializing/deserializing to/from bytes")]
     Serialization(#[from] bincode::Error),
 
+    #[error("Error while serializing/deserializing to/from json")]
+    Json(#[from] serde_json::Error),
+
     #[error("Error executing distributed jobs")]
     MapReduce(#[from] mapreduce::Error),
 
@@ -219,8 +413,29 @@ pub enum Error {
     #[error("String is not float")]
     ParseFloat(#[from] std::num::ParseFloatError),
 
+    #[error("Invalid regex")]
+    Regex(#[from] regex::Error),
+
+    #[error("Unknown warc name for source")]
+    UnknownWarcName(String),
+
+    #[error("Thread resolving the sitemaps panicked")]
+    SitemapThread,
+
+    #[error("{0} has already been configured with different settings")]
+    AlreadyConfigured(&'static str),
+
+    #[error("Invalid config: {0}")]
+    InvalidConfig(&'static str),
+
+    #[error("Response is larger than {0} bytes")]
+    ResponseTooLarge(usize),
+
     #[error("Could not open inverted-index directory")]
     Directory(#[from] tantivy::directory::error::OpenDirectoryError),
+
+    #[error("Entity index at {0} was built by an older version and must be rebuilt")]
+    OutdatedEntityIndex(String),
 }
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
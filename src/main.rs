@@ -51,6 +51,49 @@ enum Commands {
     Frontend {
         config_path: String,
     },
+    Crawler {
+        config_path: String,
+    },
+    Spell {
+        #[clap(subcommand)]
+        options: SpellOptions,
+    },
+    Autosuggest {
+        #[clap(subcommand)]
+        options: AutosuggestOptions,
+    },
+}
+
+#[derive(Subcommand)]
+enum AutosuggestOptions {
+    Build {
+        #[clap(long)]
+        index: Vec<String>,
+        #[clap(long)]
+        entity_index: Option<String>,
+        #[clap(long)]
+        query_log: Option<String>,
+        output_path: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SpellOptions {
+    Build {
+        #[clap(
+            long,
+            conflicts_with = "frequencies",
+            required_unless_present = "frequencies"
+        )]
+        index: Option<String>,
+        #[clap(long)]
+        frequencies: Option<String>,
+        output_path: String,
+    },
+    Inspect {
+        model_path: String,
+        terms: Vec<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -133,6 +176,11 @@ fn main() -> Result<()> {
         },
         Commands::Frontend { config_path } => {
             let config: FrontendConfig = load_toml_config(&config_path);
+            let bangs_paths: Vec<_> = config
+                .custom_bangs_paths
+                .into_iter()
+                .chain(config.bangs_path)
+                .collect();
 
             tokio::runtime::Builder::new_multi_thread()
                 .enable_all()
@@ -141,6 +189,9 @@ fn main() -> Result<()> {
                     &config.queries_csv_path,
                     &config.host,
                     config.search_servers,
+                    config.goggles.unwrap_or_default(),
+                    config.query_log,
+                    &bangs_paths,
                 ))?
         }
         Commands::SearchServer { config_path } => {
@@ -151,6 +202,36 @@ fn main() -> Result<()> {
                 .build()?
                 .block_on(search_server::run(config))?
         }
+        Commands::Crawler { config_path } => {
+            let config = load_toml_config(&config_path);
+            entrypoint::Crawler::run(&config)?;
+        }
+        Commands::Spell { options } => match options {
+            SpellOptions::Build {
+                index,
+                frequencies,
+                output_path,
+            } => match (index, frequencies) {
+                (Some(index_path), _) => {
+                    entrypoint::Spell::build_from_index(index_path, output_path)?
+                }
+                (None, Some(frequencies_path)) => {
+                    entrypoint::Spell::build_from_frequencies(frequencies_path, output_path)?
+                }
+                (None, None) => unreachable!("clap requires either an index or a frequency file"),
+            },
+            SpellOptions::Inspect { model_path, terms } => {
+                entrypoint::Spell::inspect(model_path, &terms)?
+            }
+        },
+        Commands::Autosuggest { options } => match options {
+            AutosuggestOptions::Build {
+                index,
+                entity_index,
+                query_log,
+                output_path,
+            } => entrypoint::Autosuggest::build(&index, entity_index, query_log, output_path)?,
+        },
     }
 
     Ok(())
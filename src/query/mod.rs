@@ -15,7 +15,6 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
-    ranking::goggles::Goggle,
     ranking::SignalAggregator,
     schema::{Field, TextField},
     Result,
@@ -150,12 +149,13 @@ impl Query {
         })
     }
 
-    pub fn set_goggles(&mut self, goggles: &[Goggle], schema: &Schema) {
+    /// Combines the query with the queries built from the goggles of the search.
+    pub fn add_goggle_queries(
+        &mut self,
+        mut queries: Vec<(Occur, Box<dyn tantivy::query::Query>)>,
+    ) {
         let mut subqueries = vec![(Occur::Must, self.tantivy_query.box_clone())];
-
-        for goggle in goggles {
-            subqueries.append(&mut goggle.as_tantivy(schema));
-        }
+        subqueries.append(&mut queries);
 
         self.tantivy_query = Box::new(BooleanQuery::new(subqueries))
     }
@@ -164,10 +164,6 @@ impl Query {
         self.simple_terms_text.clone()
     }
 
-    pub fn terms(&self) -> &[Box<Term>] {
-        &self.terms
-    }
-
     pub fn is_empty(&self) -> bool {
         self.terms.is_empty()
     }
@@ -500,6 +496,64 @@ mod tests {
         assert_eq!(result.documents[0].url, "https://www.first.com");
     }
 
+    #[test]
+    fn heading_query() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Test website</title>
+                            </head>
+                            <body>
+                                <h2>Installation guide</h2>
+                                This is a test website
+                            </body>
+                        </html>
+                    "#,
+                "https://www.first.com",
+            ))
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Test website</title>
+                            </head>
+                            <body>
+                                <h2>Introduction</h2>
+                                This is a test website with an installation guide
+                            </body>
+                        </html>
+                    "#,
+                "https://www.second.com",
+            ))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let query = Query::parse(
+            "inheading:installation",
+            index.schema(),
+            index.tokenizers(),
+            &SignalAggregator::default(),
+        )
+        .expect("Failed to parse query");
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+        let result = index
+            .search(&query, ranker.collector())
+            .expect("Search failed");
+        assert_eq!(result.num_docs, 1);
+        assert_eq!(result.documents.len(), 1);
+        assert_eq!(result.documents[0].url, "https://www.first.com");
+    }
+
     #[test]
     fn url_query() {
         let mut index = InvertedIndex::temporary().expect("Unable to open index");
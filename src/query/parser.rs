@@ -22,8 +22,9 @@ use tantivy::{
 
 use crate::{
     bangs::BANG_PREFIX,
-    ranking::FieldBoost,
-    schema::{Field, TextField, ALL_FIELDS},
+    ranking::{goggles::const_query::ConstQuery, FieldBoost},
+    schema::{FastField, Field, TextField, ALL_FIELDS},
+    webpage::ContentType,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -32,8 +33,10 @@ pub enum Term {
     Not(Box<Term>),
     Site(String),
     Title(String),
+    Heading(String),
     Body(String),
     Url(String),
+    FileType(ContentType),
     PossibleBang(String),
 }
 
@@ -44,8 +47,10 @@ impl ToString for Term {
             Term::Not(term) => "-".to_string() + term.to_string().as_str(),
             Term::Site(site) => "site:".to_string() + site.as_str(),
             Term::Title(title) => "intitle:".to_string() + title.as_str(),
+            Term::Heading(heading) => "inheading:".to_string() + heading.as_str(),
             Term::Body(body) => "inbody:".to_string() + body.as_str(),
             Term::Url(url) => "inurl:".to_string() + url.as_str(),
+            Term::FileType(content_type) => "filetype:".to_string() + content_type.filetype(),
             Term::PossibleBang(bang) => "!".to_string() + bang.as_str(),
         }
     }
@@ -131,6 +136,21 @@ impl Term {
                     Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, title),
                 )]
             }
+            Term::Heading(heading) => {
+                let (field, entry) = fields
+                    .iter()
+                    .find(|(field, _)| {
+                        matches!(
+                            ALL_FIELDS[field.field_id() as usize],
+                            Field::Text(TextField::Headings)
+                        )
+                    })
+                    .unwrap();
+                vec![(
+                    Occur::Must,
+                    Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, heading),
+                )]
+            }
             Term::Body(body) => {
                 let (field, entry) = fields
                     .iter()
@@ -161,6 +181,24 @@ impl Term {
                     Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, url),
                 )]
             }
+            Term::FileType(content_type) => {
+                let (field, _) = fields
+                    .iter()
+                    .find(|(field, _)| {
+                        matches!(
+                            ALL_FIELDS[field.field_id() as usize],
+                            Field::Fast(FastField::ContentType)
+                        )
+                    })
+                    .unwrap();
+
+                let query = TermQuery::new(
+                    tantivy::Term::from_field_u64(*field, content_type.id()),
+                    IndexRecordOption::Basic,
+                );
+
+                vec![(Occur::Must, Box::new(ConstQuery::new(Box::new(query), 0.0)))]
+            }
             Term::PossibleBang(text) => {
                 let mut term = String::new();
                 term.push(BANG_PREFIX);
@@ -297,6 +335,12 @@ fn parse_term(term: &str) -> Box<Term> {
         } else {
             Box::new(Term::Simple(term.to_string()))
         }
+    } else if let Some(heading) = term.strip_prefix("inheading:") {
+        if !heading.is_empty() {
+            Box::new(Term::Heading(heading.to_string()))
+        } else {
+            Box::new(Term::Simple(term.to_string()))
+        }
     } else if let Some(body) = term.strip_prefix("inbody:") {
         if !body.is_empty() {
             Box::new(Term::Body(body.to_string()))
@@ -309,6 +353,12 @@ fn parse_term(term: &str) -> Box<Term> {
         } else {
             Box::new(Term::Simple(term.to_string()))
         }
+    } else if let Some(filetype) = term.strip_prefix("filetype:") {
+        if let Some(content_type) = ContentType::from_filetype(filetype) {
+            Box::new(Term::FileType(content_type))
+        } else {
+            Box::new(Term::Simple(term.to_string()))
+        }
     } else if let Some(bang) = term.strip_prefix(BANG_PREFIX) {
         Box::new(Term::PossibleBang(bang.to_string()))
     } else {
@@ -377,6 +427,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn heading() {
+        assert_eq!(
+            parse("this inheading:test"),
+            vec![
+                Box::new(Term::Simple("this".to_string())),
+                Box::new(Term::Heading("test".to_string()))
+            ]
+        );
+        assert_eq!(
+            parse("inheading:"),
+            vec![Box::new(Term::Simple("inheading:".to_string()))]
+        );
+    }
+
     #[test]
     fn body() {
         assert_eq!(
@@ -398,4 +463,16 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn filetype() {
+        assert_eq!(
+            parse("this filetype:pdf filetype:exe"),
+            vec![
+                Box::new(Term::Simple("this".to_string())),
+                Box::new(Term::FileType(ContentType::Pdf)),
+                Box::new(Term::Simple("filetype:exe".to_string()))
+            ]
+        );
+    }
 }
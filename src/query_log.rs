@@ -0,0 +1,449 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Anonymous aggregation of searched queries.
+//!
+//! Queries are counted per day by the number of distinct sessions they were searched
+//! from. A session is a hash of the client address (or the /64 prefix of an IPv6
+//! address), keyed by a random key that only lives in memory and is replaced every
+//! day, so sessions cannot be linked across days or back to a client. The user agent
+//! is not part of the session, as that would let a single client count as several
+//! people just by switching browsers.
+//!
+//! Only queries searched from at least `min_sessions_per_day` sessions are written,
+//! together with their number of sessions, and everything else is dropped. The
+//! current day is written periodically and on shutdown, and rewritten in full once
+//! the day is over. Each process writes its own files, so frontend replicas never
+//! overwrite each other's counts.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{NaiveDate, Utc};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{Error, QueryLogConfig, Result};
+
+/// Longer queries are more likely to be unique to a person and are never logged.
+const MAX_QUERY_CHARS: usize = 100;
+
+/// Queries with this many digits in a row might contain phone or account numbers.
+const MAX_CONSECUTIVE_DIGITS: usize = 5;
+
+/// Lowercases the query and collapses whitespace. Returns `None` for queries that
+/// should never be logged because they might contain personal information.
+fn normalize(query: &str) -> Option<String> {
+    let query = query
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if query.is_empty() || query.chars().count() > MAX_QUERY_CHARS || query.contains('@') {
+        return None;
+    }
+
+    let mut consecutive_digits = 0;
+    for c in query.chars() {
+        if c.is_numeric() {
+            consecutive_digits += 1;
+
+            if consecutive_digits > MAX_CONSECUTIVE_DIGITS {
+                return None;
+            }
+        } else {
+            consecutive_digits = 0;
+        }
+    }
+
+    Some(query)
+}
+
+struct Day {
+    date: NaiveDate,
+    session_key: RandomState,
+    sessions: HashMap<String, HashSet<u64>>,
+}
+
+impl Day {
+    fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            session_key: RandomState::new(),
+            sessions: HashMap::new(),
+        }
+    }
+
+    fn session(&self, addr: IpAddr) -> u64 {
+        let mut hasher = self.session_key.build_hasher();
+
+        match addr {
+            IpAddr::V4(addr) => addr.octets().hash(&mut hasher),
+            // a single IPv6 client is usually handed a whole /64
+            IpAddr::V6(addr) => addr.octets()[..8].hash(&mut hasher),
+        }
+
+        hasher.finish()
+    }
+
+    /// The queries searched from at least `min_sessions` sessions, most popular first.
+    fn aggregate(&self, min_sessions: usize) -> Vec<(String, usize)> {
+        let mut queries: Vec<_> = self
+            .sessions
+            .iter()
+            .map(|(query, sessions)| (query.clone(), sessions.len()))
+            .filter(|(_, num_sessions)| *num_sessions >= min_sessions)
+            .collect();
+
+        queries.sort_by(|(a_query, a_count), (b_query, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_query.cmp(b_query))
+        });
+
+        queries
+    }
+}
+
+/// Writes the aggregated queries of a day to the csv file of this process.
+#[derive(Clone)]
+struct DayWriter {
+    output_path: PathBuf,
+    replica_id: String,
+    min_sessions: usize,
+    /// Held while writing, so a periodic flush of a day can't overwrite the counts
+    /// written once the day is over.
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl DayWriter {
+    fn path(&self, date: NaiveDate) -> PathBuf {
+        self.output_path.join(format!(
+            "{}-{}.csv",
+            date.format("%Y-%m-%d"),
+            self.replica_id
+        ))
+    }
+
+    /// Writes the queries as a csv with a query and its number of sessions on each
+    /// line, in the same shape as the csv files read by autosuggest. The csv is
+    /// written to a temporary file first, so readers never see a partly written day.
+    /// The caller must hold the write lock.
+    fn write(&self, date: NaiveDate, queries: &[(String, usize)]) -> Result<()> {
+        let path = self.path(date);
+        let tmp_path = path.with_extension("csv.tmp");
+
+        let mut wtr = csv::Writer::from_path(&tmp_path)?;
+        wtr.write_record(["query", "count"])?;
+
+        for (query, count) in queries {
+            wtr.write_record([query.as_str(), count.to_string().as_str()])?;
+        }
+
+        wtr.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+        info!("wrote {} queries to {:?}", queries.len(), path);
+
+        Ok(())
+    }
+
+    fn write_day(&self, day: &Day) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.write(day.date, &day.aggregate(self.min_sessions))
+    }
+}
+
+pub struct QueryLog {
+    config: QueryLogConfig,
+    writer: DayWriter,
+    day: Mutex<Day>,
+}
+
+impl QueryLog {
+    pub fn open(config: QueryLogConfig) -> Result<Self> {
+        if config.flush_interval_secs == 0 {
+            return Err(Error::InvalidConfig(
+                "query log flush_interval_secs must be above 0",
+            ));
+        }
+
+        fs::create_dir_all(&config.output_path)?;
+
+        let writer = DayWriter {
+            output_path: PathBuf::from(&config.output_path),
+            replica_id: Uuid::new_v4().simple().to_string()[..8].to_string(),
+            min_sessions: config.min_sessions_per_day,
+            write_lock: Arc::new(Mutex::new(())),
+        };
+
+        Ok(Self {
+            config,
+            writer,
+            day: Mutex::new(Day::new(Utc::now().naive_utc().date())),
+        })
+    }
+
+    pub fn behind_proxy(&self) -> bool {
+        self.config.behind_proxy
+    }
+
+    pub fn flush_interval(&self) -> Duration {
+        Duration::from_secs(self.config.flush_interval_secs)
+    }
+
+    /// Records a search. Must be called from within a tokio runtime, as the previous
+    /// day is written on a blocking thread once a new day starts.
+    pub fn record(&self, query: &str, addr: IpAddr) {
+        if let Some(finished_day) = self.record_at(Utc::now().naive_utc().date(), query, addr) {
+            let writer = self.writer.clone();
+
+            tokio::task::spawn_blocking(move || {
+                if let Err(err) = writer.write_day(&finished_day) {
+                    warn!("failed to write query log: {:?}", err);
+                }
+            });
+        }
+    }
+
+    /// Writes the queries counted so far today. They are written again, with updated
+    /// counts, by the next flush or once the day is over.
+    pub fn flush(&self) -> Result<()> {
+        // the counts are taken while holding the write lock, so if the day ends in the
+        // meantime, the final counts of the day are written after these.
+        let _guard = self.writer.write_lock.lock().unwrap();
+        let (date, queries) = {
+            let day = self.day.lock().unwrap();
+            (day.date, day.aggregate(self.writer.min_sessions))
+        };
+
+        self.writer.write(date, &queries)
+    }
+
+    /// Counts the search and returns the previous day if `date` started a new one.
+    fn record_at(&self, date: NaiveDate, query: &str, addr: IpAddr) -> Option<Day> {
+        let query = normalize(query)?;
+
+        let mut day = self.day.lock().unwrap();
+
+        let finished_day = if day.date != date {
+            Some(std::mem::replace(&mut *day, Day::new(date)))
+        } else {
+            None
+        };
+
+        let session = day.session(addr);
+        let max_queries = self.config.max_queries_per_day;
+        let num_queries = day.sessions.len();
+
+        match day.sessions.get_mut(&query) {
+            Some(sessions) => {
+                sessions.insert(session);
+            }
+            None if num_queries < max_queries => {
+                day.sessions.insert(query, HashSet::from([session]));
+            }
+            None => {}
+        }
+
+        finished_day
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::autosuggest::Autosuggest;
+
+    fn addr(n: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, n))
+    }
+
+    /// Records like [`QueryLog::record`], but writes a finished day right away.
+    fn record(log: &QueryLog, date: NaiveDate, query: &str, addr: IpAddr) {
+        if let Some(finished_day) = log.record_at(date, query, addr) {
+            log.writer.write_day(&finished_day).unwrap();
+        }
+    }
+
+    fn query_log(min_sessions_per_day: usize) -> QueryLog {
+        QueryLog::open(QueryLogConfig {
+            output_path: crate::gen_temp_path().to_str().unwrap().to_string(),
+            min_sessions_per_day,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn normalization() {
+        assert_eq!(
+            normalize("  Hello   World "),
+            Some("hello world".to_string())
+        );
+        assert_eq!(normalize("københavn"), Some("københavn".to_string()));
+        assert_eq!(normalize("iphone 14"), Some("iphone 14".to_string()));
+        assert_eq!(
+            normalize("best movies 2022"),
+            Some("best movies 2022".to_string())
+        );
+        assert_eq!(normalize("call 55512345"), None);
+        assert_eq!(normalize("john@example.com"), None);
+        assert_eq!(normalize("   "), None);
+        assert_eq!(normalize(&"a".repeat(MAX_QUERY_CHARS + 1)), None);
+    }
+
+    #[test]
+    fn only_queries_from_enough_sessions() {
+        let log = query_log(3);
+        let day = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let next_day = day.succ_opt().unwrap();
+
+        for n in 0..3 {
+            record(&log, day, "Rust", addr(n));
+        }
+
+        // the same session searching many times only counts once
+        for _ in 0..10 {
+            record(&log, day, "my secret", addr(1));
+        }
+
+        assert!(!log.writer.path(day).exists());
+        record(&log, next_day, "rust", addr(1));
+
+        let written = fs::read_to_string(log.writer.path(day)).unwrap();
+        assert_eq!(written, "query,count\nrust,3\n");
+        assert!(!log.writer.path(next_day).exists());
+    }
+
+    #[test]
+    fn sessions_ignore_user_agent_and_ipv6_suffix() {
+        let log = query_log(2);
+        let day = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+
+        // a client switching browsers is still the same session
+        record(&log, day, "cuely", addr(1));
+        record(&log, day, "cuely", addr(1));
+
+        let ipv6 = |suffix| IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, suffix));
+        record(&log, day, "rust", ipv6(1));
+        record(&log, day, "rust", ipv6(2));
+        record(&log, day, "rust", addr(1));
+
+        log.flush().unwrap();
+
+        let written = fs::read_to_string(log.writer.path(day)).unwrap();
+        assert_eq!(written, "query,count\nrust,2\n");
+    }
+
+    #[test]
+    fn flush_current_day() {
+        let log = query_log(1);
+        let other = query_log(1);
+        let day = log.day.lock().unwrap().date;
+
+        record(&log, day, "rust", addr(1));
+        log.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(log.writer.path(day)).unwrap(),
+            "query,count\nrust,1\n"
+        );
+
+        record(&log, day, "rust", addr(2));
+        log.flush().unwrap();
+        assert_eq!(
+            fs::read_to_string(log.writer.path(day)).unwrap(),
+            "query,count\nrust,2\n"
+        );
+
+        // replicas write to their own files
+        assert_ne!(log.writer.path(day), other.writer.path(day));
+    }
+
+    #[test]
+    fn readable_by_autosuggest() {
+        let log = query_log(1);
+        let day = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+
+        record(&log, day, "rust", addr(1));
+        record(&log, day, "rust", addr(2));
+        record(&log, day, "rust book", addr(1));
+        record(&log, day.succ_opt().unwrap(), "rust", addr(1));
+
+        let autosuggest = Autosuggest::load(log.writer.path(day)).unwrap();
+
+        assert_eq!(
+            autosuggest.suggestions("ru"),
+            vec!["rust".to_string(), "rust book".to_string()]
+        );
+    }
+
+    #[test]
+    fn limit_queries_per_day() {
+        let log = QueryLog::open(QueryLogConfig {
+            output_path: crate::gen_temp_path().to_str().unwrap().to_string(),
+            min_sessions_per_day: 1,
+            max_queries_per_day: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let day = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+
+        record(&log, day, "a", addr(1));
+        record(&log, day, "b", addr(1));
+        record(&log, day, "c", addr(1));
+        record(&log, day, "a", addr(2));
+        record(&log, day.succ_opt().unwrap(), "a", addr(1));
+
+        let written = fs::read_to_string(log.writer.path(day)).unwrap();
+        assert_eq!(written, "query,count\na,2\nb,1\n");
+    }
+
+    #[test]
+    fn no_partial_files_left() {
+        let log = query_log(1);
+        let day = log.day.lock().unwrap().date;
+
+        record(&log, day, "rust", addr(1));
+        log.flush().unwrap();
+
+        let files: Vec<_> = fs::read_dir(&log.writer.output_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files, vec![log.writer.path(day)]);
+    }
+
+    #[test]
+    fn zero_flush_interval_rejected() {
+        assert!(matches!(
+            QueryLog::open(QueryLogConfig {
+                output_path: crate::gen_temp_path().to_str().unwrap().to_string(),
+                flush_interval_secs: 0,
+                ..Default::default()
+            }),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
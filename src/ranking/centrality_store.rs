@@ -49,4 +49,13 @@ impl CentralityStore {
     pub fn flush(&self) {
         self.inner.flush();
     }
+
+    /// The `n` hosts with the highest centrality, in descending order.
+    pub fn top(&self, n: usize) -> Vec<(String, f64)> {
+        let mut hosts: Vec<_> = self.inner.iter().collect();
+        hosts.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        hosts.truncate(n);
+
+        hosts
+    }
 }
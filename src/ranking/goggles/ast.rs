@@ -65,6 +65,26 @@ impl From<Vec<GoggleBlock>> for RawGoggle {
     }
 }
 
+impl RawGoggle {
+    /// The value of a header comment such as `! name: ...`.
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.comments.iter().find_map(|comment| match comment {
+            Comment::Header { key: k, value } if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// All values of a header that can be repeated, such as `! include: ...`.
+    pub fn headers<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.comments
+            .iter()
+            .filter_map(move |comment| match comment {
+                Comment::Header { key: k, value } if k == key => Some(value.as_str()),
+                _ => None,
+            })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum GoggleBlock {
     Comment(Comment),
@@ -98,6 +118,7 @@ pub enum RawPatternOption {
     InUrl,
     InTitle,
     InDescription,
+    InHeading,
     InContent,
     Action(RawAction),
 }
@@ -263,6 +284,31 @@ mod tests {
         )
     }
 
+    #[test]
+    fn references() {
+        let goggle = parse(
+            r#"
+            ! name: layered
+            ! extends: https://example.com/base.goggle
+            ! include: /goggles/copycats_removal.goggle
+            ! include: other.goggle
+            $site=example.com
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(goggle.header("name"), Some("layered"));
+        assert_eq!(
+            goggle.header("extends"),
+            Some("https://example.com/base.goggle")
+        );
+        assert_eq!(
+            goggle.headers("include").collect::<Vec<_>>(),
+            vec!["/goggles/copycats_removal.goggle", "other.goggle"]
+        );
+        assert_eq!(goggle.header("author"), None);
+    }
+
     #[test]
     fn quickstart_parse() {
         assert!(parse(include_str!("../../../testcases/goggles/quickstart.goggle")).is_ok());
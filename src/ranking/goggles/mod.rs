@@ -17,10 +17,10 @@
 const SCALE: f32 = 500.0;
 
 pub mod ast;
-mod const_query;
+pub(crate) mod const_query;
 mod pattern_query;
 
-use std::convert::TryFrom;
+use std::{convert::TryFrom, str::FromStr};
 
 use crate::{
     query::union::UnionQuery,
@@ -28,6 +28,8 @@ use crate::{
     Result,
 };
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tantivy::{
     query::{BooleanQuery, BoostQuery, Occur, QueryClone, TermQuery},
     schema::{IndexRecordOption, Schema},
@@ -103,6 +105,7 @@ impl TryFrom<RawPatternOption> for PatternOption {
             RawPatternOption::InUrl => PatternOption::InUrl,
             RawPatternOption::InTitle => PatternOption::InTitle,
             RawPatternOption::InDescription => PatternOption::InDescription,
+            RawPatternOption::InHeading => PatternOption::InHeading,
             RawPatternOption::InContent => PatternOption::InContent,
             RawPatternOption::Action(action) => PatternOption::Action(action.try_into()?),
         };
@@ -125,13 +128,13 @@ impl TryFrom<RawAction> for Action {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Instruction {
     pub patterns: Vec<PatternPart>,
     pub options: Vec<PatternOption>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum PatternPart {
     Raw(String),
     Wildcard,
@@ -139,29 +142,73 @@ pub enum PatternPart {
     Anchor,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum PatternOption {
     Site(String),
     InUrl,
     InTitle,
     InDescription,
+    InHeading,
     InContent,
     Action(Action),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Action {
     Boost(u64),
     Downrank(u64),
     Discard,
 }
 
-#[derive(Debug, Default, Clone)]
+/// Identifies a goggle by the content of its source, so the same goggle
+/// hosted at different urls is only compiled once. The shards use it as the
+/// key of their query cache, so it must be collision resistant: anyone can
+/// write a goggle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GoggleHash([u8; 32]);
+
+impl GoggleHash {
+    pub fn of(source: &str) -> Self {
+        Self(Sha256::digest(source.as_bytes()).into())
+    }
+
+    /// The hash of a goggle composed from goggles with these hashes.
+    pub fn combine(hashes: impl Iterator<Item = GoggleHash>) -> Self {
+        let mut hasher = Sha256::new();
+
+        for hash in hashes {
+            hasher.update(hash.0);
+        }
+
+        Self(hasher.finalize().into())
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Goggle {
     pub aggregator: SignalAggregator,
     pub instructions: Vec<Instruction>,
 }
 
+/// A validated goggle as it is sent to the shards. The hash lets the shards
+/// reuse the tantivy queries built for a goggle across searches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledGoggle {
+    pub hash: GoggleHash,
+    pub goggle: Goggle,
+}
+
+impl FromStr for CompiledGoggle {
+    type Err = crate::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        Ok(Self {
+            hash: GoggleHash::of(source),
+            goggle: parse(source)?,
+        })
+    }
+}
+
 impl Goggle {
     pub fn as_tantivy(&self, schema: &Schema) -> Vec<(Occur, Box<dyn tantivy::query::Query>)> {
         if self
@@ -250,6 +297,13 @@ impl Instruction {
                             .unwrap(),
                     )
                 }
+                PatternOption::InHeading if field.is_none() => {
+                    field = Some(
+                        schema
+                            .get_field(Field::Text(TextField::Headings).name())
+                            .unwrap(),
+                    )
+                }
                 PatternOption::InContent if field.is_none() => {
                     field = Some(
                         schema
@@ -325,6 +379,7 @@ impl Instruction {
 mod tests {
     use crate::{
         index::Index,
+        ranking::Signal,
         schema::create_schema,
         searcher::{LocalSearcher, SearchQuery},
         webpage::{Html, Webpage},
@@ -396,9 +451,9 @@ mod tests {
             .search(&SearchQuery {
                 original: "website".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -414,14 +469,13 @@ mod tests {
             .search(&SearchQuery {
                 original: "website".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    r#"
+                goggles: vec![r#"
                         $discard,site=b.com
                     "#
-                    .to_string(),
-                ),
+                .parse()
+                .unwrap()],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -436,14 +490,13 @@ mod tests {
             .search(&SearchQuery {
                 original: "website".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    r#"
+                goggles: vec![r#"
                         $boost=10,site=a.com
                     "#
-                    .to_string(),
-                ),
+                .parse()
+                .unwrap()],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -454,6 +507,27 @@ mod tests {
         assert_eq!(res.len(), 2);
         assert_eq!(res[0].url, "https://www.a.com");
         assert_eq!(res[1].url, "https://www.b.com");
+
+        // a discard in one goggle wins over a boost in another
+        let res = searcher
+            .search(&SearchQuery {
+                original: "website".to_string(),
+                selected_region: None,
+                goggles: vec![
+                    "$boost=10,site=a.com".parse().unwrap(),
+                    "$discard,site=a.com".parse().unwrap(),
+                ],
+                bangs: Vec::new(),
+                skip_pages: None,
+            })
+            .unwrap()
+            .into_websites()
+            .unwrap()
+            .webpages
+            .documents;
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].url, "https://www.b.com");
     }
 
     #[test]
@@ -526,11 +600,11 @@ mod tests {
             .search(&SearchQuery {
                 original: "website".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    include_str!("../../../testcases/goggles/quickstart.goggle").to_string(),
-                ),
+                goggles: vec![include_str!("../../../testcases/goggles/quickstart.goggle")
+                    .parse()
+                    .unwrap()],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -542,11 +616,13 @@ mod tests {
             .search(&SearchQuery {
                 original: "website".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    include_str!("../../../testcases/goggles/hacker_news.goggle").to_string(),
-                ),
+                goggles: vec![
+                    include_str!("../../../testcases/goggles/hacker_news.goggle")
+                        .parse()
+                        .unwrap(),
+                ],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -558,11 +634,13 @@ mod tests {
             .search(&SearchQuery {
                 original: "website".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    include_str!("../../../testcases/goggles/copycats_removal.goggle").to_string(),
-                ),
+                goggles: vec![
+                    include_str!("../../../testcases/goggles/copycats_removal.goggle")
+                        .parse()
+                        .unwrap(),
+                ],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -571,6 +649,39 @@ mod tests {
             .documents;
     }
 
+    #[test]
+    fn compiled_goggle_roundtrip() {
+        let compiled: CompiledGoggle = r#"
+            @host_centrality = 3
+            @field_title = 2
+            $site=a.com,boost=4
+        "#
+        .parse()
+        .unwrap();
+
+        let bytes = bincode::serialize(&compiled).unwrap();
+        let decoded: CompiledGoggle = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(decoded.hash, compiled.hash);
+        assert_eq!(decoded.goggle.instructions, compiled.goggle.instructions);
+        assert_eq!(
+            decoded
+                .goggle
+                .aggregator
+                .coefficients()
+                .get(&Signal::HostCentrality),
+            3.0
+        );
+        assert_eq!(
+            decoded
+                .goggle
+                .aggregator
+                .field_boosts()
+                .get(&TextField::Title),
+            2.0
+        );
+    }
+
     #[test]
     fn empty_discard() {
         let mut index = Index::temporary().expect("Unable to open index");
@@ -659,16 +770,15 @@ mod tests {
             .search(&SearchQuery {
                 original: "website".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    r#"
+                goggles: vec![r#"
                 $discard
                 $site=a.com,boost=6
                 $site=b.com,boost=1
                 "#
-                    .to_string(),
-                ),
+                .parse()
+                .unwrap()],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -679,4 +789,87 @@ mod tests {
         assert_eq!(res.len(), 2);
         assert_eq!(res[0].url, "https://www.a.com/this/is/a/pattern");
     }
+
+    #[test]
+    fn heading_pattern() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website A</title>
+                        </head>
+                        <body>
+                            <h2>Recipes</h2>
+                            {CONTENT}
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.a.com",
+                ),
+                backlinks: vec![],
+                host_centrality: 0.0,
+                page_centrality: 0.0,
+                fetch_time_ms: 500,
+                pre_computed_score: 0.0,
+                primary_image: None,
+            })
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website B</title>
+                        </head>
+                        <body>
+                            <h2>About</h2>
+                            {CONTENT} recipes
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.b.com",
+                ),
+                backlinks: vec![],
+                host_centrality: 0.0001,
+                page_centrality: 0.0,
+                fetch_time_ms: 500,
+                pre_computed_score: 0.0,
+                primary_image: None,
+            })
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let res = searcher
+            .search(&SearchQuery {
+                original: "website".to_string(),
+                selected_region: None,
+                goggles: vec![r#"
+                $discard
+                recipes$inheading
+                "#
+                .parse()
+                .unwrap()],
+                bangs: Vec::new(),
+                skip_pages: None,
+            })
+            .unwrap()
+            .into_websites()
+            .unwrap()
+            .webpages
+            .documents;
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].url, "https://www.a.com");
+    }
 }
@@ -173,6 +173,12 @@ impl PatternWeight {
                     .get_field(Field::Fast(FastField::NumDescriptionTokens).name())
                     .unwrap(),
             ),
+            Field::Text(TextField::Headings) => reader.fast_fields().u64(
+                reader
+                    .schema()
+                    .get_field(Field::Fast(FastField::NumHeadingTokens).name())
+                    .unwrap(),
+            ),
             field => Err(TantivyError::InvalidArgument(format!(
                 "{} is not supported in pattern query",
                 field.name()
@@ -117,7 +117,7 @@ mod tests {
     use crate::{
         index::Index,
         searcher::{LocalSearcher, SearchQuery},
-        webpage::{Html, Link, Webpage},
+        webpage::{Backlink, Html, Link, LinkPosition, LinkRel, Webpage},
     };
 
     const CONTENT: &str = "this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever";
@@ -184,9 +184,9 @@ mod tests {
             .search(&SearchQuery {
                 original: "example".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -258,9 +258,9 @@ mod tests {
             .search(&SearchQuery {
                 original: "example".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -354,9 +354,9 @@ mod tests {
             .search(&SearchQuery {
                 original: "dr dk".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -429,9 +429,9 @@ mod tests {
             .search(&SearchQuery {
                 original: "title".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -509,9 +509,9 @@ mod tests {
             .search(&SearchQuery {
                 original: "test".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -521,6 +521,126 @@ mod tests {
         assert_eq!(result.webpages.documents[0].url, "https://www.first.com");
     }
 
+    fn page_with_script(url: &str, script: &str, host_centrality: f64) -> Webpage {
+        Webpage {
+            html: Html::parse(
+                &format!(
+                    r#"
+                    <html>
+                        <head>
+                            <script src="{script}"></script>
+                            <title>Test site</title>
+                        </head>
+                        <body>
+                            test
+                        </body>
+                    </html>
+                "#
+                ),
+                url,
+            ),
+            backlinks: vec![],
+            host_centrality,
+            fetch_time_ms: 500,
+            page_centrality: 0.0,
+            pre_computed_score: 0.0,
+            primary_image: None,
+        }
+    }
+
+    #[test]
+    fn cdn_is_not_a_tracker() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(page_with_script(
+                "https://www.plain.com",
+                "/js/main.js",
+                0.0,
+            ))
+            .expect("failed to insert webpage");
+        index
+            .insert(page_with_script(
+                "https://www.cdn.com",
+                "https://code.jquery.com/jquery-3.6.0.min.js",
+                0.001,
+            ))
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+        let result = searcher
+            .search(&SearchQuery {
+                original: "test".to_string(),
+                selected_region: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
+                skip_pages: None,
+            })
+            .expect("Search failed")
+            .into_websites()
+            .unwrap();
+
+        assert_eq!(result.webpages.documents.len(), 2);
+        assert_eq!(result.webpages.documents[0].url, "https://www.cdn.com");
+    }
+
+    #[test]
+    fn goggle_tracker_category() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(page_with_script(
+                "https://www.ads.com",
+                "https://securepubads.g.doubleclick.net/tag/js/gpt.js",
+                0.01,
+            ))
+            .expect("failed to insert webpage");
+        index
+            .insert(page_with_script(
+                "https://www.analytics.com",
+                "https://www.google-analytics.com/analytics.js",
+                0.0,
+            ))
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let result = searcher
+            .search(&SearchQuery {
+                original: "test".to_string(),
+                selected_region: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
+                skip_pages: None,
+            })
+            .expect("Search failed")
+            .into_websites()
+            .unwrap();
+
+        assert_eq!(result.webpages.documents.len(), 2);
+        assert_eq!(result.webpages.documents[0].url, "https://www.ads.com");
+
+        let result = searcher
+            .search(&SearchQuery {
+                original: "test".to_string(),
+                selected_region: None,
+                goggles: vec!["@num_advertising_trackers = 100".parse().unwrap()],
+                bangs: Vec::new(),
+                skip_pages: None,
+            })
+            .expect("Search failed")
+            .into_websites()
+            .unwrap();
+
+        assert_eq!(result.webpages.documents.len(), 2);
+        assert_eq!(
+            result.webpages.documents[0].url,
+            "https://www.analytics.com"
+        );
+    }
+
     #[test]
     fn backlink_text() {
         let mut index = Index::temporary().expect("Unable to open index");
@@ -540,10 +660,16 @@ mod tests {
                 "#,
                     "https://www.first.com",
                 ),
-                backlinks: vec![Link {
-                    source: "https://www.second.com".to_string().into(),
-                    destination: "https://www.first.com".to_string().into(),
-                    text: "test this is the best test site".to_string(),
+                backlinks: vec![Backlink {
+                    link: Link {
+                        source: "https://www.second.com".to_string().into(),
+                        destination: "https://www.first.com".to_string().into(),
+                        text: "test this is the best test site".to_string(),
+                        rel: LinkRel::default(),
+                        position: LinkPosition::Content,
+                        context: String::new(),
+                    },
+                    source_centrality: 0.0,
                 }],
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
@@ -582,9 +708,9 @@ mod tests {
             .search(&SearchQuery {
                 original: "test".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -673,21 +799,20 @@ mod tests {
 
         index.commit().unwrap();
 
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None);
 
         let res = searcher
             .search(&SearchQuery {
                 original: "example".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    r#"
+                goggles: vec![r#"
                         @field_title = 20000000
                         @host_centrality = 0
                     "#
-                    .to_string(),
-                ),
+                .parse()
+                .unwrap()],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -700,15 +825,14 @@ mod tests {
             .search(&SearchQuery {
                 original: "example".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    r#"
+                goggles: vec![r#"
                         @field_all_body = 20000000
                         @host_centrality = 0
                     "#
-                    .to_string(),
-                ),
+                .parse()
+                .unwrap()],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -721,14 +845,13 @@ mod tests {
             .search(&SearchQuery {
                 original: "example".to_string(),
                 selected_region: None,
-                goggle_program: Some(
-                    r#"
+                goggles: vec![r#"
                         @host_centrality = 2000000
                     "#
-                    .to_string(),
-                ),
+                .parse()
+                .unwrap()],
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .unwrap()
             .into_websites()
@@ -817,15 +940,15 @@ mod tests {
             })
             .expect("failed to insert webpage");
         index.commit().expect("failed to commit index");
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None);
 
         let result = searcher
             .search(&SearchQuery {
                 original: "termA termB".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -894,15 +1017,15 @@ mod tests {
             })
             .expect("failed to insert webpage");
         index.commit().expect("failed to commit index");
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None);
 
         let result = searcher
             .search(&SearchQuery {
                 original: "test".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
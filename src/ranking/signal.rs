@@ -23,6 +23,7 @@ use crate::{
 use std::{array, convert::TryFrom, ops::Deref, sync::Arc};
 
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use tantivy::{DocId, Score};
 
 use crate::{
@@ -42,9 +43,12 @@ pub enum Signal {
     UpdateTimestamp,
     NumTrackers,
     Region,
+    NumAnalyticsTrackers,
+    NumAdvertisingTrackers,
+    NumSocialTrackers,
 }
 
-pub const ALL_SIGNALS: [Signal; 8] = [
+pub const ALL_SIGNALS: [Signal; 11] = [
     Signal::Bm25,
     Signal::HostCentrality,
     Signal::PageCentrality,
@@ -53,6 +57,9 @@ pub const ALL_SIGNALS: [Signal; 8] = [
     Signal::UpdateTimestamp,
     Signal::NumTrackers,
     Signal::Region,
+    Signal::NumAnalyticsTrackers,
+    Signal::NumAdvertisingTrackers,
+    Signal::NumSocialTrackers,
 ];
 
 impl Signal {
@@ -100,7 +107,10 @@ impl Signal {
                     0.0
                 }
             }
-            Signal::NumTrackers => {
+            Signal::NumTrackers
+            | Signal::NumAnalyticsTrackers
+            | Signal::NumAdvertisingTrackers
+            | Signal::NumSocialTrackers => {
                 let num_trackers = fastfield_value.unwrap() as f64;
                 1.0 / (num_trackers + 1.0)
             }
@@ -128,6 +138,11 @@ impl Signal {
             Signal::UpdateTimestamp => 80.0,
             Signal::NumTrackers => 20.0,
             Signal::Region => 60.0,
+            // the categories are already included in `NumTrackers`,
+            // so they are only used when a goggle targets them
+            Signal::NumAnalyticsTrackers => 0.0,
+            Signal::NumAdvertisingTrackers => 0.0,
+            Signal::NumSocialTrackers => 0.0,
         }
     }
 
@@ -135,6 +150,10 @@ impl Signal {
         match name.as_str() {
             "bm25" => Some(Signal::Bm25),
             "host_centrality" => Some(Signal::HostCentrality),
+            "num_trackers" => Some(Signal::NumTrackers),
+            "num_analytics_trackers" => Some(Signal::NumAnalyticsTrackers),
+            "num_advertising_trackers" => Some(Signal::NumAdvertisingTrackers),
+            "num_social_trackers" => Some(Signal::NumSocialTrackers),
             _ => None,
         }
     }
@@ -149,14 +168,17 @@ impl Signal {
             Signal::UpdateTimestamp => Some(FastField::LastUpdated),
             Signal::NumTrackers => Some(FastField::NumTrackers),
             Signal::Region => Some(FastField::Region),
+            Signal::NumAnalyticsTrackers => Some(FastField::NumAnalyticsTrackers),
+            Signal::NumAdvertisingTrackers => Some(FastField::NumAdvertisingTrackers),
+            Signal::NumSocialTrackers => Some(FastField::NumSocialTrackers),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldBoost(Vec<Option<f64>>);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignalCoefficient(Vec<Option<f64>>);
 
 impl SignalCoefficient {
@@ -183,6 +205,10 @@ impl SignalCoefficient {
 
         Self(fast_coefficients)
     }
+
+    fn merge(&mut self, other: &Self) {
+        merge_sparse(&mut self.0, &other.0);
+    }
 }
 
 impl FieldBoost {
@@ -210,9 +236,27 @@ impl FieldBoost {
 
         Self(fast_scores)
     }
+
+    fn merge(&mut self, other: &Self) {
+        merge_sparse(&mut self.0, &other.0);
+    }
 }
 
-#[derive(Clone)]
+/// Overwrites the values in `values` with those set in `other`.
+fn merge_sparse(values: &mut Vec<Option<f64>>, other: &[Option<f64>]) {
+    for (idx, value) in other.iter().enumerate() {
+        if value.is_some() {
+            if idx >= values.len() {
+                values.resize(idx + 1, None);
+            }
+
+            values[idx] = *value;
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "Alterations", into = "Alterations")]
 pub struct SignalAggregator {
     fastfield_cache: Option<Arc<fastfield_cache::SegmentCache>>,
     signal_coefficients: SignalCoefficient,
@@ -241,9 +285,13 @@ impl SignalAggregator {
         coefficients: impl Iterator<Item = (Signal, f64)>,
         boosts: impl Iterator<Item = (TextField, f64)>,
     ) -> Self {
-        let signal_coefficients = SignalCoefficient::new(coefficients);
-        let field_boost = FieldBoost::new(boosts);
+        Self::from_parts(
+            SignalCoefficient::new(coefficients),
+            FieldBoost::new(boosts),
+        )
+    }
 
+    fn from_parts(signal_coefficients: SignalCoefficient, field_boost: FieldBoost) -> Self {
         let fetch_time_ms_cache = array::from_fn(|fetch_time| 1.0 / (fetch_time as f64 + 1.0));
 
         let update_time_cache = (0..(3 * 365 * 24))
@@ -259,6 +307,13 @@ impl SignalAggregator {
         }
     }
 
+    /// Takes the coefficients and field boosts that are set in `other`,
+    /// keeping the current ones for everything `other` leaves unset.
+    pub fn merge(&mut self, other: &SignalAggregator) {
+        self.signal_coefficients.merge(&other.signal_coefficients);
+        self.field_boost.merge(&other.field_boost);
+    }
+
     pub fn register_segment(&mut self, cache: Arc<fastfield_cache::SegmentCache>) {
         self.fastfield_cache = Some(cache);
     }
@@ -294,6 +349,8 @@ impl SignalAggregator {
     }
 
     pub fn precompute_score(&self, webpage: &Webpage, region_count: &RegionCount) -> f64 {
+        let tracker_counts = webpage.html.tracker_counts();
+
         ALL_SIGNALS
             .into_iter()
             .filter(|signal| signal.is_computable_before_search())
@@ -312,8 +369,11 @@ impl SignalAggregator {
                         .updated_time()
                         .map(|date| date.timestamp().max(0) as u64)
                         .unwrap_or(0),
-                    Signal::NumTrackers => webpage.html.trackers().len() as u64,
-                    Signal::Region => Region::guess_from(webpage).unwrap_or(Region::All).id(),
+                    Signal::NumTrackers => tracker_counts.num_trackers(),
+                    Signal::NumAnalyticsTrackers => tracker_counts.analytics,
+                    Signal::NumAdvertisingTrackers => tracker_counts.advertising,
+                    Signal::NumSocialTrackers => tracker_counts.social,
+                    Signal::Region => Region::guess_from(webpage).unwrap_or(Region::ALL).id(),
                     _ => panic!("signal cannot be determined from webpage"),
                 };
 
@@ -357,6 +417,29 @@ impl TryFrom<RawAlteration> for Alteration {
     }
 }
 
+/// The parts of an aggregator that a goggle can alter. Only these are sent
+/// to the shards, as everything else is rebuilt from them.
+#[derive(Serialize, Deserialize)]
+struct Alterations {
+    signal_coefficients: SignalCoefficient,
+    field_boost: FieldBoost,
+}
+
+impl From<SignalAggregator> for Alterations {
+    fn from(aggregator: SignalAggregator) -> Self {
+        Self {
+            signal_coefficients: aggregator.signal_coefficients,
+            field_boost: aggregator.field_boost,
+        }
+    }
+}
+
+impl From<Alterations> for SignalAggregator {
+    fn from(alterations: Alterations) -> Self {
+        Self::from_parts(alterations.signal_coefficients, alterations.field_boost)
+    }
+}
+
 impl TryFrom<Vec<RawAlteration>> for SignalAggregator {
     type Error = crate::Error;
 
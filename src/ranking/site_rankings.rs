@@ -17,7 +17,7 @@
 use serde::{Deserialize, Serialize};
 
 use super::{
-    goggles::{Action, Goggle, Instruction, PatternOption},
+    goggles::{Action, CompiledGoggle, Goggle, GoggleHash, Instruction, PatternOption},
     SignalAggregator,
 };
 
@@ -29,7 +29,8 @@ pub struct SiteRankings {
 }
 
 impl SiteRankings {
-    pub fn into_goggle(self) -> Goggle {
+    pub fn into_goggle(self) -> CompiledGoggle {
+        let hash = GoggleHash::of(&serde_json::to_string(&self).unwrap_or_default());
         let mut instructions = Vec::new();
 
         for site in self.preferred {
@@ -62,9 +63,12 @@ impl SiteRankings {
             });
         }
 
-        Goggle {
-            aggregator: SignalAggregator::default(),
-            instructions,
+        CompiledGoggle {
+            hash,
+            goggle: Goggle {
+                aggregator: SignalAggregator::default(),
+                instructions,
+            },
         }
     }
 }
@@ -134,19 +138,20 @@ mod tests {
             })
             .expect("failed to insert webpage");
         index.commit().expect("failed to commit index");
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None);
 
         let result = searcher
             .search(&SearchQuery {
                 original: "test".to_string(),
                 selected_region: None,
-                goggle_program: None,
-                skip_pages: None,
-                site_rankings: Some(SiteRankings {
+                goggles: vec![SiteRankings {
                     preferred: vec!["first.com".to_string()],
                     disliked: vec!["second.com".to_string()],
                     blocked: vec![],
-                }),
+                }
+                .into_goggle()],
+                bangs: Vec::new(),
+                skip_pages: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -162,13 +167,14 @@ mod tests {
             .search(&SearchQuery {
                 original: "test".to_string(),
                 selected_region: None,
-                goggle_program: None,
-                skip_pages: None,
-                site_rankings: Some(SiteRankings {
+                goggles: vec![SiteRankings {
                     preferred: vec![],
                     disliked: vec!["second.com".to_string()],
                     blocked: vec!["first.com".to_string()],
-                }),
+                }
+                .into_goggle()],
+                bangs: Vec::new(),
+                skip_pages: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -43,6 +43,12 @@ pub enum TextField {
     BacklinkText,
     PrimaryImage,
     Description,
+    /// Structured data from the page, stored so rich results can be rendered.
+    SchemaOrg,
+    /// Text of the `h1`-`h3` headings.
+    Headings,
+    /// Alt text of the images on the page.
+    ImageAlt,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -64,6 +70,14 @@ pub enum FastField {
     UrlHash,
     DomainHash,
     PreComputedScore,
+    ContentType,
+    SimHash,
+    NumAnalyticsTrackers,
+    NumAdvertisingTrackers,
+    NumSocialTrackers,
+    NumCdnHosts,
+    Lang,
+    NumHeadingTokens,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -72,7 +86,7 @@ pub enum Field {
     Text(TextField),
 }
 
-pub static ALL_FIELDS: [Field; 33] = [
+pub static ALL_FIELDS: [Field; 44] = [
     Field::Text(TextField::Title),
     Field::Text(TextField::CleanBody),
     Field::Text(TextField::StemmedTitle),
@@ -89,6 +103,9 @@ pub static ALL_FIELDS: [Field; 33] = [
     Field::Text(TextField::BacklinkText),
     Field::Text(TextField::PrimaryImage),
     Field::Text(TextField::Description),
+    Field::Text(TextField::SchemaOrg),
+    Field::Text(TextField::Headings),
+    Field::Text(TextField::ImageAlt),
     // FAST FIELDS
     Field::Fast(FastField::IsHomepage),
     Field::Fast(FastField::HostCentrality),
@@ -107,6 +124,14 @@ pub static ALL_FIELDS: [Field; 33] = [
     Field::Fast(FastField::UrlHash),
     Field::Fast(FastField::DomainHash),
     Field::Fast(FastField::PreComputedScore),
+    Field::Fast(FastField::ContentType),
+    Field::Fast(FastField::SimHash),
+    Field::Fast(FastField::NumAnalyticsTrackers),
+    Field::Fast(FastField::NumAdvertisingTrackers),
+    Field::Fast(FastField::NumSocialTrackers),
+    Field::Fast(FastField::NumCdnHosts),
+    Field::Fast(FastField::Lang),
+    Field::Fast(FastField::NumHeadingTokens),
 ];
 
 impl Field {
@@ -167,9 +192,14 @@ impl Field {
             Field::Text(TextField::PrimaryImage) => {
                 IndexingOption::Bytes(BytesOptions::default().set_stored())
             }
+            Field::Text(TextField::SchemaOrg) => {
+                IndexingOption::Bytes(BytesOptions::default().set_stored())
+            }
             Field::Text(TextField::Description) => {
                 IndexingOption::Text(self.default_text_options().set_stored())
             }
+            Field::Text(TextField::Headings) => IndexingOption::Text(self.default_text_options()),
+            Field::Text(TextField::ImageAlt) => IndexingOption::Text(self.default_text_options()),
             Field::Fast(FastField::IsHomepage) => IndexingOption::Integer(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -195,6 +225,14 @@ impl Field {
                     .set_fast(Cardinality::SingleValue)
                     .set_indexed(),
             ),
+            Field::Fast(FastField::NumAnalyticsTrackers)
+            | Field::Fast(FastField::NumAdvertisingTrackers)
+            | Field::Fast(FastField::NumSocialTrackers)
+            | Field::Fast(FastField::NumCdnHosts) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_indexed(),
+            ),
             Field::Fast(FastField::LastUpdated) => IndexingOption::Integer(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -207,6 +245,12 @@ impl Field {
                     .set_stored()
                     .set_indexed(),
             ),
+            Field::Fast(FastField::Lang) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_stored()
+                    .set_indexed(),
+            ),
             Field::Fast(FastField::NumCleanBodyTokens) => IndexingOption::Integer(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -217,6 +261,11 @@ impl Field {
                     .set_fast(Cardinality::SingleValue)
                     .set_indexed(),
             ),
+            Field::Fast(FastField::NumHeadingTokens) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_indexed(),
+            ),
             Field::Fast(FastField::NumTitleTokens) => IndexingOption::Integer(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -242,6 +291,17 @@ impl Field {
             Field::Fast(FastField::TitleHash) => IndexingOption::Integer(
                 NumericOptions::default().set_fast(Cardinality::MultiValues),
             ),
+            Field::Fast(FastField::ContentType) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_stored()
+                    .set_indexed(),
+            ),
+            Field::Fast(FastField::SimHash) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_indexed(),
+            ),
             Field::Fast(FastField::PreComputedScore) => IndexingOption::Float(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -269,6 +329,9 @@ impl Field {
             }
             Field::Text(TextField::Description) => "description",
             Field::Text(TextField::PrimaryImage) => "primary_image_uuid",
+            Field::Text(TextField::SchemaOrg) => "schema_org",
+            Field::Text(TextField::Headings) => "headings",
+            Field::Text(TextField::ImageAlt) => "image_alt",
             Field::Text(TextField::TitleIfHomepage) => "title_if_homepage",
             Field::Text(TextField::AllBody) => "all_body",
             Field::Fast(FastField::HostCentrality) => "host_centrality",
@@ -288,6 +351,14 @@ impl Field {
             Field::Fast(FastField::TitleHash) => "title_hash",
             Field::Fast(FastField::UrlHash) => "url_hash",
             Field::Fast(FastField::DomainHash) => "domain_hash",
+            Field::Fast(FastField::ContentType) => "content_type",
+            Field::Fast(FastField::SimHash) => "simhash",
+            Field::Fast(FastField::NumAnalyticsTrackers) => "num_analytics_trackers",
+            Field::Fast(FastField::NumAdvertisingTrackers) => "num_advertising_trackers",
+            Field::Fast(FastField::NumSocialTrackers) => "num_social_trackers",
+            Field::Fast(FastField::NumCdnHosts) => "num_cdn_hosts",
+            Field::Fast(FastField::Lang) => "lang",
+            Field::Fast(FastField::NumHeadingTokens) => "num_heading_tokens",
         }
     }
 
@@ -306,10 +377,13 @@ impl Field {
             Field::Text(TextField::Domain) => Some(1.0),
             Field::Text(TextField::AllBody) => Some(0.01),
             Field::Text(TextField::BacklinkText) => Some(4.0),
+            Field::Text(TextField::Headings) => Some(6.0),
+            Field::Text(TextField::ImageAlt) => Some(1.0),
             Field::Text(TextField::SiteNoTokenizer)
             | Field::Text(TextField::DomainNoTokenizer)
             | Field::Text(TextField::Description)
-            | Field::Text(TextField::PrimaryImage) => None,
+            | Field::Text(TextField::PrimaryImage)
+            | Field::Text(TextField::SchemaOrg) => None,
             Field::Fast(_) => None,
         }
     }
@@ -317,7 +391,9 @@ impl Field {
     pub fn is_searchable(&self) -> bool {
         !matches!(
             self,
-            Field::Text(TextField::PrimaryImage) | Field::Text(TextField::BacklinkText)
+            Field::Text(TextField::PrimaryImage)
+                | Field::Text(TextField::BacklinkText)
+                | Field::Text(TextField::SchemaOrg)
         ) && !self.is_fast()
     }
 
@@ -337,6 +413,9 @@ impl Field {
             "domain" => Some(Field::Text(TextField::Domain)),
             "domain_if_homepage" => Some(Field::Text(TextField::DomainIfHomepage)),
             "primary_image_uuid" => Some(Field::Text(TextField::PrimaryImage)),
+            "schema_org" => Some(Field::Text(TextField::SchemaOrg)),
+            "headings" => Some(Field::Text(TextField::Headings)),
+            "image_alt" => Some(Field::Text(TextField::ImageAlt)),
             "domain_name_if_homepage_no_tokenizer" => {
                 Some(Field::Text(TextField::DomainNameIfHomepageNoTokenizer))
             }
@@ -356,6 +435,14 @@ impl Field {
             "url_hash" => Some(Field::Fast(FastField::UrlHash)),
             "domain_hash" => Some(Field::Fast(FastField::DomainHash)),
             "title_hash" => Some(Field::Fast(FastField::TitleHash)),
+            "content_type" => Some(Field::Fast(FastField::ContentType)),
+            "simhash" => Some(Field::Fast(FastField::SimHash)),
+            "num_analytics_trackers" => Some(Field::Fast(FastField::NumAnalyticsTrackers)),
+            "num_advertising_trackers" => Some(Field::Fast(FastField::NumAdvertisingTrackers)),
+            "num_social_trackers" => Some(Field::Fast(FastField::NumSocialTrackers)),
+            "num_cdn_hosts" => Some(Field::Fast(FastField::NumCdnHosts)),
+            "lang" => Some(Field::Fast(FastField::Lang)),
+            "num_heading_tokens" => Some(Field::Fast(FastField::NumHeadingTokens)),
             _ => None,
         }
     }
@@ -423,6 +510,14 @@ impl FastField {
             FastField::UrlHash => DataType::U64s,
             FastField::DomainHash => DataType::U64s,
             FastField::PreComputedScore => DataType::F64,
+            FastField::ContentType => DataType::U64,
+            FastField::SimHash => DataType::U64,
+            FastField::NumAnalyticsTrackers => DataType::U64,
+            FastField::NumAdvertisingTrackers => DataType::U64,
+            FastField::NumSocialTrackers => DataType::U64,
+            FastField::NumCdnHosts => DataType::U64,
+            FastField::Lang => DataType::U64,
+            FastField::NumHeadingTokens => DataType::U64,
         }
     }
 }
@@ -14,26 +14,472 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+//! Structured data embedded in webpages as schema.org JSON-LD or microdata.
+//!
+//! Real world markup is loose: most properties can be a string, an object or an
+//! array of either, so everything is read through [`serde_json::Value`] and converted
+//! into the typed representation below. The typed representation is externally
+//! tagged so it can be stored with the document and sent between search servers.
+
+use itertools::Itertools;
+use kuchiki::NodeRef;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::Result;
 
 #[non_exhaustive]
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
-#[serde(tag = "@type")]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum SchemaOrg {
     ImageObject(ImageObject),
+    Article(Article),
+    Product(Product),
+    Recipe(Recipe),
+    FaqPage(FaqPage),
+    Organization(Organization),
+    BreadcrumbList(BreadcrumbList),
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct ImageObject {
     pub name: Option<String>,
     pub description: Option<String>,
     pub author: Option<String>,
-    #[serde(rename = "contentUrl")]
     pub content_url: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Article {
+    pub headline: Option<String>,
+    pub author: Option<String>,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    pub image: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Product {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub price: Option<Price>,
+    pub rating: Option<Rating>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Price {
+    pub amount: String,
+    pub currency: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Rating {
+    pub value: f64,
+    pub best: f64,
+    pub count: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Recipe {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub total_time: Option<String>,
+    pub ingredients: Vec<String>,
+    pub rating: Option<Rating>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct FaqPage {
+    pub questions: Vec<Question>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Question {
+    pub question: String,
+    pub answer: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Organization {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub logo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct BreadcrumbList {
+    pub items: Vec<Breadcrumb>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Breadcrumb {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+/// Parse a JSON-LD document. Top-level arrays and `@graph` containers are flattened
+/// and objects of unknown types are skipped, so only malformed JSON is an error.
+pub fn parse_json_ld(json: &str) -> Result<Vec<SchemaOrg>> {
+    let value: Value = serde_json::from_str(json)?;
+    let mut schemas = Vec::new();
+    collect(&value, &mut schemas);
+
+    Ok(schemas)
+}
+
+/// Extract all top-level microdata items (`itemscope` elements that are not
+/// themselves the property of another item) below `root`.
+pub fn parse_microdata(root: &NodeRef) -> Vec<SchemaOrg> {
+    let mut schemas = Vec::new();
+
+    if let Ok(items) = root.select("[itemscope][itemtype]") {
+        for item in items.filter(|item| !item.attributes.borrow().contains("itemprop")) {
+            if let Value::Object(object) = microdata_item(item.as_node()) {
+                if let Some(schema) = SchemaOrg::from_object(&object) {
+                    schemas.push(schema);
+                }
+            }
+        }
+    }
+
+    schemas
+}
+
+fn collect(value: &Value, schemas: &mut Vec<SchemaOrg>) {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                collect(value, schemas);
+            }
+        }
+        Value::Object(object) => {
+            if let Some(graph) = object.get("@graph") {
+                collect(graph, schemas);
+            }
+
+            if let Some(schema) = SchemaOrg::from_object(object) {
+                schemas.push(schema);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn microdata_item(node: &NodeRef) -> Value {
+    let mut object = Map::new();
+
+    if let Some(element) = node.as_element() {
+        if let Some(item_type) = element.attributes.borrow().get("itemtype") {
+            object.insert("@type".to_string(), Value::String(item_type.to_string()));
+        }
+    }
+
+    for child in node.children() {
+        microdata_properties(&child, &mut object);
+    }
+
+    Value::Object(object)
+}
+
+fn microdata_properties(node: &NodeRef, object: &mut Map<String, Value>) {
+    let element = match node.as_element() {
+        Some(element) => element,
+        None => return,
+    };
+
+    let attributes = element.attributes.borrow();
+    let is_item = attributes.contains("itemscope");
+
+    if let Some(props) = attributes.get("itemprop") {
+        let value = if is_item {
+            microdata_item(node)
+        } else {
+            let value = ["content", "datetime", "href", "src"]
+                .iter()
+                .find_map(|attr| attributes.get(*attr))
+                .map(str::to_string)
+                .unwrap_or_else(|| node.text_contents());
+
+            Value::String(value.split_whitespace().join(" "))
+        };
+
+        for prop in props.split_whitespace() {
+            match object.get_mut(prop) {
+                Some(Value::Array(values)) => values.push(value.clone()),
+                Some(existing) => *existing = Value::Array(vec![existing.clone(), value.clone()]),
+                None => {
+                    object.insert(prop.to_string(), value.clone());
+                }
+            }
+        }
+    }
+
+    if !is_item {
+        for child in node.children() {
+            microdata_properties(&child, object);
+        }
+    }
+}
+
+fn types(object: &Map<String, Value>) -> Vec<&str> {
+    let types = match object.get("@type") {
+        Some(Value::String(schema_type)) => vec![schema_type.as_str()],
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+
+    types
+        .into_iter()
+        .map(|schema_type| {
+            schema_type
+                .trim_start_matches("http://")
+                .trim_start_matches("https://")
+                .trim_start_matches("schema.org/")
+        })
+        .collect()
+}
+
+/// The textual value of a property. Objects are represented by their name (or url),
+/// and for arrays the first usable element is used.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => {
+            let s = s.trim();
+            if s.is_empty() {
+                None
+            } else {
+                Some(s.to_string())
+            }
+        }
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(values) => values.iter().find_map(text),
+        Value::Object(object) => ["name", "@value", "text", "url", "contentUrl"]
+            .iter()
+            .find_map(|key| object.get(*key).and_then(text)),
+        _ => None,
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().replace(',', ".").parse().ok(),
+        Value::Array(values) => values.iter().find_map(number),
+        Value::Object(object) => object.get("@value").and_then(number),
+        _ => None,
+    }
+}
+
+fn items(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    }
+}
+
+fn field(object: &Map<String, Value>, key: &str) -> Option<String> {
+    object.get(key).and_then(text)
+}
+
+/// Multiple authors are joined into a single comma separated string.
+fn authors(object: &Map<String, Value>) -> Option<String> {
+    let authors = object
+        .get("author")
+        .map(|author| items(author).into_iter().filter_map(text).join(", "))
+        .unwrap_or_default();
+
+    if authors.is_empty() {
+        None
+    } else {
+        Some(authors)
+    }
+}
+
+impl SchemaOrg {
+    fn from_object(object: &Map<String, Value>) -> Option<Self> {
+        types(object)
+            .into_iter()
+            .find_map(|schema_type| match schema_type {
+                "ImageObject" => Some(SchemaOrg::ImageObject(ImageObject::from_object(object))),
+                "Article"
+                | "NewsArticle"
+                | "BlogPosting"
+                | "ReportageNewsArticle"
+                | "TechArticle"
+                | "ScholarlyArticle" => Some(SchemaOrg::Article(Article::from_object(object))),
+                "Product" => Some(SchemaOrg::Product(Product::from_object(object))),
+                "Recipe" => Some(SchemaOrg::Recipe(Recipe::from_object(object))),
+                "FAQPage" => Some(SchemaOrg::FaqPage(FaqPage::from_object(object))),
+                "Organization" | "Corporation" | "NewsMediaOrganization" => {
+                    Some(SchemaOrg::Organization(Organization::from_object(object)))
+                }
+                "BreadcrumbList" => Some(SchemaOrg::BreadcrumbList(BreadcrumbList::from_object(
+                    object,
+                ))),
+                _ => None,
+            })
+    }
+}
+
+impl ImageObject {
+    fn from_object(object: &Map<String, Value>) -> Self {
+        Self {
+            name: field(object, "name"),
+            description: field(object, "description"),
+            author: authors(object),
+            content_url: field(object, "contentUrl"),
+        }
+    }
+}
+
+impl Article {
+    fn from_object(object: &Map<String, Value>) -> Self {
+        Self {
+            headline: field(object, "headline").or_else(|| field(object, "name")),
+            author: authors(object),
+            date_published: field(object, "datePublished"),
+            date_modified: field(object, "dateModified"),
+            image: field(object, "image"),
+        }
+    }
+}
+
+impl Product {
+    fn from_object(object: &Map<String, Value>) -> Self {
+        let price = object.get("offers").and_then(|offers| {
+            items(offers).into_iter().find_map(|offer| {
+                let offer = offer.as_object()?;
+                let amount = field(offer, "price").or_else(|| field(offer, "lowPrice"))?;
+
+                Some(Price {
+                    amount,
+                    currency: field(offer, "priceCurrency"),
+                })
+            })
+        });
+
+        Self {
+            name: field(object, "name"),
+            description: field(object, "description"),
+            price,
+            rating: Rating::from_parent(object),
+        }
+    }
+}
+
+impl Rating {
+    fn from_parent(object: &Map<String, Value>) -> Option<Self> {
+        let rating = object.get("aggregateRating")?.as_object()?;
+        let value = rating.get("ratingValue").and_then(number)?;
+        let best = rating.get("bestRating").and_then(number).unwrap_or(5.0);
+
+        if best <= 0.0 || value < 0.0 || value > best {
+            return None;
+        }
+
+        let count = rating
+            .get("ratingCount")
+            .or_else(|| rating.get("reviewCount"))
+            .and_then(number)
+            .map(|count| count as u64);
+
+        Some(Self { value, best, count })
+    }
+
+    /// The rating on a scale from 0 to 5.
+    pub fn normalized(&self) -> f64 {
+        self.value / self.best * 5.0
+    }
+}
+
+impl Recipe {
+    fn from_object(object: &Map<String, Value>) -> Self {
+        let ingredients = object
+            .get("recipeIngredient")
+            .or_else(|| object.get("ingredients"))
+            .map(|ingredients| items(ingredients).into_iter().filter_map(text).collect())
+            .unwrap_or_default();
+
+        Self {
+            name: field(object, "name"),
+            author: authors(object),
+            total_time: field(object, "totalTime"),
+            ingredients,
+            rating: Rating::from_parent(object),
+        }
+    }
+}
+
+impl FaqPage {
+    fn from_object(object: &Map<String, Value>) -> Self {
+        let questions = object
+            .get("mainEntity")
+            .map(|entities| {
+                items(entities)
+                    .into_iter()
+                    .filter_map(Value::as_object)
+                    .filter_map(|question| {
+                        Some(Question {
+                            question: field(question, "name")?,
+                            answer: question.get("acceptedAnswer").and_then(text),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self { questions }
+    }
+}
+
+impl Organization {
+    fn from_object(object: &Map<String, Value>) -> Self {
+        Self {
+            name: field(object, "name"),
+            url: field(object, "url"),
+            logo: field(object, "logo"),
+        }
+    }
+}
+
+impl BreadcrumbList {
+    fn from_object(object: &Map<String, Value>) -> Self {
+        let mut crumbs: Vec<_> = object
+            .get("itemListElement")
+            .map(|elements| {
+                items(elements)
+                    .into_iter()
+                    .filter_map(Value::as_object)
+                    .filter_map(|element| {
+                        let position = element.get("position").and_then(number);
+                        let item = element.get("item");
+                        let name = field(element, "name").or_else(|| item.and_then(text))?;
+                        let url = item
+                            .and_then(|item| match item {
+                                Value::Object(item) => {
+                                    field(item, "@id").or_else(|| field(item, "url"))
+                                }
+                                item => text(item),
+                            })
+                            .filter(|url| url != &name);
+
+                        Some((position, Breadcrumb { name, url }))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        crumbs.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        Self {
+            items: crumbs.into_iter().map(|(_, crumb)| crumb).collect(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use kuchiki::traits::TendrilSink;
+
     use super::*;
 
     #[test]
@@ -52,15 +498,201 @@ mod tests {
           }
         "#;
 
-        let parsed: SchemaOrg = serde_json::from_str(json).unwrap();
+        let parsed = parse_json_ld(json).unwrap();
         assert_eq!(
             parsed,
-            SchemaOrg::ImageObject(ImageObject {
+            vec![SchemaOrg::ImageObject(ImageObject {
                 name: Some("Beach in Mexico".to_string()),
                 description: Some("I took this picture while on vacation last year.".to_string()),
                 author: Some("Jane Doe".to_string()),
                 content_url: Some("mexico-beach.jpg".to_string()),
-            }),
+            })],
+        );
+    }
+
+    #[test]
+    fn graph_with_article_and_breadcrumbs() {
+        let json = r#"
+        {
+            "@context": "https://schema.org",
+            "@graph": [
+                {
+                    "@type": ["NewsArticle", "Article"],
+                    "headline": "Something happened",
+                    "author": [
+                        {"@type": "Person", "name": "Jane Doe"},
+                        {"@type": "Person", "name": "John Doe"}
+                    ],
+                    "datePublished": "2022-08-01T10:00:00+02:00",
+                    "image": {"@type": "ImageObject", "url": "https://example.com/a.jpg"}
+                },
+                {
+                    "@type": "BreadcrumbList",
+                    "itemListElement": [
+                        {"@type": "ListItem", "position": 2, "name": "World", "item": "https://example.com/world"},
+                        {"@type": "ListItem", "position": 1, "name": "News", "item": {"@id": "https://example.com/news"}},
+                        {"@type": "ListItem", "position": 3, "name": "Something happened"}
+                    ]
+                },
+                {
+                    "@type": "WebSite",
+                    "name": "Example"
+                }
+            ]
+        }
+        "#;
+
+        let parsed = parse_json_ld(json).unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                SchemaOrg::Article(Article {
+                    headline: Some("Something happened".to_string()),
+                    author: Some("Jane Doe, John Doe".to_string()),
+                    date_published: Some("2022-08-01T10:00:00+02:00".to_string()),
+                    date_modified: None,
+                    image: Some("https://example.com/a.jpg".to_string()),
+                }),
+                SchemaOrg::BreadcrumbList(BreadcrumbList {
+                    items: vec![
+                        Breadcrumb {
+                            name: "News".to_string(),
+                            url: Some("https://example.com/news".to_string()),
+                        },
+                        Breadcrumb {
+                            name: "World".to_string(),
+                            url: Some("https://example.com/world".to_string()),
+                        },
+                        Breadcrumb {
+                            name: "Something happened".to_string(),
+                            url: None,
+                        },
+                    ]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn product_recipe_faq_and_organization() {
+        let json = r#"
+        [
+            {
+                "@context": "https://schema.org",
+                "@type": "Product",
+                "name": "Executive Anvil",
+                "offers": {"@type": "Offer", "price": 119.99, "priceCurrency": "USD"},
+                "aggregateRating": {"@type": "AggregateRating", "ratingValue": "4.4", "reviewCount": "89"}
+            },
+            {
+                "@type": "Recipe",
+                "name": "Pancakes",
+                "author": "Jane Doe",
+                "totalTime": "PT20M",
+                "recipeIngredient": ["flour", "milk", "eggs"],
+                "aggregateRating": {"ratingValue": 9, "bestRating": 10, "ratingCount": 12}
+            },
+            {
+                "@type": "FAQPage",
+                "mainEntity": [{
+                    "@type": "Question",
+                    "name": "What is a search engine?",
+                    "acceptedAnswer": {"@type": "Answer", "text": "A program that finds webpages."}
+                }]
+            },
+            {
+                "@type": "Organization",
+                "name": "Example Inc.",
+                "url": "https://example.com",
+                "logo": "https://example.com/logo.png"
+            }
+        ]
+        "#;
+
+        let parsed = parse_json_ld(json).unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                SchemaOrg::Product(Product {
+                    name: Some("Executive Anvil".to_string()),
+                    description: None,
+                    price: Some(Price {
+                        amount: "119.99".to_string(),
+                        currency: Some("USD".to_string()),
+                    }),
+                    rating: Some(Rating {
+                        value: 4.4,
+                        best: 5.0,
+                        count: Some(89),
+                    }),
+                }),
+                SchemaOrg::Recipe(Recipe {
+                    name: Some("Pancakes".to_string()),
+                    author: Some("Jane Doe".to_string()),
+                    total_time: Some("PT20M".to_string()),
+                    ingredients: vec!["flour".to_string(), "milk".to_string(), "eggs".to_string()],
+                    rating: Some(Rating {
+                        value: 9.0,
+                        best: 10.0,
+                        count: Some(12),
+                    }),
+                }),
+                SchemaOrg::FaqPage(FaqPage {
+                    questions: vec![Question {
+                        question: "What is a search engine?".to_string(),
+                        answer: Some("A program that finds webpages.".to_string()),
+                    }]
+                }),
+                SchemaOrg::Organization(Organization {
+                    name: Some("Example Inc.".to_string()),
+                    url: Some("https://example.com".to_string()),
+                    logo: Some("https://example.com/logo.png".to_string()),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_json_ld() {
+        assert!(parse_json_ld(r#"{"@type": "Article",}"#).is_err());
+        assert!(parse_json_ld(r#"{"invalid": "schema"}"#)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn microdata_product() {
+        let html = r#"
+        <div itemscope itemtype="https://schema.org/Product">
+            <h1 itemprop="name">Executive   Anvil</h1>
+            <div itemprop="aggregateRating" itemscope itemtype="https://schema.org/AggregateRating">
+                Rated <span itemprop="ratingValue">3.5</span>/5
+                based on <span itemprop="reviewCount">11</span> reviews
+            </div>
+            <div itemprop="offers" itemscope itemtype="https://schema.org/Offer">
+                <meta itemprop="priceCurrency" content="EUR" />
+                <span itemprop="price" content="55.00">€55</span>
+            </div>
+        </div>
+        "#;
+
+        let root = kuchiki::parse_html().one(html);
+
+        assert_eq!(
+            parse_microdata(&root),
+            vec![SchemaOrg::Product(Product {
+                name: Some("Executive Anvil".to_string()),
+                description: None,
+                price: Some(Price {
+                    amount: "55.00".to_string(),
+                    currency: Some("EUR".to_string()),
+                }),
+                rating: Some(Rating {
+                    value: 3.5,
+                    best: 5.0,
+                    count: Some(11),
+                }),
+            })]
         );
     }
 }
@@ -16,13 +16,14 @@
 
 use std::collections::BTreeMap;
 
-use chrono::{NaiveDate, NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use itertools::{intersperse, Itertools};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     entity_index::{entity::Span, StoredEntity},
     inverted_index::{self, RetrievedWebpage},
+    schema_org::{Rating, SchemaOrg},
     searcher::{self, LocalSearcher},
     webpage::Url,
 };
@@ -91,6 +92,114 @@ pub struct DisplayedWebpage {
     pub body: String,
     pub primary_image_uuid: Option<String>,
     pub last_updated: Option<String>,
+    pub published: Option<String>,
+    pub breadcrumbs: Vec<DisplayedBreadcrumb>,
+    pub rating: Option<DisplayedRating>,
+    pub price: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DisplayedBreadcrumb {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DisplayedRating {
+    pub stars: String,
+    pub value: String,
+    pub count: Option<String>,
+}
+
+impl From<&Rating> for DisplayedRating {
+    fn from(rating: &Rating) -> Self {
+        let normalized = rating.normalized();
+        let full = (normalized.round() as usize).min(5);
+
+        Self {
+            stars: "★".repeat(full) + "☆".repeat(5 - full).as_str(),
+            value: format!("{normalized:.1}"),
+            count: rating
+                .count
+                .map(|count| thousand_sep_number(count as usize)),
+        }
+    }
+}
+
+/// The urls are controlled by the page, so they are resolved against the url of the page
+/// and only kept if they are http(s). Otherwise the breadcrumb is shown without a link.
+fn breadcrumbs(schemas: &[SchemaOrg], page_url: &Url) -> Vec<DisplayedBreadcrumb> {
+    schemas
+        .iter()
+        .find_map(|schema| match schema {
+            SchemaOrg::BreadcrumbList(list) if !list.items.is_empty() => Some(
+                list.items
+                    .iter()
+                    .map(|item| DisplayedBreadcrumb {
+                        name: html_escape(&item.name),
+                        url: item
+                            .url
+                            .as_deref()
+                            .and_then(|url| page_url.resolve(url))
+                            .map(|url| url.full()),
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn rating(schemas: &[SchemaOrg]) -> Option<DisplayedRating> {
+    schemas.iter().find_map(|schema| match schema {
+        SchemaOrg::Product(product) => product.rating.as_ref().map(DisplayedRating::from),
+        SchemaOrg::Recipe(recipe) => recipe.rating.as_ref().map(DisplayedRating::from),
+        _ => None,
+    })
+}
+
+/// The price is written by the page and rendered unescaped, so it is only shown when
+/// the amount is a number and the currency looks like an ISO 4217 code.
+fn price(schemas: &[SchemaOrg]) -> Option<String> {
+    schemas.iter().find_map(|schema| match schema {
+        SchemaOrg::Product(product) => {
+            let price = product.price.as_ref()?;
+            let amount = price.amount.trim();
+
+            if !matches!(amount.parse::<f64>(), Ok(amount) if amount.is_finite()) {
+                return None;
+            }
+
+            match price.currency.as_deref().map(str::trim) {
+                Some(currency)
+                    if currency.len() == 3 && currency.chars().all(|c| c.is_ascii_uppercase()) =>
+                {
+                    Some(format!("{amount} {currency}"))
+                }
+                Some(_) => None,
+                None => Some(amount.to_string()),
+            }
+        }
+        _ => None,
+    })
+}
+
+fn published(schemas: &[SchemaOrg]) -> Option<String> {
+    schemas
+        .iter()
+        .find_map(|schema| match schema {
+            SchemaOrg::Article(article) => article.date_published.as_deref(),
+            _ => None,
+        })
+        .and_then(|date| {
+            DateTime::parse_from_rfc3339(date)
+                .map(|date| date.naive_utc())
+                .or_else(|_| {
+                    NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|date| date.and_hms(0, 0, 0))
+                })
+                .ok()
+        })
+        .map(prettify_date)
 }
 
 fn prettify_url(url: &Url) -> String {
@@ -149,6 +258,8 @@ impl From<RetrievedWebpage> for DisplayedWebpage {
             .map(|favicon| favicon.as_raw_bytes())
             .unwrap_or_else(|| include_bytes!("../frontend/dist/images/globe.png").to_vec());
 
+        let schemas = webpage.schema_org;
+
         Self {
             title,
             site: url.site().to_string(),
@@ -160,6 +271,10 @@ impl From<RetrievedWebpage> for DisplayedWebpage {
             body: webpage.body,
             primary_image_uuid: webpage.primary_image.map(|image| image.uuid.to_string()),
             last_updated,
+            published: published(&schemas),
+            breadcrumbs: breadcrumbs(&schemas, &url),
+            rating: rating(&schemas),
+            price: price(&schemas),
         }
     }
 }
@@ -354,7 +469,10 @@ fn entity_link_to_html(span: Span, trunace_to: usize) -> String {
 mod tests {
     use chrono::{DateTime, Utc};
 
-    use crate::entity_index::entity::Link;
+    use crate::{
+        entity_index::entity::Link,
+        schema_org::{Breadcrumb, BreadcrumbList, Price, Product},
+    };
 
     use super::*;
 
@@ -437,6 +555,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rich_results() {
+        let schemas = vec![
+            SchemaOrg::BreadcrumbList(BreadcrumbList {
+                items: vec![
+                    Breadcrumb {
+                        name: "Tools".to_string(),
+                        url: Some("https://example.com/tools".to_string()),
+                    },
+                    Breadcrumb {
+                        name: "Anvils".to_string(),
+                        url: Some("anvils".to_string()),
+                    },
+                    Breadcrumb {
+                        name: "Executive".to_string(),
+                        url: Some("javascript:alert(1)".to_string()),
+                    },
+                    Breadcrumb {
+                        name: "Sale".to_string(),
+                        url: None,
+                    },
+                ],
+            }),
+            SchemaOrg::Product(Product {
+                name: Some("Executive Anvil".to_string()),
+                description: None,
+                price: Some(Price {
+                    amount: "119.99".to_string(),
+                    currency: Some("USD".to_string()),
+                }),
+                rating: Some(Rating {
+                    value: 8.6,
+                    best: 10.0,
+                    count: Some(1200),
+                }),
+            }),
+        ];
+
+        assert_eq!(
+            breadcrumbs(
+                &schemas,
+                &Url::from("https://example.com/tools/".to_string())
+            ),
+            vec![
+                DisplayedBreadcrumb {
+                    name: "Tools".to_string(),
+                    url: Some("https://example.com/tools".to_string()),
+                },
+                DisplayedBreadcrumb {
+                    name: "Anvils".to_string(),
+                    url: Some("https://example.com/tools/anvils".to_string()),
+                },
+                DisplayedBreadcrumb {
+                    name: "Executive".to_string(),
+                    url: None,
+                },
+                DisplayedBreadcrumb {
+                    name: "Sale".to_string(),
+                    url: None,
+                },
+            ]
+        );
+        assert_eq!(
+            rating(&schemas),
+            Some(DisplayedRating {
+                stars: "★★★★☆".to_string(),
+                value: "4.3".to_string(),
+                count: Some("1.200".to_string()),
+            })
+        );
+        assert_eq!(price(&schemas), Some("119.99 USD".to_string()));
+        assert_eq!(published(&schemas), None);
+    }
+
+    #[test]
+    fn unsafe_prices_not_shown() {
+        let product = |amount: &str, currency: Option<&str>| {
+            vec![SchemaOrg::Product(Product {
+                name: None,
+                description: None,
+                price: Some(Price {
+                    amount: amount.to_string(),
+                    currency: currency.map(str::to_string),
+                }),
+                rating: None,
+            })]
+        };
+
+        assert_eq!(price(&product(" 5 ", None)), Some("5".to_string()));
+        assert_eq!(price(&product("<script>alert(1)</script>", None)), None);
+        assert_eq!(price(&product("5", Some("<b>USD</b>"))), None);
+        assert_eq!(price(&product("5", Some("usd"))), None);
+        assert_eq!(price(&product("NaN", Some("EUR"))), None);
+    }
+
     #[test]
     fn sep_number() {
         assert_eq!(thousand_sep_number(0), "0".to_string());
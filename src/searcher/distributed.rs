@@ -15,10 +15,11 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
+    bangs::{Bang, Bangs},
     collector::{self, BucketCollector},
     exponential_backoff::ExponentialBackoff,
     inverted_index::{self, RetrievedWebpage},
-    search_prettifier::DisplayedWebpage,
+    search_prettifier::{self, DisplayedWebpage},
     searcher::{PrettifiedWebsitesResult, SearchResult, WebsitesResult, NUM_RESULTS_PER_PAGE},
 };
 
@@ -31,9 +32,7 @@ use thiserror::Error;
 
 use crate::sonic;
 
-use super::{
-    InitialPrettifiedSearchResult, InitialSearchResult, PrettifiedSearchResult, SearchQuery,
-};
+use super::{InitialWebsiteResult, PrettifiedSearchResult, SearchQuery};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -51,7 +50,7 @@ pub enum Error {
 }
 
 impl RemoteSearcher {
-    async fn search(&self, query: &SearchQuery) -> Result<InitialSearchResult> {
+    async fn search(&self, query: &SearchQuery) -> Result<InitialWebsiteResult> {
         for timeout in ExponentialBackoff::from_millis(30).take(5) {
             if let Ok(connection) = sonic::Connection::create_with_timeout(self.addr, timeout).await
             {
@@ -69,7 +68,7 @@ impl RemoteSearcher {
     async fn search_prettified(
         &self,
         query: &SearchQuery,
-    ) -> Result<InitialPrettifiedSearchResult> {
+    ) -> Result<search_prettifier::InitialWebsiteResult> {
         for timeout in ExponentialBackoff::from_millis(30).take(5) {
             if let Ok(connection) = sonic::Connection::create_with_timeout(self.addr, timeout).await
             {
@@ -231,12 +230,12 @@ impl Shard {
 }
 
 struct InitialSearchResultShard {
-    local_result: InitialSearchResult,
+    local_result: InitialWebsiteResult,
     shard: ShardId,
 }
 
 struct InitialPrettifiedSearchResultShard {
-    local_result: InitialPrettifiedSearchResult,
+    local_result: search_prettifier::InitialWebsiteResult,
     shard: ShardId,
 }
 
@@ -256,6 +255,7 @@ pub enum Request {
 
 pub struct DistributedSearcher {
     shards: Vec<Shard>,
+    bangs: Bangs,
 }
 
 #[derive(Clone)]
@@ -279,8 +279,13 @@ impl collector::Doc for ScoredWebsitePointer {
 }
 
 impl DistributedSearcher {
-    pub fn new(shards: Vec<Shard>) -> Self {
-        Self { shards }
+    pub fn new(shards: Vec<Shard>, bangs: Bangs) -> Self {
+        Self { shards, bangs }
+    }
+
+    /// Bangs where the tag starts with `query`, or where the site or domain contains it.
+    pub fn bangs(&self, query: &str) -> Vec<Bang> {
+        self.bangs.search(query)
     }
 
     pub async fn search_api(&self, query: &SearchQuery) -> Result<SearchResult> {
@@ -290,6 +295,11 @@ impl DistributedSearcher {
             return Err(Error::EmptyQuery);
         }
 
+        // bangs are resolved here so they redirect without searching the shards
+        if let Some(bang) = self.bangs.get(&query.original, &query.bangs) {
+            return Ok(SearchResult::Bang(bang));
+        }
+
         // search shards
         let initial_results = self
             .shards
@@ -302,41 +312,17 @@ impl DistributedSearcher {
             .filter_map(|result| result.ok())
             .collect::<Vec<_>>();
 
-        // check if any result has a bang hit
-        if let Some(result) = initial_results
-            .iter()
-            .find(|result| matches!(result.local_result, InitialSearchResult::Bang(_)))
-        {
-            if let InitialSearchResult::Bang(bang) = &result.local_result {
-                return Ok(SearchResult::Bang(bang.clone()));
-            }
-        }
+        let spell_corrected_query = initial_results
+            .first()
+            .and_then(|result| result.local_result.spell_corrected_query.clone());
 
-        let spell_corrected_query = initial_results.first().and_then(|result| {
-            if let InitialSearchResult::Websites(result) = &result.local_result {
-                result.spell_corrected_query.clone()
-            } else {
-                None
-            }
-        });
-
-        let entity = initial_results.first().and_then(|result| {
-            if let InitialSearchResult::Websites(result) = &result.local_result {
-                result.entity.clone()
-            } else {
-                None
-            }
-        });
+        let entity = initial_results
+            .first()
+            .and_then(|result| result.local_result.entity.clone());
 
         let num_docs = initial_results
             .iter()
-            .map(|result| {
-                if let InitialSearchResult::Websites(result) = &result.local_result {
-                    result.websites.num_websites
-                } else {
-                    0
-                }
-            })
+            .map(|result| result.local_result.websites.num_websites)
             .sum();
 
         // combine results
@@ -344,15 +330,13 @@ impl DistributedSearcher {
         let mut collector = BucketCollector::new(top_n);
 
         for result in initial_results {
-            if let InitialSearchResult::Websites(local_result) = result.local_result {
-                for website in local_result.websites.top_websites {
-                    let pointer = ScoredWebsitePointer {
-                        local_pointer: website,
-                        shard: result.shard.clone(),
-                    };
-
-                    collector.insert(pointer);
-                }
+            for website in result.local_result.websites.top_websites {
+                let pointer = ScoredWebsitePointer {
+                    local_pointer: website,
+                    shard: result.shard.clone(),
+                };
+
+                collector.insert(pointer);
             }
         }
 
@@ -411,6 +395,11 @@ impl DistributedSearcher {
             return Err(Error::EmptyQuery);
         }
 
+        // bangs are resolved here so they redirect without searching the shards
+        if let Some(bang) = self.bangs.get(&query.original, &query.bangs) {
+            return Ok(PrettifiedSearchResult::Bang(bang));
+        }
+
         // search shards
         let initial_results = self
             .shards
@@ -423,41 +412,17 @@ impl DistributedSearcher {
             .filter_map(|result| result.ok())
             .collect::<Vec<_>>();
 
-        // check if any result has a bang hit
-        if let Some(result) = initial_results
-            .iter()
-            .find(|result| matches!(result.local_result, InitialPrettifiedSearchResult::Bang(_)))
-        {
-            if let InitialPrettifiedSearchResult::Bang(bang) = &result.local_result {
-                return Ok(PrettifiedSearchResult::Bang(bang.clone()));
-            }
-        }
+        let spell_corrected_query = initial_results
+            .first()
+            .and_then(|result| result.local_result.spell_corrected_query.clone());
 
-        let spell_corrected_query = initial_results.first().and_then(|result| {
-            if let InitialPrettifiedSearchResult::Websites(result) = &result.local_result {
-                result.spell_corrected_query.clone()
-            } else {
-                None
-            }
-        });
-
-        let entity = initial_results.first().and_then(|result| {
-            if let InitialPrettifiedSearchResult::Websites(result) = &result.local_result {
-                result.entity.clone()
-            } else {
-                None
-            }
-        });
+        let entity = initial_results
+            .first()
+            .and_then(|result| result.local_result.entity.clone());
 
         let num_docs = initial_results
             .iter()
-            .map(|result| {
-                if let InitialPrettifiedSearchResult::Websites(result) = &result.local_result {
-                    result.websites.num_websites
-                } else {
-                    0
-                }
-            })
+            .map(|result| result.local_result.websites.num_websites)
             .sum();
 
         // combine results
@@ -465,15 +430,13 @@ impl DistributedSearcher {
         let mut collector = BucketCollector::new(top_n);
 
         for result in initial_results {
-            if let InitialPrettifiedSearchResult::Websites(local_result) = result.local_result {
-                for website in local_result.websites.top_websites {
-                    let pointer = ScoredWebsitePointer {
-                        local_pointer: website,
-                        shard: result.shard.clone(),
-                    };
-
-                    collector.insert(pointer);
-                }
+            for website in result.local_result.websites.top_websites {
+                let pointer = ScoredWebsitePointer {
+                    local_pointer: website,
+                    shard: result.shard.clone(),
+                };
+
+                collector.insert(pointer);
             }
         }
 
@@ -526,3 +489,38 @@ impl DistributedSearcher {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bangs_redirect_without_shards() {
+        let bangs = Bangs::from_json(r#"[{"t": "w", "u": "https://en.wikipedia.org/?q={{{s}}}"}]"#)
+            .unwrap();
+        let searcher = DistributedSearcher::new(vec![], bangs);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let query = SearchQuery {
+            original: "!w rust".to_string(),
+            selected_region: None,
+            goggles: Vec::new(),
+            bangs: Vec::new(),
+            skip_pages: None,
+        };
+
+        match runtime.block_on(searcher.search_api(&query)).unwrap() {
+            SearchResult::Bang(hit) => {
+                assert_eq!(hit.redirect_to.full(), "https://en.wikipedia.org/?q=rust")
+            }
+            SearchResult::Websites(_) => panic!("bang query should not search the shards"),
+        }
+
+        assert!(matches!(
+            runtime
+                .block_on(searcher.search_prettified(&query))
+                .unwrap(),
+            PrettifiedSearchResult::Bang(_)
+        ));
+    }
+}
@@ -15,33 +15,47 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::Instant;
 
+use lru::LruCache;
 use serde::{Deserialize, Serialize};
+use tantivy::query::{Occur, QueryClone};
 use uuid::Uuid;
 
-use crate::bangs::Bangs;
 use crate::entity_index::{EntityIndex, StoredEntity};
 use crate::image_store::Image;
 use crate::index::Index;
 use crate::query::Query;
-use crate::ranking::goggles;
+use crate::ranking::goggles::GoggleHash;
 use crate::ranking::{Ranker, SignalAggregator};
 use crate::webpage::region::Region;
 use crate::webpage::Url;
 use crate::{inverted_index, Error, Result};
 
-use super::{InitialSearchResult, SearchQuery, SearchResult, WebsitesResult, NUM_RESULTS_PER_PAGE};
+use super::{SearchQuery, SearchResult, WebsitesResult, NUM_RESULTS_PER_PAGE};
+
+/// Number of goggles whose tantivy queries are kept between searches.
+const GOGGLE_CACHE_SIZE: usize = 256;
+
+type GoggleQueries = Vec<(Occur, Box<dyn tantivy::query::Query>)>;
+
+fn box_clone_queries(queries: &GoggleQueries) -> GoggleQueries {
+    queries
+        .iter()
+        .map(|(occur, query)| (*occur, query.box_clone()))
+        .collect()
+}
 
 pub struct LocalSearcher {
     index: Index,
     entity_index: Option<EntityIndex>,
-    bangs: Option<Bangs>,
+    goggle_cache: Mutex<LruCache<GoggleHash, GoggleQueries>>,
 }
 
 impl From<Index> for LocalSearcher {
     fn from(index: Index) -> Self {
-        Self::new(index, None, None)
+        Self::new(index, None)
     }
 }
 
@@ -53,60 +67,71 @@ pub struct InitialWebsiteResult {
 }
 
 impl LocalSearcher {
-    pub fn new(index: Index, entity_index: Option<EntityIndex>, bangs: Option<Bangs>) -> Self {
+    pub fn new(index: Index, entity_index: Option<EntityIndex>) -> Self {
         LocalSearcher {
             index,
             entity_index,
-            bangs,
+            goggle_cache: Mutex::new(LruCache::new(GOGGLE_CACHE_SIZE)),
         }
     }
 
+    fn goggle_queries(&self, query: &SearchQuery) -> GoggleQueries {
+        let mut queries = Vec::new();
+
+        for compiled in &query.goggles {
+            let cached = self
+                .goggle_cache
+                .lock()
+                .unwrap()
+                .get(&compiled.hash)
+                .map(box_clone_queries);
+
+            match cached {
+                Some(cached) => queries.extend(cached),
+                None => {
+                    // the lock is not held while building the queries, so a slow goggle
+                    // doesn't block the searches that use other goggles.
+                    let goggle_queries = compiled.goggle.as_tantivy(&self.index.schema());
+
+                    queries.extend(box_clone_queries(&goggle_queries));
+                    self.goggle_cache
+                        .lock()
+                        .unwrap()
+                        .put(compiled.hash, goggle_queries);
+                }
+            }
+        }
+
+        queries
+    }
+
     pub fn search_initial(
         &self,
         query: &SearchQuery,
         de_rank_similar: bool,
-    ) -> Result<InitialSearchResult> {
+    ) -> Result<InitialWebsiteResult> {
         let raw_query = query.original.clone();
-        let goggle = query
-            .goggle_program
-            .as_ref()
-            .and_then(|program| goggles::parse(program).ok());
+        let mut aggregator = SignalAggregator::default();
+        for compiled in &query.goggles {
+            aggregator.merge(&compiled.goggle.aggregator);
+        }
 
         let mut parsed_query = Query::parse(
             &query.original,
             self.index.schema(),
             self.index.tokenizers(),
-            goggle
-                .as_ref()
-                .map(|goggle| &goggle.aggregator)
-                .unwrap_or(&SignalAggregator::default()),
+            &aggregator,
         )?;
 
         if parsed_query.is_empty() {
             return Err(Error::EmptyQuery);
         }
 
-        if let Some(bangs) = self.bangs.as_ref() {
-            if let Some(bang) = bangs.get(&parsed_query) {
-                return Ok(InitialSearchResult::Bang(bang));
-            }
-        }
-
-        let mut goggles = Vec::new();
-
-        if let Some(goggle) = &goggle {
-            goggles.push(goggle.clone());
-        }
-
-        if let Some(site_rankings) = &query.site_rankings {
-            goggles.push(site_rankings.clone().into_goggle())
-        }
-
-        parsed_query.set_goggles(&goggles, &self.index.schema());
+        parsed_query.add_goggle_queries(self.goggle_queries(query));
 
         let mut ranker = Ranker::new(
             self.index.region_count.clone(),
-            goggle.map(|goggle| goggle.aggregator).unwrap_or_default(),
+            aggregator,
             self.index.inverted_index.fastfield_cache(),
         );
 
@@ -115,7 +140,7 @@ impl LocalSearcher {
         }
 
         if let Some(region) = query.selected_region {
-            if region != Region::All {
+            if region != Region::ALL {
                 ranker = ranker.with_region(region);
             }
         }
@@ -133,11 +158,11 @@ impl LocalSearcher {
             .as_ref()
             .and_then(|index| index.search(&raw_query));
 
-        Ok(InitialSearchResult::Websites(InitialWebsiteResult {
+        Ok(InitialWebsiteResult {
             spell_corrected_query: correction,
             websites: webpages,
             entity,
-        }))
+        })
     }
 
     pub fn retrieve_websites(
@@ -163,25 +188,20 @@ impl LocalSearcher {
         let start = Instant::now();
 
         let query_text = query.original.clone();
-        let initial_result = self.search_initial(query, true)?;
-
-        match initial_result {
-            InitialSearchResult::Websites(search_result) => {
-                let retrieved_sites =
-                    self.retrieve_websites(&search_result.websites.top_websites, &query_text)?;
-
-                Ok(SearchResult::Websites(WebsitesResult {
-                    spell_corrected_query: search_result.spell_corrected_query,
-                    webpages: inverted_index::SearchResult {
-                        num_docs: search_result.websites.num_websites,
-                        documents: retrieved_sites,
-                    },
-                    entity: search_result.entity,
-                    search_duration_ms: start.elapsed().as_millis(),
-                }))
-            }
-            InitialSearchResult::Bang(bang) => Ok(SearchResult::Bang(bang)),
-        }
+        let search_result = self.search_initial(query, true)?;
+
+        let retrieved_sites =
+            self.retrieve_websites(&search_result.websites.top_websites, &query_text)?;
+
+        Ok(SearchResult::Websites(WebsitesResult {
+            spell_corrected_query: search_result.spell_corrected_query,
+            webpages: inverted_index::SearchResult {
+                num_docs: search_result.websites.num_websites,
+                documents: retrieved_sites,
+            },
+            entity: search_result.entity,
+            search_duration_ms: start.elapsed().as_millis(),
+        }))
     }
 
     pub fn favicon(&self, site: &Url) -> Option<Image> {
@@ -259,16 +279,16 @@ mod tests {
 
         index.commit().unwrap();
 
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None);
 
         for p in 0..NUM_PAGES {
             let urls: Vec<_> = searcher
                 .search(&SearchQuery {
                     original: "test".to_string(),
                     selected_region: None,
-                    goggle_program: None,
+                    goggles: Vec::new(),
+                    bangs: Vec::new(),
                     skip_pages: Some(p),
-                    site_rankings: None,
                 })
                 .unwrap()
                 .into_websites()
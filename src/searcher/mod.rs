@@ -22,11 +22,11 @@ pub use local::*;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    bangs::BangHit,
+    bangs::{Bang, BangHit},
     entity_index::StoredEntity,
     inverted_index,
-    ranking::site_rankings::SiteRankings,
-    search_prettifier::{self, DisplayedEntity, DisplayedWebpage},
+    ranking::goggles::CompiledGoggle,
+    search_prettifier::{DisplayedEntity, DisplayedWebpage},
     webpage::region::Region,
 };
 
@@ -61,25 +61,21 @@ pub struct PrettifiedWebsitesResult {
     pub search_duration_ms: u128,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum InitialSearchResult {
-    Websites(local::InitialWebsiteResult),
-    Bang(BangHit),
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub enum InitialPrettifiedSearchResult {
-    Websites(search_prettifier::InitialWebsiteResult),
-    Bang(BangHit),
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SearchQuery {
     pub original: String,
     pub selected_region: Option<Region>,
-    pub goggle_program: Option<String>,
+    /// Goggles applied to the search, in order. Discards of any goggle remove
+    /// the page, boosts and downranks of all goggles add up, and alterations of
+    /// later goggles take precedence over those of earlier ones.
+    pub goggles: Vec<CompiledGoggle>,
+    /// Bangs defined by the user. These take precedence over the bangs of the frontend.
+    /// The frontend reads them from a cookie, never from the url, so a link can't make
+    /// a search redirect somewhere the user didn't choose. Bangs are handled before the
+    /// search reaches the shards, so they are not sent along.
+    #[serde(skip)]
+    pub bangs: Vec<Bang>,
     pub skip_pages: Option<usize>,
-    pub site_rankings: Option<SiteRankings>,
 }
 
 impl SearchQuery {
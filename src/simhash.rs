@@ -0,0 +1,303 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! SimHash fingerprints of documents. Documents with similar text get fingerprints
+//! that differ in only a few bits, so near-duplicates (mirrors, scraped copies etc.)
+//! can be found by comparing the hamming distance of their fingerprints.
+
+use std::collections::HashMap;
+
+use crate::prehashed::hash as md5_hash;
+
+const SHINGLE_SIZE: usize = 2;
+
+/// Fingerprints with a hamming distance of at most this are considered near-duplicates.
+pub const MAX_DISTANCE: u32 = 4;
+
+/// Number of blocks the fingerprint is split into when looking up near-duplicates.
+/// Must be larger than `MAX_DISTANCE` so two near-duplicates are guaranteed to have
+/// at least one identical block.
+const NUM_BLOCKS: usize = MAX_DISTANCE as usize + 1;
+const BLOCK_BITS: usize = 64 / NUM_BLOCKS;
+
+fn feature_hash(words: &[&str]) -> u64 {
+    (md5_hash(words.join(" ")).0 & u64::MAX as u128) as u64
+}
+
+/// Fingerprint of the text based on overlapping word shingles.
+/// Text without any words has the fingerprint 0.
+pub fn hash(text: &str) -> u64 {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+    if words.is_empty() {
+        return 0;
+    }
+
+    let mut weights = [0i64; 64];
+
+    let mut add_feature = |feature: u64| {
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if feature & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    };
+
+    if words.len() < SHINGLE_SIZE {
+        add_feature(feature_hash(&words));
+    } else {
+        for shingle in words.windows(SHINGLE_SIZE) {
+            add_feature(feature_hash(shingle));
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |acc, (bit, _)| acc | (1 << bit))
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Documents without text (fingerprint 0) are never considered duplicates.
+pub fn is_near_duplicate(a: u64, b: u64) -> bool {
+    a != 0 && b != 0 && hamming_distance(a, b) <= MAX_DISTANCE
+}
+
+fn blocks(fingerprint: u64) -> impl Iterator<Item = (usize, u64)> {
+    (0..NUM_BLOCKS).map(move |block| {
+        let mask = (1 << BLOCK_BITS) - 1;
+        (block, (fingerprint >> (block * BLOCK_BITS)) & mask)
+    })
+}
+
+/// Lookup of near-duplicate fingerprints. Each fingerprint is stored under each of its blocks,
+/// so only fingerprints that share at least one block with the query need to be compared.
+/// With a maximum number of entries, the oldest entries are forgotten first.
+pub struct NearDuplicateIndex<T> {
+    blocks: HashMap<(usize, u64), Vec<usize>>,
+    entries: HashMap<usize, (u64, T)>,
+    next_id: usize,
+    oldest_id: usize,
+    max_entries: Option<usize>,
+}
+
+impl<T> Default for NearDuplicateIndex<T> {
+    fn default() -> Self {
+        Self {
+            blocks: HashMap::new(),
+            entries: HashMap::new(),
+            next_id: 0,
+            oldest_id: 0,
+            max_entries: None,
+        }
+    }
+}
+
+impl<T> NearDuplicateIndex<T> {
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self {
+            max_entries: Some(max_entries),
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn insert(&mut self, fingerprint: u64, value: T) {
+        if fingerprint == 0 {
+            return;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.insert(id, (fingerprint, value));
+
+        for block in blocks(fingerprint) {
+            self.blocks.entry(block).or_default().push(id);
+        }
+
+        if let Some(max_entries) = self.max_entries {
+            while self.entries.len() > max_entries {
+                let oldest = self.oldest_id;
+                self.oldest_id += 1;
+                self.remove(oldest);
+            }
+        }
+    }
+
+    fn candidates(&self, fingerprint: u64) -> Vec<usize> {
+        if fingerprint == 0 {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<usize> = blocks(fingerprint)
+            .filter_map(|block| self.blocks.get(&block))
+            .flatten()
+            .copied()
+            .filter(|id| {
+                matches!(self.entries.get(id), Some((other, _)) if is_near_duplicate(fingerprint, *other))
+            })
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        candidates
+    }
+
+    /// All stored values whose fingerprint is a near-duplicate of `fingerprint`.
+    pub fn near_duplicates(&self, fingerprint: u64) -> Vec<&T> {
+        self.candidates(fingerprint)
+            .into_iter()
+            .map(|id| &self.entries[&id].1)
+            .collect()
+    }
+
+    /// Removes the near-duplicates of `fingerprint` whose value matches the predicate,
+    /// and returns them with their fingerprints.
+    pub fn remove_near_duplicates<P: Fn(&T) -> bool>(
+        &mut self,
+        fingerprint: u64,
+        predicate: P,
+    ) -> Vec<(u64, T)> {
+        let removed: Vec<usize> = self
+            .candidates(fingerprint)
+            .into_iter()
+            .filter(|id| predicate(&self.entries[id].1))
+            .collect();
+
+        removed
+            .into_iter()
+            .filter_map(|id| self.remove(id))
+            .collect()
+    }
+
+    fn remove(&mut self, id: usize) -> Option<(u64, T)> {
+        let (fingerprint, value) = self.entries.remove(&id)?;
+
+        for block in blocks(fingerprint) {
+            if let Some(ids) = self.blocks.get_mut(&block) {
+                ids.retain(|other| *other != id);
+
+                if ids.is_empty() {
+                    self.blocks.remove(&block);
+                }
+            }
+        }
+
+        Some((fingerprint, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Cuely is an open source web search engine. The index is built from \
+        crawled webpages and ranked using a combination of text and link based signals such \
+        as harmonic centrality of the host and the page. The crawler fetches pages politely \
+        and respects the robots.txt file of every host it visits. Pages are parsed, cleaned \
+        and tokenized before they are inserted into the inverted index. Queries are parsed \
+        into terms that are matched against several fields with different boosts, and the \
+        best results are reranked by a combination of signals. Goggles let users change the \
+        ranking by boosting or discarding sites and pages that match their patterns, which \
+        makes it possible to tailor the results to a specific topic or audience.";
+
+    #[test]
+    fn identical_text() {
+        assert_eq!(hash(TEXT), hash(TEXT));
+        assert_eq!(hash(TEXT), hash(&TEXT.to_uppercase()));
+        assert_ne!(hash(TEXT), 0);
+    }
+
+    #[test]
+    fn near_duplicates() {
+        let modified = TEXT.replacen("index", "database", 1);
+        assert!(is_near_duplicate(hash(TEXT), hash(&modified)));
+
+        let different = "A completely unrelated text about cooking pasta with tomatoes, \
+                         garlic and basil. Boil the water and add plenty of salt before \
+                         adding the pasta to the pot.";
+        assert!(!is_near_duplicate(hash(TEXT), hash(different)));
+    }
+
+    #[test]
+    fn empty_text() {
+        assert_eq!(hash(""), 0);
+        assert_eq!(hash(" .,- "), 0);
+        assert!(!is_near_duplicate(0, 0));
+    }
+
+    #[test]
+    fn index_lookup() {
+        let mut index = NearDuplicateIndex::default();
+
+        index.insert(0b1111, "a");
+        index.insert(u64::MAX, "b");
+        index.insert(0, "empty");
+
+        assert_eq!(index.near_duplicates(0b0111), vec![&"a"]);
+        assert_eq!(index.near_duplicates(u64::MAX ^ (1 << 63) ^ 1), vec![&"b"]);
+        assert!(index.near_duplicates(0b1111 << 32).is_empty());
+        assert!(index.near_duplicates(0).is_empty());
+    }
+
+    #[test]
+    fn remove_near_duplicates() {
+        let mut index = NearDuplicateIndex::default();
+
+        index.insert(0b1111, 1);
+        index.insert(0b0111, 3);
+        index.insert(u64::MAX, 1);
+
+        assert_eq!(
+            index.remove_near_duplicates(0b1011, |value| *value < 2),
+            vec![(0b1111, 1)]
+        );
+        assert_eq!(index.near_duplicates(0b1011), vec![&3]);
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn oldest_entries_forgotten() {
+        let mut index = NearDuplicateIndex::with_max_entries(2);
+
+        index.insert(0b1111, "a");
+        index.insert(u64::MAX, "b");
+        index.insert(0b1111 << 32, "c");
+
+        assert_eq!(index.len(), 2);
+        assert!(index.near_duplicates(0b1111).is_empty());
+        assert_eq!(index.near_duplicates(u64::MAX), vec![&"b"]);
+        assert_eq!(index.near_duplicates(0b1111 << 32), vec![&"c"]);
+    }
+}
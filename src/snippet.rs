@@ -17,7 +17,6 @@
 use crate::schema::TextField;
 use crate::search_prettifier::html_escape;
 use crate::tokenizer::Stemmed;
-use crate::webpage::region::Region;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Range;
@@ -262,11 +261,11 @@ pub fn generate(
     text: &str,
     dirty_text: &str,
     description: &Option<String>,
-    region: &Region,
+    page_lang: Option<&Lang>,
     searcher: &tantivy::Searcher,
 ) -> Result<String> {
-    let lang = match region.lang() {
-        Some(lang) => lang,
+    let lang = match page_lang {
+        Some(lang) => *lang,
         None => whatlang::detect_lang(text).unwrap_or(Lang::Eng),
     };
 
@@ -279,8 +278,8 @@ pub fn generate(
         if text.is_empty() {
             match description {
                 Some(desc) => {
-                    let lang = match region.lang() {
-                        Some(lang) => lang,
+                    let lang = match page_lang {
+                        Some(lang) => *lang,
                         None => whatlang::detect_lang(desc).unwrap_or(Lang::Eng),
                     };
 
@@ -293,8 +292,8 @@ pub fn generate(
                     }
                 }
                 None => {
-                    let lang = match region.lang() {
-                        Some(lang) => lang,
+                    let lang = match page_lang {
+                        Some(lang) => *lang,
                         None => whatlang::detect_lang(dirty_text).unwrap_or(Lang::Eng),
                     };
 
@@ -371,9 +370,9 @@ Survey in 2016, 2017, and 2018."#;
             .search(&SearchQuery {
                 original: "rust language".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -414,9 +413,9 @@ Survey in 2016, 2017, and 2018."#;
             .search(&SearchQuery {
                 original: "describe".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -612,9 +611,9 @@ Survey in 2016, 2017, and 2018."#;
             .search(&SearchQuery {
                 original: "paradigms".to_string(),
                 selected_region: None,
-                goggle_program: None,
+                goggles: Vec::new(),
+                bangs: Vec::new(),
                 skip_pages: None,
-                site_rankings: None,
             })
             .expect("Search failed")
             .into_websites()
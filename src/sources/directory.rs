@@ -0,0 +1,277 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs,
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use regex::Regex;
+
+use crate::{
+    warc::{HttpHeader, Metadata, Request, Response, WarcRecord, WarcWriter},
+    webpage::ContentType,
+    DirectoryConfig, Result,
+};
+
+const NAME_PREFIX: &str = "directory-";
+const DEFAULT_FILES_PER_WARC: usize = 1_000;
+
+struct UrlMapper {
+    base_url: String,
+    rules: Vec<(Regex, String)>,
+}
+
+impl UrlMapper {
+    fn new(config: &DirectoryConfig) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        for rule in &config.url_rules {
+            rules.push((Regex::new(&rule.pattern)?, rule.replacement.clone()));
+        }
+
+        Ok(Self {
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            rules,
+        })
+    }
+
+    fn url(&self, relative_path: &Path) -> String {
+        let mut path = relative_path
+            .components()
+            .filter_map(|component| component.as_os_str().to_str())
+            .join("/");
+
+        for (regex, replacement) in &self.rules {
+            path = regex.replace_all(&path, replacement.as_str()).into_owned();
+        }
+
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+}
+
+/// All indexable files in the directory tree, sorted so every worker agrees on the chunks.
+/// Symlinked directories are not followed, as they might form a cycle.
+fn files(folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut stack = vec![folder.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+
+            if file_type.is_dir() {
+                stack.push(path);
+            } else if path.is_file()
+                && path
+                    .to_str()
+                    .and_then(ContentType::from_extension)
+                    .is_some()
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+fn files_per_warc(config: &DirectoryConfig) -> usize {
+    config
+        .files_per_warc
+        .unwrap_or(DEFAULT_FILES_PER_WARC)
+        .max(1)
+}
+
+pub fn warc_names(config: &DirectoryConfig) -> Result<Vec<String>> {
+    let num_files = files(Path::new(&config.folder))?.len();
+
+    Ok(super::warc_names(
+        NAME_PREFIX,
+        num_files,
+        files_per_warc(config),
+    ))
+}
+
+fn record(path: &Path, url: String) -> Result<WarcRecord> {
+    let content_type = path
+        .to_str()
+        .and_then(ContentType::from_extension)
+        .unwrap_or_default();
+
+    let last_modified = fs::metadata(path)?
+        .modified()
+        .ok()
+        .map(|time| DateTime::<Utc>::from(time).into());
+
+    let http = HttpHeader {
+        status: 200,
+        content_type: Some(content_type.mime().to_string()),
+        last_modified,
+        ..Default::default()
+    };
+
+    Ok(WarcRecord {
+        request: Request { url },
+        response: Response::new(http, fs::read(path)?),
+        metadata: Metadata { fetch_time_ms: 0 },
+    })
+}
+
+pub fn write_warc<W: Write + Seek>(
+    name: &str,
+    config: &DirectoryConfig,
+    buf: &mut W,
+) -> Result<()> {
+    let chunk = super::chunk_index(name, NAME_PREFIX)?;
+    let files_per_warc = files_per_warc(config);
+    let folder = Path::new(&config.folder);
+    let mapper = UrlMapper::new(config)?;
+
+    buf.seek(SeekFrom::Start(0))?;
+    let mut writer = WarcWriter::new(buf)?;
+
+    for path in files(folder)?
+        .into_iter()
+        .skip(chunk * files_per_warc)
+        .take(files_per_warc)
+    {
+        let relative_path = path.strip_prefix(folder).unwrap_or(&path);
+        writer.write(&record(&path, mapper.url(relative_path))?)?;
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{gen_temp_path, warc::WarcFile, UrlRule};
+
+    use super::*;
+
+    #[test]
+    fn url_mapping() {
+        let mapper = UrlMapper::new(&DirectoryConfig {
+            folder: String::new(),
+            base_url: "https://docs.example.com/".to_string(),
+            url_rules: vec![
+                UrlRule {
+                    pattern: r"index\.html$".to_string(),
+                    replacement: String::new(),
+                },
+                UrlRule {
+                    pattern: r"\.html$".to_string(),
+                    replacement: String::new(),
+                },
+            ],
+            files_per_warc: None,
+        })
+        .unwrap();
+
+        assert_eq!(
+            mapper.url(Path::new("index.html")),
+            "https://docs.example.com/"
+        );
+        assert_eq!(
+            mapper.url(Path::new("guide/index.html")),
+            "https://docs.example.com/guide/"
+        );
+        assert_eq!(
+            mapper.url(Path::new("guide/install.html")),
+            "https://docs.example.com/guide/install"
+        );
+    }
+
+    #[test]
+    fn directory_to_warc() {
+        let folder = gen_temp_path();
+        fs::create_dir_all(folder.join("guide")).unwrap();
+        fs::write(folder.join("index.html"), "<title>Home</title>").unwrap();
+        fs::write(
+            folder.join("guide").join("install.html"),
+            "<title>Install</title>",
+        )
+        .unwrap();
+        fs::write(folder.join("logo.png"), [0u8; 8]).unwrap();
+
+        let config = DirectoryConfig {
+            folder: folder.to_str().unwrap().to_string(),
+            base_url: "https://docs.example.com".to_string(),
+            url_rules: Vec::new(),
+            files_per_warc: Some(1),
+        };
+
+        let names = warc_names(&config).unwrap();
+        assert_eq!(names.len(), 2);
+
+        let mut records = Vec::new();
+        for name in &names {
+            let mut buf = Cursor::new(Vec::new());
+            write_warc(name, &config, &mut buf).unwrap();
+
+            records.extend(
+                WarcFile::new(buf.into_inner())
+                    .records()
+                    .map(|record| record.unwrap()),
+            );
+        }
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].request.url,
+            "https://docs.example.com/guide/install.html"
+        );
+        assert_eq!(records[0].response.body(), "<title>Install</title>");
+        assert_eq!(
+            records[1].request.url,
+            "https://docs.example.com/index.html"
+        );
+        assert!(records[1].response.is_success());
+        assert_eq!(
+            records[1].response.http.content_type.as_deref(),
+            Some("text/html")
+        );
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle() {
+        let folder = gen_temp_path();
+        fs::create_dir_all(folder.join("docs")).unwrap();
+        fs::write(folder.join("docs").join("a.html"), "<title>A</title>").unwrap();
+        std::os::unix::fs::symlink(&folder, folder.join("docs").join("loop")).unwrap();
+
+        assert_eq!(
+            files(&folder).unwrap(),
+            vec![folder.join("docs").join("a.html")]
+        );
+
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Sources that are not WARC files themselves, but are turned into WARC files
+//! when downloaded so the indexer and webgraph jobs can consume them unchanged.
+
+pub mod directory;
+pub mod sitemap;
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    warc::{Metadata, Request, Response, WarcRecord},
+    Error, Result,
+};
+
+const WARC_SUFFIX: &str = ".warc.gz";
+pub(crate) const USER_AGENT: &str = "Mozilla/5.0 (compatible; CuelyBot/0.1)";
+
+/// Page bodies are cut off after this many bytes. Pages are only parsed up to
+/// `max_body_bytes` of the html limits anyway.
+const MAX_PAGE_BYTES: usize = 10_000_000;
+
+/// Redirects are not followed, so they end up in the warc as redirect responses.
+pub(crate) fn http_client(timeout: Duration) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(timeout)
+        .build()?)
+}
+
+/// Reads the body, but stops once `max_bytes` have been read. The content length
+/// can't be trusted, so the size is checked while reading.
+pub(crate) async fn read_body(mut res: reqwest::Response, max_bytes: usize) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = res.chunk().await? {
+        let remaining = max_bytes - bytes.len();
+        bytes.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+
+        if bytes.len() == max_bytes {
+            break;
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Fetch a single url and store the response as it would appear in a warc file.
+/// The body is truncated to `MAX_PAGE_BYTES`.
+pub(crate) async fn fetch(client: &reqwest::Client, url: String) -> Result<WarcRecord> {
+    let start = Instant::now();
+    let res = client.get(&url).send().await?;
+
+    let mut raw = format!("HTTP/1.1 {}\r\n", res.status().as_u16());
+    for (name, value) in res.headers() {
+        if let Ok(value) = value.to_str() {
+            raw.push_str(name.as_str());
+            raw.push_str(": ");
+            raw.push_str(value);
+            raw.push_str("\r\n");
+        }
+    }
+    raw.push_str("\r\n");
+
+    let mut raw = raw.into_bytes();
+    raw.extend_from_slice(&read_body(res, MAX_PAGE_BYTES).await?);
+
+    Ok(WarcRecord {
+        request: Request { url },
+        response: Response::from_http_bytes(&raw)?,
+        metadata: Metadata {
+            fetch_time_ms: start.elapsed().as_millis() as usize,
+        },
+    })
+}
+
+fn warc_names(prefix: &str, num_items: usize, items_per_warc: usize) -> Vec<String> {
+    let num_warcs = (num_items + items_per_warc - 1) / items_per_warc;
+
+    (0..num_warcs)
+        .map(|idx| format!("{prefix}{idx:05}{WARC_SUFFIX}"))
+        .collect()
+}
+
+/// The index of the chunk of items that should be written to the warc with this name.
+fn chunk_index(name: &str, prefix: &str) -> Result<usize> {
+    name.split('/')
+        .last()
+        .and_then(|name| name.strip_prefix(prefix))
+        .and_then(|name| name.strip_suffix(WARC_SUFFIX))
+        .and_then(|idx| idx.parse().ok())
+        .ok_or_else(|| Error::UnknownWarcName(name.to_string()))
+}
+
+/// Serve the router on a random local port from a background thread.
+/// Used as a stand-in for remote sites in tests.
+#[cfg(test)]
+pub(crate) fn serve_locally(
+    router: impl FnOnce(std::net::SocketAddr) -> axum::Router,
+) -> std::net::SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = router(addr);
+
+    std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service())
+                    .await
+                    .unwrap();
+            });
+    });
+
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_roundtrip() {
+        let names = warc_names("test-", 11, 5);
+        assert_eq!(
+            names,
+            vec![
+                "test-00000.warc.gz".to_string(),
+                "test-00001.warc.gz".to_string(),
+                "test-00002.warc.gz".to_string()
+            ]
+        );
+
+        for (idx, name) in names.iter().enumerate() {
+            assert_eq!(chunk_index(name, "test-").unwrap(), idx);
+        }
+
+        assert!(chunk_index("other-00000.warc.gz", "test-").is_err());
+    }
+}
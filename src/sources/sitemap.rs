@@ -0,0 +1,372 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+use flate2::read::GzDecoder;
+use quick_xml::{events::Event, Reader};
+use tracing::debug;
+
+use crate::{warc::WarcWriter, Error, Result, SitemapConfig};
+
+const NAME_PREFIX: &str = "sitemap-";
+const DEFAULT_URLS_PER_WARC: usize = 1_000;
+const MAX_SITEMAPS: usize = 10_000;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sitemaps may be at most 50MB uncompressed according to the sitemap protocol.
+const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ParsedSitemap {
+    sitemaps: Vec<String>,
+    urls: Vec<String>,
+}
+
+/// Parses both `<urlset>` sitemaps and `<sitemapindex>` files.
+fn parse(xml: &[u8]) -> Result<ParsedSitemap> {
+    let mut reader = Reader::from_reader(xml);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut res = ParsedSitemap::default();
+
+    let mut inside_sitemap = false;
+    let mut inside_loc = false;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) if e.local_name() == b"sitemap" => inside_sitemap = true,
+            Event::End(ref e) if e.local_name() == b"sitemap" => inside_sitemap = false,
+            Event::Start(ref e) if e.local_name() == b"loc" => inside_loc = true,
+            Event::End(ref e) if e.local_name() == b"loc" => inside_loc = false,
+            Event::Text(e) if inside_loc => {
+                let loc = e.unescape_and_decode(&reader)?.trim().to_string();
+
+                if inside_sitemap {
+                    res.sitemaps.push(loc);
+                } else {
+                    res.urls.push(loc);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    Ok(res)
+}
+
+async fn fetch_sitemap(client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let res = client.get(url).send().await?.error_for_status()?;
+    let mut bytes = super::read_body(res, MAX_SITEMAP_BYTES + 1).await?;
+
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&bytes[..])
+            .take(MAX_SITEMAP_BYTES as u64 + 1)
+            .read_to_end(&mut decompressed)?;
+        bytes = decompressed;
+    }
+
+    if bytes.len() > MAX_SITEMAP_BYTES {
+        return Err(Error::ResponseTooLarge(MAX_SITEMAP_BYTES));
+    }
+
+    Ok(bytes)
+}
+
+/// All page urls from the configured sitemaps, in the order they are listed.
+async fn page_urls(config: &SitemapConfig, client: &reqwest::Client) -> Result<Vec<String>> {
+    let max_urls = config.max_urls.unwrap_or(usize::MAX);
+
+    let mut queue: VecDeque<String> = config.sitemaps.iter().cloned().collect();
+    let mut seen_sitemaps = HashSet::new();
+
+    let mut urls = Vec::new();
+    let mut seen_urls = HashSet::new();
+
+    while let Some(sitemap) = queue.pop_front() {
+        if urls.len() >= max_urls || seen_sitemaps.len() >= MAX_SITEMAPS {
+            break;
+        }
+
+        if !seen_sitemaps.insert(sitemap.clone()) {
+            continue;
+        }
+
+        let parsed = match fetch_sitemap(client, &sitemap)
+            .await
+            .and_then(|xml| parse(&xml))
+        {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                debug!("failed to read sitemap {}: {:?}", sitemap, err);
+                continue;
+            }
+        };
+
+        queue.extend(parsed.sitemaps);
+
+        for url in parsed.urls {
+            if seen_urls.insert(url.clone()) {
+                urls.push(url);
+            }
+        }
+    }
+
+    urls.truncate(max_urls);
+
+    Ok(urls)
+}
+
+fn urls_per_warc(config: &SitemapConfig) -> usize {
+    config.urls_per_warc.unwrap_or(DEFAULT_URLS_PER_WARC).max(1)
+}
+
+/// Fetches the sitemaps and splits the page urls into warcs. The urls of each warc are
+/// stored in the config, and the names of the warcs are returned.
+pub fn resolve(config: &mut SitemapConfig) -> Result<Vec<String>> {
+    let thread_config = config.clone();
+
+    // this might be called from within an async runtime, so the sitemaps are
+    // fetched on a separate thread with its own runtime.
+    let urls = std::thread::spawn(move || -> Result<Vec<String>> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let client = super::http_client(TIMEOUT)?;
+                page_urls(&thread_config, &client).await
+            })
+    })
+    .join()
+    .map_err(|_| Error::SitemapThread)??;
+
+    let urls_per_warc = urls_per_warc(config);
+    let names = super::warc_names(NAME_PREFIX, urls.len(), urls_per_warc);
+
+    config.pages = names
+        .iter()
+        .cloned()
+        .zip(urls.chunks(urls_per_warc).map(<[String]>::to_vec))
+        .collect();
+
+    Ok(names)
+}
+
+/// The config with only the page urls of `warc_paths`, to be sent along with a job.
+pub fn for_warcs(config: &SitemapConfig, warc_paths: &[String]) -> SitemapConfig {
+    SitemapConfig {
+        sitemaps: config.sitemaps.clone(),
+        urls_per_warc: config.urls_per_warc,
+        max_urls: config.max_urls,
+        pages: warc_paths
+            .iter()
+            .filter_map(|name| Some((name.clone(), config.pages.get(name)?.clone())))
+            .collect(),
+    }
+}
+
+pub async fn write_warc<W: Write + Seek>(
+    name: &str,
+    config: &SitemapConfig,
+    buf: &mut W,
+) -> Result<()> {
+    let urls = config
+        .pages
+        .get(name)
+        .ok_or_else(|| Error::UnknownWarcName(name.to_string()))?;
+    let client = super::http_client(TIMEOUT)?;
+
+    buf.seek(SeekFrom::Start(0))?;
+    let mut writer = WarcWriter::new(buf)?;
+
+    for url in urls {
+        match super::fetch(&client, url.clone()).await {
+            Ok(record) => writer.write(&record)?,
+            Err(err) => debug!("failed to fetch page: {:?}", err),
+        }
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        io::Cursor,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use axum::{http::StatusCode, response::Html, routing::get, Router};
+
+    use crate::{sources::serve_locally, warc::WarcFile};
+
+    use super::*;
+
+    #[test]
+    fn parse_urlset() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <url>
+                    <loc>https://example.com/</loc>
+                    <lastmod>2022-06-01</lastmod>
+                </url>
+                <url><loc>https://example.com/a?x=1&amp;y=2</loc></url>
+            </urlset>"#;
+
+        assert_eq!(
+            parse(xml).unwrap(),
+            ParsedSitemap {
+                sitemaps: Vec::new(),
+                urls: vec![
+                    "https://example.com/".to_string(),
+                    "https://example.com/a?x=1&y=2".to_string()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_index() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+                <sitemap><loc>https://example.com/sitemap1.xml</loc></sitemap>
+                <sitemap><loc>https://example.com/sitemap2.xml.gz</loc></sitemap>
+            </sitemapindex>"#;
+
+        assert_eq!(
+            parse(xml).unwrap(),
+            ParsedSitemap {
+                sitemaps: vec![
+                    "https://example.com/sitemap1.xml".to_string(),
+                    "https://example.com/sitemap2.xml.gz".to_string()
+                ],
+                urls: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn sitemap_to_warc() {
+        let sitemap_fetches = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&sitemap_fetches);
+
+        let addr = serve_locally(move |addr| {
+            let index = format!(
+                "<sitemapindex><sitemap><loc>http://{addr}/sitemap.xml</loc></sitemap></sitemapindex>"
+            );
+            let sitemap = format!(
+                "<urlset>\
+                    <url><loc>http://{addr}/a</loc></url>\
+                    <url><loc>http://{addr}/b</loc></url>\
+                    <url><loc>http://{addr}/missing</loc></url>\
+                </urlset>"
+            );
+
+            Router::new()
+                .route("/index.xml", get(move || async move { index }))
+                .route(
+                    "/sitemap.xml",
+                    get(move || async move {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        sitemap
+                    }),
+                )
+                .route("/a", get(|| async { Html("<title>A</title>") }))
+                .route("/b", get(|| async { Html("<title>B</title>") }))
+                .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+        });
+
+        let mut config = SitemapConfig {
+            sitemaps: vec![format!("http://{addr}/index.xml")],
+            urls_per_warc: Some(2),
+            max_urls: None,
+            pages: BTreeMap::new(),
+        };
+
+        let names = resolve(&mut config).unwrap();
+        assert_eq!(names.len(), 2);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut records = Vec::new();
+
+        for name in &names {
+            // each job only gets the urls of its own warcs
+            let job_config = for_warcs(&config, &[name.clone()]);
+            assert_eq!(job_config.pages.len(), 1);
+
+            let mut buf = Cursor::new(Vec::new());
+            runtime
+                .block_on(write_warc(name, &job_config, &mut buf))
+                .unwrap();
+
+            records.extend(
+                WarcFile::new(buf.into_inner())
+                    .records()
+                    .map(|record| record.unwrap()),
+            );
+        }
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].request.url, format!("http://{addr}/a"));
+        assert_eq!(records[0].response.body(), "<title>A</title>");
+        assert_eq!(records[1].request.url, format!("http://{addr}/b"));
+        assert_eq!(records[2].response.http.status, 404);
+        assert!(!records[2].response.is_success());
+
+        // the url list is resolved once and split into chunks
+        assert_eq!(sitemap_fetches.load(Ordering::SeqCst), 1);
+
+        let mut buf = Cursor::new(Vec::new());
+        assert!(runtime
+            .block_on(write_warc(&names[0], &for_warcs(&config, &[]), &mut buf))
+            .is_err());
+    }
+
+    #[test]
+    fn oversized_sitemaps_rejected() {
+        let addr = serve_locally(|_| {
+            Router::new().route(
+                "/sitemap.xml",
+                get(|| async { "a".repeat(MAX_SITEMAP_BYTES + 1) }),
+            )
+        });
+
+        let client = super::super::http_client(TIMEOUT).unwrap();
+        let res = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(fetch_sitemap(
+                &client,
+                &format!("http://{addr}/sitemap.xml"),
+            ));
+
+        assert!(matches!(res, Err(Error::ResponseTooLarge(_))));
+    }
+}
@@ -45,7 +45,7 @@ impl LogarithmicEdit {
 
 impl EditStrategy for LogarithmicEdit {
     fn distance_for_string(&self, string: &str) -> usize {
-        let log_value: usize = (string.len() as f32).log2() as usize;
+        let log_value: usize = (string.chars().count() as f32).log2() as usize;
         cmp::max(1, cmp::min(log_value, self.max_edit_distance))
     }
 
@@ -110,6 +110,8 @@ pub enum DictionaryError {
 
 pub type Result<T> = std::result::Result<T, DictionaryError>;
 
+const DICTIONARY_FILE_NAME: &str = "dictionary";
+
 enum InnerMap {
     Memory(Map<Vec<u8>>),
     File(Map<Mmap>),
@@ -156,7 +158,7 @@ impl<const TOP_N: usize> Dictionary<TOP_N> {
 
         let map = match &folder_path {
             Some(path) => {
-                let dictionary_path = Path::new(path).join("dictionary");
+                let dictionary_path = Path::new(path).join(DICTIONARY_FILE_NAME);
 
                 if !dictionary_path.exists() {
                     let file = File::create(dictionary_path.clone())?;
@@ -180,6 +182,18 @@ impl<const TOP_N: usize> Dictionary<TOP_N> {
         })
     }
 
+    /// Moves a dictionary stored in `from` to `to`. Other files in `from` are left untouched.
+    pub fn relocate<P: AsRef<Path>>(from: P, to: P) -> Result<()> {
+        let dictionary_path = from.as_ref().join(DICTIONARY_FILE_NAME);
+
+        if dictionary_path.exists() {
+            fs::create_dir_all(to.as_ref())?;
+            fs::rename(dictionary_path, to.as_ref().join(DICTIONARY_FILE_NAME))?;
+        }
+
+        Ok(())
+    }
+
     fn store_union(&mut self, mut union: Union) -> Result<()> {
         match &self.folder_path {
             Some(path) => {
@@ -211,8 +225,8 @@ impl<const TOP_N: usize> Dictionary<TOP_N> {
 
                 let path = Path::new(path);
                 builder.finish()?;
-                std::fs::rename(path.join("new_dictionary"), path.join("dictionary"))?;
-                let mmap = unsafe { Mmap::map(&File::open(path.join("dictionary"))?)? };
+                std::fs::rename(path.join("new_dictionary"), path.join(DICTIONARY_FILE_NAME))?;
+                let mmap = unsafe { Mmap::map(&File::open(path.join(DICTIONARY_FILE_NAME))?)? };
                 self.map = InnerMap::File(Map::new(mmap)?);
             }
             None => {
@@ -264,17 +278,25 @@ impl<const TOP_N: usize> Dictionary<TOP_N> {
         Ok(())
     }
 
+    /// Number of distinct terms inserted since the last commit.
+    pub fn num_uncommitted(&self) -> usize {
+        self.cache.len()
+    }
+
     pub fn insert(&mut self, term: &str) {
+        self.insert_with_frequency(term, 1);
+    }
+
+    pub fn insert_with_frequency(&mut self, term: &str, frequency: u64) {
         self.cache
             .entry(
                 term.chars()
-                    .into_iter()
-                    .map(|c| c.to_ascii_lowercase())
+                    .flat_map(char::to_lowercase)
                     .filter(|c| !matches!(c, ',' | '.' | '\\' | '=' | '*' | '(' | ')'))
                     .collect(),
             )
             .or_insert(0)
-            .add_assign(1);
+            .add_assign(frequency);
     }
 
     #[inline]
@@ -318,13 +340,21 @@ impl<const TOP_N: usize> Dictionary<TOP_N> {
     }
 
     #[inline]
-    pub fn probability(&self, term: &str) -> Option<f64> {
+    pub fn frequency(&self, term: &str) -> Option<u64> {
         let searcher = fst::automaton::Str::new(term);
         let mut matches = self.perform_search(&searcher);
 
-        matches
-            .next()
-            .map(|(_key, frequency)| frequency as f64 / self.total_freq as f64)
+        matches.next().map(|(_key, frequency)| frequency)
+    }
+
+    #[inline]
+    pub fn probability(&self, term: &str) -> Option<f64> {
+        self.frequency(term)
+            .map(|frequency| frequency as f64 / self.total_freq as f64)
+    }
+
+    pub fn total_frequency(&self) -> u64 {
+        self.total_freq
     }
 
     #[cfg(test)]
@@ -477,4 +507,18 @@ mod tests {
         assert!(dict.contains("lennon"));
         assert!(!dict.contains("lennon,"));
     }
+
+    #[test]
+    fn non_ascii_lowercased() {
+        let mut dict = Dictionary::default();
+
+        dict.insert("København");
+        dict.insert("ÆBLE");
+
+        dict.commit().unwrap();
+
+        assert!(dict.contains("københavn"));
+        assert!(dict.contains("æble"));
+        assert!(!dict.contains("København"));
+    }
 }
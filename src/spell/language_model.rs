@@ -0,0 +1,201 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::spell::dictionary::Result;
+use crate::spell::Dictionary;
+use crate::tokenizer::Normal;
+use crate::webpage::Webpage;
+use std::path::Path;
+use tantivy::tokenizer::Tokenizer;
+
+const BIGRAM_SUBFOLDER_NAME: &str = "bigrams";
+
+/// Weight of the bigram estimate when interpolating with the unigram estimate.
+const BIGRAM_WEIGHT: f64 = 0.8;
+
+/// Unknown terms get this fraction of the probability of a term seen once.
+const UNKNOWN_TERM_DISCOUNT: f64 = 0.1;
+
+/// Bigram language model over the terms seen during indexing. Unigrams are stored
+/// exactly like before, so existing spell dictionaries can still be opened, and the
+/// bigrams are stored as "first second" keys in a dictionary of their own.
+pub struct LanguageModel<const UNIGRAMS_N: usize, const BIGRAMS_N: usize> {
+    unigrams: Dictionary<UNIGRAMS_N>,
+    bigrams: Dictionary<BIGRAMS_N>,
+}
+
+impl<const UNIGRAMS_N: usize, const BIGRAMS_N: usize> LanguageModel<UNIGRAMS_N, BIGRAMS_N> {
+    pub fn open<P: AsRef<Path>>(folder_path: Option<P>) -> Result<Self> {
+        let bigram_path = folder_path
+            .as_ref()
+            .map(|path| path.as_ref().join(BIGRAM_SUBFOLDER_NAME));
+
+        Ok(Self {
+            unigrams: Dictionary::open(folder_path)?,
+            bigrams: Dictionary::open(bigram_path)?,
+        })
+    }
+
+    /// Moves a model stored in `from` to `to`. Other files in `from` are left untouched.
+    pub fn relocate<P: AsRef<Path>>(from: P, to: P) -> Result<()> {
+        Dictionary::<UNIGRAMS_N>::relocate(from.as_ref(), to.as_ref())?;
+
+        let bigram_path = from.as_ref().join(BIGRAM_SUBFOLDER_NAME);
+        Dictionary::<BIGRAMS_N>::relocate(
+            bigram_path.as_path(),
+            to.as_ref().join(BIGRAM_SUBFOLDER_NAME).as_path(),
+        )?;
+
+        if bigram_path.exists() && bigram_path.read_dir()?.next().is_none() {
+            std::fs::remove_dir(bigram_path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn unigrams(&self) -> &Dictionary<UNIGRAMS_N> {
+        &self.unigrams
+    }
+
+    /// Counts the terms of `text` and the pairs of consecutive terms. Punctuation ends
+    /// a pair, so the last word of a sentence and the first word of the next one are
+    /// not counted as a bigram.
+    ///
+    /// The model keeps at most `BIGRAMS_N` bigrams when it is committed, so once that
+    /// many bigrams are waiting to be committed they are committed right away. This
+    /// bounds the memory used between commits, at the cost of dropping rare bigrams
+    /// a bit earlier.
+    pub fn insert_text(&mut self, text: &str) -> Result<()> {
+        let mut stream = Normal::default().token_stream(text);
+        let mut prev: Option<String> = None;
+
+        while let Some(token) = stream.next() {
+            self.unigrams.insert(&token.text);
+
+            if !token.text.chars().any(char::is_alphanumeric) {
+                prev = None;
+                continue;
+            }
+
+            if let Some(prev) = prev.as_ref() {
+                self.bigrams.insert(&format!("{prev} {}", token.text));
+            }
+
+            prev = Some(token.text.clone());
+        }
+
+        if self.bigrams.num_uncommitted() > BIGRAMS_N {
+            self.bigrams.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds `frequency` occurrences of a term, or of a bigram if `term` consists of two
+    /// whitespace separated terms.
+    pub fn insert_with_frequency(&mut self, term: &str, frequency: u64) {
+        let mut words = term.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (Some(unigram), None, None) => self.unigrams.insert_with_frequency(unigram, frequency),
+            (Some(first), Some(second), None) => self
+                .bigrams
+                .insert_with_frequency(&format!("{first} {second}"), frequency),
+            _ => {}
+        }
+    }
+
+    pub fn insert_page(&mut self, webpage: &Webpage) -> Result<()> {
+        let text = webpage.html.clean_text().unwrap_or_default();
+        self.insert_text(&text)
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        self.unigrams.commit()?;
+        self.bigrams.commit()
+    }
+
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            unigrams: self.unigrams.merge(other.unigrams),
+            bigrams: self.bigrams.merge(other.bigrams),
+        }
+    }
+
+    fn unigram_prob(&self, term: &str) -> f64 {
+        let total = self.unigrams.total_frequency().max(1) as f64;
+
+        match self.unigrams.frequency(term) {
+            Some(freq) => freq as f64 / total,
+            None => UNKNOWN_TERM_DISCOUNT / total,
+        }
+    }
+
+    /// Log-probability of `term` following `prev`. The bigram estimate is interpolated
+    /// with the unigram estimate, so unseen pairs of known terms are not ruled out.
+    pub fn log_prob(&self, prev: Option<&str>, term: &str) -> f64 {
+        let unigram = self.unigram_prob(term);
+
+        let bigram = prev.and_then(|prev| {
+            let prev_freq = self.unigrams.frequency(prev)?;
+            let pair_freq = self.bigrams.frequency(&format!("{prev} {term}"))?;
+
+            Some(pair_freq as f64 / prev_freq as f64)
+        });
+
+        match bigram {
+            Some(bigram) => (BIGRAM_WEIGHT * bigram + (1.0 - BIGRAM_WEIGHT) * unigram).ln(),
+            None => ((1.0 - BIGRAM_WEIGHT) * unigram).ln(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bigrams_are_counted() {
+        let mut model: LanguageModel<1_000, 1_000> = LanguageModel::open::<&str>(None).unwrap();
+
+        model
+            .insert_text("the new york times. new york is big")
+            .unwrap();
+        model.commit().unwrap();
+
+        assert_eq!(model.unigrams().frequency("york"), Some(2));
+        assert_eq!(model.bigrams.frequency("new york"), Some(2));
+        assert_eq!(model.bigrams.frequency("york times"), Some(1));
+        assert_eq!(model.bigrams.frequency("times new"), None);
+
+        assert!(model.log_prob(Some("new"), "york") > model.log_prob(Some("new"), "times"));
+        assert!(model.log_prob(Some("new"), "york") > model.log_prob(None, "york"));
+        assert!(model.log_prob(None, "york") > model.log_prob(None, "yrok"));
+    }
+
+    #[test]
+    fn uncommitted_bigrams_are_bounded() {
+        let mut model: LanguageModel<1_000, 10> = LanguageModel::open::<&str>(None).unwrap();
+
+        for i in 0..100 {
+            model
+                .insert_text(&format!("first{i} second{i} third{i}"))
+                .unwrap();
+            assert!(model.bigrams.num_uncommitted() <= 10);
+        }
+
+        assert_eq!(model.unigrams().num_uncommitted(), 300);
+    }
+}
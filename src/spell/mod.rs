@@ -15,9 +15,11 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 pub mod dictionary;
 pub mod distance;
+pub mod language_model;
 pub mod spell_checker;
 pub mod splitter;
 
 pub use self::dictionary::{Dictionary, DictionaryResult, EditStrategy, LogarithmicEdit};
-pub use self::spell_checker::SpellChecker;
+pub use self::language_model::LanguageModel;
+pub use self::spell_checker::{QueryCorrector, SpellChecker};
 pub use self::splitter::TermSplitter;
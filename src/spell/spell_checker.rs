@@ -14,9 +14,27 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use crate::spell::dictionary::EditStrategy;
-use crate::spell::{Dictionary, DictionaryResult};
+use crate::spell::distance::LevenshteinDistance;
+use crate::spell::{Dictionary, DictionaryResult, LanguageModel};
 use std::iter::FromIterator;
 
+/// Number of corrections considered for each term of a query.
+const MAX_CANDIDATES_PER_TERM: usize = 5;
+
+/// Probability that a term in a query was typed as intended.
+const KEEP_PROB: f64 = 0.95;
+
+/// Probability of each single-character edit made while typing a term.
+const EDIT_PROB: f64 = 0.1;
+
+/// Only terms made of letters are corrected. Digits, whitespace and ASCII symbols
+/// usually mean the term is a version, a programming language or similar ("c++").
+fn is_correctable(term: &str) -> bool {
+    !term
+        .chars()
+        .any(|c| c.is_numeric() || c.is_whitespace() || c.is_ascii_punctuation())
+}
+
 pub struct SpellChecker<'a, T: EditStrategy, const DICT_N: usize> {
     dict: &'a Dictionary<DICT_N>,
     edit_strategy: T,
@@ -33,7 +51,7 @@ impl<'a, T: EditStrategy, const DICT_N: usize> SpellChecker<'a, T, DICT_N> {
     pub fn correct_top(&self, term: &str, top_n: usize) -> Vec<String> {
         let mut res = Vec::new();
 
-        if term.chars().any(|c| !c.is_alphabetic()) {
+        if !is_correctable(term) {
             return Vec::new();
         }
 
@@ -63,7 +81,7 @@ impl<'a, T: EditStrategy, const DICT_N: usize> SpellChecker<'a, T, DICT_N> {
 
     pub fn correct(&self, term: &str) -> Option<String> {
         if let Some(correction) = self.correct_top(term, 1).into_iter().next() {
-            if correction.to_ascii_lowercase() == term.to_ascii_lowercase() {
+            if correction.to_lowercase() == term.to_lowercase() {
                 None
             } else {
                 Some(correction)
@@ -74,6 +92,116 @@ impl<'a, T: EditStrategy, const DICT_N: usize> SpellChecker<'a, T, DICT_N> {
     }
 }
 
+struct Candidate {
+    term: String,
+    channel_log_prob: f64,
+}
+
+/// Noisy channel corrector for whole queries. Every term is either kept or replaced
+/// by one of its closest dictionary terms, and the combination that is most likely
+/// under the bigram language model (weighted by how many edits it takes) is chosen.
+pub struct QueryCorrector<'a, T: EditStrategy, const UNIGRAMS_N: usize, const BIGRAMS_N: usize> {
+    model: &'a LanguageModel<UNIGRAMS_N, BIGRAMS_N>,
+    spell_checker: SpellChecker<'a, T, UNIGRAMS_N>,
+}
+
+impl<'a, T: EditStrategy, const UNIGRAMS_N: usize, const BIGRAMS_N: usize>
+    QueryCorrector<'a, T, UNIGRAMS_N, BIGRAMS_N>
+{
+    pub fn new(model: &'a LanguageModel<UNIGRAMS_N, BIGRAMS_N>, edit_strategy: T) -> Self {
+        Self {
+            model,
+            spell_checker: SpellChecker::new(model.unigrams(), edit_strategy),
+        }
+    }
+
+    fn candidates(&self, term: &str) -> Vec<Candidate> {
+        let mut candidates = vec![Candidate {
+            term: term.to_string(),
+            channel_log_prob: KEEP_PROB.ln(),
+        }];
+
+        candidates.extend(
+            self.spell_checker
+                .correct_top(term, MAX_CANDIDATES_PER_TERM)
+                .into_iter()
+                .filter(|correction| correction != term)
+                .map(|correction| Candidate {
+                    channel_log_prob: LevenshteinDistance::compare(term, &correction) as f64
+                        * EDIT_PROB.ln(),
+                    term: correction,
+                }),
+        );
+
+        candidates
+    }
+
+    /// Returns the most likely intended query, or `None` if that is the query itself.
+    pub fn correct(&self, terms: &[String]) -> Option<Vec<String>> {
+        let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+        let candidates: Vec<Vec<Candidate>> =
+            terms.iter().map(|term| self.candidates(term)).collect();
+
+        if candidates.iter().all(|candidates| candidates.len() == 1) {
+            return None;
+        }
+
+        // best[i][j] is the log-probability of the most likely correction of terms[..=i]
+        // that ends in candidates[i][j], together with the candidate it follows.
+        let mut best: Vec<Vec<(f64, usize)>> = Vec::with_capacity(terms.len());
+
+        for (i, current) in candidates.iter().enumerate() {
+            let row = current
+                .iter()
+                .map(|candidate| {
+                    if i == 0 {
+                        return (
+                            candidate.channel_log_prob + self.model.log_prob(None, &candidate.term),
+                            0,
+                        );
+                    }
+
+                    candidates[i - 1]
+                        .iter()
+                        .zip(best[i - 1].iter())
+                        .enumerate()
+                        .map(|(k, (prev, (score, _)))| {
+                            (
+                                score
+                                    + candidate.channel_log_prob
+                                    + self.model.log_prob(Some(&prev.term), &candidate.term),
+                                k,
+                            )
+                        })
+                        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+                        .unwrap()
+                })
+                .collect();
+
+            best.push(row);
+        }
+
+        let (mut idx, _) = best
+            .last()?
+            .iter()
+            .enumerate()
+            .max_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))?;
+
+        let mut correction = Vec::with_capacity(terms.len());
+        for i in (0..terms.len()).rev() {
+            correction.push(candidates[i][idx].term.clone());
+            idx = best[i][idx].1;
+        }
+        correction.reverse();
+
+        if correction == terms {
+            None
+        } else {
+            Some(correction)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -189,4 +317,71 @@ mod tests {
         assert_eq!(spell_checker.correct("c++"), None);
         assert_eq!(spell_checker.correct("c#"), None);
     }
+
+    #[test]
+    fn correct_non_ascii() {
+        let mut dict = Dictionary::default();
+
+        dict.insert("København");
+        dict.insert("Köln");
+
+        dict.commit().unwrap();
+
+        let spell_checker = SpellChecker::new(&dict, LogarithmicEdit::new(4));
+
+        assert_eq!(
+            spell_checker.correct("kobenhavn"),
+            Some("københavn".to_string())
+        );
+        assert_eq!(spell_checker.correct("KÖLN"), None);
+    }
+
+    fn model(text: &str) -> LanguageModel<1_000, 1_000> {
+        let mut model = LanguageModel::open::<&str>(None).unwrap();
+        model.insert_text(text).unwrap();
+        model.commit().unwrap();
+
+        model
+    }
+
+    fn terms(query: &str) -> Vec<String> {
+        query
+            .split_whitespace()
+            .map(|term| term.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn query_context() {
+        let model = model(
+            "egg yolk. the yolk of an egg. a yellow yolk. \
+             read the new york times. new york times articles",
+        );
+        let corrector = QueryCorrector::new(&model, LogarithmicEdit::new(4));
+
+        assert_eq!(
+            corrector.correct(&terms("new yrok times")),
+            Some(terms("new york times"))
+        );
+        assert_eq!(
+            corrector.correct(&terms("egg yrok")),
+            Some(terms("egg yolk"))
+        );
+    }
+
+    #[test]
+    fn valid_but_wrong_words() {
+        let model = model(
+            "egg yolk. the yolk of an egg. a yellow yolk. \
+             read the new york times. new york times articles",
+        );
+        let corrector = QueryCorrector::new(&model, LogarithmicEdit::new(4));
+
+        assert_eq!(
+            corrector.correct(&terms("new yolk times")),
+            Some(terms("new york times"))
+        );
+        assert_eq!(corrector.correct(&terms("egg yolk")), None);
+        assert_eq!(corrector.correct(&terms("new york times")), None);
+    }
 }
@@ -0,0 +1,248 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Classification of third-party hosts into tracker categories. The bundled list
+//! in `trackers/trackers.txt` is used unless another list is loaded with [`load_database`].
+//! Loading a list replaces the one in use, so the list can be updated without a restart.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{Error, Result};
+
+static DATABASE: Lazy<RwLock<Arc<TrackerDatabase>>> = Lazy::new(|| {
+    RwLock::new(Arc::new(
+        TrackerDatabase::parse(include_str!("../trackers/trackers.txt"))
+            .expect("Failed to parse bundled tracker list"),
+    ))
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrackerCategory {
+    Analytics,
+    Advertising,
+    Social,
+    Cdn,
+}
+
+impl TrackerCategory {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "analytics" => Some(TrackerCategory::Analytics),
+            "advertising" | "ads" => Some(TrackerCategory::Advertising),
+            "social" => Some(TrackerCategory::Social),
+            "cdn" => Some(TrackerCategory::Cdn),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrackerCounts {
+    pub analytics: u64,
+    pub advertising: u64,
+    pub social: u64,
+    pub cdn: u64,
+    /// Third-party hosts that are not in the list.
+    pub unknown: u64,
+}
+
+impl TrackerCounts {
+    pub fn add(&mut self, category: Option<TrackerCategory>) {
+        match category {
+            Some(TrackerCategory::Analytics) => self.analytics += 1,
+            Some(TrackerCategory::Advertising) => self.advertising += 1,
+            Some(TrackerCategory::Social) => self.social += 1,
+            Some(TrackerCategory::Cdn) => self.cdn += 1,
+            None => self.unknown += 1,
+        }
+    }
+
+    /// Number of third-party hosts that might track the user. Only hosts listed as
+    /// CDNs are known not to, so all other hosts are counted.
+    pub fn num_trackers(&self) -> u64 {
+        self.analytics + self.advertising + self.social + self.unknown
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TrackerDatabase {
+    hosts: HashMap<String, TrackerCategory>,
+}
+
+impl TrackerDatabase {
+    /// The list consists of `[category]` sections followed by one host per line.
+    /// Adblock-style host rules (`||example.com^`) are also accepted,
+    /// and lines starting with `!` or `#` are comments.
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut hosts = HashMap::new();
+        let mut category = None;
+
+        for line in list.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('!') || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                category = Some(TrackerCategory::from_name(name).ok_or_else(|| {
+                    Error::ParsingError(format!("Unknown tracker category: {name}"))
+                })?);
+                continue;
+            }
+
+            let category = category.ok_or_else(|| {
+                Error::ParsingError(format!("Tracker host outside of a category: {line}"))
+            })?;
+
+            let host = line.strip_prefix("||").unwrap_or(line);
+            let host = host.strip_suffix('^').unwrap_or(host);
+
+            hosts.insert(host.to_ascii_lowercase(), category);
+        }
+
+        Ok(Self { hosts })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// The category of the host or the closest of its parent domains that is in the list.
+    pub fn category(&self, host: &str) -> Option<TrackerCategory> {
+        let host = host.to_ascii_lowercase();
+        let mut host = host.as_str();
+
+        loop {
+            if let Some(category) = self.hosts.get(host) {
+                return Some(*category);
+            }
+
+            match host.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => host = parent,
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Use the tracker list at `path` instead of the list currently in use. The list
+/// in use is kept if the new list can't be read.
+pub fn load_database<P: AsRef<Path>>(path: P) -> Result<()> {
+    load_into(&DATABASE, path.as_ref())
+}
+
+fn load_into(current: &RwLock<Arc<TrackerDatabase>>, path: &Path) -> Result<()> {
+    let database = TrackerDatabase::open(path)?;
+    *current.write().unwrap() = Arc::new(database);
+
+    Ok(())
+}
+
+pub fn database() -> Arc<TrackerDatabase> {
+    Arc::clone(&DATABASE.read().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_classify() {
+        let db = TrackerDatabase::parse(
+            r#"
+            ! comment
+            [analytics]
+            google-analytics.com
+            ||hotjar.com^
+
+            [cdn]
+            cdnjs.cloudflare.com
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.category("www.google-analytics.com"),
+            Some(TrackerCategory::Analytics)
+        );
+        assert_eq!(
+            db.category("static.HOTJAR.com"),
+            Some(TrackerCategory::Analytics)
+        );
+        assert_eq!(
+            db.category("cdnjs.cloudflare.com"),
+            Some(TrackerCategory::Cdn)
+        );
+        assert_eq!(db.category("cloudflare.com"), None);
+        assert_eq!(db.category("com"), None);
+    }
+
+    #[test]
+    fn invalid_lists() {
+        assert!(TrackerDatabase::parse("[unknown]\nexample.com").is_err());
+        assert!(TrackerDatabase::parse("example.com").is_err());
+    }
+
+    #[test]
+    fn reload_other_list() {
+        let dir = crate::gen_temp_path();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first = dir.join("first.txt");
+        let second = dir.join("second.txt");
+        std::fs::write(&first, "[cdn]\ncdn.example.com").unwrap();
+        std::fs::write(&second, "[ads]\nads.example.com").unwrap();
+
+        let current = RwLock::new(Arc::new(TrackerDatabase::default()));
+        load_into(&current, &first).unwrap();
+        assert_eq!(
+            current.read().unwrap().category("cdn.example.com"),
+            Some(TrackerCategory::Cdn)
+        );
+
+        load_into(&current, &second).unwrap();
+        assert_eq!(current.read().unwrap().category("cdn.example.com"), None);
+        assert_eq!(
+            current.read().unwrap().category("ads.example.com"),
+            Some(TrackerCategory::Advertising)
+        );
+
+        // a list that can't be read leaves the current one in place
+        assert!(load_into(&current, &dir.join("missing.txt")).is_err());
+        assert_eq!(
+            current.read().unwrap().category("ads.example.com"),
+            Some(TrackerCategory::Advertising)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bundled_list() {
+        let db = database();
+
+        assert_eq!(
+            db.category("securepubads.g.doubleclick.net"),
+            Some(TrackerCategory::Advertising)
+        );
+        assert_eq!(db.category("code.jquery.com"), Some(TrackerCategory::Cdn));
+    }
+}
@@ -15,7 +15,9 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::exponential_backoff::ExponentialBackoff;
-use crate::{Error, Result, WarcSource};
+use crate::{sources, Error, Result, WarcSource};
+use chrono::{DateTime, FixedOffset};
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
@@ -23,6 +25,8 @@ use std::path::Path;
 use std::time::Duration;
 
 use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use futures::StreamExt;
 use tokio::time::sleep;
 use tracing::debug;
@@ -35,27 +39,36 @@ fn rtrim(s: &mut String) {
     s.truncate(s.trim_end().len());
 }
 
-fn decode(raw: &[u8]) -> String {
-    if let Ok(res) = String::from_utf8(raw.to_owned()) {
-        res
-    } else {
-        let encodings = [
-            encoding_rs::WINDOWS_1251,
-            encoding_rs::GBK,
-            encoding_rs::SHIFT_JIS,
-            encoding_rs::EUC_JP,
-            encoding_rs::EUC_KR,
-        ];
-
-        for enc in encodings {
-            let (cow, _, had_errors) = enc.decode(raw);
-            if !had_errors {
-                return cow.to_string();
-            }
-        }
+fn decode<'a>(raw: &'a [u8], charset: Option<&str>) -> Cow<'a, str> {
+    let encoding = charset
+        .and_then(|label| encoding_rs::Encoding::for_label(label.trim().as_bytes()))
+        .or_else(|| sniff_meta_charset(raw))
+        .unwrap_or(encoding_rs::UTF_8);
 
-        String::from_utf8_lossy(raw).to_string()
-    }
+    let (cow, _, _) = encoding.decode(raw);
+    cow
+}
+
+/// Look for a `<meta charset="...">` or `<meta http-equiv="content-type" content="...; charset=...">`
+/// declaration in the first kilobyte of the body. This is only used when the
+/// HTTP header does not declare a charset.
+fn sniff_meta_charset(raw: &[u8]) -> Option<&'static encoding_rs::Encoding> {
+    let prefix = String::from_utf8_lossy(&raw[..raw.len().min(1024)]).to_ascii_lowercase();
+
+    let start = prefix.find("charset=")? + "charset=".len();
+    let label: String = prefix[start..]
+        .trim_start_matches(|c| c == '"' || c == '\'')
+        .chars()
+        .take_while(|c| !matches!(c, '"' | '\'' | ';' | '>' | '/') && !c.is_whitespace())
+        .collect();
+
+    encoding_rs::Encoding::for_label(label.as_bytes())
+}
+
+fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
 }
 
 impl WarcFile {
@@ -104,6 +117,12 @@ impl WarcFile {
                 WarcSource::Local(config) => {
                     WarcFile::load_from_folder(warc_path, &config.folder, buf)
                 }
+                WarcSource::Directory(config) => {
+                    sources::directory::write_warc(warc_path, &config, buf)
+                }
+                WarcSource::Sitemap(config) => {
+                    sources::sitemap::write_warc(warc_path, &config, buf).await
+                }
             };
 
             if res.is_ok() {
@@ -190,22 +209,176 @@ impl Request {
 
 #[derive(Debug)]
 pub(crate) struct Response {
-    pub(crate) body: String,
+    // the undecoded http payload. textual content is decoded on demand with `body`
+    // so we don't keep a second copy of every payload around.
+    pub(crate) payload: Vec<u8>,
     pub(crate) payload_type: Option<String>,
+    pub(crate) http: HttpHeader,
+}
+
+/// The parsed status line and the subset of HTTP headers we care about.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct HttpHeader {
+    pub(crate) status: u16,
+    // mime type without parameters, e.g. `text/html`
+    pub(crate) content_type: Option<String>,
+    pub(crate) charset: Option<String>,
+    pub(crate) last_modified: Option<DateTime<FixedOffset>>,
+    pub(crate) content_language: Option<String>,
+    pub(crate) location: Option<String>,
+}
+
+impl HttpHeader {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        let raw = String::from_utf8_lossy(raw);
+        let mut lines = raw.lines();
+
+        let status = lines
+            .next()
+            .filter(|line| line.to_ascii_uppercase().starts_with("HTTP/"))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or(Error::WarcParse("Invalid http status line"))?;
+
+        let mut header = HttpHeader {
+            status,
+            ..Default::default()
+        };
+
+        for line in lines {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            if value.is_empty() {
+                continue;
+            }
+
+            match key.as_str() {
+                "content-type" => {
+                    let mut params = value.split(';');
+
+                    header.content_type = params
+                        .next()
+                        .map(|mime| mime.trim().to_ascii_lowercase())
+                        .filter(|mime| !mime.is_empty());
+
+                    header.charset = params
+                        .filter_map(|param| param.split_once('='))
+                        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+                        .map(|(_, charset)| charset.trim().trim_matches('"').to_ascii_lowercase());
+                }
+                "last-modified" => {
+                    header.last_modified = DateTime::parse_from_rfc2822(value).ok();
+                }
+                "content-language" => {
+                    header.content_language = Some(value.to_ascii_lowercase());
+                }
+                "location" => {
+                    header.location = Some(value.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(header)
+    }
 }
 
 impl Response {
-    fn from_raw(record: RawWarcRecord) -> Result<Self> {
-        let content = decode(&record.content[..]);
+    pub(crate) fn new(http: HttpHeader, payload: Vec<u8>) -> Self {
+        Self {
+            payload,
+            payload_type: None,
+            http,
+        }
+    }
 
-        let (_header, content) = content
-            .split_once("\r\n\r\n")
+    /// Parse a raw http response (status line, headers and body).
+    pub(crate) fn from_http_bytes(content: &[u8]) -> Result<Self> {
+        let (header_end, separator_len) = find_subsequence(content, b"\r\n\r\n")
+            .map(|idx| (idx, 4))
+            .or_else(|| find_subsequence(content, b"\n\n").map(|idx| (idx, 2)))
             .ok_or(Error::WarcParse("Invalid http body"))?;
 
-        Ok(Self {
-            body: content.to_string(),
-            payload_type: record.header.get("WARC-IDENTIFIED-PAYLOAD-TYPE").cloned(),
-        })
+        let http = HttpHeader::parse(&content[..header_end])?;
+        let payload = content[header_end + separator_len..].to_vec();
+
+        Ok(Self::new(http, payload))
+    }
+
+    fn from_raw(record: RawWarcRecord) -> Result<Self> {
+        let mut response = Self::from_http_bytes(&record.content)?;
+        response.payload_type = record.header.get("WARC-IDENTIFIED-PAYLOAD-TYPE").cloned();
+
+        Ok(response)
+    }
+
+    fn to_http_bytes(&self) -> Vec<u8> {
+        let mut header = format!("HTTP/1.1 {}\r\n", self.http.status);
+
+        if let Some(content_type) = &self.http.content_type {
+            header.push_str("Content-Type: ");
+            header.push_str(content_type);
+            if let Some(charset) = &self.http.charset {
+                header.push_str("; charset=");
+                header.push_str(charset);
+            }
+            header.push_str("\r\n");
+        }
+
+        if let Some(last_modified) = &self.http.last_modified {
+            header.push_str("Last-Modified: ");
+            header.push_str(&last_modified.to_rfc2822());
+            header.push_str("\r\n");
+        }
+
+        if let Some(content_language) = &self.http.content_language {
+            header.push_str("Content-Language: ");
+            header.push_str(content_language);
+            header.push_str("\r\n");
+        }
+
+        if let Some(location) = &self.http.location {
+            header.push_str("Location: ");
+            header.push_str(location);
+            header.push_str("\r\n");
+        }
+
+        header.push_str("\r\n");
+
+        let mut bytes = header.into_bytes();
+        bytes.extend_from_slice(&self.payload);
+
+        bytes
+    }
+
+    /// The payload decoded with its declared (or sniffed) charset.
+    /// Borrows the payload when no transcoding is needed.
+    pub(crate) fn body(&self) -> Cow<'_, str> {
+        decode(&self.payload, self.http.charset.as_deref())
+    }
+
+    /// The mime type identified by the crawler is preferred over the one declared by the server.
+    pub(crate) fn mime(&self) -> Option<&str> {
+        self.payload_type
+            .as_deref()
+            .or(self.http.content_type.as_deref())
+    }
+
+    /// Only successful responses should be indexed as pages.
+    pub(crate) fn is_success(&self) -> bool {
+        self.http.status == 200
+    }
+
+    /// Returns the redirect target if this is a 3xx response with a `Location` header.
+    pub(crate) fn redirect_location(&self) -> Option<&str> {
+        if (300..400).contains(&self.http.status) {
+            self.http.location.as_deref()
+        } else {
+            None
+        }
     }
 }
 
@@ -382,12 +555,68 @@ impl<R: Read> Iterator for RecordIterator<R> {
     }
 }
 
+/// Writes records in the same layout as Common Crawl (a warcinfo record followed by
+/// request, response and metadata records for each page), so the output can be read by
+/// [`WarcFile::records`].
+pub(crate) struct WarcWriter<W: Write> {
+    encoder: GzEncoder<W>,
+}
+
+impl<W: Write> WarcWriter<W> {
+    pub(crate) fn new(writer: W) -> Result<Self> {
+        let mut writer = Self {
+            encoder: GzEncoder::new(writer, Compression::default()),
+        };
+
+        writer.write_raw(&[("WARC-Type", "warcinfo")], b"software: cuely\r\n")?;
+
+        Ok(writer)
+    }
+
+    fn write_raw(&mut self, header: &[(&str, &str)], content: &[u8]) -> Result<()> {
+        self.encoder.write_all(b"WARC/1.0\r\n")?;
+
+        for (key, value) in header {
+            write!(self.encoder, "{}: {}\r\n", key, value)?;
+        }
+
+        write!(self.encoder, "Content-Length: {}\r\n\r\n", content.len())?;
+        self.encoder.write_all(content)?;
+        self.encoder.write_all(b"\r\n\r\n")?;
+
+        Ok(())
+    }
+
+    pub(crate) fn write(&mut self, record: &WarcRecord) -> Result<()> {
+        let url = record.request.url.as_str();
+
+        self.write_raw(
+            &[("WARC-Type", "request"), ("WARC-Target-URI", url)],
+            format!("GET {} HTTP/1.1\r\n", url).as_bytes(),
+        )?;
+
+        let mut response_header = vec![("WARC-Type", "response"), ("WARC-Target-URI", url)];
+        if let Some(payload_type) = &record.response.payload_type {
+            response_header.push(("WARC-Identified-Payload-Type", payload_type));
+        }
+        self.write_raw(&response_header, &record.response.to_http_bytes())?;
+
+        self.write_raw(
+            &[("WARC-Type", "metadata"), ("WARC-Target-URI", url)],
+            format!("fetchTimeMs: {}\r\n", record.metadata.fetch_time_ms).as_bytes(),
+        )?;
+
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<W> {
+        Ok(self.encoder.finish()?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use flate2::write::GzEncoder;
-    use flate2::Compression;
-    use std::io::Write;
 
     #[test]
     fn it_works() {
@@ -407,9 +636,10 @@ mod tests {
                 \r\n\
                 warc/1.0\r\n\
                 warc-tYPE: response\r\n\
-                cONTENT-lENGTH: 29\r\n\
+                cONTENT-lENGTH: 75\r\n\
                 \r\n\
-                http-body\r\n\
+                HTTP/1.1 200 OK\r\n\
+                Content-Type: text/html; charset=utf-8\r\n\
                 \r\n\
                 body of response\r\n\
                 \r\n\
@@ -430,7 +660,112 @@ mod tests {
 
         assert_eq!(records.len(), 1);
         assert_eq!(&records[0].request.url, "http://0575ls.cn/news-52300.htm");
-        assert_eq!(&records[0].response.body, "body of response");
+        assert_eq!(records[0].response.body(), "body of response");
         assert_eq!(records[0].metadata.fetch_time_ms, 937);
+        assert_eq!(records[0].response.http.status, 200);
+        assert_eq!(
+            records[0].response.http.content_type.as_deref(),
+            Some("text/html")
+        );
+        assert_eq!(records[0].response.http.charset.as_deref(), Some("utf-8"));
+        assert!(records[0].response.is_success());
+    }
+
+    #[test]
+    fn http_header() {
+        let header = HttpHeader::parse(
+            b"HTTP/1.1 301 Moved Permanently\r\n\
+            content-type: text/html;charset=\"ISO-8859-1\"\r\n\
+            Last-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\n\
+            Content-Language: da-DK\r\n\
+            Location: https://example.com/new",
+        )
+        .unwrap();
+
+        assert_eq!(header.status, 301);
+        assert_eq!(header.content_type.as_deref(), Some("text/html"));
+        assert_eq!(header.charset.as_deref(), Some("iso-8859-1"));
+        assert_eq!(
+            header.last_modified,
+            Some(DateTime::parse_from_rfc3339("2015-10-21T07:28:00+00:00").unwrap())
+        );
+        assert_eq!(header.content_language.as_deref(), Some("da-dk"));
+        assert_eq!(header.location.as_deref(), Some("https://example.com/new"));
+
+        assert!(HttpHeader::parse(b"http-body").is_err());
+    }
+
+    #[test]
+    fn redirect() {
+        let mut content = b"HTTP/1.1 301 Moved Permanently\r\n\
+            Location: https://example.com/new\r\n\
+            \r\n"
+            .to_vec();
+        content.extend_from_slice(b"moved");
+
+        let response = Response::from_raw(RawWarcRecord {
+            header: BTreeMap::new(),
+            content,
+        })
+        .unwrap();
+
+        assert!(!response.is_success());
+        assert_eq!(
+            response.redirect_location(),
+            Some("https://example.com/new")
+        );
+    }
+
+    #[test]
+    fn write_and_read() {
+        let record = WarcRecord {
+            request: Request {
+                url: "https://example.com/".to_string(),
+            },
+            response: Response::new(
+                HttpHeader {
+                    status: 200,
+                    content_type: Some("text/html".to_string()),
+                    charset: Some("utf-8".to_string()),
+                    last_modified: Some(
+                        DateTime::parse_from_rfc3339("2015-10-21T07:28:00+00:00").unwrap(),
+                    ),
+                    content_language: Some("en".to_string()),
+                    location: None,
+                },
+                b"<html>hello</html>".to_vec(),
+            ),
+            metadata: Metadata { fetch_time_ms: 42 },
+        };
+
+        let mut writer = WarcWriter::new(Vec::new()).unwrap();
+        writer.write(&record).unwrap();
+        writer.write(&record).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let records: Vec<WarcRecord> = WarcFile::new(bytes)
+            .records()
+            .map(|res| res.unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 2);
+        for res in records {
+            assert_eq!(res.request.url, record.request.url);
+            assert_eq!(res.response.body(), "<html>hello</html>");
+            assert_eq!(res.response.http, record.response.http);
+            assert_eq!(res.metadata.fetch_time_ms, 42);
+        }
+    }
+
+    #[test]
+    fn decode_declared_charset() {
+        let (latin1, _, _) = encoding_rs::WINDOWS_1252.encode("blåbærgrød");
+
+        assert_eq!(decode(&latin1, Some("iso-8859-1")), "blåbærgrød");
+        assert_eq!(decode("blåbærgrød".as_bytes(), None), "blåbærgrød");
+
+        let mut sniffed = b"<html><head><meta charset=\"windows-1252\"></head>".to_vec();
+        sniffed.extend_from_slice(&latin1);
+        assert!(decode(&sniffed, None).ends_with("blåbærgrød"));
     }
 }
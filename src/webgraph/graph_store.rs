@@ -21,14 +21,14 @@ use std::{
 use lru::LruCache;
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::{Edge, EdgeIterator, Node, NodeID, Store, StoredEdge};
+use super::{Edge, EdgeIterator, Label, Node, NodeID, Store, StoredEdge};
 use crate::kv::{rocksdb_store::RocksDbStore, Kv};
 pub(crate) struct Adjacency {
     pub(crate) tree: BlockedCachedTree<NodeID, Vec<StoredEdge>>,
 }
 
 impl Adjacency {
-    fn insert(&mut self, from: NodeID, to: NodeID, label: String) {
+    fn insert(&mut self, from: NodeID, to: NodeID, label: Label) {
         self.tree.insert(from, &mut |block| {
             block.entry(from).or_default().push(StoredEdge {
                 other: to,
@@ -247,7 +247,7 @@ impl<S: Store> GraphStore<S> {
             .into_iter()
     }
 
-    pub fn insert(&mut self, from: Node, to: Node, label: String) {
+    pub fn insert(&mut self, from: Node, to: Node, label: Label) {
         let from_id = self.id_or_assign(from);
         let to_id = self.id_or_assign(to);
 
@@ -381,10 +381,10 @@ mod test {
             name: "C".to_string(),
         };
 
-        store.insert(a.clone(), b.clone(), String::new());
-        store.insert(b.clone(), c.clone(), String::new());
-        store.insert(c.clone(), a.clone(), String::new());
-        store.insert(a.clone(), c.clone(), String::new());
+        store.insert(a.clone(), b.clone(), Label::default());
+        store.insert(b.clone(), c.clone(), Label::default());
+        store.insert(c.clone(), a.clone(), Label::default());
+        store.insert(a.clone(), c.clone(), Label::default());
 
         store.flush();
 
@@ -404,12 +404,12 @@ mod test {
                 Edge {
                     from: a_id,
                     to: b_id,
-                    label: String::new()
+                    label: Label::default()
                 },
                 Edge {
                     from: a_id,
                     to: c_id,
-                    label: String::new()
+                    label: Label::default()
                 },
             ]
         );
@@ -419,7 +419,7 @@ mod test {
             vec![Edge {
                 from: b_id,
                 to: c_id,
-                label: String::new()
+                label: Label::default()
             },]
         );
 
@@ -429,12 +429,12 @@ mod test {
                 Edge {
                     from: b_id,
                     to: c_id,
-                    label: String::new()
+                    label: Label::default()
                 },
                 Edge {
                     from: a_id,
                     to: c_id,
-                    label: String::new()
+                    label: Label::default()
                 },
             ]
         );
@@ -444,7 +444,7 @@ mod test {
             vec![Edge {
                 from: c_id,
                 to: a_id,
-                label: String::new()
+                label: Label::default()
             },]
         );
 
@@ -453,7 +453,7 @@ mod test {
             vec![Edge {
                 from: a_id,
                 to: b_id,
-                label: String::new()
+                label: Label::default()
             },]
         );
     }
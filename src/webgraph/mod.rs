@@ -26,17 +26,41 @@ use tracing::info;
 use graph_store::GraphStore;
 
 use crate::directory::{self, DirEntry};
-use crate::webpage::Url;
+use crate::webpage::{Link, LinkPosition, Url};
 
 use self::graph_store::Adjacency;
 use crate::kv::rocksdb_store::RocksDbStore;
 
 type NodeID = u64;
 
+/// Version of the on-disk edge format. Must be bumped whenever `StoredEdge` changes,
+/// as the edges are stored with bincode which can't detect an old format.
+/// Version 1 (no version file) stored the label as a plain string.
+const FORMAT_VERSION: u32 = 2;
+const FORMAT_VERSION_FILE: &str = "format_version";
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) struct StoredEdge {
     other: NodeID,
-    label: String,
+    label: Label,
+}
+
+/// The anchor text of a link and where on the source page it was found.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Label {
+    pub text: String,
+    pub position: LinkPosition,
+    pub context: String,
+}
+
+impl From<Link> for Label {
+    fn from(link: Link) -> Self {
+        Self {
+            text: link.text,
+            position: link.position,
+            context: link.context,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -157,14 +181,14 @@ impl<'a> Iterator for EdgeIterator<'a> {
 pub struct Edge {
     from: NodeID,
     to: NodeID,
-    label: String,
+    label: Label,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FullEdge {
     pub from: Node,
     pub to: Node,
-    pub label: String,
+    pub label: Label,
 }
 
 pub struct WebgraphBuilder {
@@ -208,7 +232,42 @@ impl WebgraphBuilder {
         self
     }
 
+    /// Panics if the graph was built with another edge format, as it has to be rebuilt.
+    fn check_format_version(&self) {
+        let version_path = self.path.join(FORMAT_VERSION_FILE);
+
+        let version = match fs::read_to_string(&version_path) {
+            Ok(version) => version.trim().parse::<u32>().ok(),
+            Err(_) => {
+                let has_graph = [self.path.join("full"), self.path.join("host")]
+                    .iter()
+                    .any(|path| path.exists());
+
+                if has_graph {
+                    Some(1)
+                } else {
+                    if !self.read_only {
+                        fs::create_dir_all(&self.path).unwrap();
+                        fs::write(&version_path, FORMAT_VERSION.to_string()).unwrap();
+                    }
+
+                    Some(FORMAT_VERSION)
+                }
+            }
+        };
+
+        if version != Some(FORMAT_VERSION) {
+            panic!(
+                "webgraph at {:?} has edge format version {:?} but version {} is required. \
+                 The webgraph must be rebuilt.",
+                self.path, version, FORMAT_VERSION
+            );
+        }
+    }
+
     pub fn open(self) -> Webgraph {
+        self.check_format_version();
+
         if self.read_only {
             Webgraph {
                 full_graph: self.full_graph_path.map(GraphStore::open_read_only),
@@ -244,7 +303,7 @@ pub struct Webgraph<S: Store = RocksDbStore> {
 }
 
 impl<S: Store> Webgraph<S> {
-    pub fn insert(&mut self, from: Node, to: Node, label: String) {
+    pub fn insert(&mut self, from: Node, to: Node, label: Label) {
         if let Some(full_graph) = &mut self.full_graph {
             full_graph.insert(from.clone(), to.clone(), label.clone());
         }
@@ -574,11 +633,11 @@ mod test {
             .with_host_graph()
             .open();
 
-        graph.insert(Node::from("A"), Node::from("B"), String::new());
-        graph.insert(Node::from("B"), Node::from("C"), String::new());
-        graph.insert(Node::from("A"), Node::from("C"), String::new());
-        graph.insert(Node::from("C"), Node::from("A"), String::new());
-        graph.insert(Node::from("D"), Node::from("C"), String::new());
+        graph.insert(Node::from("A"), Node::from("B"), Label::default());
+        graph.insert(Node::from("B"), Node::from("C"), Label::default());
+        graph.insert(Node::from("A"), Node::from("C"), Label::default());
+        graph.insert(Node::from("C"), Node::from("A"), Label::default());
+        graph.insert(Node::from("D"), Node::from("C"), Label::default());
 
         graph.flush();
 
@@ -645,20 +704,68 @@ mod test {
             .with_host_graph()
             .open();
 
-        graph.insert(Node::from("A.com/1"), Node::from("A.com/2"), String::new());
-        graph.insert(Node::from("A.com/1"), Node::from("A.com/3"), String::new());
-        graph.insert(Node::from("A.com/1"), Node::from("A.com/4"), String::new());
-        graph.insert(Node::from("A.com/2"), Node::from("A.com/1"), String::new());
-        graph.insert(Node::from("A.com/2"), Node::from("A.com/3"), String::new());
-        graph.insert(Node::from("A.com/2"), Node::from("A.com/4"), String::new());
-        graph.insert(Node::from("A.com/3"), Node::from("A.com/1"), String::new());
-        graph.insert(Node::from("A.com/3"), Node::from("A.com/2"), String::new());
-        graph.insert(Node::from("A.com/3"), Node::from("A.com/4"), String::new());
-        graph.insert(Node::from("A.com/4"), Node::from("A.com/1"), String::new());
-        graph.insert(Node::from("A.com/4"), Node::from("A.com/2"), String::new());
-        graph.insert(Node::from("A.com/4"), Node::from("A.com/3"), String::new());
-        graph.insert(Node::from("C.com"), Node::from("B.com"), String::new());
-        graph.insert(Node::from("D.com"), Node::from("B.com"), String::new());
+        graph.insert(
+            Node::from("A.com/1"),
+            Node::from("A.com/2"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/1"),
+            Node::from("A.com/3"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/1"),
+            Node::from("A.com/4"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/2"),
+            Node::from("A.com/1"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/2"),
+            Node::from("A.com/3"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/2"),
+            Node::from("A.com/4"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/3"),
+            Node::from("A.com/1"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/3"),
+            Node::from("A.com/2"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/3"),
+            Node::from("A.com/4"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/4"),
+            Node::from("A.com/1"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/4"),
+            Node::from("A.com/2"),
+            Label::default(),
+        );
+        graph.insert(
+            Node::from("A.com/4"),
+            Node::from("A.com/3"),
+            Label::default(),
+        );
+        graph.insert(Node::from("C.com"), Node::from("B.com"), Label::default());
+        graph.insert(Node::from("D.com"), Node::from("B.com"), Label::default());
 
         graph.flush();
 
@@ -682,8 +789,12 @@ mod test {
             .with_host_graph()
             .open();
 
-        graph.insert(Node::from("B.com"), Node::from("A.com"), String::new());
-        graph.insert(Node::from("B.com"), Node::from("www.A.com"), String::new());
+        graph.insert(Node::from("B.com"), Node::from("A.com"), Label::default());
+        graph.insert(
+            Node::from("B.com"),
+            Node::from("www.A.com"),
+            Label::default(),
+        );
 
         graph.flush();
 
@@ -699,13 +810,13 @@ mod test {
             .with_host_graph()
             .open();
 
-        graph1.insert(Node::from("A"), Node::from("B"), String::new());
+        graph1.insert(Node::from("A"), Node::from("B"), Label::default());
 
         let mut graph2 = WebgraphBuilder::new_memory()
             .with_full_graph()
             .with_host_graph()
             .open();
-        graph2.insert(Node::from("B"), Node::from("C"), String::new());
+        graph2.insert(Node::from("B"), Node::from("C"), Label::default());
 
         graph1.merge(graph2);
 
@@ -715,6 +826,33 @@ mod test {
         )
     }
 
+    #[test]
+    fn reopen_graph() {
+        let path = crate::gen_temp_path();
+
+        let mut graph = WebgraphBuilder::new(&path).with_full_graph().open();
+        graph.insert(Node::from("A"), Node::from("B"), Label::default());
+        graph.flush();
+        drop(graph);
+
+        let graph = WebgraphBuilder::new(&path).with_full_graph().open();
+        assert_eq!(
+            graph.distances(Node::from("A")).get(&Node::from("B")),
+            Some(&1)
+        );
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn unversioned_graph_is_rejected() {
+        let path = crate::gen_temp_path();
+        std::fs::create_dir_all(path.join("full")).unwrap();
+
+        WebgraphBuilder::new(&path).with_full_graph().open();
+    }
+
     #[test]
     fn serialize_deserialize_bincode() {
         let graph = test_graph();
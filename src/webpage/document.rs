@@ -0,0 +1,417 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Non-HTML documents (PDF, plain text and markdown) are converted into an
+//! equivalent HTML document, so the rest of the pipeline (text extraction,
+//! signals, indexing) can treat every document as `Html`.
+
+use std::panic;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_DESCRIPTION_CHARS: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContentType {
+    Html,
+    Pdf,
+    PlainText,
+    Markdown,
+}
+
+impl Default for ContentType {
+    fn default() -> Self {
+        ContentType::Html
+    }
+}
+
+/// The mime type without parameters, e.g. `text/html` for `text/html; charset=utf-8`.
+fn mime_essence(mime: &str) -> String {
+    mime.split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+impl ContentType {
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        match mime_essence(mime).as_str() {
+            "text/html" | "application/xhtml+xml" => Some(ContentType::Html),
+            "application/pdf" | "application/x-pdf" => Some(ContentType::Pdf),
+            "text/plain" => Some(ContentType::PlainText),
+            "text/markdown" | "text/x-markdown" => Some(ContentType::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn from_extension(url: &str) -> Option<Self> {
+        let path = url
+            .split(|c| c == '?' || c == '#')
+            .next()
+            .unwrap_or_default();
+        let last_segment = path.rsplit('/').next().unwrap_or_default();
+        let (_, extension) = last_segment.rsplit_once('.')?;
+
+        ContentType::from_filetype(extension)
+    }
+
+    /// Determine the content type from the declared mime type, falling back to
+    /// the file extension in the url if the mime type is missing or generic.
+    pub fn guess(mime: Option<&str>, url: &str) -> Option<Self> {
+        match mime {
+            Some(mime) => ContentType::from_mime(mime).or_else(|| {
+                if matches!(
+                    mime_essence(mime).as_str(),
+                    "application/octet-stream" | "binary/octet-stream"
+                ) {
+                    ContentType::from_extension(url)
+                } else {
+                    None
+                }
+            }),
+            None => ContentType::from_extension(url).or(Some(ContentType::Html)),
+        }
+    }
+
+    pub fn from_filetype(filetype: &str) -> Option<Self> {
+        match filetype.to_ascii_lowercase().as_str() {
+            "html" | "htm" | "xhtml" => Some(ContentType::Html),
+            "pdf" => Some(ContentType::Pdf),
+            "txt" | "text" => Some(ContentType::PlainText),
+            "md" | "markdown" => Some(ContentType::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn filetype(&self) -> &'static str {
+        match self {
+            ContentType::Html => "html",
+            ContentType::Pdf => "pdf",
+            ContentType::PlainText => "txt",
+            ContentType::Markdown => "md",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            ContentType::Html => "text/html",
+            ContentType::Pdf => "application/pdf",
+            ContentType::PlainText => "text/plain",
+            ContentType::Markdown => "text/markdown",
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        match self {
+            ContentType::Html => 0,
+            ContentType::Pdf => 1,
+            ContentType::PlainText => 2,
+            ContentType::Markdown => 3,
+        }
+    }
+
+    pub fn from_id(id: u64) -> Option<Self> {
+        match id {
+            0 => Some(ContentType::Html),
+            1 => Some(ContentType::Pdf),
+            2 => Some(ContentType::PlainText),
+            3 => Some(ContentType::Markdown),
+            _ => None,
+        }
+    }
+}
+
+pub enum Document<'a> {
+    Html(&'a str),
+    Pdf(&'a [u8]),
+    PlainText(&'a str),
+    Markdown(&'a str),
+}
+
+impl<'a> Document<'a> {
+    /// `text` is the payload decoded with its declared charset, `raw` is the undecoded payload.
+    /// Binary formats are read from `raw`, textual formats from `text`.
+    pub fn new(content_type: ContentType, text: &'a str, raw: &'a [u8]) -> Self {
+        match content_type {
+            ContentType::Html => Document::Html(text),
+            ContentType::Pdf => Document::Pdf(raw),
+            ContentType::PlainText => Document::PlainText(text),
+            ContentType::Markdown => Document::Markdown(text),
+        }
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            Document::Html(_) => ContentType::Html,
+            Document::Pdf(_) => ContentType::Pdf,
+            Document::PlainText(_) => ContentType::PlainText,
+            Document::Markdown(_) => ContentType::Markdown,
+        }
+    }
+
+    pub fn into_html(self) -> Result<String> {
+        match self {
+            Document::Html(html) => Ok(html.to_string()),
+            Document::Pdf(bytes) => {
+                let text = extract_pdf_text(bytes)?;
+                Ok(text_into_html(&text))
+            }
+            Document::PlainText(text) => Ok(text_into_html(text)),
+            Document::Markdown(markdown) => Ok(markdown_into_html(markdown)),
+        }
+    }
+}
+
+fn extract_pdf_text(bytes: &[u8]) -> Result<String> {
+    // the pdf parser panics on some malformed documents
+    let res = panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+        .map_err(|_| Error::ParsingError("PDF parser panicked".to_string()))?;
+
+    res.map_err(|err| Error::ParsingError(format!("Could not extract text from PDF: {err:?}")))
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => text[..idx].to_string(),
+        None => text.to_string(),
+    }
+}
+
+fn paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(line);
+    }
+
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+
+    paragraphs
+}
+
+fn head(title: Option<&str>, description: Option<&str>) -> String {
+    let mut head = String::from("<head>");
+
+    if let Some(title) = title {
+        head.push_str("<title>");
+        head.push_str(&html_escape::encode_text(&truncate_chars(
+            title,
+            MAX_TITLE_CHARS,
+        )));
+        head.push_str("</title>");
+    }
+
+    if let Some(description) = description {
+        head.push_str(r#"<meta property="og:description" content=""#);
+        head.push_str(&html_escape::encode_double_quoted_attribute(
+            &truncate_chars(description, MAX_DESCRIPTION_CHARS),
+        ));
+        head.push_str(r#"">"#);
+    }
+
+    head.push_str("</head>");
+
+    head
+}
+
+/// The first line is used as title and the first paragraph after it as description.
+fn text_into_html(text: &str) -> String {
+    let title = text.lines().map(str::trim).find(|line| !line.is_empty());
+    let paragraphs = paragraphs(text);
+
+    let description = paragraphs
+        .iter()
+        .map(String::as_str)
+        .find(|paragraph| Some(*paragraph) != title);
+
+    let mut html = String::from("<html>");
+    html.push_str(&head(title, description));
+    html.push_str("<body>");
+
+    for paragraph in &paragraphs {
+        html.push_str("<p>");
+        html.push_str(&html_escape::encode_text(paragraph));
+        html.push_str("</p>");
+    }
+
+    html.push_str("</body></html>");
+
+    html
+}
+
+/// The first heading is used as title and the first paragraph as description.
+fn markdown_into_html(markdown: &str) -> String {
+    use pulldown_cmark::{Event, Parser, Tag};
+
+    let mut title: Option<String> = None;
+    let mut description: Option<String> = None;
+
+    let mut in_heading = false;
+    let mut in_paragraph = false;
+    let mut text = String::new();
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Heading(..)) => {
+                in_heading = true;
+                text.clear();
+            }
+            Event::End(Tag::Heading(..)) => {
+                in_heading = false;
+                if title.is_none() && !text.trim().is_empty() {
+                    title = Some(text.trim().to_string());
+                }
+            }
+            Event::Start(Tag::Paragraph) => {
+                in_paragraph = true;
+                text.clear();
+            }
+            Event::End(Tag::Paragraph) => {
+                in_paragraph = false;
+                if description.is_none() && !text.trim().is_empty() {
+                    description = Some(text.trim().to_string());
+                }
+            }
+            Event::Text(t) | Event::Code(t) if in_heading || in_paragraph => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak if in_heading || in_paragraph => text.push(' '),
+            _ => {}
+        }
+    }
+
+    let title = title.or_else(|| {
+        markdown
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+    });
+
+    let mut html = String::from("<html>");
+    html.push_str(&head(title.as_deref(), description.as_deref()));
+    html.push_str("<body>");
+    pulldown_cmark::html::push_html(&mut html, Parser::new(markdown));
+    html.push_str("</body></html>");
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guess_content_type() {
+        assert_eq!(
+            ContentType::guess(Some("text/html; charset=utf-8"), "https://a.com"),
+            Some(ContentType::Html)
+        );
+        assert_eq!(
+            ContentType::guess(Some("application/pdf"), "https://a.com/paper"),
+            Some(ContentType::Pdf)
+        );
+        assert_eq!(
+            ContentType::guess(Some("application/octet-stream"), "https://a.com/paper.PDF"),
+            Some(ContentType::Pdf)
+        );
+        assert_eq!(
+            ContentType::guess(
+                Some("Application/Octet-Stream; charset=binary"),
+                "https://a.com/paper.pdf"
+            ),
+            Some(ContentType::Pdf)
+        );
+        assert_eq!(
+            ContentType::guess(None, "https://a.com/README.md?raw=true"),
+            Some(ContentType::Markdown)
+        );
+        assert_eq!(
+            ContentType::guess(None, "https://a.com/"),
+            Some(ContentType::Html)
+        );
+        assert_eq!(ContentType::guess(Some("image/png"), "https://a.com"), None);
+    }
+
+    #[test]
+    fn id_roundtrip() {
+        for content_type in [
+            ContentType::Html,
+            ContentType::Pdf,
+            ContentType::PlainText,
+            ContentType::Markdown,
+        ] {
+            assert_eq!(ContentType::from_id(content_type.id()), Some(content_type));
+            assert_eq!(
+                ContentType::from_filetype(content_type.filetype()),
+                Some(content_type)
+            );
+        }
+    }
+
+    #[test]
+    fn plain_text() {
+        let html = Document::PlainText(
+            "A <tiny> title\n\nFirst paragraph\nspanning two lines.\n\nSecond paragraph.",
+        )
+        .into_html()
+        .unwrap();
+
+        assert_eq!(
+            html,
+            "<html><head><title>A &lt;tiny&gt; title</title>\
+            <meta property=\"og:description\" content=\"First paragraph spanning two lines.\">\
+            </head><body><p>A &lt;tiny&gt; title</p>\
+            <p>First paragraph spanning two lines.</p>\
+            <p>Second paragraph.</p></body></html>"
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        let html = Document::Markdown("Intro line\n\n# The *title*\n\nSome text.\n")
+            .into_html()
+            .unwrap();
+
+        assert!(html.starts_with(
+            "<html><head><title>The title</title>\
+            <meta property=\"og:description\" content=\"Intro line\">"
+        ));
+        assert!(html.contains("<h1>The <em>title</em></h1>"));
+    }
+
+    #[test]
+    fn invalid_pdf() {
+        assert!(Document::Pdf(b"not a pdf").into_html().is_err());
+    }
+}
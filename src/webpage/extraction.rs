@@ -0,0 +1,373 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Main content extraction. Pages are extracted with a site specific rule if one
+//! matches the url, and otherwise with the configured default extractor. If a
+//! strategy produces no text, the next one in line is tried and the strategy that
+//! produced the text is recorded on the page.
+
+use std::fmt;
+
+use kuchiki::NodeRef;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+use super::{
+    just_text::{JustText, Paragraph},
+    readability::Readability,
+    Url,
+};
+use crate::{Error, ExtractionConfig, Result};
+
+static EXTRACTOR: OnceCell<ConfiguredExtractor> = OnceCell::new();
+
+struct ConfiguredExtractor {
+    config: ExtractionConfig,
+    extractor: ContentExtractor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionStrategy {
+    JustText,
+    Readability,
+    /// Everything inside the elements matching the CSS selector.
+    Selector(String),
+}
+
+impl Default for ExtractionStrategy {
+    fn default() -> Self {
+        ExtractionStrategy::JustText
+    }
+}
+
+impl fmt::Display for ExtractionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractionStrategy::JustText => write!(f, "just_text"),
+            ExtractionStrategy::Readability => write!(f, "readability"),
+            ExtractionStrategy::Selector(selector) => write!(f, "selector({selector})"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct SiteRule {
+    host: String,
+    path_prefix: String,
+    strategy: ExtractionStrategy,
+}
+
+impl SiteRule {
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host();
+
+        (host == self.host || host.ends_with(&format!(".{}", self.host)))
+            && url.path().starts_with(&self.path_prefix)
+    }
+
+    /// Longer hosts are more specific than longer paths.
+    fn specificity(&self) -> (usize, usize) {
+        (self.host.len(), self.path_prefix.len())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SiteRules {
+    rules: Vec<SiteRule>,
+}
+
+impl SiteRules {
+    pub fn parse(rules: &str) -> Result<Self> {
+        let mut res = Vec::new();
+
+        for (line_num, line) in rules.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('!') {
+                continue;
+            }
+
+            let (pattern, action) = line.split_once(char::is_whitespace).ok_or_else(|| {
+                Error::ParsingError(format!(
+                    "site rule on line {} is missing a selector",
+                    line_num + 1
+                ))
+            })?;
+            let action = action.trim();
+
+            let strategy = match action {
+                "@just_text" => ExtractionStrategy::JustText,
+                "@readability" => ExtractionStrategy::Readability,
+                selector => {
+                    if kuchiki::Selectors::compile(selector).is_err() {
+                        return Err(Error::ParsingError(format!(
+                            "invalid selector '{}' on line {}",
+                            selector,
+                            line_num + 1
+                        )));
+                    }
+
+                    ExtractionStrategy::Selector(selector.to_string())
+                }
+            };
+
+            let (host, path_prefix) = match pattern.find('/') {
+                Some(idx) => (&pattern[..idx], &pattern[idx..]),
+                None => (pattern, ""),
+            };
+
+            res.push(SiteRule {
+                host: host.to_lowercase(),
+                path_prefix: path_prefix.to_string(),
+                strategy,
+            });
+        }
+
+        Ok(Self { rules: res })
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// The strategy of the most specific rule matching the url.
+    pub fn strategy(&self, url: &Url) -> Option<&ExtractionStrategy> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(url))
+            .max_by_key(|rule| rule.specificity())
+            .map(|rule| &rule.strategy)
+    }
+}
+
+pub struct ContentExtractor {
+    default_strategy: ExtractionStrategy,
+    just_text: JustText,
+    site_rules: SiteRules,
+}
+
+impl ContentExtractor {
+    pub fn new(config: &ExtractionConfig) -> Result<Self> {
+        let site_rules = match &config.site_rules_path {
+            Some(path) => SiteRules::open(path)?,
+            None => SiteRules::parse(include_str!("../../extraction/site_rules.txt"))?,
+        };
+
+        Ok(Self {
+            default_strategy: config.default_strategy.clone(),
+            just_text: config.just_text.clone(),
+            site_rules,
+        })
+    }
+
+    /// Extract the main content of the page. Returns the text together with the
+    /// strategy that produced it.
+    pub fn extract(
+        &self,
+        root: &NodeRef,
+        url: &Url,
+        paragraphs: &[Paragraph],
+        lang: &Lang,
+    ) -> (String, ExtractionStrategy) {
+        let mut strategies = Vec::with_capacity(3);
+        strategies.extend(self.site_rules.strategy(url).cloned());
+        strategies.push(self.default_strategy.clone());
+        strategies.push(ExtractionStrategy::JustText);
+        strategies.dedup();
+
+        let mut res = (String::new(), ExtractionStrategy::JustText);
+
+        for strategy in strategies {
+            let text = self.extract_with(&strategy, root, paragraphs, lang);
+            res = (text, strategy);
+
+            if !res.0.is_empty() {
+                break;
+            }
+        }
+
+        res
+    }
+
+    fn extract_with(
+        &self,
+        strategy: &ExtractionStrategy,
+        root: &NodeRef,
+        paragraphs: &[Paragraph],
+        lang: &Lang,
+    ) -> String {
+        match strategy {
+            ExtractionStrategy::JustText => {
+                self.just_text.extract_from_paragraphs(paragraphs, lang)
+            }
+            ExtractionStrategy::Readability => Readability::default().extract(root, lang),
+            ExtractionStrategy::Selector(selector) => {
+                let matches: Vec<_> = match root.select(selector) {
+                    Ok(matches) => matches.map(|node| node.as_node().clone()).collect(),
+                    Err(_) => return String::new(),
+                };
+
+                // nested matches are already covered by their ancestor
+                let paragraphs: Vec<_> = matches
+                    .iter()
+                    .filter(|node| !node.ancestors().any(|ancestor| matches.contains(&ancestor)))
+                    .cloned()
+                    .flat_map(JustText::paragraphs)
+                    .collect();
+
+                JustText::permissive().extract_from_paragraphs(&paragraphs, lang)
+            }
+        }
+    }
+}
+
+/// Use `config` for all pages extracted by this process. Must be called before the
+/// first page is parsed. Configuring the same settings again is a no-op, but it is an
+/// error to change them (or to configure them after the defaults have been used).
+pub fn configure(config: &ExtractionConfig) -> Result<()> {
+    configure_cell(&EXTRACTOR, config)
+}
+
+fn configure_cell(cell: &OnceCell<ConfiguredExtractor>, config: &ExtractionConfig) -> Result<()> {
+    let configured = cell.get_or_try_init(|| -> Result<ConfiguredExtractor> {
+        Ok(ConfiguredExtractor {
+            config: config.clone(),
+            extractor: ContentExtractor::new(config)?,
+        })
+    })?;
+
+    if &configured.config != config {
+        return Err(Error::AlreadyConfigured("content extraction"));
+    }
+
+    Ok(())
+}
+
+pub fn extractor() -> &'static ContentExtractor {
+    &EXTRACTOR
+        .get_or_init(|| {
+            let config = ExtractionConfig::default();
+
+            ConfiguredExtractor {
+                extractor: ContentExtractor::new(&config)
+                    .expect("bundled site rules should be valid"),
+                config,
+            }
+        })
+        .extractor
+}
+
+#[cfg(test)]
+mod tests {
+    use kuchiki::traits::TendrilSink;
+
+    use super::*;
+
+    #[test]
+    fn parse_rules() {
+        let rules = SiteRules::parse(
+            r#"
+            ! comment
+            example.com         #content
+            example.com/forum   @readability
+            docs.example.com    div[role="main"]
+        "#,
+        )
+        .unwrap();
+
+        let strategy = |url: &str| rules.strategy(&Url::from(url.to_string())).cloned();
+
+        assert_eq!(
+            strategy("https://example.com/about"),
+            Some(ExtractionStrategy::Selector("#content".to_string()))
+        );
+        assert_eq!(
+            strategy("https://www.example.com/forum/thread/1"),
+            Some(ExtractionStrategy::Readability)
+        );
+        assert_eq!(
+            strategy("https://docs.example.com/forum"),
+            Some(ExtractionStrategy::Selector(
+                r#"div[role="main"]"#.to_string()
+            ))
+        );
+        assert_eq!(strategy("https://notexample.com/"), None);
+
+        assert!(SiteRules::parse("example.com").is_err());
+        assert!(SiteRules::parse("example.com ][").is_err());
+    }
+
+    #[test]
+    fn bundled_rules_are_valid() {
+        assert!(SiteRules::parse(include_str!("../../extraction/site_rules.txt")).is_ok());
+    }
+
+    #[test]
+    fn reconfigure() {
+        let cell = OnceCell::new();
+        let readability = ExtractionConfig {
+            default_strategy: ExtractionStrategy::Readability,
+            ..Default::default()
+        };
+
+        configure_cell(&cell, &readability).unwrap();
+        configure_cell(&cell, &readability).unwrap();
+        assert!(configure_cell(&cell, &ExtractionConfig::default()).is_err());
+    }
+
+    #[test]
+    fn selector_falls_back_to_default() {
+        let extractor = ContentExtractor {
+            default_strategy: ExtractionStrategy::JustText,
+            just_text: JustText::default(),
+            site_rules: SiteRules::parse("example.com  #main").unwrap(),
+        };
+
+        let html = r#"
+            <html>
+                <body>
+                    <div id="main"><a href="/a">a</a> <a href="/b">b</a> short</div>
+                </body>
+            </html>
+        "#;
+        let root = kuchiki::parse_html().one(html);
+        let paragraphs = JustText::paragraphs(root.clone());
+
+        let (text, strategy) = extractor.extract(
+            &root,
+            &Url::from("https://example.com/".to_string()),
+            &paragraphs,
+            &Lang::Eng,
+        );
+        assert_eq!(text, "a b short");
+        assert_eq!(strategy, ExtractionStrategy::Selector("#main".to_string()));
+
+        let html = "<html><body><div id=\"other\"><a href=\"/a\">a</a></div></body></html>";
+        let root = kuchiki::parse_html().one(html);
+        let paragraphs = JustText::paragraphs(root.clone());
+
+        let (text, strategy) = extractor.extract(
+            &root,
+            &Url::from("https://example.com/".to_string()),
+            &paragraphs,
+            &Lang::Eng,
+        );
+        assert!(text.is_empty());
+        assert_eq!(strategy, ExtractionStrategy::JustText);
+    }
+}
@@ -17,6 +17,7 @@
 use std::collections::{HashMap, HashSet};
 
 use kuchiki::{iter::NodeEdge, NodeRef};
+use serde::{Deserialize, Serialize};
 use whatlang::Lang;
 
 use super::Preprocessor;
@@ -121,6 +122,8 @@ static STOPWORDS: once_cell::sync::Lazy<HashMap<Lang, HashSet<String>>> =
         )
     });
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct JustText {
     pub max_link_density: f64,
     pub length_low: usize,
@@ -143,6 +146,20 @@ impl Default for JustText {
     }
 }
 
+impl JustText {
+    /// Keeps every paragraph that contains text.
+    pub fn permissive() -> Self {
+        Self {
+            max_link_density: 2.0,
+            length_low: 0,
+            length_high: 0,
+            stopwords_low: -1.0,
+            stopwords_high: -1.0,
+            max_heading_distance: 10000,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Paragraph {
     is_heading: bool,
@@ -188,6 +205,45 @@ impl Paragraph {
     }
 }
 
+/// Whether the element starts or ends a paragraph.
+pub fn is_paragraph_boundary(name: &str) -> bool {
+    matches!(
+        name,
+        "body"
+            | "blockquote"
+            | "caption"
+            | "center"
+            | "col"
+            | "colgroup"
+            | "dd"
+            | "div"
+            | "dl"
+            | "dt"
+            | "fieldset"
+            | "form"
+            | "legend"
+            | "optgroup"
+            | "option"
+            | "p"
+            | "pre"
+            | "table"
+            | "td"
+            | "textarea"
+            | "tfoot"
+            | "th"
+            | "thead"
+            | "tr"
+            | "ul"
+            | "li"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+    )
+}
+
 #[derive(Debug)]
 struct ClassifiedParagraph {
     paragraph: Paragraph,
@@ -223,42 +279,7 @@ impl JustText {
                             heading_count += 1;
                         }
 
-                        if matches!(
-                            name,
-                            "body"
-                                | "blockquote"
-                                | "caption"
-                                | "center"
-                                | "col"
-                                | "colgroup"
-                                | "dd"
-                                | "div"
-                                | "dl"
-                                | "dt"
-                                | "fieldset"
-                                | "form"
-                                | "legend"
-                                | "optgroup"
-                                | "option"
-                                | "p"
-                                | "pre"
-                                | "table"
-                                | "td"
-                                | "textarea"
-                                | "tfoot"
-                                | "th"
-                                | "thead"
-                                | "tr"
-                                | "ul"
-                                | "li"
-                                | "h1"
-                                | "h2"
-                                | "h3"
-                                | "h4"
-                                | "h5"
-                                | "h6"
-                        ) || (name == "br" && br)
-                        {
+                        if is_paragraph_boundary(name) || (name == "br" && br) {
                             if name == "br" {
                                 // the <br><br> is a paragraph separator and should
                                 // not be included in the number of tags within the
@@ -307,19 +328,12 @@ impl JustText {
 
                         paragraph.append_text(" ");
 
-                        match name {
-                            "body" | "blockquote" | "caption" | "center" | "col" | "colgroup"
-                            | "dd" | "div" | "dl" | "dt" | "fieldset" | "form" | "legend"
-                            | "optgroup" | "option" | "p" | "pre" | "table" | "td" | "textarea"
-                            | "tfoot" | "th" | "thead" | "tr" | "ul" | "li" | "h1" | "h2"
-                            | "h3" | "h4" | "h5" | "h6" => {
-                                if paragraph.contains_text() {
-                                    res.push(paragraph);
-                                }
-
-                                paragraph = Paragraph::new();
+                        if is_paragraph_boundary(name) {
+                            if paragraph.contains_text() {
+                                res.push(paragraph);
                             }
-                            _ => {}
+
+                            paragraph = Paragraph::new();
                         }
 
                         if name == "a" {
@@ -566,8 +580,17 @@ impl JustText {
         Classification::Intermediate(IntermediateClassification::Bad)
     }
 
-    fn extract_text(paragraphs: Vec<ClassifiedParagraph>) -> String {
-        paragraphs
+    /// The text of the paragraphs classified as main content, with whitespace normalized.
+    pub fn content_paragraphs(&self, paragraphs: &[Paragraph], lang: &Lang) -> Vec<String> {
+        let mut classified = self
+            .initial_classification(paragraphs, lang)
+            .into_iter()
+            .filter(|par| par.paragraph.text.chars().any(|c| !c.is_whitespace()))
+            .collect();
+
+        self.contextual_classification(&mut classified);
+
+        classified
             .into_iter()
             .filter(|paragraph| {
                 matches!(
@@ -575,24 +598,19 @@ impl JustText {
                     Classification::Final(FinalClassification::Good)
                 )
             })
-            .map(|paragraph| paragraph.paragraph.text)
-            .collect::<Vec<_>>()
-            .join(" ")
+            .map(|paragraph| {
+                paragraph
+                    .paragraph
+                    .text
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
     }
 
     pub fn extract_from_paragraphs(&self, paragraphs: &[Paragraph], lang: &Lang) -> String {
-        let mut classified = self
-            .initial_classification(paragraphs, lang)
-            .into_iter()
-            .filter(|par| par.paragraph.text.chars().any(|c| !c.is_whitespace()))
-            .collect();
-
-        self.contextual_classification(&mut classified);
-
-        Self::extract_text(classified)
-            .split_whitespace()
-            .collect::<Vec<_>>()
-            .join(" ")
+        self.content_paragraphs(paragraphs, lang).join(" ")
     }
 }
 impl Classification {
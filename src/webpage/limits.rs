@@ -0,0 +1,146 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! Limits on how much of a page is parsed and indexed, so a single pathological
+//! page can't exhaust the memory or stack of the worker processing it. All
+//! truncation is deterministic: the same page is always cut at the same place.
+
+use kuchiki::NodeRef;
+use once_cell::sync::OnceCell;
+
+use crate::{Error, HtmlLimits, Result};
+
+static LIMITS: OnceCell<HtmlLimits> = OnceCell::new();
+
+/// Sets the limits used by all pages parsed afterwards. Configuring the same limits
+/// again is a no-op, but it is an error to change them (or to configure them after
+/// the defaults have been used).
+pub fn configure(limits: &HtmlLimits) -> Result<()> {
+    configure_cell(&LIMITS, limits)
+}
+
+fn configure_cell(cell: &OnceCell<HtmlLimits>, limits: &HtmlLimits) -> Result<()> {
+    if cell.get_or_init(|| limits.clone()) != limits {
+        return Err(Error::AlreadyConfigured("html limits"));
+    }
+
+    Ok(())
+}
+
+pub fn limits() -> &'static HtmlLimits {
+    LIMITS.get_or_init(HtmlLimits::default)
+}
+
+/// The limits that were hit while parsing a page.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Truncation {
+    pub body: bool,
+    pub depth: bool,
+    pub text: bool,
+    pub links: bool,
+}
+
+impl Truncation {
+    pub fn any(&self) -> bool {
+        self.body || self.depth || self.text || self.links
+    }
+}
+
+/// The longest prefix of `text` that is at most `max_bytes` long and ends on a char boundary.
+pub fn truncate_str(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &text[..end]
+}
+
+/// Removes everything nested more than `max_depth` levels below `root`.
+/// Returns whether any nodes were removed.
+pub fn limit_depth(root: &NodeRef, max_depth: usize) -> bool {
+    let mut truncated = false;
+
+    // the tree is walked without recursion, as deep documents are exactly
+    // the ones that would overflow the stack.
+    let mut stack = vec![(root.clone(), 0)];
+
+    while let Some((node, depth)) = stack.pop() {
+        if depth >= max_depth {
+            let children: Vec<_> = node.children().collect();
+            truncated |= !children.is_empty();
+
+            for child in children {
+                child.detach();
+            }
+
+            continue;
+        }
+
+        stack.extend(node.children().map(|child| (child, depth + 1)));
+    }
+
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use kuchiki::traits::TendrilSink;
+
+    use super::*;
+
+    #[test]
+    fn truncate_on_char_boundary() {
+        assert_eq!(truncate_str("hello", 10), "hello");
+        assert_eq!(truncate_str("hello", 3), "hel");
+        assert_eq!(truncate_str("æøå", 3), "æ");
+        assert_eq!(truncate_str("æøå", 1), "");
+    }
+
+    #[test]
+    fn reconfigure() {
+        let cell = OnceCell::new();
+        let limits = HtmlLimits::default();
+
+        assert!(configure_cell(&cell, &limits).is_ok());
+        assert!(configure_cell(&cell, &limits).is_ok());
+
+        let other = HtmlLimits {
+            max_links: 10,
+            ..HtmlLimits::default()
+        };
+        assert!(matches!(
+            configure_cell(&cell, &other),
+            Err(Error::AlreadyConfigured(_))
+        ));
+    }
+
+    #[test]
+    fn deep_documents_are_cut() {
+        let html = "<div>".repeat(100) + "deep text" + &"</div>".repeat(100);
+        let root = kuchiki::parse_html().one(html.as_str());
+
+        assert!(!limit_depth(&root, 1_000));
+        assert!(root.text_contents().contains("deep text"));
+
+        assert!(limit_depth(&root, 10));
+        assert!(!root.text_contents().contains("deep text"));
+        assert!(!limit_depth(&root, 10));
+    }
+}
@@ -16,12 +16,15 @@
 use crate::{
     prehashed::{hash, split_u128},
     schema::{FastField, TextField},
-    schema_org::SchemaOrg,
-    tokenizer, Error, Result,
+    schema_org::{self, SchemaOrg},
+    simhash, tokenizer,
+    trackers::{self, TrackerCounts},
+    Error, Result,
 };
 use chrono::{DateTime, FixedOffset, Utc};
 use itertools::Itertools;
 use kuchiki::{iter::NodeEdge, traits::TendrilSink, NodeRef};
+use once_cell::unsync::OnceCell;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -30,31 +33,89 @@ use std::{
     panic,
 };
 use tantivy::tokenizer::{PreTokenizedString, Tokenizer};
+use tracing::debug;
 use uuid::Uuid;
 use whatlang::Lang;
 
+mod document;
+mod extraction;
 mod just_text;
+mod limits;
+mod readability;
 pub mod region;
 mod url;
 
 use crate::schema::{Field, ALL_FIELDS, CENTRALITY_SCALING};
 
+pub use self::document::{ContentType, Document};
+pub use self::extraction::{configure as configure_extraction, ExtractionStrategy};
+pub use self::just_text::JustText;
+pub use self::limits::{configure as configure_limits, Truncation};
 pub use self::url::Url;
-use self::{
-    just_text::{JustText, Paragraph},
-    region::Region,
-};
+use self::{just_text::Paragraph, region::Region};
 
 static URL_REGEX: once_cell::sync::Lazy<Regex> = once_cell::sync::Lazy::new(|| {
     Regex::new(r#"(((http|ftp|https):/{2})+(([0-9a-z_-]+\.)+(aero|asia|biz|cat|com|coop|edu|gov|info|int|jobs|mil|mobi|museum|name|net|org|pro|tel|travel|ac|ad|ae|af|ag|ai|al|am|an|ao|aq|ar|as|at|au|aw|ax|az|ba|bb|bd|be|bf|bg|bh|bi|bj|bm|bn|bo|br|bs|bt|bv|bw|by|bz|ca|cc|cd|cf|cg|ch|ci|ck|cl|cm|cn|co|cr|cu|cv|cx|cy|cz|cz|de|dj|dk|dm|do|dz|ec|ee|eg|er|es|et|eu|fi|fj|fk|fm|fo|fr|ga|gb|gd|ge|gf|gg|gh|gi|gl|gm|gn|gp|gq|gr|gs|gt|gu|gw|gy|hk|hm|hn|hr|ht|hu|id|ie|il|im|in|io|iq|ir|is|it|je|jm|jo|jp|ke|kg|kh|ki|km|kn|kp|kr|kw|ky|kz|la|lb|lc|li|lk|lr|ls|lt|lu|lv|ly|ma|mc|md|me|mg|mh|mk|ml|mn|mn|mo|mp|mr|ms|mt|mu|mv|mw|mx|my|mz|na|nc|ne|nf|ng|ni|nl|no|np|nr|nu|nz|nom|pa|pe|pf|pg|ph|pk|pl|pm|pn|pr|ps|pt|pw|py|qa|re|ra|rs|ru|rw|sa|sb|sc|sd|se|sg|sh|si|sj|sj|sk|sl|sm|sn|so|sr|st|su|sv|sy|sz|tc|td|tf|tg|th|tj|tk|tl|tm|tn|to|tp|tr|tt|tv|tw|tz|ua|ug|uk|us|uy|uz|va|vc|ve|vg|vi|vn|vu|wf|ws|ye|yt|yu|za|zm|zw|arpa)(:[0-9]+)?((/([~0-9a-zA-Z\#\+%@\./_-]+))?(\?[0-9a-zA-Z\+%@/&\[\];=_-]+)?)?))\b"#).unwrap()
 });
 
+/// Upper bound on how many times a single anchor text is repeated in the backlink field.
+const MAX_ANCHOR_REPETITIONS: usize = 4;
+/// The context is stored with every edge in the webgraph, so it is kept short.
+const MAX_LINK_CONTEXT_CHARS: usize = 100;
+
+/// Favicons are shown at this size (in pixels) next to the search results.
+const FAVICON_DISPLAY_SIZE: u32 = 32;
+
 #[derive(PartialEq, Eq, Debug)]
 pub struct FaviconLink {
     pub link: Url,
     width: Option<u32>,
     height: Option<u32>,
     image_type: Option<String>,
+    is_touch_icon: bool,
+}
+
+impl FaviconLink {
+    /// Parses the `sizes` attribute (e.g. `16x16 32x32`) and returns the largest size.
+    fn parse_sizes(sizes: &str) -> (Option<u32>, Option<u32>) {
+        sizes
+            .split_ascii_whitespace()
+            .filter_map(|size| {
+                let (width, height) = size.split_once(['x', 'X'])?;
+                Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?))
+            })
+            .max()
+            .map(|(width, height)| (Some(width), Some(height)))
+            .unwrap_or_default()
+    }
+
+    fn is_svg(&self) -> bool {
+        matches!(self.image_type.as_deref(), Some("image/svg+xml"))
+            || self.link.path().to_ascii_lowercase().ends_with(".svg")
+    }
+
+    /// Lower is better. Svg icons can't be decoded by the image store so they are ranked last,
+    /// otherwise icons closest to the display size are preferred, with icons that would have
+    /// to be scaled up being penalized more than icons that are too large.
+    fn rank(&self) -> (bool, u32) {
+        let size = self
+            .width
+            .max(self.height)
+            .unwrap_or(if self.is_touch_icon {
+                // the size apple uses when nothing is specified
+                180
+            } else {
+                16
+            });
+
+        let penalty = if size >= FAVICON_DISPLAY_SIZE {
+            size - FAVICON_DISPLAY_SIZE
+        } else {
+            (FAVICON_DISPLAY_SIZE - size) * 4
+        };
+
+        (self.is_svg(), penalty)
+    }
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -122,7 +183,7 @@ pub struct StoredPrimaryImage {
 
 pub struct Webpage {
     pub html: Html,
-    pub backlinks: Vec<Link>,
+    pub backlinks: Vec<Backlink>,
     pub host_centrality: f64,
     pub page_centrality: f64,
     pub fetch_time_ms: u64,
@@ -163,12 +224,18 @@ impl Webpage {
                 schema
                     .get_field(Field::Fast(FastField::Region).name())
                     .expect("Failed to get region field"),
-                Region::All.id() as u64,
+                Region::ALL.id() as u64,
             );
         }
 
         let backlink_text: String = itertools::intersperse(
-            self.backlinks.into_iter().map(|link| link.text),
+            self.backlinks
+                .into_iter()
+                .filter(|backlink| !backlink.link.text.is_empty())
+                .flat_map(|backlink| {
+                    let repetitions = backlink.repetitions();
+                    std::iter::repeat(backlink.link.text).take(repetitions)
+                }),
             "\n".to_string(),
         )
         .collect();
@@ -254,10 +321,39 @@ struct Script {
 #[derive(Debug)]
 pub struct Html {
     url: Url,
+    // the url the page was fetched from. Relative links are resolved against it,
+    // even after the page has been folded into its canonical url.
+    fetched_url: Url,
     root: NodeRef, // this is reference counted (cheap to clone)
     all_text: Option<String>,
     clean_text: Option<String>,
+    extraction_strategy: Option<ExtractionStrategy>,
     lang: Option<Lang>,
+    content_type: ContentType,
+    truncation: Truncation,
+    // paragraphs classified as main content, used to find the position of links
+    content_paragraphs: OnceCell<HashSet<String>>,
+    // fields read from the dom, both to check their size and when indexing the page
+    title: OnceCell<Option<String>>,
+    description: OnceCell<Option<String>>,
+    headings: OnceCell<Vec<String>>,
+    image_alt_texts: OnceCell<Vec<String>>,
+}
+
+/// The language of the longest paragraph if it can be reliably detected.
+fn detect_lang(paragraphs: &[Paragraph]) -> Option<Lang> {
+    paragraphs
+        .iter()
+        .max_by_key(|paragraph| paragraph.text.len())
+        .and_then(|paragraph| {
+            whatlang::detect(&paragraph.text).and_then(|info| {
+                if info.is_reliable() && info.confidence() > 0.95 {
+                    Some(info.lang())
+                } else {
+                    None
+                }
+            })
+        })
 }
 
 impl Html {
@@ -269,43 +365,146 @@ impl Html {
         html
     }
 
+    pub fn parse_document(document: Document<'_>, url: &str) -> Result<Self> {
+        let mut html = Self::parse_document_without_text(document, url)?;
+
+        html.parse_text();
+
+        Ok(html)
+    }
+
+    pub fn parse_document_without_text(document: Document<'_>, url: &str) -> Result<Self> {
+        let content_type = document.content_type();
+        let raw = document.into_html()?;
+
+        let mut html = Self::parse_without_text(&raw, url);
+        html.content_type = content_type;
+
+        Ok(html)
+    }
+
     pub fn parse_without_text(html: &str, url: &str) -> Self {
-        let root = kuchiki::parse_html().one(html);
-        let mut url: Url = url.to_string().into();
-        url = url.full_without_id_tags().into();
+        let limits = limits::limits();
+        let mut truncation = Truncation::default();
+
+        let body = limits::truncate_str(html, limits.max_body_bytes);
+        truncation.body = body.len() < html.len();
+
+        let root = kuchiki::parse_html().one(body);
+        truncation.depth = limits::limit_depth(&root, limits.max_dom_depth);
+        truncation.links = root
+            .select("a[href]")
+            .map(|links| links.count() > limits.max_links)
+            .unwrap_or(false);
+
+        let url = Url::from(url.to_string()).normalized();
+
+        if truncation.any() {
+            debug!("truncated {}: {:?}", url, truncation);
+        }
 
         Self {
             root,
             all_text: None,
             clean_text: None,
+            extraction_strategy: None,
             lang: None,
+            content_type: ContentType::Html,
+            fetched_url: url.clone(),
             url,
+            truncation,
+            content_paragraphs: OnceCell::new(),
+            title: OnceCell::new(),
+            description: OnceCell::new(),
+            headings: OnceCell::new(),
+            image_alt_texts: OnceCell::new(),
         }
     }
 
+    /// The limits that were hit while parsing the page.
+    pub fn truncation(&self) -> Truncation {
+        self.truncation
+    }
+
     pub fn parse_text(&mut self) {
         let paragraphs = JustText::paragraphs(self.root.clone());
 
-        self.lang = paragraphs
-            .iter()
-            .max_by_key(|paragraph| paragraph.text.len())
-            .and_then(|paragraph| {
-                whatlang::detect(&paragraph.text).and_then(|info| {
-                    if info.is_reliable() && info.confidence() > 0.95 {
-                        Some(info.lang())
-                    } else {
-                        None
-                    }
-                })
-            });
+        self.lang = detect_lang(&paragraphs);
+
+        let lang = self.lang.unwrap_or(Lang::Eng);
+        self.all_text =
+            Html::calculate_all_text(&paragraphs, &lang).map(|text| self.truncate_field(text));
+
+        let (clean_text, strategy) =
+            extraction::extractor().extract(&self.root, &self.url, &paragraphs, &lang);
+        debug!("extracted {} with {}", self.url, strategy);
+
+        self.clean_text = if clean_text.is_empty() {
+            None
+        } else {
+            Some(self.truncate_field(clean_text))
+        };
+        self.extraction_strategy = Some(strategy);
+
+        // the remaining fields are read from the dom when the page is indexed and
+        // only cut to size then, so check them here to have them counted.
+        if self.raw_field_exceeds_limit() {
+            self.truncation.text = true;
+        }
+    }
+
+    fn raw_field_exceeds_limit(&self) -> bool {
+        let max_bytes = limits::limits().max_field_bytes;
+
+        let joined_len = |texts: &[String]| {
+            texts.iter().map(String::len).sum::<usize>() + texts.len().saturating_sub(1)
+        };
+
+        self.cached_title().as_ref().map_or(0, String::len) > max_bytes
+            || self.url.full().len() > max_bytes
+            || self.cached_description().as_ref().map_or(0, String::len) > max_bytes
+            || joined_len(self.cached_headings()) > max_bytes
+            || joined_len(self.cached_image_alt_texts()) > max_bytes
+    }
+
+    fn truncate_field(&mut self, mut text: String) -> String {
+        let len = limits::truncate_str(&text, limits::limits().max_field_bytes).len();
+
+        if len < text.len() {
+            text.truncate(len);
+            self.truncation.text = true;
+        }
+
+        text
+    }
 
-        self.all_text = Html::calculate_all_text(&paragraphs, &self.lang.unwrap_or(Lang::Eng));
-        self.clean_text = Html::calculate_clean_text(&paragraphs, &self.lang.unwrap_or(Lang::Eng));
+    /// The strategy that extracted the clean text of the page. `None` if the text
+    /// has not been parsed.
+    pub fn extraction_strategy(&self) -> Option<&ExtractionStrategy> {
+        self.extraction_strategy.as_ref()
+    }
+
+    /// The url that relative links on the page are resolved against.
+    /// This is the `<base href>` if the page has one, otherwise the url the page was fetched from.
+    fn base_url(&self) -> Url {
+        self.root
+            .select_first("base[href]")
+            .ok()
+            .and_then(|base| {
+                base.attributes
+                    .borrow()
+                    .get("href")
+                    .and_then(|href| self.fetched_url.resolve(href))
+            })
+            .unwrap_or_else(|| self.fetched_url.clone())
     }
 
     pub fn links(&self) -> Vec<Link> {
+        let base = self.base_url();
         let mut links = Vec::new();
         let mut open_links = Vec::new();
+        let mut paragraphs = Vec::new();
+        let mut paragraph = String::new();
         let mut preprocessor = Preprocessor::new(["script", "style", "head", "noscript"]);
 
         for edge in self.root.traverse() {
@@ -317,32 +516,71 @@ impl Html {
             match edge {
                 NodeEdge::Start(node) => {
                     if let Some(element) = node.as_element() {
-                        if &element.name.local == "a" {
-                            open_links.push((String::new(), element.attributes.clone()));
+                        let name: &str = &element.name.local;
+
+                        if just_text::is_paragraph_boundary(name) {
+                            paragraphs.push(std::mem::take(&mut paragraph));
+                        } else if name == "br" {
+                            paragraph.push(' ');
+                        }
+
+                        if name == "a" {
+                            open_links.push((
+                                String::new(),
+                                element.attributes.clone(),
+                                LinkPosition::from_ancestors(&node),
+                                paragraphs.len(),
+                            ));
                         }
                     }
                 }
                 NodeEdge::End(node) => {
                     if let Some(element) = node.as_element() {
-                        if &element.name.local == "a" {
-                            if let Some((text, attributes)) = open_links.pop() {
-                                if let Some(dest) = attributes.borrow().get("href") {
-                                    links.push(Link {
-                                        source: self.url.clone(),
-                                        destination: dest.to_string().into(),
-                                        text: text.trim().to_string(),
-                                    });
+                        let name: &str = &element.name.local;
+
+                        if name == "a" {
+                            if let Some((text, attributes, position, paragraph_idx)) =
+                                open_links.pop()
+                            {
+                                if let Some(destination) = attributes
+                                    .borrow()
+                                    .get("href")
+                                    .and_then(|href| base.resolve(href))
+                                {
+                                    links.push((
+                                        paragraph_idx,
+                                        Link {
+                                            source: self.url.clone(),
+                                            destination,
+                                            text: text.trim().to_string(),
+                                            rel: attributes
+                                                .borrow()
+                                                .get("rel")
+                                                .map(LinkRel::parse)
+                                                .unwrap_or_default(),
+                                            position,
+                                            context: String::new(),
+                                        },
+                                    ));
                                 }
                             }
                         }
+
+                        if just_text::is_paragraph_boundary(name) {
+                            paragraphs.push(std::mem::take(&mut paragraph));
+                        } else {
+                            paragraph.push(' ');
+                        }
                     }
 
                     if let Some(text) = node.as_text() {
                         let raw_text = text.borrow();
+                        paragraph.push_str(&raw_text);
+
                         let text = raw_text.trim();
 
                         if !text.is_empty() {
-                            for (link_text, _) in &mut open_links {
+                            for (link_text, _, _, _) in &mut open_links {
                                 link_text.push('\n');
                                 link_text.push_str(text);
                             }
@@ -352,56 +590,140 @@ impl Html {
             }
         }
 
-        links
-    }
+        paragraphs.push(paragraph);
 
-    pub fn favicon(&self) -> Option<FaviconLink> {
-        for node in self.root.select("link").unwrap() {
-            if !matches!(node.attributes.borrow().get("rel"), Some("icon")) {
-                continue;
-            }
+        let paragraphs: Vec<String> = paragraphs
+            .into_iter()
+            .map(|paragraph| paragraph.split_whitespace().join(" "))
+            .collect();
 
-            if let Some(link) = node.attributes.borrow().get("href") {
-                let (width, height) = match node.attributes.borrow().get("sizes") {
-                    Some(size) => {
-                        if let Some((width, height)) = size.split_once('x') {
-                            (width.parse().ok(), height.parse().ok())
-                        } else {
-                            (None, None)
-                        }
-                    }
-                    _ => (None, None),
-                };
+        let content = self.content_paragraphs();
 
-                let image_type = node.attributes.borrow().get("type").map(|t| t.to_string());
-                let mut link: Url = link.to_string().into();
+        links
+            .into_iter()
+            .take(limits::limits().max_links)
+            .map(|(paragraph_idx, mut link)| {
+                let paragraph = &paragraphs[paragraph_idx];
 
-                if !link.is_full_path() {
-                    link.prefix_with(&self.url);
+                if link.position == LinkPosition::Other && content.contains(paragraph) {
+                    link.position = LinkPosition::Content;
                 }
 
-                let favicon = FaviconLink {
-                    link,
-                    width,
-                    height,
-                    image_type,
-                };
+                link.context =
+                    surrounding_sentence(paragraph, &link.text.split_whitespace().join(" "));
 
-                return Some(favicon);
-            }
+                link
+            })
+            .collect()
+    }
+
+    /// Classified once per page. The language is detected here if the text of the page
+    /// has not been parsed.
+    fn content_paragraphs(&self) -> &HashSet<String> {
+        self.content_paragraphs.get_or_init(|| {
+            let paragraphs = JustText::paragraphs(self.root.clone());
+            let lang = self
+                .lang
+                .or_else(|| detect_lang(&paragraphs))
+                .unwrap_or(Lang::Eng);
+
+            JustText::default()
+                .content_paragraphs(&paragraphs, &lang)
+                .into_iter()
+                .collect()
+        })
+    }
+
+    /// The url from `<link rel="canonical">` if it differs from the url of the page.
+    /// Only canonical urls on the same domain as the page are trusted, as anyone
+    /// could otherwise claim to be the canonical version of a popular page.
+    pub fn canonical_url(&self) -> Option<Url> {
+        let base = self.base_url();
+
+        self.root
+            .select_first(r#"link[rel="canonical"]"#)
+            .ok()
+            .and_then(|node| {
+                node.attributes
+                    .borrow()
+                    .get("href")
+                    .and_then(|href| base.resolve(href))
+            })
+            .filter(|canonical| canonical != &self.url && canonical.domain() == self.url.domain())
+    }
+
+    /// Use the canonical url as the url of the page, so non-canonical duplicates
+    /// are indexed and linked from the same url as the canonical page.
+    pub fn fold_into_canonical(&mut self) {
+        if let Some(canonical) = self.canonical_url() {
+            self.url = canonical;
         }
+    }
 
-        None
+    /// The best favicon candidate for the page. See [`Html::favicons`].
+    pub fn favicon(&self) -> Option<FaviconLink> {
+        self.favicons().into_iter().next()
     }
 
-    fn calculate_clean_text(paragraphs: &[Paragraph], lang: &Lang) -> Option<String> {
-        let text = JustText::default().extract_from_paragraphs(paragraphs, lang);
+    /// All favicon candidates for the page, best first. Icons declared with
+    /// `<link rel="icon">`, `rel="shortcut icon"` and `rel="apple-touch-icon"` are
+    /// ranked by their declared size and type, and `/favicon.ico` is always tried last.
+    pub fn favicons(&self) -> Vec<FaviconLink> {
+        let base = self.base_url();
+        let mut favicons = Vec::new();
+
+        for node in self.root.select("link[rel][href]").unwrap() {
+            let attributes = node.attributes.borrow();
+            let rel = attributes
+                .get("rel")
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+
+            let is_touch_icon = rel
+                .split_ascii_whitespace()
+                .any(|rel| matches!(rel, "apple-touch-icon" | "apple-touch-icon-precomposed"));
+
+            if !is_touch_icon && !rel.split_ascii_whitespace().any(|rel| rel == "icon") {
+                continue;
+            }
 
-        if text.is_empty() {
-            None
-        } else {
-            Some(text)
+            let link = match attributes.get("href").and_then(|href| base.resolve(href)) {
+                Some(link) => link,
+                None => continue,
+            };
+
+            let (width, height) = attributes
+                .get("sizes")
+                .map(FaviconLink::parse_sizes)
+                .unwrap_or_default();
+
+            favicons.push(FaviconLink {
+                link,
+                width,
+                height,
+                image_type: attributes.get("type").map(|t| t.to_string()),
+                is_touch_icon,
+            });
         }
+
+        favicons.sort_by_key(FaviconLink::rank);
+
+        let mut seen = HashSet::new();
+        favicons.retain(|favicon| seen.insert(favicon.link.clone()));
+
+        if let Some(fallback) = self.url.resolve("/favicon.ico") {
+            if !favicons.iter().any(|favicon| favicon.link == fallback) {
+                favicons.push(FaviconLink {
+                    link: fallback,
+                    width: None,
+                    height: None,
+                    image_type: None,
+                    is_touch_icon: false,
+                });
+            }
+        }
+
+        favicons
     }
 
     pub fn clean_text(&self) -> Option<String> {
@@ -409,15 +731,7 @@ impl Html {
     }
 
     fn calculate_all_text(paragraphs: &[Paragraph], lang: &Lang) -> Option<String> {
-        let text = JustText {
-            max_link_density: 2.0,
-            length_low: 0,
-            length_high: 0,
-            stopwords_low: -1.0,
-            stopwords_high: -1.0,
-            max_heading_distance: 10000,
-        }
-        .extract_from_paragraphs(paragraphs, lang);
+        let text = JustText::permissive().extract_from_paragraphs(paragraphs, lang);
 
         if text.is_empty() {
             None
@@ -431,22 +745,75 @@ impl Html {
     }
 
     pub fn title(&self) -> Option<String> {
-        if let Ok(title) = self.root.select_first("title") {
-            let title = title.text_contents().trim().to_string();
-            if title.is_empty() {
-                None
+        self.cached_title().clone()
+    }
+
+    fn cached_title(&self) -> &Option<String> {
+        self.title.get_or_init(|| {
+            if let Ok(title) = self.root.select_first("title") {
+                let title = title.text_contents().trim().to_string();
+                if title.is_empty() {
+                    None
+                } else {
+                    Some(title)
+                }
             } else {
-                Some(title)
+                None
             }
-        } else {
-            None
-        }
+        })
     }
 
     pub fn url(&self) -> &Url {
         &self.url
     }
 
+    /// The detected language of the page text.
+    pub fn lang(&self) -> Option<&Lang> {
+        self.lang.as_ref()
+    }
+
+    /// The language tag from the `lang` attribute of `<html>`, e.g. `en-GB`.
+    pub fn declared_lang(&self) -> Option<String> {
+        self.root
+            .select_first("html[lang]")
+            .ok()
+            .and_then(|html| {
+                html.attributes
+                    .borrow()
+                    .get("lang")
+                    .map(|lang| lang.trim().to_string())
+            })
+            .filter(|lang| !lang.is_empty())
+    }
+
+    /// The `hreflang` of the alternate link that points back to the page itself.
+    pub fn hreflang(&self) -> Option<String> {
+        let base = self.base_url();
+
+        self.root
+            .select(r#"link[rel="alternate"][hreflang][href]"#)
+            .ok()?
+            .find_map(|link| {
+                let attributes = link.attributes.borrow();
+                let href = base.resolve(attributes.get("href")?)?;
+
+                if href == self.url {
+                    attributes.get("hreflang").map(|lang| lang.to_string())
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Fingerprint of the clean text used to detect near-duplicate pages.
+    pub fn simhash(&self) -> u64 {
+        simhash::hash(self.clean_text.as_deref().unwrap_or_default())
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+    }
+
     pub fn metadata(&self) -> Vec<Meta> {
         let mut metas = Vec::new();
 
@@ -510,7 +877,21 @@ impl Html {
         self.pretokenize_string(text)
     }
 
-    fn pretokenize_string(&self, text: String) -> PreTokenizedString {
+    fn pretokenize_headings(&self) -> PreTokenizedString {
+        let text = self.headings().join("\n");
+
+        self.pretokenize_string(text)
+    }
+
+    fn pretokenize_image_alt_texts(&self) -> PreTokenizedString {
+        let text = self.image_alt_texts().join("\n");
+
+        self.pretokenize_string(text)
+    }
+
+    fn pretokenize_string(&self, mut text: String) -> PreTokenizedString {
+        text.truncate(limits::truncate_str(&text, limits::limits().max_field_bytes).len());
+
         let mut tokens = Vec::new();
 
         {
@@ -531,6 +912,10 @@ impl Html {
         let clean_text = self.pretokenize_clean_text();
         let url = self.pretokenize_url();
         let description = self.pretokenize_description();
+        let headings = self.pretokenize_headings();
+        let image_alt_texts = self.pretokenize_image_alt_texts();
+        let simhash = self.simhash();
+        let tracker_counts = self.tracker_counts();
 
         for field in &ALL_FIELDS {
             let tantivy_field = schema
@@ -571,6 +956,15 @@ impl Html {
                 Field::Text(TextField::Description) => {
                     doc.add_pre_tokenized_text(tantivy_field, description.clone());
                 }
+                Field::Text(TextField::Headings) => {
+                    doc.add_pre_tokenized_text(tantivy_field, headings.clone());
+                }
+                Field::Text(TextField::ImageAlt) => {
+                    doc.add_pre_tokenized_text(tantivy_field, image_alt_texts.clone());
+                }
+                Field::Text(TextField::SchemaOrg) => {
+                    doc.add_bytes(tantivy_field, bincode::serialize(&self.schema_org())?);
+                }
                 Field::Text(TextField::Url) => {
                     doc.add_pre_tokenized_text(tantivy_field, url.clone())
                 }
@@ -646,8 +1040,24 @@ impl Html {
                     self.updated_time()
                         .map_or(0, |time| time.timestamp().max(0) as u64),
                 ),
+                Field::Fast(FastField::ContentType) => {
+                    doc.add_u64(tantivy_field, self.content_type.id())
+                }
+                Field::Fast(FastField::SimHash) => doc.add_u64(tantivy_field, simhash),
                 Field::Fast(FastField::NumTrackers) => {
-                    doc.add_u64(tantivy_field, self.trackers().len() as u64)
+                    doc.add_u64(tantivy_field, tracker_counts.num_trackers())
+                }
+                Field::Fast(FastField::NumAnalyticsTrackers) => {
+                    doc.add_u64(tantivy_field, tracker_counts.analytics)
+                }
+                Field::Fast(FastField::NumAdvertisingTrackers) => {
+                    doc.add_u64(tantivy_field, tracker_counts.advertising)
+                }
+                Field::Fast(FastField::NumSocialTrackers) => {
+                    doc.add_u64(tantivy_field, tracker_counts.social)
+                }
+                Field::Fast(FastField::NumCdnHosts) => {
+                    doc.add_u64(tantivy_field, tracker_counts.cdn)
                 }
                 Field::Fast(FastField::NumUrlTokens) => {
                     doc.add_u64(tantivy_field, url.tokens.len() as u64)
@@ -661,6 +1071,9 @@ impl Html {
                 Field::Fast(FastField::NumDescriptionTokens) => {
                     doc.add_u64(tantivy_field, description.tokens.len() as u64)
                 }
+                Field::Fast(FastField::NumHeadingTokens) => {
+                    doc.add_u64(tantivy_field, headings.tokens.len() as u64)
+                }
                 Field::Fast(FastField::SiteHash) => {
                     let hash = hash(self.url().site()).0;
                     let u64s = split_u128(hash);
@@ -691,6 +1104,9 @@ impl Html {
                     doc.add_u64(tantivy_field, u64s[0]);
                     doc.add_u64(tantivy_field, u64s[1]);
                 }
+                Field::Fast(FastField::Lang) => {
+                    doc.add_u64(tantivy_field, region::lang_id(self.lang()));
+                }
                 Field::Text(TextField::BacklinkText)
                 | Field::Fast(FastField::HostCentrality)
                 | Field::Fast(FastField::PageCentrality)
@@ -731,23 +1147,45 @@ impl Html {
         scripts
     }
 
+    /// Structured data from both JSON-LD scripts and microdata.
     pub fn schema_org(&self) -> Vec<SchemaOrg> {
         let mut schemas = Vec::new();
 
-        for schema in self.scripts().into_iter().filter(|script| {
+        for script in self.scripts().into_iter().filter(|script| {
             matches!(
                 script.attributes.get("type").map(String::as_str),
                 Some("application/ld+json")
             )
         }) {
-            if let Ok(schema) = serde_json::from_str(&schema.content) {
-                schemas.push(schema);
+            match schema_org::parse_json_ld(&script.content) {
+                Ok(parsed) => schemas.extend(parsed),
+                Err(err) => debug!(
+                    "failed to parse json-ld on {}: {:?}",
+                    self.url().full(),
+                    err
+                ),
             }
         }
 
+        schemas.extend(schema_org::parse_microdata(&self.root));
+
         schemas
     }
 
+    /// Number of third-party hosts in each tracker category. Hosts that are not
+    /// in the tracker database are counted as unknown.
+    pub fn tracker_counts(&self) -> TrackerCounts {
+        let database = trackers::database();
+        let mut counts = TrackerCounts::default();
+
+        for url in self.trackers() {
+            counts.add(database.category(url.host()));
+        }
+
+        counts
+    }
+
+    /// Third-party hosts referenced by scripts and `<link>` tags.
     pub fn trackers(&self) -> Vec<Url> {
         let mut links: Vec<Url> = Vec::new();
 
@@ -871,25 +1309,68 @@ impl Html {
                     })
             })
             .map(|mut image| {
-                if !image.url.is_full_path() {
-                    image.url.prefix_with(&self.url);
+                if let Some(url) = self.base_url().resolve(image.url.raw()) {
+                    image.url = url;
                 }
 
                 image
             })
     }
 
+    /// The text of the `h1`-`h3` headings on the page.
+    pub fn headings(&self) -> Vec<String> {
+        self.cached_headings().clone()
+    }
+
+    fn cached_headings(&self) -> &Vec<String> {
+        self.headings.get_or_init(|| {
+            self.root
+                .select("h1, h2, h3")
+                .unwrap()
+                .map(|heading| heading.text_contents().split_whitespace().join(" "))
+                .filter(|heading| !heading.is_empty())
+                .collect()
+        })
+    }
+
+    /// The `alt` text of the images on the page.
+    pub fn image_alt_texts(&self) -> Vec<String> {
+        self.cached_image_alt_texts().clone()
+    }
+
+    fn cached_image_alt_texts(&self) -> &Vec<String> {
+        self.image_alt_texts.get_or_init(|| {
+            self.root
+                .select("img[alt]")
+                .unwrap()
+                .filter_map(|img| {
+                    img.attributes
+                        .borrow()
+                        .get("alt")
+                        .map(|alt| alt.split_whitespace().join(" "))
+                })
+                .filter(|alt| !alt.is_empty())
+                .collect()
+        })
+    }
+
     pub fn description(&self) -> Option<String> {
-        self.metadata()
-            .into_iter()
-            .find(|metadata| {
-                if let Some(property) = metadata.get("property") {
-                    property == &String::from("og:description")
-                } else {
-                    false
-                }
-            })
-            .and_then(|metadata| metadata.get("content").cloned())
+        self.cached_description().clone()
+    }
+
+    fn cached_description(&self) -> &Option<String> {
+        self.description.get_or_init(|| {
+            self.metadata()
+                .into_iter()
+                .find(|metadata| {
+                    if let Some(property) = metadata.get("property") {
+                        property == &String::from("og:description")
+                    } else {
+                        false
+                    }
+                })
+                .and_then(|metadata| metadata.get("content").cloned())
+        })
     }
 
     pub fn og_title(&self) -> Option<String> {
@@ -944,6 +1425,146 @@ pub struct Link {
     pub source: Url,
     pub destination: Url,
     pub text: String,
+    pub rel: LinkRel,
+    pub position: LinkPosition,
+    /// The sentence the link appears in on the source page.
+    pub context: String,
+}
+
+/// A link pointing to the page being indexed.
+#[derive(Debug)]
+pub struct Backlink {
+    pub link: Link,
+    pub source_centrality: f64,
+}
+
+impl Backlink {
+    /// How many times the anchor text is repeated in the backlink field. Anchors from the
+    /// main content of central hosts get repeated more, which increases their term frequency.
+    fn repetitions(&self) -> usize {
+        let centrality = 0.5 + 0.5 * self.source_centrality.clamp(0.0, 1.0);
+        let weight = self.link.position.weight() * centrality;
+
+        ((weight * MAX_ANCHOR_REPETITIONS as f64).ceil() as usize).max(1)
+    }
+}
+
+/// Where on the source page a link was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkPosition {
+    /// Part of the main content as classified by JustText.
+    Content,
+    Navigation,
+    Footer,
+    Other,
+}
+
+impl Default for LinkPosition {
+    fn default() -> Self {
+        LinkPosition::Other
+    }
+}
+
+impl LinkPosition {
+    fn from_ancestors(node: &NodeRef) -> Self {
+        for ancestor in node.ancestors() {
+            if let Some(element) = ancestor.as_element() {
+                let role = element
+                    .attributes
+                    .borrow()
+                    .get("role")
+                    .map(|role| role.to_ascii_lowercase());
+
+                match (&*element.name.local, role.as_deref()) {
+                    ("footer", _) | (_, Some("contentinfo")) => return LinkPosition::Footer,
+                    ("nav" | "header", _) | (_, Some("navigation" | "banner")) => {
+                        return LinkPosition::Navigation
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        LinkPosition::Other
+    }
+
+    /// How much an anchor in this position says about its destination compared
+    /// to an anchor in the main content.
+    pub fn weight(&self) -> f64 {
+        match self {
+            LinkPosition::Content => 1.0,
+            LinkPosition::Other => 0.5,
+            LinkPosition::Navigation => 0.25,
+            LinkPosition::Footer => 0.1,
+        }
+    }
+}
+
+/// The sentence of the (whitespace normalized) paragraph that contains the anchor text.
+/// The whole paragraph is used if the anchor text can't be found.
+fn surrounding_sentence(paragraph: &str, anchor: &str) -> String {
+    let is_sentence_end = |idx: usize, c: char| {
+        matches!(c, '.' | '!' | '?')
+            && (paragraph[idx + 1..].is_empty() || paragraph[idx + 1..].starts_with(' '))
+    };
+
+    let sentence = match paragraph.find(anchor).filter(|_| !anchor.is_empty()) {
+        Some(start) => {
+            let end = start + anchor.len();
+
+            let sentence_start = paragraph[..start]
+                .char_indices()
+                .rev()
+                .find(|(idx, c)| is_sentence_end(*idx, *c))
+                .map(|(idx, _)| idx + 1)
+                .unwrap_or(0);
+
+            let sentence_end = paragraph[end..]
+                .char_indices()
+                .find(|(idx, c)| is_sentence_end(end + idx, *c))
+                .map(|(idx, _)| end + idx + 1)
+                .unwrap_or(paragraph.len());
+
+            &paragraph[sentence_start..sentence_end]
+        }
+        None => paragraph,
+    };
+
+    sentence
+        .trim()
+        .chars()
+        .take(MAX_LINK_CONTEXT_CHARS)
+        .collect()
+}
+
+/// The `rel` attribute values of a link that signal that the page does not vouch for the destination.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LinkRel {
+    pub nofollow: bool,
+    pub ugc: bool,
+    pub sponsored: bool,
+}
+
+impl LinkRel {
+    pub fn parse(rel: &str) -> Self {
+        let mut res = Self::default();
+
+        for value in rel.split_ascii_whitespace() {
+            match value.to_ascii_lowercase().as_str() {
+                "nofollow" => res.nofollow = true,
+                "ugc" => res.ugc = true,
+                "sponsored" => res.sponsored = true,
+                _ => {}
+            }
+        }
+
+        res
+    }
+
+    /// Whether the link should count as an endorsement of the destination (e.g. in centrality).
+    pub fn is_endorsement(&self) -> bool {
+        !(self.nofollow || self.ugc || self.sponsored)
+    }
 }
 
 pub type Meta = HashMap<String, String>;
@@ -983,8 +1604,11 @@ mod tests {
             webpage.links(),
             vec![Link {
                 source: "https://www.example.com/whatever".to_string().into(),
-                destination: "example.com".to_string().into(),
-                text: "Link to example".to_string()
+                destination: "https://www.example.com/example.com".to_string().into(),
+                text: "Link to example".to_string(),
+                rel: LinkRel::default(),
+                position: LinkPosition::Other,
+                context: "Link to example".to_string(),
             }]
         );
         assert_eq!(webpage.clean_text(), Some(CONTENT.to_string()));
@@ -1201,11 +1825,57 @@ mod tests {
                 link: "https://example.com/favicon.png".to_string().into(),
                 width: Some(192),
                 height: Some(192),
-                image_type: None
+                image_type: None,
+                is_touch_icon: false,
             })
         );
     }
 
+    #[test]
+    fn favicon_candidates() {
+        let raw = r#"
+            <html>
+                <head>
+                    <link rel="mask-icon" href="/mask.svg" />
+                    <link rel="icon" type="image/svg+xml" href="/icon.svg" />
+                    <link rel="apple-touch-icon" href="/apple-touch-icon.png" />
+                    <link rel="shortcut icon" href="/small.ico" />
+                    <link rel="icon" sizes="16x16 48x48" href="/multi.ico" />
+                    <link rel="icon" sizes="32x32" href="/exact.png" />
+                </head>
+            </html>
+        "#;
+
+        let webpage = Html::parse(raw, "https://www.example.com/some/page");
+        let links: Vec<_> = webpage
+            .favicons()
+            .into_iter()
+            .map(|favicon| favicon.link.full())
+            .collect();
+
+        assert_eq!(
+            links,
+            vec![
+                "https://www.example.com/exact.png".to_string(),
+                "https://www.example.com/multi.ico".to_string(),
+                "https://www.example.com/small.ico".to_string(),
+                "https://www.example.com/apple-touch-icon.png".to_string(),
+                "https://www.example.com/icon.svg".to_string(),
+                "https://www.example.com/favicon.ico".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn favicon_fallback() {
+        let webpage = Html::parse("<html></html>", "https://www.example.com/some/page");
+
+        assert_eq!(
+            webpage.favicon().map(|favicon| favicon.link),
+            Some("https://www.example.com/favicon.ico".to_string().into())
+        );
+    }
+
     fn full_link_favicon(href: &str, site_url: &str, expected: &str) {
         let raw = format!(
             r#"
@@ -1224,7 +1894,8 @@ mod tests {
                 link: expected.to_string().into(),
                 width: Some(192),
                 height: Some(192),
-                image_type: None
+                image_type: None,
+                is_touch_icon: false,
             })
         );
     }
@@ -1254,6 +1925,11 @@ mod tests {
         full_link_favicon(
             "favicon.png",
             "https://www.example.com/test",
+            "https://www.example.com/favicon.png",
+        );
+        full_link_favicon(
+            "favicon.png",
+            "https://www.example.com/test/",
             "https://www.example.com/test/favicon.png",
         );
         full_link_favicon(
@@ -1305,6 +1981,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extraction_strategy_is_recorded() {
+        let raw = format!(
+            r#"
+            <html>
+                <head>
+                    <title>Best website</title>
+                </head>
+                <body>
+                    {CONTENT}
+                </body>
+            </html>
+        "#
+        );
+
+        let html = Html::parse_without_text(&raw, "https://www.example.com");
+        assert_eq!(html.extraction_strategy(), None);
+
+        let html = Html::parse(&raw, "https://www.example.com");
+        assert_eq!(
+            html.extraction_strategy(),
+            Some(&ExtractionStrategy::JustText)
+        );
+        assert_eq!(html.clean_text(), Some(CONTENT.to_string()));
+    }
+
     #[test]
     fn no_schema_dot_org_json_ld() {
         let html = r#"
@@ -1576,6 +2278,18 @@ mod tests {
                 "securepubads.g.doubleclick.net".to_string()
             ]
         );
+
+        assert_eq!(
+            html.tracker_counts(),
+            TrackerCounts {
+                analytics: 2,
+                advertising: 1,
+                social: 0,
+                cdn: 0,
+                unknown: 1,
+            }
+        );
+        assert_eq!(html.tracker_counts().num_trackers(), 4);
     }
 
     #[test]
@@ -1611,4 +2325,245 @@ mod tests {
         assert_eq!(html.title(), Some("Test site".to_string()));
         assert_eq!(html.all_text(), Some("test".to_string()));
     }
+
+    #[test]
+    fn plain_text_document() {
+        let text = format!("Plain title\n\nA short summary.\n\n{CONTENT}");
+        let html = Html::parse_document(
+            Document::PlainText(&text),
+            "https://www.example.com/notes.txt",
+        )
+        .unwrap();
+
+        assert_eq!(html.content_type(), ContentType::PlainText);
+        assert_eq!(html.title(), Some("Plain title".to_string()));
+        assert_eq!(html.description(), Some("A short summary.".to_string()));
+        assert!(html.clean_text().unwrap().contains(CONTENT));
+    }
+
+    #[test]
+    fn link_rel() {
+        let html = Html::parse(
+            r#"
+            <html>
+                <body>
+                    <a href="https://a.com">a</a>
+                    <a href="https://b.com" rel="nofollow">b</a>
+                    <a href="https://c.com" rel="UGC noopener">c</a>
+                    <a href="https://d.com" rel="sponsored nofollow">d</a>
+                </body>
+            </html>
+            "#,
+            "https://www.example.com",
+        );
+
+        let rels: Vec<_> = html.links().into_iter().map(|link| link.rel).collect();
+
+        assert_eq!(
+            rels,
+            vec![
+                LinkRel::default(),
+                LinkRel {
+                    nofollow: true,
+                    ..Default::default()
+                },
+                LinkRel {
+                    ugc: true,
+                    ..Default::default()
+                },
+                LinkRel {
+                    nofollow: true,
+                    sponsored: true,
+                    ..Default::default()
+                },
+            ]
+        );
+
+        assert!(rels[0].is_endorsement());
+        assert!(!rels[1].is_endorsement());
+        assert!(!rels[2].is_endorsement());
+    }
+
+    #[test]
+    fn headings_and_alt_texts() {
+        let html = Html::parse(
+            r#"
+            <html>
+                <body>
+                    <h1>Main  title</h1>
+                    <h2>Sub <em>title</em></h2>
+                    <h4>Too deep</h4>
+                    <img src="a.png" alt="A cat on a mat">
+                    <img src="b.png" alt="">
+                    <img src="c.png">
+                </body>
+            </html>
+            "#,
+            "https://www.example.com",
+        );
+
+        assert_eq!(
+            html.headings(),
+            vec!["Main title".to_string(), "Sub title".to_string()]
+        );
+        assert_eq!(html.image_alt_texts(), vec!["A cat on a mat".to_string()]);
+    }
+
+    #[test]
+    fn long_title_counts_as_truncated() {
+        let title = "a".repeat(limits::limits().max_field_bytes + 1);
+        let html = Html::parse(
+            &format!(
+                r#"
+            <html>
+                <head>
+                    <title>{title}</title>
+                </head>
+                <body>
+                    {CONTENT}
+                </body>
+            </html>
+            "#
+            ),
+            "https://www.example.com",
+        );
+
+        assert!(html.truncation().text);
+        assert!(!html.truncation().body);
+    }
+
+    #[test]
+    fn link_positions() {
+        let html = Html::parse(
+            &format!(
+                r#"
+            <html>
+                <body>
+                    <nav><a href="/">Home</a></nav>
+                    <p>{CONTENT}. You can read more about it on <a href="https://a.com">the a website</a> which is great. {CONTENT}</p>
+                    <div>Some <a href="https://b.com">unrelated</a> link</div>
+                    <footer><a href="/about">About</a></footer>
+                </body>
+            </html>
+            "#
+            ),
+            "https://www.example.com",
+        );
+
+        let links: Vec<_> = html
+            .links()
+            .into_iter()
+            .map(|link| (link.text, link.position, link.context))
+            .collect();
+
+        assert_eq!(
+            links,
+            vec![
+                (
+                    "Home".to_string(),
+                    LinkPosition::Navigation,
+                    "Home".to_string()
+                ),
+                (
+                    "the a website".to_string(),
+                    LinkPosition::Content,
+                    "You can read more about it on the a website which is great.".to_string()
+                ),
+                (
+                    "unrelated".to_string(),
+                    LinkPosition::Other,
+                    "Some unrelated link".to_string()
+                ),
+                (
+                    "About".to_string(),
+                    LinkPosition::Footer,
+                    "About".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn backlink_repetitions() {
+        let backlink = |position, source_centrality| Backlink {
+            link: Link {
+                source: "https://a.com".to_string().into(),
+                destination: "https://b.com".to_string().into(),
+                text: "anchor".to_string(),
+                rel: LinkRel::default(),
+                position,
+                context: String::new(),
+            },
+            source_centrality,
+        };
+
+        assert_eq!(backlink(LinkPosition::Content, 1.0).repetitions(), 4);
+        assert_eq!(backlink(LinkPosition::Content, 0.0).repetitions(), 2);
+        assert_eq!(backlink(LinkPosition::Footer, 1.0).repetitions(), 1);
+        assert!(
+            backlink(LinkPosition::Navigation, 1.0).repetitions()
+                < backlink(LinkPosition::Content, 0.0).repetitions()
+        );
+    }
+
+    #[test]
+    fn canonical_url() {
+        let page = |canonical: &str| {
+            format!(
+                r#"
+                <html>
+                    <head>
+                        <link rel="canonical" href="{canonical}">
+                    </head>
+                    <body>
+                        <a href="https://b.com">b</a>
+                    </body>
+                </html>
+                "#
+            )
+        };
+
+        let mut html = Html::parse(
+            &page("/article"),
+            "https://www.example.com/article?ref=frontpage",
+        );
+        assert_eq!(
+            html.canonical_url(),
+            Some("https://www.example.com/article".to_string().into())
+        );
+
+        html.fold_into_canonical();
+        assert_eq!(html.url().raw(), "https://www.example.com/article");
+        assert_eq!(
+            html.links()[0].source.raw(),
+            "https://www.example.com/article"
+        );
+
+        // relative links are resolved against the url the page was fetched from
+        let mut html = Html::parse(
+            r#"<link rel="canonical" href="/article"><a href="next">next</a>"#,
+            "https://www.example.com/2022/article",
+        );
+        html.fold_into_canonical();
+        assert_eq!(html.url().raw(), "https://www.example.com/article");
+        assert_eq!(
+            html.links()[0].destination.raw(),
+            "https://www.example.com/2022/next"
+        );
+
+        let html = Html::parse(
+            &page("https://www.example.com/article"),
+            "https://www.example.com/article",
+        );
+        assert_eq!(html.canonical_url(), None);
+
+        let mut html = Html::parse(
+            &page("https://www.popular.com/"),
+            "https://www.example.com/article",
+        );
+        assert_eq!(html.canonical_url(), None);
+
+        html.fold_into_canonical();
+        assert_eq!(html.url().raw(), "https://www.example.com/article");
+    }
 }
@@ -0,0 +1,334 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use kuchiki::{Node, NodeRef};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use whatlang::Lang;
+
+use super::just_text::JustText;
+
+// simplified version of the content scoring in Mozilla's readability: https://github.com/mozilla/readability
+// paragraphs give points to their parent and grandparent, and the best scoring
+// element (adjusted for link density) is assumed to hold the main content.
+
+const MIN_PARAGRAPH_LENGTH_DEFAULT: usize = 25;
+const SIBLING_THRESHOLD_DEFAULT: f64 = 0.2;
+const MIN_SIBLING_SCORE: f64 = 10.0;
+
+static UNLIKELY_CANDIDATES: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)banner|breadcrumb|combx|cookie|disqus|extra|footer|gdpr|header|legends|menu|modal|pager|popup|remark|sidebar|skyscraper|sponsor|ad-break|agegate|social|share")
+        .unwrap()
+});
+
+static MAYBE_CANDIDATES: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)and|article|body|column|content|main|shadow|post|thread|message").unwrap()
+});
+
+static POSITIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)article|body|content|entry|hentry|main|page|post|text|blog|story|thread|message|answer|reply|documentation")
+        .unwrap()
+});
+
+static NEGATIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)hidden|banner|combx|contact|foot|masthead|media|meta|outbrain|promo|related|scroll|shoutbox|sidebar|skyscraper|sponsor|shopping|tags|tool|widget|nav|menu|cookie|advert")
+        .unwrap()
+});
+
+/// Elements whose content is never part of the main content.
+const REMOVED_TAGS: [&str; 8] = [
+    "script", "style", "noscript", "iframe", "nav", "header", "footer", "aside",
+];
+
+/// Elements that break a `div` into blocks. A `div` without any of these as
+/// children is scored as if it was a paragraph.
+const BLOCK_TAGS: [&str; 14] = [
+    "div",
+    "p",
+    "pre",
+    "table",
+    "ul",
+    "ol",
+    "dl",
+    "blockquote",
+    "section",
+    "article",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+];
+
+pub struct Readability {
+    pub min_paragraph_length: usize,
+    pub sibling_threshold: f64,
+}
+
+impl Default for Readability {
+    fn default() -> Self {
+        Self {
+            min_paragraph_length: MIN_PARAGRAPH_LENGTH_DEFAULT,
+            sibling_threshold: SIBLING_THRESHOLD_DEFAULT,
+        }
+    }
+}
+
+struct Candidate {
+    node: NodeRef,
+    score: f64,
+}
+
+fn key(node: &NodeRef) -> *const Node {
+    &**node as *const Node
+}
+
+fn tag_name(node: &NodeRef) -> Option<String> {
+    node.as_element()
+        .map(|element| element.name.local.to_string())
+}
+
+fn class_and_id(node: &NodeRef) -> String {
+    node.as_element()
+        .map(|element| {
+            let attributes = element.attributes.borrow();
+            format!(
+                "{} {}",
+                attributes.get("class").unwrap_or_default(),
+                attributes.get("id").unwrap_or_default()
+            )
+        })
+        .unwrap_or_default()
+}
+
+fn class_weight(node: &NodeRef) -> f64 {
+    let class_and_id = class_and_id(node);
+    let mut weight = 0.0;
+
+    if NEGATIVE.is_match(&class_and_id) {
+        weight -= 25.0;
+    }
+
+    if POSITIVE.is_match(&class_and_id) {
+        weight += 25.0;
+    }
+
+    weight
+}
+
+fn initial_score(node: &NodeRef) -> f64 {
+    let tag_score = match tag_name(node).as_deref() {
+        Some("div" | "article" | "main") => 5.0,
+        Some("pre" | "td" | "blockquote") => 3.0,
+        Some("address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form") => -3.0,
+        Some("h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th") => -5.0,
+        _ => 0.0,
+    };
+
+    tag_score + class_weight(node)
+}
+
+fn link_density(node: &NodeRef) -> f64 {
+    let text_len = node.text_contents().trim().len();
+
+    if text_len == 0 {
+        return 0.0;
+    }
+
+    let link_len: usize = node
+        .select("a")
+        .map(|links| {
+            links
+                .map(|link| link.as_node().text_contents().trim().len())
+                .sum()
+        })
+        .unwrap_or(0);
+
+    (link_len as f64 / text_len as f64).min(1.0)
+}
+
+fn is_removed(node: &NodeRef) -> bool {
+    node.inclusive_ancestors().any(|ancestor| {
+        if let Some(name) = tag_name(&ancestor) {
+            if REMOVED_TAGS.contains(&name.as_str()) {
+                return true;
+            }
+
+            if matches!(name.as_str(), "body" | "html" | "article" | "main") {
+                return false;
+            }
+
+            let class_and_id = class_and_id(&ancestor);
+            UNLIKELY_CANDIDATES.is_match(&class_and_id) && !MAYBE_CANDIDATES.is_match(&class_and_id)
+        } else {
+            false
+        }
+    })
+}
+
+fn is_paragraph(node: &NodeRef) -> bool {
+    match tag_name(node).as_deref() {
+        Some("p" | "pre" | "td" | "blockquote") => true,
+        Some("div" | "section") => !node.children().any(
+            |child| matches!(tag_name(&child), Some(name) if BLOCK_TAGS.contains(&name.as_str())),
+        ),
+        _ => false,
+    }
+}
+
+impl Readability {
+    fn candidates(&self, root: &NodeRef) -> HashMap<*const Node, Candidate> {
+        let mut candidates: HashMap<*const Node, Candidate> = HashMap::new();
+
+        for node in root.descendants().filter(is_paragraph) {
+            let text = node.text_contents();
+            let text = text.trim();
+
+            if text.len() < self.min_paragraph_length || is_removed(&node) {
+                continue;
+            }
+
+            let score =
+                1.0 + text.matches(',').count() as f64 + (text.len() as f64 / 100.0).min(3.0);
+
+            for (level, ancestor) in node.ancestors().take(2).enumerate() {
+                if tag_name(&ancestor).is_none() {
+                    break;
+                }
+
+                let divider = if level == 0 { 1.0 } else { 2.0 };
+
+                candidates
+                    .entry(key(&ancestor))
+                    .or_insert_with(|| Candidate {
+                        score: initial_score(&ancestor),
+                        node: ancestor.clone(),
+                    })
+                    .score += score / divider;
+            }
+        }
+
+        for candidate in candidates.values_mut() {
+            candidate.score *= 1.0 - link_density(&candidate.node);
+        }
+
+        candidates
+    }
+
+    /// The elements that make up the main content: the best candidate and
+    /// the siblings that score almost as well.
+    pub fn content_nodes(&self, root: &NodeRef) -> Vec<NodeRef> {
+        let candidates = self.candidates(root);
+
+        let top = match candidates
+            .values()
+            .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap())
+        {
+            Some(top) => top,
+            None => return Vec::new(),
+        };
+
+        let parent = match top.node.parent() {
+            Some(parent) => parent,
+            None => return vec![top.node.clone()],
+        };
+
+        let threshold = MIN_SIBLING_SCORE.max(top.score * self.sibling_threshold);
+
+        parent
+            .children()
+            .filter(|sibling| {
+                *sibling == top.node
+                    || matches!(
+                        candidates.get(&key(sibling)),
+                        Some(candidate) if candidate.score >= threshold
+                    )
+            })
+            .collect()
+    }
+
+    pub fn extract(&self, root: &NodeRef, lang: &Lang) -> String {
+        let paragraphs: Vec<_> = self
+            .content_nodes(root)
+            .into_iter()
+            .flat_map(JustText::paragraphs)
+            .collect();
+
+        JustText::permissive().extract_from_paragraphs(&paragraphs, lang)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kuchiki::traits::TendrilSink;
+
+    use super::*;
+
+    #[test]
+    fn forum_thread() {
+        let post = |author: &str| {
+            format!(
+                r#"
+                <div class="post">
+                    <div class="author"><a href="/u/{author}">{author}</a></div>
+                    <div class="message">
+                        I had the same problem with <a href="/t/1">the parser</a>, and after some digging
+                        it turned out to be caused by <a href="/t/2">this bug</a> in the tokenizer.
+                    </div>
+                </div>
+            "#
+            )
+        };
+
+        let html = format!(
+            r#"
+            <html>
+                <body>
+                    <nav><a href="/">Home</a> <a href="/forum">Forum index, all topics and categories</a></nav>
+                    <div class="sidebar">
+                        <p>Popular threads, hot topics, most viewed and latest replies from this week</p>
+                    </div>
+                    <div id="thread">
+                        {}
+                        {}
+                        {}
+                    </div>
+                    <footer><p>Copyright, all rights reserved, forum software by someone</p></footer>
+                </body>
+            </html>
+        "#,
+            post("alice"),
+            post("bob"),
+            post("carol")
+        );
+
+        let root = kuchiki::parse_html().one(html);
+        let text = Readability::default().extract(&root, &Lang::Eng);
+
+        assert!(text.contains("I had the same problem with the parser"));
+        assert_eq!(text.matches("the same problem").count(), 3);
+        assert!(!text.contains("Popular threads"));
+        assert!(!text.contains("Copyright"));
+        assert!(!text.contains("Forum index"));
+    }
+
+    #[test]
+    fn no_content() {
+        let root = kuchiki::parse_html().one("<html><body><a href=\"/\">home</a></body></html>");
+        assert!(Readability::default().extract(&root, &Lang::Eng).is_empty());
+    }
+}
@@ -16,111 +16,329 @@
 
 use std::{collections::HashMap, fs::File, io::Write, path::Path};
 
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+use whatlang::Lang;
 
 use crate::{Error, Result};
 
-use super::Webpage;
+use super::{Html, Webpage};
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
-pub enum Region {
-    All,
-    Denmark,
-    France,
-    Germany,
-    Spain,
-    US,
+static REGIONS: Lazy<RegionTable> = Lazy::new(|| {
+    RegionTable::parse(include_str!("../../regions/regions.csv"))
+        .expect("Failed to parse region list")
+});
+
+#[derive(Deserialize)]
+struct RegionRecord {
+    gl: String,
+    name: String,
+    country: String,
+    tlds: String,
+    langs: String,
+}
+
+struct RegionInfo {
+    gl: String,
+    name: String,
+}
+
+struct RegionTable {
+    regions: Vec<RegionInfo>,
+    by_gl: HashMap<String, Region>,
+    by_country: HashMap<String, Region>,
+    by_tld: HashMap<String, Region>,
+    by_lang: HashMap<Lang, Region>,
+}
+
+impl RegionTable {
+    fn parse(csv: &str) -> Result<Self> {
+        let mut table = RegionTable {
+            regions: Vec::new(),
+            by_gl: HashMap::new(),
+            by_country: HashMap::new(),
+            by_tld: HashMap::new(),
+            by_lang: HashMap::new(),
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .comment(Some(b'#'))
+            .from_reader(csv.as_bytes());
+
+        for record in reader.deserialize() {
+            let record: RegionRecord = record?;
+            let region = Region(table.regions.len());
+
+            if region == Region::ALL && record.gl != "all" {
+                return Err(Error::ParsingError(
+                    "the first region must be 'all'".to_string(),
+                ));
+            }
+
+            if table.by_gl.insert(record.gl.clone(), region).is_some() {
+                return Err(Error::ParsingError(format!(
+                    "region '{}' is listed twice",
+                    record.gl
+                )));
+            }
+
+            if !record.country.is_empty() {
+                table
+                    .by_country
+                    .insert(record.country.to_ascii_uppercase(), region);
+            }
+
+            for tld in record.tlds.split_whitespace() {
+                table.by_tld.insert(tld.to_ascii_lowercase(), region);
+            }
+
+            for code in record.langs.split_whitespace() {
+                let (code, is_marked) = match code.strip_suffix('*') {
+                    Some(code) => (code, true),
+                    None => (code, false),
+                };
+
+                let lang = Lang::from_code(code).ok_or_else(|| {
+                    Error::ParsingError(format!("unknown language '{}' for '{}'", code, record.gl))
+                })?;
+
+                if is_marked && table.by_lang.insert(lang, region).is_some() {
+                    return Err(Error::ParsingError(format!(
+                        "language '{}' is marked for multiple regions",
+                        code
+                    )));
+                }
+            }
+
+            table.regions.push(RegionInfo {
+                gl: record.gl,
+                name: record.name,
+            });
+        }
+
+        Ok(table)
+    }
 }
 
+/// A region from the region list. The region of a page is inferred from its markup,
+/// top level domain and language (in that order).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Region(usize);
+
 impl Default for Region {
     fn default() -> Self {
-        Region::All
+        Region::ALL
     }
 }
 
-pub const ALL_REGIONS: [Region; 6] = [
-    Region::All,
-    Region::Denmark,
-    Region::France,
-    Region::Germany,
-    Region::Spain,
-    Region::US,
+/// Regions used to be serialized by their enum variant name, which is still
+/// found in existing region count files.
+const LEGACY_NAMES: [(&str, &str); 6] = [
+    ("All", "all"),
+    ("Denmark", "dk"),
+    ("France", "fr"),
+    ("Germany", "ger"),
+    ("Spain", "spa"),
+    ("US", "us"),
 ];
 
+impl TryFrom<String> for Region {
+    type Error = Error;
+
+    fn try_from(gl: String) -> Result<Self> {
+        let gl = LEGACY_NAMES
+            .iter()
+            .find(|(name, _)| *name == gl)
+            .map_or(gl.as_str(), |(_, gl)| gl);
+
+        Region::from_gl(gl)
+    }
+}
+
+impl From<Region> for String {
+    fn from(region: Region) -> Self {
+        region.gl()
+    }
+}
+
 impl Region {
+    pub const ALL: Region = Region(0);
+
+    /// All regions in the order of the region list.
+    pub fn all() -> impl Iterator<Item = Region> {
+        (0..REGIONS.regions.len()).map(Region)
+    }
+
+    fn info(&self) -> &'static RegionInfo {
+        &REGIONS.regions[self.0]
+    }
+
     pub fn name(&self) -> String {
-        match self {
-            Region::All => "All Regions".to_string(),
-            Region::Denmark => "Denmark".to_string(),
-            Region::France => "France".to_string(),
-            Region::Germany => "Germany".to_string(),
-            Region::Spain => "Spain".to_string(),
-            Region::US => "United States".to_string(),
-        }
+        self.info().name.clone()
     }
 
     pub fn gl(&self) -> String {
-        match self {
-            Region::All => "all".to_string(),
-            Region::Denmark => "dk".to_string(),
-            Region::France => "fr".to_string(),
-            Region::Germany => "ger".to_string(),
-            Region::Spain => "spa".to_string(),
-            Region::US => "us".to_string(),
-        }
+        self.info().gl.clone()
     }
 
     pub fn id(&self) -> u64 {
-        ALL_REGIONS
-            .iter()
-            .enumerate()
-            .find(|(_, region)| self == *region)
-            .map(|(id, _)| id as u64)
-            .unwrap()
+        self.0 as u64
     }
 
     pub fn from_gl(gl: &str) -> Result<Self> {
-        match gl {
-            "all" => Ok(Region::All),
-            "dk" => Ok(Region::Denmark),
-            "fr" => Ok(Region::France),
-            "ger" => Ok(Region::Germany),
-            "spa" => Ok(Region::Spain),
-            "us" => Ok(Region::US),
-            _ => Err(Error::UnknownRegion),
-        }
+        REGIONS.by_gl.get(gl).copied().ok_or(Error::UnknownRegion)
     }
 
-    pub fn guess_from(webpage: &Webpage) -> Result<Self> {
-        match webpage.html.lang {
-            Some(lang) => match lang {
-                whatlang::Lang::Eng => Ok(Region::US),
-                whatlang::Lang::Spa => Ok(Region::Spain),
-                whatlang::Lang::Fra => Ok(Region::France),
-                whatlang::Lang::Deu => Ok(Region::Germany),
-                whatlang::Lang::Dan => Ok(Region::Denmark),
-                _ => Err(Error::UnknownRegion),
-            },
-            None => Err(Error::UnknownRegion),
+    pub fn from_id(id: u64) -> Self {
+        if (id as usize) < REGIONS.regions.len() {
+            Region(id as usize)
+        } else {
+            Region::ALL
         }
     }
 
-    pub fn from_id(doc: u64) -> Self {
-        ALL_REGIONS[doc as usize]
+    /// Region from an ISO 3166-1 alpha-2 country code.
+    pub fn from_country_code(code: &str) -> Option<Self> {
+        REGIONS.by_country.get(&code.to_ascii_uppercase()).copied()
     }
 
-    pub fn lang(&self) -> Option<whatlang::Lang> {
-        match self {
-            Region::Denmark => Some(whatlang::Lang::Dan),
-            Region::France => Some(whatlang::Lang::Fra),
-            Region::Germany => Some(whatlang::Lang::Deu),
-            Region::Spain => Some(whatlang::Lang::Spa),
-            Region::US => Some(whatlang::Lang::Eng),
-            Region::All => None,
-        }
+    pub fn from_tld(tld: &str) -> Option<Self> {
+        REGIONS.by_tld.get(&tld.to_ascii_lowercase()).copied()
+    }
+
+    /// The region a text in `lang` is attributed to, if the language is
+    /// mainly used in a single region.
+    pub fn from_lang(lang: &Lang) -> Option<Self> {
+        REGIONS.by_lang.get(lang).copied()
+    }
+
+    /// Region from the region subtag of a BCP 47 language tag (e.g. `en-GB`).
+    pub fn from_language_tag(tag: &str) -> Option<Self> {
+        tag.trim()
+            .split(['-', '_'])
+            .skip(1)
+            .find(|subtag| subtag.len() == 2 && subtag.chars().all(|c| c.is_ascii_alphabetic()))
+            .and_then(Region::from_country_code)
+    }
+
+    pub fn guess_from(webpage: &Webpage) -> Result<Self> {
+        Region::guess_from_html(&webpage.html)
+    }
+
+    pub fn guess_from_html(html: &Html) -> Result<Self> {
+        html.hreflang()
+            .and_then(|tag| Region::from_language_tag(&tag))
+            .or_else(|| {
+                html.declared_lang()
+                    .and_then(|tag| Region::from_language_tag(&tag))
+            })
+            .or_else(|| {
+                html.url()
+                    .host()
+                    .rsplit('.')
+                    .next()
+                    .and_then(Region::from_tld)
+            })
+            .or_else(|| html.lang.as_ref().and_then(Region::from_lang))
+            .ok_or(Error::UnknownRegion)
     }
 }
 
+/// Ids of the languages in the `lang` fast field. The ids are stored in the index, so they
+/// must never change and new languages must get new ids.
+const LANG_IDS: [(Lang, u64); 69] = [
+    (Lang::Epo, 1),
+    (Lang::Eng, 2),
+    (Lang::Rus, 3),
+    (Lang::Cmn, 4),
+    (Lang::Spa, 5),
+    (Lang::Por, 6),
+    (Lang::Ita, 7),
+    (Lang::Ben, 8),
+    (Lang::Fra, 9),
+    (Lang::Deu, 10),
+    (Lang::Ukr, 11),
+    (Lang::Kat, 12),
+    (Lang::Ara, 13),
+    (Lang::Hin, 14),
+    (Lang::Jpn, 15),
+    (Lang::Heb, 16),
+    (Lang::Yid, 17),
+    (Lang::Pol, 18),
+    (Lang::Amh, 19),
+    (Lang::Jav, 20),
+    (Lang::Kor, 21),
+    (Lang::Nob, 22),
+    (Lang::Dan, 23),
+    (Lang::Swe, 24),
+    (Lang::Fin, 25),
+    (Lang::Tur, 26),
+    (Lang::Nld, 27),
+    (Lang::Hun, 28),
+    (Lang::Ces, 29),
+    (Lang::Ell, 30),
+    (Lang::Bul, 31),
+    (Lang::Bel, 32),
+    (Lang::Mar, 33),
+    (Lang::Kan, 34),
+    (Lang::Ron, 35),
+    (Lang::Slv, 36),
+    (Lang::Hrv, 37),
+    (Lang::Srp, 38),
+    (Lang::Mkd, 39),
+    (Lang::Lit, 40),
+    (Lang::Lav, 41),
+    (Lang::Est, 42),
+    (Lang::Tam, 43),
+    (Lang::Vie, 44),
+    (Lang::Urd, 45),
+    (Lang::Tha, 46),
+    (Lang::Guj, 47),
+    (Lang::Uzb, 48),
+    (Lang::Pan, 49),
+    (Lang::Aze, 50),
+    (Lang::Ind, 51),
+    (Lang::Tel, 52),
+    (Lang::Pes, 53),
+    (Lang::Mal, 54),
+    (Lang::Ori, 55),
+    (Lang::Mya, 56),
+    (Lang::Nep, 57),
+    (Lang::Sin, 58),
+    (Lang::Khm, 59),
+    (Lang::Tuk, 60),
+    (Lang::Aka, 61),
+    (Lang::Zul, 62),
+    (Lang::Sna, 63),
+    (Lang::Afr, 64),
+    (Lang::Lat, 65),
+    (Lang::Slk, 66),
+    (Lang::Cat, 67),
+    (Lang::Tgl, 68),
+    (Lang::Hye, 69),
+];
+
+/// Id of the language in the `lang` fast field. 0 is used for pages where the
+/// language could not be detected.
+pub fn lang_id(lang: Option<&Lang>) -> u64 {
+    lang.and_then(|lang| {
+        LANG_IDS
+            .iter()
+            .find(|(other, _)| other == lang)
+            .map(|(_, id)| *id)
+    })
+    .unwrap_or(0)
+}
+
+pub fn lang_from_id(id: u64) -> Option<Lang> {
+    LANG_IDS
+        .iter()
+        .find(|(_, other)| *other == id)
+        .map(|(lang, _)| *lang)
+}
+
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct RegionCount {
     map: HashMap<Region, u64>,
@@ -130,17 +348,36 @@ pub struct RegionCount {
 }
 
 impl RegionCount {
-    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+    /// Regions that are no longer in the region list are skipped, so an index can
+    /// still be opened after a region has been removed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let map: HashMap<Region, u64> = if !path.as_ref().exists() {
             if let Some(parent) = path.as_ref().parent() {
-                std::fs::create_dir_all(parent).unwrap();
+                std::fs::create_dir_all(parent)?;
             }
-            File::create(path.as_ref()).unwrap();
+            File::create(path.as_ref())?;
 
             HashMap::new()
         } else {
-            let json = std::fs::read_to_string(path.as_ref()).unwrap_or_default();
-            serde_json::from_str(&json).unwrap_or_else(|_| HashMap::new())
+            let json = std::fs::read_to_string(path.as_ref())?;
+
+            // the file is created empty and only filled on commit
+            if json.trim().is_empty() {
+                HashMap::new()
+            } else {
+                let counts: HashMap<String, u64> = serde_json::from_str(&json)?;
+
+                counts
+                    .into_iter()
+                    .filter_map(|(gl, count)| match Region::try_from(gl.clone()) {
+                        Ok(region) => Some((region, count)),
+                        Err(_) => {
+                            warn!("skipping unknown region {:?} in {:?}", gl, path.as_ref());
+                            None
+                        }
+                    })
+                    .collect()
+            }
         };
 
         let mut fast_count = Vec::new();
@@ -155,12 +392,12 @@ impl RegionCount {
             fast_count[idx] = Some(*count);
         }
 
-        RegionCount {
+        Ok(RegionCount {
             total_counts: map.iter().map(|(_, count)| count).sum(),
             map,
             fast_count,
             path: path.as_ref().to_str().unwrap().to_string(),
-        }
+        })
     }
 
     pub fn increment(&mut self, region: &Region) {
@@ -219,24 +456,161 @@ mod tests {
 
     #[test]
     fn simple() {
-        let mut a = RegionCount::open(gen_temp_path().join("region_count.json"));
+        let denmark = Region::from_gl("dk").unwrap();
+        let us = Region::from_gl("us").unwrap();
+        let germany = Region::from_gl("ger").unwrap();
+        let france = Region::from_gl("fr").unwrap();
 
-        a.increment(&Region::Denmark);
-        a.increment(&Region::Denmark);
-        a.increment(&Region::US);
+        let mut a = RegionCount::open(gen_temp_path().join("region_count.json")).unwrap();
 
-        let mut b = RegionCount::open(gen_temp_path().join("region_count.json"));
+        a.increment(&denmark);
+        a.increment(&denmark);
+        a.increment(&us);
 
-        b.increment(&Region::US);
-        b.increment(&Region::Germany);
+        let mut b = RegionCount::open(gen_temp_path().join("region_count.json")).unwrap();
+
+        b.increment(&us);
+        b.increment(&germany);
 
         a.merge(b);
 
-        assert_eq!(a.map.get(&Region::Denmark), Some(&2));
-        assert_eq!(a.map.get(&Region::US), Some(&2));
-        assert_eq!(a.map.get(&Region::Germany), Some(&1));
+        assert_eq!(a.map.get(&denmark), Some(&2));
+        assert_eq!(a.map.get(&us), Some(&2));
+        assert_eq!(a.map.get(&germany), Some(&1));
+
+        assert_eq!(a.score(&denmark), 0.4);
+        assert_eq!(a.score(&france), 0.0);
+    }
+
+    #[test]
+    fn legacy_region_count() {
+        let path = gen_temp_path().join("region_count.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, r#"{"Denmark":3,"US":1,"ger":4}"#).unwrap();
+
+        let counts = RegionCount::open(&path).unwrap();
 
-        assert_eq!(a.score(&Region::Denmark), 0.4);
-        assert_eq!(a.score(&Region::France), 0.0);
+        assert_eq!(counts.map.get(&Region::from_gl("dk").unwrap()), Some(&3));
+        assert_eq!(counts.map.get(&Region::from_gl("us").unwrap()), Some(&1));
+        assert_eq!(counts.map.get(&Region::from_gl("ger").unwrap()), Some(&4));
+    }
+
+    #[test]
+    fn unknown_regions_skipped() {
+        let path = gen_temp_path().join("region_count.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, r#"{"dk":3,"atlantis":2}"#).unwrap();
+
+        let counts = RegionCount::open(&path).unwrap();
+
+        assert_eq!(counts.map.len(), 1);
+        assert_eq!(counts.score(&Region::from_gl("dk").unwrap()), 1.0);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(RegionCount::open(&path).is_err());
+    }
+
+    #[test]
+    fn lang_ids_are_unique() {
+        for lang in Lang::all() {
+            assert_eq!(lang_from_id(lang_id(Some(lang))), Some(*lang));
+        }
+
+        assert_eq!(lang_id(Some(&Lang::Eng)), 2);
+        assert_eq!(lang_id(None), 0);
+        assert_eq!(lang_from_id(0), None);
+    }
+
+    #[test]
+    fn region_list() {
+        assert_eq!(Region::all().next(), Some(Region::ALL));
+        assert_eq!(Region::ALL.gl(), "all");
+
+        // ids of the original regions are stored in existing indexes
+        for (id, gl) in ["all", "dk", "fr", "ger", "spa", "us"]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(Region::from_gl(gl).unwrap().id(), id as u64);
+        }
+
+        assert_eq!(Region::from_id(10_000), Region::ALL);
+        assert!(Region::from_gl("atlantis").is_err());
+
+        assert!(
+            RegionTable::parse("gl,name,country,tlds,langs\nall,All,,,\ndk,Denmark,DK,dk,xyz")
+                .is_err()
+        );
+        assert!(RegionTable::parse(
+            "gl,name,country,tlds,langs\nall,All,,,\ndk,Denmark,DK,dk,dan*\ngl,Greenland,GL,gl,dan*"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn language_tags() {
+        let gb = Region::from_gl("gb").unwrap();
+
+        assert_eq!(Region::from_language_tag("en-GB"), Some(gb));
+        assert_eq!(Region::from_language_tag("en_gb"), Some(gb));
+        assert_eq!(
+            Region::from_language_tag("zh-Hant-CN"),
+            Region::from_gl("cn").ok()
+        );
+        assert_eq!(Region::from_language_tag("en"), None);
+        assert_eq!(Region::from_language_tag("x-default"), None);
+    }
+
+    #[test]
+    fn guess_region() {
+        let guess = |html: &str, url: &str| Region::guess_from_html(&Html::parse(html, url)).ok();
+
+        let english = "<p>This is a longer paragraph of english text, which should be more than enough for the language detection to be confident about the language of the text.</p>";
+        let german = "<p>Dies ist ein längerer Text auf Deutsch, der mehr als genug sein sollte, damit die Spracherkennung sich über die Sprache des Textes sicher ist.</p>";
+
+        // english is used in too many regions to say anything about the region
+        assert_eq!(
+            guess(
+                &format!("<html><body>{english}</body></html>"),
+                "https://example.com"
+            ),
+            None
+        );
+        assert_eq!(
+            guess(
+                &format!("<html><body>{german}</body></html>"),
+                "https://example.com"
+            ),
+            Region::from_gl("ger").ok()
+        );
+        assert_eq!(
+            guess(
+                &format!("<html><body>{english}</body></html>"),
+                "https://example.co.uk"
+            ),
+            Region::from_gl("gb").ok()
+        );
+        assert_eq!(
+            guess(
+                &format!("<html lang=\"en-AU\"><body>{english}</body></html>"),
+                "https://example.co.uk"
+            ),
+            Region::from_gl("au").ok()
+        );
+        assert_eq!(
+            guess(
+                &format!(
+                    r#"<html lang="en">
+                        <head>
+                            <link rel="alternate" hreflang="en-ie" href="https://example.com/ie" />
+                            <link rel="alternate" hreflang="en-ca" href="/ca" />
+                        </head>
+                        <body>{english}</body>
+                    </html>"#
+                ),
+                "https://example.com/ca"
+            ),
+            Region::from_gl("ca").ok()
+        );
     }
 }
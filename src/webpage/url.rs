@@ -14,10 +14,66 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{fmt::Display, time::Duration};
+use std::{fmt::Display, net::IpAddr, time::Duration};
 
+use once_cell::sync::Lazy;
+use publicsuffix::{List, Psl};
 use serde::{Deserialize, Serialize};
 use tracing::debug;
+use url::Position;
+
+static PUBLIC_SUFFIX_LIST: Lazy<List> = Lazy::new(|| {
+    include_str!("../../publicsuffix/public_suffix_list.dat")
+        .parse()
+        .expect("Failed to parse public suffix list")
+});
+
+/// Query parameters that only track where the visitor came from.
+/// They don't change the content of the page, so they are removed during normalisation.
+const TRACKING_PARAMS: [&str; 7] = [
+    "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid",
+];
+const TRACKING_PARAM_PREFIXES: [&str; 1] = ["utm_"];
+
+fn is_tracking_param(param: &str) -> bool {
+    let key = param
+        .split('=')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    TRACKING_PARAMS.contains(&key.as_str())
+        || TRACKING_PARAM_PREFIXES
+            .iter()
+            .any(|prefix| key.starts_with(prefix))
+}
+
+/// The parser already lowercases the host, removes default ports and resolves
+/// dot-segments in the path. The root path is always written without the "/", so
+/// `https://example.com/` and `https://example.com` end up as the same url.
+fn normalize(mut url: url::Url) -> Url {
+    url.set_fragment(None);
+
+    if let Some(query) = url.query() {
+        let params: Vec<String> = query
+            .split('&')
+            .filter(|param| !param.is_empty() && !is_tracking_param(param))
+            .map(str::to_string)
+            .collect();
+
+        if params.is_empty() {
+            url.set_query(None);
+        } else if params.join("&") != query {
+            url.set_query(Some(&params.join("&")));
+        }
+    }
+
+    if url.path() == "/" {
+        Url(url[..Position::AfterPort].to_string() + &url[Position::AfterPath..])
+    } else {
+        Url(url.into())
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
 pub struct Url(String);
@@ -69,24 +125,33 @@ impl Url {
         &url[..end_site]
     }
 
-    pub fn domain(&self) -> &str {
+    /// The site without the port.
+    pub fn host(&self) -> &str {
         let site = self.site();
-        let num_punctuations: usize = site.chars().map(|c| (c == '.') as usize).sum();
-        if num_punctuations > 1 {
-            let domain_index = site.rfind('.').unwrap();
-            let mut start_index = site[..domain_index].rfind('.').unwrap() + 1;
-
-            if &site[start_index..] == "co.uk" {
-                if let Some(new_start_index) = site[..start_index - 1].rfind('.') {
-                    start_index = new_start_index + 1;
-                } else {
-                    start_index = 0;
-                }
-            }
 
-            &site[start_index..]
+        if site.starts_with('[') {
+            // ipv6 address
+            match site.find(']') {
+                Some(end) => &site[..=end],
+                None => site,
+            }
         } else {
-            site
+            site.rsplit_once(':').map_or(site, |(host, _)| host)
+        }
+    }
+
+    /// The registrable domain of the host according to the public suffix list
+    /// (e.g. `example.co.uk` for `www.example.co.uk`).
+    pub fn domain(&self) -> &str {
+        let host = self.host();
+
+        if host.starts_with('[') || host.parse::<IpAddr>().is_ok() {
+            return host;
+        }
+
+        match PUBLIC_SUFFIX_LIST.domain(host.as_bytes()) {
+            Some(domain) => &host[host.len() - domain.as_bytes().len()..],
+            None => host,
         }
     }
 
@@ -101,7 +166,7 @@ impl Url {
     }
 
     pub fn subdomain(&self) -> Option<&str> {
-        if let Some(subdomain) = self.site().strip_suffix(self.domain()) {
+        if let Some(subdomain) = self.host().strip_suffix(self.domain()) {
             if subdomain.is_empty() || subdomain == "." {
                 None
             } else {
@@ -112,6 +177,17 @@ impl Url {
         }
     }
 
+    /// The path without the query, e.g. `/a/b` for `https://example.com/a/b?c=d`.
+    pub fn path(&self) -> &str {
+        let url = self.strip_protocol();
+        let url = url.find('?').map_or(url, |query_begin| &url[..query_begin]);
+
+        match url.find('/') {
+            Some(path_begin) => &url[path_begin..],
+            None => "",
+        }
+    }
+
     pub fn is_homepage(&self) -> bool {
         let url = self.strip_protocol();
         match url.find('/') {
@@ -127,11 +203,6 @@ impl Url {
             start_host = url
                 .find(':')
                 .expect("It was checked that url starts with protocol");
-        } else if url.starts_with("//") {
-            start_host = url
-                .find('/')
-                .expect("It was checked that url starts with protocol")
-                + 1;
         }
         start_host
     }
@@ -139,21 +210,34 @@ impl Url {
         &self.0[..self.find_protocol_end()]
     }
 
-    pub fn is_full_path(&self) -> bool {
-        matches!(self.protocol(), "http" | "https" | "pdf")
+    /// Normalise the url (RFC 3986): lowercase host, no default port, no fragment and
+    /// no tracking parameters. Urls that cannot be parsed only have their fragment removed.
+    pub fn normalized(&self) -> Url {
+        match url::Url::parse(&self.full()) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => normalize(url),
+            _ => self.full_without_id_tags().into(),
+        }
     }
 
-    pub fn prefix_with(&mut self, url: &Url) {
-        self.0 = match (url.0.ends_with('/'), self.0.starts_with('/')) {
-            (true, true) => url.protocol().to_string() + "://" + url.site() + &self.0,
-            (true, false) => url.full() + &self.0,
-            (false, true) => url.protocol().to_string() + "://" + url.site() + &self.0,
-            (false, false) => url.full() + "/" + &self.0,
-        };
+    /// Resolve a (possibly relative) link with this url as base according to RFC 3986.
+    /// The result is normalised, and only http(s) links are returned.
+    pub fn resolve(&self, link: &str) -> Option<Url> {
+        let base = url::Url::parse(&self.full()).ok()?;
+        let url = base.join(link.trim()).ok()?;
+
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+
+        Some(normalize(url))
     }
 
+    /// The url with a protocol. Urls without one, including scheme-relative urls
+    /// like `//example.com`, are assumed to be https.
     pub fn full(&self) -> String {
-        if self.find_protocol_end() == 0 {
+        if self.0.starts_with("//") {
+            "https:".to_string() + &self.0
+        } else if self.find_protocol_end() == 0 {
             "https://".to_string() + &self.0
         } else {
             self.0.clone()
@@ -223,6 +307,7 @@ mod tests {
         assert_eq!(url.domain(), "dailymail.co.uk");
         assert_eq!(url.domain_name(), "dailymail");
         assert_eq!(url.site(), "scripts.dailymail.co.uk");
+        assert_eq!(url.full(), "https://scripts.dailymail.co.uk");
     }
 
     #[test]
@@ -244,32 +329,117 @@ mod tests {
     }
 
     #[test]
-    fn prefix_with() {
-        let mut a: Url = "/test".to_string().into();
-        let b: Url = "https://example.com".to_string().into();
-        a.prefix_with(&b);
-        assert_eq!(a.full().as_str(), "https://example.com/test");
+    fn resolve() {
+        let base: Url = "https://example.com".to_string().into();
+        assert_eq!(
+            base.resolve("/test").unwrap().raw(),
+            "https://example.com/test"
+        );
+        assert_eq!(
+            base.resolve("test").unwrap().raw(),
+            "https://example.com/test"
+        );
+
+        let base: Url = "https://example.com/".to_string().into();
+        assert_eq!(
+            base.resolve("test").unwrap().raw(),
+            "https://example.com/test"
+        );
+
+        let base: Url = "https://example.com/a/b/page.html".to_string().into();
+        assert_eq!(
+            base.resolve("../about").unwrap().raw(),
+            "https://example.com/a/about"
+        );
+        assert_eq!(
+            base.resolve("c").unwrap().raw(),
+            "https://example.com/a/b/c"
+        );
+        assert_eq!(base.resolve("/").unwrap().raw(), "https://example.com");
+        assert_eq!(
+            base.resolve("https://other.com").unwrap().raw(),
+            "https://other.com"
+        );
+        assert_eq!(
+            base.resolve("?q=1").unwrap().raw(),
+            "https://example.com/a/b/page.html?q=1"
+        );
+        assert_eq!(
+            base.resolve("//other.com/x#top").unwrap().raw(),
+            "https://other.com/x"
+        );
+        assert_eq!(
+            base.resolve("//other.com").unwrap().raw(),
+            base.resolve("https://other.com/").unwrap().raw()
+        );
+        assert_eq!(
+            base.resolve("HTTP://Other.COM:80/x").unwrap().raw(),
+            "http://other.com/x"
+        );
+
+        assert_eq!(base.resolve("mailto:test@example.com"), None);
+        assert_eq!(base.resolve("javascript:void(0)"), None);
+    }
+
+    #[test]
+    fn normalized() {
+        let url: Url = "https://Example.COM:443/a/./b/../c?utm_source=x&id=2&fbclid=abc#frag"
+            .to_string()
+            .into();
+        assert_eq!(url.normalized().raw(), "https://example.com/a/c?id=2");
+
+        let url: Url = "http://example.com:8080/?utm_medium=email"
+            .to_string()
+            .into();
+        assert_eq!(url.normalized().raw(), "http://example.com:8080");
+
+        let url: Url = "example.com/test?a=b%20c".to_string().into();
+        assert_eq!(url.normalized().raw(), "https://example.com/test?a=b%20c");
+
+        let url: Url = "https://Example.com/".to_string().into();
+        assert_eq!(url.normalized().raw(), "https://example.com");
+
+        let url: Url = "https://Example.com#top".to_string().into();
+        assert_eq!(url.normalized().raw(), "https://example.com");
+
+        let url: Url = "https://example.com/?q=1".to_string().into();
+        assert_eq!(url.normalized().raw(), "https://example.com?q=1");
+
+        let url: Url = "//Example.com/".to_string().into();
+        assert_eq!(url.normalized().raw(), "https://example.com");
+    }
+
+    #[test]
+    fn public_suffix_domain() {
+        let url: Url = "https://www.example.com".to_string().into();
+        assert_eq!(url.domain(), "example.com");
+
+        let url: Url = "https://a.b.example.co.jp".to_string().into();
+        assert_eq!(url.domain(), "example.co.jp");
+        assert_eq!(url.subdomain(), Some("a.b"));
 
-        let mut a: Url = "test".to_string().into();
-        let b: Url = "https://example.com".to_string().into();
-        a.prefix_with(&b);
-        assert_eq!(a.full().as_str(), "https://example.com/test");
+        let url: Url = "https://www.example.com.au:8080/test".to_string().into();
+        assert_eq!(url.host(), "www.example.com.au");
+        assert_eq!(url.domain(), "example.com.au");
+        assert_eq!(url.subdomain(), Some("www"));
 
-        let mut a: Url = "test".to_string().into();
-        let b: Url = "https://example.com/".to_string().into();
-        a.prefix_with(&b);
-        assert_eq!(a.full().as_str(), "https://example.com/test");
+        let url: Url = "http://127.0.0.1:3000".to_string().into();
+        assert_eq!(url.domain(), "127.0.0.1");
 
-        let mut a: Url = "/test".to_string().into();
-        let b: Url = "https://example.com/".to_string().into();
-        a.prefix_with(&b);
-        assert_eq!(a.full().as_str(), "https://example.com/test");
+        let url: Url = "http://localhost".to_string().into();
+        assert_eq!(url.domain(), "localhost");
     }
 
     #[test]
-    fn is_full_path() {
-        let url: Url = "https://dailymail.co.uk".to_string().into();
-        assert!(url.is_full_path());
+    fn path() {
+        let url: Url = "https://example.com/a/b?c=d".to_string().into();
+        assert_eq!(url.path(), "/a/b");
+
+        let url: Url = "https://example.com?c=d/e".to_string().into();
+        assert_eq!(url.path(), "");
+
+        let url: Url = "example.com/a".to_string().into();
+        assert_eq!(url.path(), "/a");
     }
 
     #[test]